@@ -1,33 +1,23 @@
+use perseus::{template::LayoutProps, Layout};
 use sycamore::prelude::*;
 
-// NOTE: None of the code in this file is Perseus-specific! This could easily be
-// applied to any Sycamore app.
-
-#[component]
-pub fn Layout<'a, G: Html>(
-    cx: Scope<'a>,
-    LayoutProps { title, children }: LayoutProps<'a, G>,
-) -> View<G> {
-    let children = children.call(cx);
-
-    view! { cx,
-        // These elements are styled with bright colors for demonstration purposes
-        header(style = "background-color: red; color: white; padding: 1rem") {
-            p { (title.to_string()) }
-        }
-        main(style = "padding: 1rem") {
-            (children)
+// Because this is a Perseus layout, rather than a component that each template
+// renders itself, it will only be rendered once in the browser, and it will
+// stay mounted as the user navigates between pages that use it
+pub fn get_layout<G: Html>() -> Layout<G> {
+    Layout::new("main").template(|cx, LayoutProps { content, .. }| {
+        view! { cx,
+            // These elements are styled with bright colors for demonstration purposes
+            header(style = "background-color: red; color: white; padding: 1rem") {
+                p { "Hey there, I'm a header!" }
+            }
+            main(style = "padding: 1rem") {
+                // This is where the page will be rendered
+                (content)
+            }
+            footer(style = "background-color: black; color: white; padding: 1rem") {
+                p { "Hey there, I'm a footer!" }
+            }
         }
-        footer(style = "background-color: black; color: white; padding: 1rem") {
-            p { "Hey there, I'm a footer!" }
-        }
-    }
-}
-
-#[derive(Prop)]
-pub struct LayoutProps<'a, G: Html> {
-    /// The title of the page, which will be displayed in the header.
-    pub title: &'a str,
-    /// The content to put inside the layout.
-    pub children: Children<'a, G>,
+    })
 }
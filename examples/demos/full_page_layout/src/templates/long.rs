@@ -1,17 +1,15 @@
-use crate::components::layout::Layout;
+use crate::components::layout::get_layout;
 use perseus::Template;
 use sycamore::prelude::{view, Html, Scope, SsrNode, View};
 
 #[perseus::template_rx]
 pub fn long_page<G: Html>(cx: Scope) -> View<G> {
     view! { cx,
-        Layout(title = "Long") {
-            // Anything we put in here will be rendered inside the `<main>` block of the layout
-            a(href = "") { "Index" }
-            br {}
-            p {
-                ("This is a test. ".repeat(5000))
-            }
+        // Anything we put in here will be rendered inside the `<main>` block of the layout
+        a(href = "") { "Index" }
+        br {}
+        p {
+            ("This is a test. ".repeat(5000))
         }
     }
 }
@@ -24,5 +22,8 @@ pub fn head(cx: Scope) -> View<SsrNode> {
 }
 
 pub fn get_template<G: Html>() -> Template<G> {
-    Template::new("long").template(long_page).head(head)
+    Template::new("long")
+        .template(long_page)
+        .layout(get_layout())
+        .head(head)
 }
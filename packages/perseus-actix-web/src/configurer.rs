@@ -1,11 +1,12 @@
 use crate::initial_load::initial_load;
 use crate::page_data::page_data;
+use crate::state_push::state_push;
 use crate::translations::translations;
 use actix_files::{Files, NamedFile};
-use actix_web::{web, HttpRequest};
+use actix_web::{http::header, middleware::DefaultHeaders, web, HttpRequest};
 use perseus::{
     i18n::TranslationsManager,
-    server::{get_render_cfg, ServerOptions, ServerProps},
+    server::{get_render_cfg, ServerOptions, ServerProps, IMMUTABLE_CACHE_CONTROL},
     stores::MutableStore,
 };
 
@@ -90,6 +91,24 @@ pub async fn configurer<M: MutableStore + 'static, T: TranslationsManager + 'sta
             // 'inlined') These won't change, so they can be set as a filesystem
             // dependency safely
             .service(Files::new("/.perseus/snippets", &opts.snippets));
+        // If we know the hashes of the bundles, we can also serve them from URLs that
+        // change whenever they do, which means they can be cached forever
+        if let Some(bundles) = &opts.bundles {
+            cfg.service(
+                web::scope(&bundles.get_url_root(""))
+                    .wrap(
+                        DefaultHeaders::new().add((header::CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL)),
+                    )
+                    .route("/bundle.js", web::get().to(js_bundle))
+                    .route("/bundle.wasm", web::get().to(wasm_bundle))
+                    .route("/bundle.wasm.js", web::get().to(wasm_js_bundle))
+                    .service(Files::new("/snippets", &opts.snippets)),
+            );
+        }
+        // This streams any state the app publishes to the browser
+        if opts.state_publisher.is_some() {
+            cfg.route("/.perseus/state-push", web::get().to(state_push));
+        }
         // Now we add support for any static content the user wants to provide
         if let Some(static_dir) = &opts.static_dir {
             cfg.service(Files::new("/.perseus/static", static_dir));
@@ -1,18 +1,26 @@
 use crate::configurer;
 use actix_web::{App, HttpServer};
 use futures::executor::block_on;
-use perseus::{i18n::TranslationsManager, server::ServerProps, stores::MutableStore};
+use perseus::{
+    i18n::TranslationsManager,
+    server::{ServerProps, TlsConfig},
+    stores::MutableStore,
+};
 
 /// Creates and starts the default Perseus server using Actix Web. This should
 /// be run in a `main()` function annotated with `#[tokio::main]` (which
 /// requires the `macros` and `rt-multi-thread` features on the `tokio`
 /// dependency).
+///
+/// If the `PERSEUS_TLS_CERT` and `PERSEUS_TLS_KEY` environment variables are
+/// set, this will serve over HTTPS, which requires the `tls` feature (see
+/// [`TlsConfig`] for details).
 pub async fn dflt_server<M: MutableStore + 'static, T: TranslationsManager + 'static>(
     props: ServerProps<M, T>,
     (host, port): (String, u16),
 ) {
     // TODO Fix issues here
-    HttpServer::new(move ||
+    let server = HttpServer::new(move ||
         App::new()
             .configure(
                 block_on(
@@ -21,10 +29,87 @@ pub async fn dflt_server<M: MutableStore + 'static, T: TranslationsManager + 'st
                     )
                 )
             )
-    )
-        .bind((host, port))
-        .expect("Couldn't bind to given address. Maybe something is already running on the selected port?")
-        .run()
-        .await
-        .expect("Server failed.") // TODO Improve error message here
+    );
+    match TlsConfig::from_env() {
+        #[cfg(feature = "tls")]
+        Some(tls) => {
+            let server = server
+                .bind_rustls((host.as_str(), port), get_rustls_config(&tls))
+                .expect("Couldn't bind to given address. Maybe something is already running on the selected port?")
+                .run();
+            match tls.redirect_port {
+                Some(redirect_port) => {
+                    let redirect_host = host.clone();
+                    let redirect_server = HttpServer::new(move || {
+                        let host = host.clone();
+                        App::new().default_service(actix_web::web::to(move |req: actix_web::HttpRequest| {
+                            let url = perseus::server::get_https_redirect_url(
+                                req.headers()
+                                    .get(actix_web::http::header::HOST)
+                                    .and_then(|host_header| host_header.to_str().ok()),
+                                req.uri()
+                                    .path_and_query()
+                                    .map(|path_and_query| path_and_query.as_str())
+                                    .unwrap_or("/"),
+                                (&host, port),
+                            );
+                            async move {
+                                actix_web::HttpResponse::PermanentRedirect()
+                                    .insert_header((actix_web::http::header::LOCATION, url))
+                                    .finish()
+                            }
+                        }))
+                    })
+                    .bind((redirect_host, redirect_port))
+                    .expect("Couldn't bind to given address for HTTPS redirection.")
+                    .run();
+                    let (res, redirect_res) = futures::future::join(server, redirect_server).await;
+                    res.expect("Server failed.");
+                    redirect_res.expect("HTTPS redirection server failed.");
+                }
+                None => server.await.expect("Server failed."),
+            }
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(_) => panic!("TLS has been configured, but the `tls` feature of `perseus-actix-web` isn't enabled"),
+        None => server
+            .bind((host, port))
+            .expect("Couldn't bind to given address. Maybe something is already running on the selected port?")
+            .run()
+            .await
+            .expect("Server failed."), // TODO Improve error message here
+    }
+}
+
+/// Loads the certificate chain and private key from the given configuration
+/// into a `rustls` configuration.
+#[cfg(feature = "tls")]
+fn get_rustls_config(tls: &TlsConfig) -> rustls::ServerConfig {
+    use rustls_pemfile::Item;
+    use std::{fs::File, io::BufReader};
+
+    let mut cert_file =
+        BufReader::new(File::open(&tls.cert_path).expect("Couldn't open TLS certificate."));
+    let certs = rustls_pemfile::certs(&mut cert_file)
+        .expect("Couldn't parse TLS certificate.")
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let mut key_file =
+        BufReader::new(File::open(&tls.key_path).expect("Couldn't open TLS private key."));
+    // We'll take the first key of any supported type
+    let key = rustls_pemfile::read_all(&mut key_file)
+        .expect("Couldn't parse TLS private key.")
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(key),
+            _ => None,
+        })
+        .expect("No private key found in TLS key file.");
+
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, rustls::PrivateKey(key))
+        .expect("Invalid TLS certificate or private key.")
 }
@@ -1,13 +1,16 @@
 use crate::conv_req::convert_req;
-use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
 use fmterr::fmt_err;
 use perseus::{
     errors::err_to_status_code,
     i18n::{TranslationsManager, Translator},
     router::{match_route_atomic, RouteInfoAtomic, RouteVerdictAtomic},
     server::{
-        build_error_page, get_page_for_template, get_path_slice, GetPageProps, HtmlShell,
-        ServerOptions,
+        build_error_page, generate_nonce, get_page_for_template, get_path_slice, GetPageProps,
+        HtmlShell, ServerOptions,
     },
     stores::{ImmutableStore, MutableStore},
     utils::get_path_prefix_server,
@@ -26,11 +29,20 @@ fn return_error_page(
     translator: Option<Rc<Translator>>,
     error_pages: &ErrorPages<SsrNode>,
     html_shell: &HtmlShell,
+    csp_header: &Option<String>,
 ) -> HttpResponse {
     let html = build_error_page(url, status, err, translator, error_pages, html_shell);
-    HttpResponse::build(StatusCode::from_u16(status).unwrap())
-        .content_type("text/html")
-        .body(html)
+    let mut http_res = HttpResponse::build(StatusCode::from_u16(status).unwrap());
+    add_csp_header(&mut http_res, csp_header);
+    http_res.content_type("text/html").body(html)
+}
+
+/// Adds the `Content-Security-Policy` header for this response, if the app
+/// has one.
+fn add_csp_header(http_res: &mut HttpResponseBuilder, csp_header: &Option<String>) {
+    if let Some(csp_header) = csp_header {
+        http_res.insert_header((header::CONTENT_SECURITY_POLICY, csp_header.as_str()));
+    }
 }
 
 /// The handler for calls to any actual pages (first-time visits), which will
@@ -50,10 +62,30 @@ pub async fn initial_load<M: MutableStore, T: TranslationsManager>(
     let error_pages = &opts.error_pages;
     let path = req.path();
     let path_slice = get_path_slice(path);
+    // If the app has a CSP, every initial load gets a fresh nonce for its inline
+    // scripts
+    let (html_shell, csp_header) = match &opts.csp {
+        Some(csp) => {
+            let nonce = generate_nonce();
+            (
+                html_shell.get_ref().clone().with_nonce(&nonce),
+                Some(csp.get_header(&nonce)),
+            )
+        }
+        None => (html_shell.get_ref().clone(), None),
+    };
     // Create a closure to make returning error pages easier (most have the same
     // data)
     let html_err = |status: u16, err: &str| {
-        return return_error_page(path, status, err, None, error_pages, html_shell.get_ref());
+        return return_error_page(
+            path,
+            status,
+            err,
+            None,
+            error_pages,
+            &html_shell,
+            &csp_header,
+        );
     };
 
     // Run the routing algorithms on the path to figure out which template we need
@@ -115,8 +147,8 @@ pub async fn initial_load<M: MutableStore, T: TranslationsManager>(
             };
 
             let final_html = html_shell
-                .get_ref()
                 .clone()
+                .for_template(template)
                 .page_data(&page_data, &global_state, &translations)
                 .to_string();
 
@@ -126,6 +158,7 @@ pub async fn initial_load<M: MutableStore, T: TranslationsManager>(
             for (key, val) in template.get_headers(page_data.state) {
                 http_res.insert_header((key.unwrap(), val));
             }
+            add_csp_header(&mut http_res, &csp_header);
 
             http_res.body(final_html)
         }
@@ -135,9 +168,10 @@ pub async fn initial_load<M: MutableStore, T: TranslationsManager>(
             // We use a `302 Found` status code to indicate a redirect
             // We 'should' generate a `Location` field for the redirect, but it's not
             // RFC-mandated, so we can use the app shell
-            HttpResponse::Found().content_type("text/html").body(
+            let mut http_res = HttpResponse::Found();
+            add_csp_header(&mut http_res, &csp_header);
+            http_res.content_type("text/html").body(
                 html_shell
-                    .get_ref()
                     .clone()
                     .locale_redirection_fallback(
                         // We'll redirect the user to the default locale
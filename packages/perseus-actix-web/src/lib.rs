@@ -15,6 +15,7 @@ mod dflt_server;
 pub mod errors;
 mod initial_load;
 mod page_data;
+mod state_push;
 mod translations;
 
 pub use crate::configurer::configurer;
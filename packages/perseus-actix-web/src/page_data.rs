@@ -5,7 +5,7 @@ use perseus::{
     errors::err_to_status_code,
     i18n::TranslationsManager,
     internal::PageDataPartial,
-    server::{get_page_for_template, GetPageProps, ServerOptions},
+    server::{get_page_for_template, restore_page_uri, GetPageProps, ServerOptions},
     stores::{ImmutableStore, MutableStore},
 };
 use serde::Deserialize;
@@ -40,7 +40,7 @@ pub async fn page_data<M: MutableStore, T: TranslationsManager>(
         // We need to turn the Actix Web request into one acceptable for Perseus (uses
         // `http` internally)
         let http_req = convert_req(&req);
-        let http_req = match http_req {
+        let mut http_req = match http_req {
             Ok(http_req) => http_req,
             // If this fails, the client request is malformed, so it's a 400
             Err(err) => {
@@ -57,6 +57,9 @@ pub async fn page_data<M: MutableStore, T: TranslationsManager>(
                 return HttpResponse::InternalServerError().body("template not found".to_string());
             }
         };
+        // Make sure request state functions see the page's own URI (with its query
+        // parameters)
+        restore_page_uri(&mut http_req, locale, path);
         let page_data = get_page_for_template(
             GetPageProps {
                 raw_path: path,
@@ -77,6 +80,7 @@ pub async fn page_data<M: MutableStore, T: TranslationsManager>(
                 let partial_page_data = PageDataPartial {
                     state: page_data.state,
                     head: page_data.head,
+                    layout_states: page_data.layout_states,
                 };
                 let mut http_res = HttpResponse::Ok();
                 http_res.content_type("text/html");
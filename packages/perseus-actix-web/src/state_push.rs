@@ -0,0 +1,49 @@
+use actix_web::{http::header, rt::time, web, HttpResponse};
+use futures::{stream, StreamExt};
+use perseus::server::ServerOptions;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// How often to send a comment to the browser to keep the connection alive,
+/// which is the same as the defaults for the other integrations.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+pub struct StatePushReq {
+    pub url: String,
+}
+
+/// The handler for calls to `.perseus/state-push`, which streams any state
+/// published on the server for the page with the given URL to the browser as
+/// server-sent events.
+pub async fn state_push(
+    web::Query(StatePushReq { url }): web::Query<StatePushReq>,
+    opts: web::Data<ServerOptions>,
+) -> HttpResponse {
+    let publisher = match &opts.state_publisher {
+        Some(publisher) => publisher,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    let msgs = publisher
+        .subscribe(&url)
+        .map(|msg| format!("data: {}\n\n", msg));
+    // Proxies will often close connections that have been idle for too long, so we
+    // periodically send a comment, which browsers will ignore
+    let keep_alive = stream::unfold(
+        time::interval_at(
+            time::Instant::now() + KEEP_ALIVE_INTERVAL,
+            KEEP_ALIVE_INTERVAL,
+        ),
+        |mut interval| async move {
+            interval.tick().await;
+            Some((":\n\n".to_string(), interval))
+        },
+    );
+    let stream = stream::select(msgs, keep_alive)
+        .map(|msg| Ok::<_, actix_web::Error>(web::Bytes::from(msg)));
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(stream)
+}
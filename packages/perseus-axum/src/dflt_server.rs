@@ -1,12 +1,18 @@
 use crate::get_router;
 use perseus::{
-    i18n::TranslationsManager, server::ServerProps, stores::MutableStore, PerseusAppBase, SsrNode,
+    i18n::TranslationsManager,
+    server::{ServerProps, TlsConfig},
+    stores::MutableStore,
 };
 use std::net::SocketAddr;
 
 /// Creates and starts the default Perseus server with Axum. This should be run
 /// in a `main` function annotated with `#[tokio::main]` (which requires the
 /// `macros` and `rt-multi-thread` features on the `tokio` dependency).
+///
+/// If the `PERSEUS_TLS_CERT` and `PERSEUS_TLS_KEY` environment variables are
+/// set, this will serve over HTTPS, which requires the `tls` feature (see
+/// [`TlsConfig`] for details).
 pub async fn dflt_server<M: MutableStore + 'static, T: TranslationsManager + 'static>(
     props: ServerProps<M, T>,
     (host, port): (String, u16),
@@ -15,8 +21,59 @@ pub async fn dflt_server<M: MutableStore + 'static, T: TranslationsManager + 'st
         .parse()
         .expect("Invalid address provided to bind to.");
     let app = get_router(props).await;
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    match TlsConfig::from_env() {
+        #[cfg(feature = "tls")]
+        Some(tls) => {
+            let config =
+                axum_server::tls_rustls::RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+                    .await
+                    .expect("Couldn't load TLS certificate and key.");
+            let server = axum_server::bind_rustls(addr, config).serve(app.into_make_service());
+            match tls.redirect_port {
+                Some(redirect_port) => {
+                    let redirect_addr = SocketAddr::new(addr.ip(), redirect_port);
+                    let redirect_server = axum::Server::bind(&redirect_addr)
+                        .serve(redirect_router(host, port).into_make_service());
+                    let (res, redirect_res) = futures::future::join(server, redirect_server).await;
+                    res.unwrap();
+                    redirect_res.unwrap();
+                }
+                None => server.await.unwrap(),
+            }
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(_) => {
+            panic!("TLS has been configured, but the `tls` feature of `perseus-axum` isn't enabled")
+        }
+        None => axum::Server::bind(&addr)
+            .serve(app.into_make_service())
+            .await
+            .unwrap(),
+    }
+}
+
+/// Creates a router that redirects every request to the HTTPS server on the
+/// given host and port.
+#[cfg(feature = "tls")]
+fn redirect_router(host: String, port: u16) -> axum::Router {
+    use axum::{
+        http::{header, HeaderMap, Uri},
+        response::Redirect,
+        routing::get,
+    };
+
+    axum::Router::new().fallback(get(move |headers: HeaderMap, uri: Uri| async move {
+        let host_header = headers
+            .get(header::HOST)
+            .and_then(|host_header| host_header.to_str().ok());
+        let path_and_query = uri
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/");
+        Redirect::permanent(&perseus::server::get_https_redirect_url(
+            host_header,
+            path_and_query,
+            (&host, port),
+        ))
+    }))
 }
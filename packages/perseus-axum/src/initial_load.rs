@@ -1,6 +1,6 @@
 use axum::{
     body::Body,
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::Html,
 };
 use fmterr::fmt_err;
@@ -9,8 +9,8 @@ use perseus::{
     i18n::{TranslationsManager, Translator},
     router::{match_route_atomic, RouteInfoAtomic, RouteVerdictAtomic},
     server::{
-        build_error_page, get_page_for_template, get_path_slice, GetPageProps, HtmlShell,
-        ServerOptions,
+        build_error_page, generate_nonce, get_page_for_template, get_path_slice, GetPageProps,
+        HtmlShell, ServerOptions,
     },
     stores::{ImmutableStore, MutableStore},
     utils::get_path_prefix_server,
@@ -28,15 +28,46 @@ fn return_error_page(
     translator: Option<Rc<Translator>>,
     error_pages: &ErrorPages<SsrNode>,
     html_shell: &HtmlShell,
+    csp_header: &Option<String>,
 ) -> (StatusCode, HeaderMap, Html<String>) {
     let html = build_error_page(url, status, err, translator, error_pages, html_shell);
-    (
-        StatusCode::from_u16(status).unwrap(),
-        HeaderMap::new(),
-        Html(html),
+    with_csp_header(
+        (
+            StatusCode::from_u16(status).unwrap(),
+            HeaderMap::new(),
+            Html(html),
+        ),
+        csp_header,
     )
 }
 
+/// Adds the `Content-Security-Policy` header to the given response, if the app
+/// has one. The policy was validated when the server started, and nonces are
+/// always alphanumeric, so the header should always be valid, but, if it
+/// isn't, this will return a `500 Internal Server Error` instead (as the other
+/// integrations do), rather than serving the page without its policy.
+fn with_csp_header(
+    (status, mut header_map, html): (StatusCode, HeaderMap, Html<String>),
+    csp_header: &Option<String>,
+) -> (StatusCode, HeaderMap, Html<String>) {
+    if let Some(csp_header) = csp_header {
+        match HeaderValue::from_str(csp_header) {
+            Ok(csp_header) => {
+                header_map.insert(header::CONTENT_SECURITY_POLICY, csp_header);
+            }
+            Err(err) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    HeaderMap::new(),
+                    Html(fmt_err(&err)),
+                )
+            }
+        }
+    }
+
+    (status, header_map, html)
+}
+
 /// The handler for calls to any actual pages (first-time visits), which will
 /// render the appropriate HTML and then interpolate it into the app shell.
 #[allow(clippy::too_many_arguments)] // As for `page_data_handler`, we don't have a choice
@@ -56,10 +87,30 @@ pub async fn initial_load_handler<M: MutableStore, T: TranslationsManager>(
     let templates = &opts.templates_map;
     let error_pages = &opts.error_pages;
     let path_slice = get_path_slice(&path);
+    // If the app has a CSP, every initial load gets a fresh nonce for its inline
+    // scripts
+    let (html_shell, csp_header) = match &opts.csp {
+        Some(csp) => {
+            let nonce = generate_nonce();
+            (
+                html_shell.as_ref().clone().with_nonce(&nonce),
+                Some(csp.get_header(&nonce)),
+            )
+        }
+        None => (html_shell.as_ref().clone(), None),
+    };
     // Create a closure to make returning error pages easier (most have the same
     // data)
     let html_err = |status: u16, err: &str| {
-        return return_error_page(&path, status, err, None, error_pages, html_shell.as_ref());
+        return return_error_page(
+            &path,
+            status,
+            err,
+            None,
+            error_pages,
+            &html_shell,
+            &csp_header,
+        );
     };
 
     // Run the routing algorithms on the path to figure out which template we need
@@ -110,8 +161,8 @@ pub async fn initial_load_handler<M: MutableStore, T: TranslationsManager>(
             };
 
             let final_html = html_shell
-                .as_ref()
                 .clone()
+                .for_template(template)
                 .page_data(&page_data, &global_state, &translations)
                 .to_string();
 
@@ -122,7 +173,7 @@ pub async fn initial_load_handler<M: MutableStore, T: TranslationsManager>(
                 header_map.insert(key.unwrap(), val);
             }
 
-            (StatusCode::OK, header_map, Html(final_html))
+            with_csp_header((StatusCode::OK, header_map, Html(final_html)), &csp_header)
         }
         // For locale detection, we don't know the user's locale, so there's not much we can do
         // except send down the app shell, which will do the rest and fetch from `.perseus/page/...`
@@ -130,24 +181,26 @@ pub async fn initial_load_handler<M: MutableStore, T: TranslationsManager>(
             // We use a `302 Found` status code to indicate a redirect
             // We 'should' generate a `Location` field for the redirect, but it's not
             // RFC-mandated, so we can use the app shell
-            (
-                StatusCode::FOUND,
-                HeaderMap::new(),
-                Html(
-                    html_shell
-                        .as_ref()
-                        .clone()
-                        .locale_redirection_fallback(
-                            // We'll redirect the user to the default locale
-                            &format!(
-                                "{}/{}/{}",
-                                get_path_prefix_server(),
-                                opts.locales.default,
-                                path
-                            ),
-                        )
-                        .to_string(),
+            with_csp_header(
+                (
+                    StatusCode::FOUND,
+                    HeaderMap::new(),
+                    Html(
+                        html_shell
+                            .clone()
+                            .locale_redirection_fallback(
+                                // We'll redirect the user to the default locale
+                                &format!(
+                                    "{}/{}/{}",
+                                    get_path_prefix_server(),
+                                    opts.locales.default,
+                                    path
+                                ),
+                            )
+                            .to_string(),
+                    ),
                 ),
+                &csp_header,
             )
         }
         RouteVerdictAtomic::NotFound => html_err(404, "page not found"),
@@ -15,6 +15,7 @@ mod dflt_server;
 mod initial_load;
 mod page_data;
 mod router;
+mod state_push;
 mod translations;
 
 pub use crate::router::get_router;
@@ -8,7 +8,7 @@ use perseus::{
     errors::err_to_status_code,
     i18n::TranslationsManager,
     internal::PageDataPartial,
-    server::{get_page_for_template, GetPageProps, ServerOptions},
+    server::{get_page_for_template, restore_page_uri, GetPageProps, ServerOptions},
     stores::{ImmutableStore, MutableStore},
     Request,
 };
@@ -73,7 +73,10 @@ pub async fn page_handler<M: MutableStore, T: TranslationsManager>(
         };
         // Convert the request into one palatable for Perseus (which doesn't have the
         // body attached)
-        let http_req = Request::from_parts(http_req.into_parts().0, ());
+        let mut http_req = Request::from_parts(http_req.into_parts().0, ());
+        // Make sure request state functions see the page's own URI (with its query
+        // parameters)
+        restore_page_uri(&mut http_req, locale, path);
         let page_data = get_page_for_template(
             GetPageProps::<M, T> {
                 raw_path: path,
@@ -94,6 +97,7 @@ pub async fn page_handler<M: MutableStore, T: TranslationsManager>(
                 let partial_page_data = PageDataPartial {
                     state: page_data.state,
                     head: page_data.head,
+                    layout_states: page_data.layout_states,
                 };
                 // http_res.content_type("text/html");
                 // Generate and add HTTP headers
@@ -1,17 +1,21 @@
 use crate::initial_load::initial_load_handler;
 use crate::page_data::page_handler;
+use crate::state_push::state_push_handler;
 use crate::translations::translations_handler;
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::IntoResponse,
     routing::{get, get_service},
     Router,
 };
 use closure::closure;
-use perseus::server::{get_render_cfg, ServerProps};
+use perseus::server::{get_render_cfg, ServerProps, IMMUTABLE_CACHE_CONTROL};
 use perseus::{i18n::TranslationsManager, stores::MutableStore};
 use std::sync::Arc;
-use tower_http::services::{ServeDir, ServeFile};
+use tower_http::{
+    services::{ServeDir, ServeFile},
+    set_header::SetResponseHeaderLayer,
+};
 
 /// Gets the `Router` needed to configure an existing Axum app for Perseus, and
 /// should be provided after any other routes, as they include a wildcard route.
@@ -44,7 +48,7 @@ pub async fn get_router<M: MutableStore + 'static, T: TranslationsManager + 'sta
     let static_dir = opts.static_dir.clone();
     let static_aliases = opts.static_aliases.clone();
 
-    let router = Router::new()
+    let mut router = Router::new()
         .route(
             "/.perseus/bundle.js",
             get_service(ServeFile::new(opts.js_bundle.clone())).handle_error(handle_fs_error),
@@ -61,6 +65,34 @@ pub async fn get_router<M: MutableStore + 'static, T: TranslationsManager + 'sta
             "/.perseus/snippets/*path",
             get_service(ServeDir::new(opts.snippets.clone())).handle_error(handle_fs_error),
         );
+    // If we know the hashes of the bundles, we can also serve them from URLs that
+    // change whenever they do, which means they can be cached forever
+    if let Some(bundles) = &opts.bundles {
+        let bundles_router = Router::new()
+            .route(
+                "/bundle.js",
+                get_service(ServeFile::new(opts.js_bundle.clone())).handle_error(handle_fs_error),
+            )
+            .route(
+                "/bundle.wasm",
+                get_service(ServeFile::new(opts.wasm_bundle.clone())).handle_error(handle_fs_error),
+            )
+            .route(
+                "/bundle.wasm.js",
+                get_service(ServeFile::new(opts.wasm_js_bundle.clone()))
+                    .handle_error(handle_fs_error),
+            )
+            .nest(
+                "/snippets",
+                get_service(ServeDir::new(opts.snippets.clone())).handle_error(handle_fs_error),
+            )
+            .layer(SetResponseHeaderLayer::overriding(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+            ));
+        // The path prefix is handled by whatever's in front of us
+        router = router.nest(&bundles.get_url_root(""), bundles_router);
+    }
     let opts = Arc::new(opts);
     let mut router = router
         .route(
@@ -87,6 +119,13 @@ pub async fn get_router<M: MutableStore + 'static, T: TranslationsManager + 'sta
                     )
             )
         ));
+    // Only serve state pushes if the app can publish them
+    if let Some(publisher) = opts.state_publisher.clone() {
+        router = router.route(
+            "/.perseus/state-push",
+            get(closure!(clone publisher, |query| state_push_handler(query, publisher))),
+        );
+    }
     // Only add the static content directory route if such a directory is being used
     if let Some(static_dir) = static_dir {
         router = router.nest(
@@ -0,0 +1,27 @@
+use axum::{
+    extract::Query,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{Stream, StreamExt};
+use perseus::state::StatePublisher;
+use serde::Deserialize;
+use std::convert::Infallible;
+
+#[derive(Deserialize)]
+pub struct StatePushReq {
+    pub url: String,
+}
+
+/// The handler for `/.perseus/state-push`, which streams any state published
+/// on the server for the page with the given URL to the browser as server-sent
+/// events.
+pub async fn state_push_handler(
+    Query(StatePushReq { url }): Query<StatePushReq>,
+    publisher: StatePublisher,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = publisher
+        .subscribe(&url)
+        .map(|msg| Ok(Event::default().data(msg)));
+    // Proxies will often close connections that have been idle for too long
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
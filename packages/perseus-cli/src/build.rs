@@ -1,4 +1,4 @@
-use crate::cmd::{cfg_spinner, run_stage};
+use crate::cmd::{cfg_spinner, fail_spinner, run_cmd, run_stage};
 use crate::install::Tools;
 use crate::parse::{BuildOpts, Opts};
 use crate::thread::{spawn_thread, ThreadHandle};
@@ -6,6 +6,7 @@ use crate::{errors::*, get_user_crate_name};
 use console::{style, Emoji};
 use indicatif::{MultiProgress, ProgressBar};
 use std::path::PathBuf;
+use std::sync::mpsc;
 
 // Emoji for stages
 static GENERATING: Emoji<'_, '_> = Emoji("🔨", "");
@@ -76,8 +77,31 @@ pub fn build_internal(
     let wb_spinner = cfg_spinner(wb_spinner, &wb_msg);
     let wb_dir = dir;
     let cargo_engine_exec = tools.cargo_engine.clone();
+    // The engine hashes the Wasm bundle after generating the app, so it can't run
+    // until that's been built (but it can still be compiled in parallel)
+    let (wb_done_tx, wb_done_rx) = mpsc::channel::<()>();
     let sg_thread = spawn_thread(
         move || {
+            let (_, _, exit_code) = run_cmd(
+                format!(
+                    "{} build {} {}",
+                    cargo_engine_exec,
+                    if is_release { "--release" } else { "" },
+                    cargo_engine_args
+                ),
+                &sg_dir,
+                vec![("CARGO_TARGET_DIR", "dist/target_engine")],
+                || fail_spinner(&sg_spinner, &sg_msg),
+            )?;
+            if exit_code != 0 {
+                return Ok(exit_code);
+            }
+            // If the Wasm build failed, the sender will have been dropped (and the error
+            // will be reported from that thread)
+            if wb_done_rx.recv().is_err() {
+                fail_spinner(&sg_spinner, &sg_msg);
+                return Ok(1);
+            }
             handle_exit_code!(run_stage(
                 vec![&format!(
                     "{} run {} {}",
@@ -140,6 +164,9 @@ pub fn build_internal(
                     vec![("CARGO_TARGET_DIR", "dist/target_wasm")]
                 }
             )?);
+            // Let the engine know it can start generating the app (if it's already
+            // finished because of an error, it won't be listening anymore, which is fine)
+            let _ = wb_done_tx.send(());
 
             Ok(0)
         },
@@ -161,18 +188,20 @@ pub fn build(
 
     let (sg_thread, wb_thread) =
         build_internal(dir, &spinners, 2, opts.release, tools, global_opts)?;
-    let sg_res = sg_thread
-        .join()
-        .map_err(|_| ExecutionError::ThreadWaitFailed)??;
-    if sg_res != 0 {
-        return Ok(sg_res);
-    }
+    // We join the Wasm build first, since static generation waits on it (this
+    // matters if the 'threads' are actually sequential)
     let wb_res = wb_thread
         .join()
         .map_err(|_| ExecutionError::ThreadWaitFailed)??;
     if wb_res != 0 {
         return Ok(wb_res);
     }
+    let sg_res = sg_thread
+        .join()
+        .map_err(|_| ExecutionError::ThreadWaitFailed)??;
+    if sg_res != 0 {
+        return Ok(sg_res);
+    }
 
     // We've handled errors in the component threads, so the exit code is now zero
     Ok(0)
@@ -150,6 +150,29 @@ fn deploy_full(
             }
             .into());
         }
+        // The bundles were hashed when they were built, and the server needs those
+        // hashes to serve them
+        let from = dir.join("dist/bundle_manifest.json");
+        if let Err(err) = fs::copy(&from, &output_path.join("dist/bundle_manifest.json")) {
+            return Err(DeployError::MoveAssetFailed {
+                to: output,
+                from: from.to_str().map(|s| s.to_string()).unwrap(),
+                source: err,
+            }
+            .into());
+        }
+        // Copy in the service worker if the app has one
+        let from = dir.join("dist/service-worker.js");
+        if from.exists() {
+            if let Err(err) = fs::copy(&from, &output_path.join("dist/service-worker.js")) {
+                return Err(DeployError::MoveAssetFailed {
+                    to: output,
+                    from: from.to_str().map(|s| s.to_string()).unwrap(),
+                    source: err,
+                }
+                .into());
+            }
+        }
 
         println!();
         println!("Deployment complete 🚀! Your app is now available for serving in the standalone folder '{}'! You can run it by executing the `server` binary in that folder.", &output_path.to_str().map(|s| s.to_string()).unwrap());
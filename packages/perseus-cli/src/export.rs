@@ -1,4 +1,4 @@
-use crate::cmd::{cfg_spinner, run_stage};
+use crate::cmd::{cfg_spinner, fail_spinner, run_cmd, run_stage};
 use crate::install::Tools;
 use crate::parse::{ExportOpts, Opts};
 use crate::thread::{spawn_thread, ThreadHandle};
@@ -7,6 +7,7 @@ use console::{style, Emoji};
 use indicatif::{MultiProgress, ProgressBar};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 
 // Emojis for stages
 static EXPORTING: Emoji<'_, '_> = Emoji("📦", "");
@@ -37,25 +38,49 @@ macro_rules! copy_file {
     };
 }
 
+/// Gets the directory (relative to the project root) that the bundles should
+/// be placed in for the exported app. If the engine hashed them, this will be
+/// the content-hashed directory that the exported HTML references, otherwise
+/// it'll just be `.perseus/`.
+fn get_bundle_dir(target: &Path) -> String {
+    let manifest = fs::read_to_string(target.join("dist/bundle_manifest.json"))
+        .ok()
+        .and_then(|manifest| serde_json::from_str::<serde_json::Value>(&manifest).ok());
+    match manifest
+        .as_ref()
+        .and_then(|manifest| manifest.get("id"))
+        .and_then(|id| id.as_str())
+    {
+        Some(id) => format!("dist/exported/.perseus/bundles/{}", id),
+        None => "dist/exported/.perseus".to_string(),
+    }
+}
+
 /// Finalizes the export by copying assets. This is very different from the
 /// finalization process of normal building.
 pub fn finalize_export(target: &Path) -> Result<(), ExportError> {
+    // The pages have already been exported, so the engine will have told us where
+    // the bundles need to go
+    let bundle_dir = get_bundle_dir(target);
+    if let Err(err) = fs::create_dir_all(target.join(&bundle_dir)) {
+        return Err(ExportError::DirStructureCreationFailed { source: err });
+    }
     // Copy files over (the directory structure should already exist from exporting
     // the pages)
     copy_file!(
         "dist/pkg/perseus_engine.js",
-        "dist/exported/.perseus/bundle.js",
+        format!("{}/bundle.js", bundle_dir),
         target
     );
     copy_file!(
         "dist/pkg/perseus_engine_bg.wasm",
-        "dist/exported/.perseus/bundle.wasm",
+        format!("{}/bundle.wasm", bundle_dir),
         target
     );
     // Copy any JS snippets over (if the directory doesn't exist though, don't do
     // anything) This takes a target of the `dist/` directory, and then extends
     // on that
-    fn copy_snippets(ext: &str, parent: &Path) -> Result<(), ExportError> {
+    fn copy_snippets(ext: &str, parent: &Path, bundle_dir: &str) -> Result<(), ExportError> {
         // We read from the parent directory (`.perseus`), extended with `ext`
         if let Ok(snippets) = fs::read_dir(&parent.join(ext)) {
             for file in snippets {
@@ -76,6 +101,7 @@ pub fn finalize_export(target: &Path) -> Result<(), ExportError> {
                     copy_snippets(
                         &format!("{}/{}", ext, path.file_name().unwrap().to_str().unwrap()),
                         parent,
+                        bundle_dir,
                     )?;
                 } else {
                     // `ext` holds the folder structure of this file, which we'll preserve
@@ -92,11 +118,9 @@ pub fn finalize_export(target: &Path) -> Result<(), ExportError> {
                     };
                     let filename = path.file_name().unwrap().to_str().unwrap();
                     let final_dir_tree =
-                        parent.join(format!("dist/exported/.perseus/snippets{}", dir_tree));
-                    let path_to_copy_to = parent.join(&format!(
-                        "dist/exported/.perseus/snippets{}/{}",
-                        dir_tree, filename
-                    ));
+                        parent.join(format!("{}/snippets{}", bundle_dir, dir_tree));
+                    let path_to_copy_to =
+                        parent.join(&format!("{}/snippets{}/{}", bundle_dir, dir_tree, filename));
                     // Create the directory structure needed for this
                     if let Err(err) = fs::create_dir_all(&final_dir_tree) {
                         return Err(ExportError::DirStructureCreationFailed { source: err });
@@ -112,7 +136,7 @@ pub fn finalize_export(target: &Path) -> Result<(), ExportError> {
 
         Ok(())
     }
-    copy_snippets("dist/pkg/snippets", target)?;
+    copy_snippets("dist/pkg/snippets", target, &bundle_dir)?;
 
     Ok(())
 }
@@ -171,8 +195,31 @@ pub fn export_internal(
     let wb_spinner = cfg_spinner(wb_spinner, &wb_msg);
     let wb_target = dir;
     let cargo_engine_exec = tools.cargo_engine.clone();
+    // The engine hashes the Wasm bundle while exporting, so it can't run until that's
+    // been built (but it can still be compiled in parallel)
+    let (wb_done_tx, wb_done_rx) = mpsc::channel::<()>();
     let ep_thread = spawn_thread(
         move || {
+            let (_, _, exit_code) = run_cmd(
+                format!(
+                    "{} build {} {}",
+                    cargo_engine_exec,
+                    if is_release { "--release" } else { "" },
+                    cargo_engine_args
+                ),
+                &ep_target,
+                vec![("CARGO_TARGET_DIR", "dist/target_engine")],
+                || fail_spinner(&ep_spinner, &ep_msg),
+            )?;
+            if exit_code != 0 {
+                return Ok(exit_code);
+            }
+            // If the Wasm build failed, the sender will have been dropped (and the error
+            // will be reported from that thread)
+            if wb_done_rx.recv().is_err() {
+                fail_spinner(&ep_spinner, &ep_msg);
+                return Ok(1);
+            }
             handle_exit_code!(run_stage(
                 vec![&format!(
                     "{} run {} {}",
@@ -235,6 +282,9 @@ pub fn export_internal(
                     vec![("CARGO_TARGET_DIR", "dist/target_wasm")]
                 }
             )?);
+            // Let the engine know it can start exporting (if it's already finished
+            // because of an error, it won't be listening anymore, which is fine)
+            let _ = wb_done_tx.send(());
 
             Ok(0)
         },
@@ -264,18 +314,20 @@ pub fn export(
         tools,
         global_opts,
     )?;
-    let ep_res = ep_thread
-        .join()
-        .map_err(|_| ExecutionError::ThreadWaitFailed)??;
-    if ep_res != 0 {
-        return Ok(ep_res);
-    }
+    // We join the Wasm build first, since the export stage waits on it (this matters
+    // if the 'threads' are actually sequential)
     let wb_res = wb_thread
         .join()
         .map_err(|_| ExecutionError::ThreadWaitFailed)??;
     if wb_res != 0 {
         return Ok(wb_res);
     }
+    let ep_res = ep_thread
+        .join()
+        .map_err(|_| ExecutionError::ThreadWaitFailed)??;
+    if ep_res != 0 {
+        return Ok(ep_res);
+    }
 
     // And now we can run the finalization stage
     finalize_export(&dir)?;
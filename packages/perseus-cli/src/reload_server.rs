@@ -10,7 +10,8 @@ use warp::ws::Message;
 use warp::Filter;
 
 /// A representation of the clients to the server. These are only the clients
-/// that will be told about reloads, any user can command a reload over the HTTP
+/// that will be told about reloads (and that can send us reports of problems
+/// to print), any user can command a reload over the HTTP
 /// endpoint (unauthenticated because this is a development server).
 type Clients = Arc<RwLock<HashMap<usize, UnboundedSender<Message>>>>;
 
@@ -71,11 +72,15 @@ pub async fn run_reload_server(host: String, port: u16) {
                 // Save the sender and their intermediary channel
                 clients.write().await.insert(id, tx);
 
-                // Because we don't accept messages from listening clients, we'll just hold a
-                // loop until the client disconnects Then, this will become
-                // `None` and we'll move on
-                while ws_rx.next().await.is_some() {
-                    continue;
+                // The only messages clients send us are reports of problems they've found
+                // (like hydration mismatches), which we'll print for the developer, and we'll
+                // keep listening for them until the client disconnects (then, this will
+                // become `None` and we'll move on)
+                while let Some(message) = ws_rx.next().await {
+                    let message = message.ok();
+                    if let Some(text) = message.as_ref().and_then(|msg| msg.to_str().ok()) {
+                        eprintln!("{}", text);
+                    }
                 }
 
                 // Once we're here, the client has disconnected
@@ -236,16 +236,18 @@ pub fn serve(
     if did_build {
         let (sg_thread, wb_thread) =
             build_internal(dir.clone(), &spinners, 4, opts.release, tools, global_opts)?;
-        let sg_res = sg_thread
+        // We join the Wasm build first, since static generation waits on it (this
+        // matters if the 'threads' are actually sequential)
+        let wb_res = wb_thread
             .join()
             .map_err(|_| ExecutionError::ThreadWaitFailed)??;
-        let wb_res = wb_thread
+        let sg_res = sg_thread
             .join()
             .map_err(|_| ExecutionError::ThreadWaitFailed)??;
-        if sg_res != 0 {
-            return Ok((sg_res, None));
-        } else if wb_res != 0 {
+        if wb_res != 0 {
             return Ok((wb_res, None));
+        } else if sg_res != 0 {
+            return Ok((sg_res, None));
         }
     }
     // Handle errors from the server building
@@ -23,7 +23,7 @@ use darling::FromMeta;
 use proc_macro::TokenStream;
 use quote::quote;
 use state_fns::StateFnType;
-use syn::{ItemStruct, Path};
+use syn::{Item, Path};
 
 /// Annotates functions used for generating state at build time to support
 /// automatic serialization/deserialization of app state and client/server
@@ -260,10 +260,49 @@ pub fn engine_main(_args: TokenStream, input: TokenStream) -> TokenStream {
 /// reactivity on, and `FieldTypeRx` is the wrapper type that will be expected.
 /// This should be created by using this macro on the original `struct` type.
 ///
+/// Alternatively, you can add `#[rx(nested)]` to the field itself, which will
+/// use whatever reactive version its type declares in its `MakeRx`
+/// implementation, and give you a reference to that (without calling
+/// `.to_ref_struct()`). This is how the fine-grained reactive collections
+/// `perseus::state::RxVec` and `perseus::state::RxHashMap` are used, which give
+/// each of their elements their own signals, so that updating one element
+/// doesn't update anything that depends on the others.
+///
+/// This macro can also be used on `enum`s, in which case the active variant
+/// will be held in a signal, so it can be changed reactively, and each of the
+/// fields of each variant will be made reactive as well (`#[rx(nested)]` can
+/// be used on these fields too). The variants will be in another `enum` with
+/// the same name as the reactive type, but with `Variant` on the end (e.g.
+/// `MyStateRxVariant`), which is what you'll match on, and the reactive type
+/// itself will be a reference to something that dereferences to the signal
+/// holding that.
+///
+/// If your app freezes state, and you change the fields of a `struct` in a way
+/// that would stop old frozen state from being deserialized into it, you can
+/// set a new schema version with `#[rx::version(n)]` (versions start at `0`),
+/// and provide a function to upgrade old frozen state with
+/// `#[rx::migrate(my_fn)]`. That function will be given the version the state
+/// was frozen with and the state as a `serde_json::Value`, and it should
+/// return the state in the current format as another `serde_json::Value`, or
+/// an error message as a `String`, in which case the frozen state will be
+/// discarded.
+///
+/// By default, every field will be included when state is frozen, but you can
+/// add `#[rx(skip_freeze)]` to a field to leave it out (e.g. for secrets or
+/// transient UI state), in which case it will be frozen as its default value,
+/// and thawed as that. Alternatively, `#[rx(freeze_with = "path::to::fn",
+/// thaw_with = "path::to::other_fn")]` will pass a reference to the field's
+/// value through the first function, and freeze whatever that returns instead
+/// (e.g. to trim a large cache), and then pass the thawed value through the
+/// second function (which takes and returns the field's type by value). Neither
+/// of these affect how state is sent from the server, and, when frozen state is
+/// merged into live state (e.g. from another tab), fields that weren't frozen
+/// will be left alone.
+///
 /// Note that this will be deprecated or significantly altered by Sycamore's new
 /// observables system (when it's released). For that reason, this doesn't
-/// support more advanced features like leaving some fields unreactive, this is
-/// an all-or-nothing solution for now.
+/// support more advanced features like leaving some fields unreactive, and
+/// tuple `struct`s aren't supported.
 ///
 /// # Examples
 ///
@@ -321,10 +360,16 @@ pub fn engine_main(_args: TokenStream, input: TokenStream) -> TokenStream {
 /// ```
 #[proc_macro_attribute]
 pub fn make_rx(args: TokenStream, input: TokenStream) -> TokenStream {
-    let parsed = syn::parse_macro_input!(input as ItemStruct);
+    let parsed = syn::parse_macro_input!(input as Item);
     let name = syn::parse_macro_input!(args as syn::Ident);
 
-    rx_state::make_rx_impl(parsed, name).into()
+    match parsed {
+        Item::Struct(parsed) => rx_state::make_rx_impl(parsed, name).into(),
+        Item::Enum(parsed) => rx_state::make_rx_enum_impl(parsed, name).into(),
+        item => syn::Error::new_spanned(item, "only structs and enums can be made reactive")
+            .to_compile_error()
+            .into(),
+    }
 }
 
 /// Marks the annotated code as only to be run as part of the engine (the
@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use proc_macro2::{Span, TokenStream};
 use quote::quote;
-use syn::{GenericParam, Ident, ItemStruct, Lifetime, LifetimeDef, Lit, Meta, NestedMeta, Result};
+use syn::{
+    Attribute, Fields, GenericParam, Ident, ItemEnum, ItemStruct, Lifetime, LifetimeDef, Lit, Meta,
+    NestedMeta, Result, Variant,
+};
 
 pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream {
     // Note: we create three `struct`s with this macro: the original, the new one
@@ -86,19 +89,46 @@ pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream
             Err(err) => return err.to_compile_error(),
         };
     }
+    // Look for the version of the state's schema and the function that migrates
+    // frozen state from older versions (both of which are optional)
+    let (state_version, migrate_fn) = match parse_version_attrs(&mid_struct.attrs) {
+        Ok(res) => res,
+        Err(err) => return err.to_compile_error(),
+    };
+
     // Now remove our attributes from all the `struct`s
     let mut filtered_attrs = Vec::new();
     for attr in orig_struct.attrs.iter() {
-        if !(attr.path.segments.len() == 2
-            && attr.path.segments.first().unwrap().ident == "rx"
-            && attr.path.segments.last().unwrap().ident == "nested")
-        {
+        if !is_rx_type_attr(attr) {
             filtered_attrs.push(attr.clone());
         }
     }
     orig_struct.attrs = filtered_attrs.clone();
     mid_struct.attrs = filtered_attrs;
 
+    // Look through the fields for our own attributes that control how they're
+    // made reactive and frozen, removing them from the original `struct` as we go
+    // (they'll be removed from the others along with any Serde attributes)
+    let mut freeze_fields_map = HashMap::new();
+    // Fields here use nested reactivity through the `MakeRx` implementation of
+    // their own type (which is how we support generic types like `RxVec<T>`)
+    let mut rx_nested_fields = HashSet::new();
+    if let syn::Fields::Named(ref mut fields) = orig_struct.fields {
+        for field in fields.named.iter_mut() {
+            let field_attrs = match take_field_attrs(&mut field.attrs) {
+                Ok(field_attrs) => field_attrs,
+                Err(err) => return err.to_compile_error(),
+            };
+            let field_name = field.ident.clone().unwrap();
+            if field_attrs.nested {
+                rx_nested_fields.insert(field_name.clone());
+            }
+            if let Some(freeze) = field_attrs.freeze {
+                freeze_fields_map.insert(field_name, freeze);
+            }
+        }
+    }
+
     // Now define the final `struct` that uses references
     let mut ref_struct = mid_struct.clone();
     ref_struct.ident = ref_name.clone();
@@ -122,15 +152,16 @@ pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream
                         Span::call_site(),
                     );
                     syn::Type::Verbatim(quote!(#mid_wrapper_ty))
+                } else if rx_nested_fields.contains(field.ident.as_ref().unwrap()) {
+                    syn::Type::Verbatim(quote!(<#orig_ty as ::perseus::state::MakeRx>::Rx))
                 } else {
                     syn::Type::Verbatim(quote!(::sycamore::prelude::RcSignal<#orig_ty>))
                 };
-                // Remove any `serde` attributes (Serde can't be used with the reactive version)
+                // Remove any `serde` attributes (Serde can't be used with the reactive version),
+                // and any of our own
                 let mut new_attrs = Vec::new();
                 for attr in field.attrs.iter() {
-                    if !(attr.path.segments.len() == 1
-                        && attr.path.segments.first().unwrap().ident == "serde")
-                    {
+                    if !(attr.path.is_ident("serde") || attr.path.is_ident("rx")) {
                         new_attrs.push(attr.clone());
                     }
                 }
@@ -157,17 +188,20 @@ pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream
                     // nice for ergonomics) TODO Check back on this, could bite
                     // back!
                     syn::Type::Verbatim(quote!(&'rx #wrapper_ty<'rx>))
+                } else if rx_nested_fields.contains(field.ident.as_ref().unwrap()) {
+                    // There's no general way to get to a type with references from the
+                    // intermediary type, so we just use a reference to that
+                    syn::Type::Verbatim(quote!(&'rx <#orig_ty as ::perseus::state::MakeRx>::Rx))
                 } else {
                     // This is the only difference from the intermediate `struct` (this lifetime is
                     // declared above)
                     syn::Type::Verbatim(quote!(&'rx ::sycamore::prelude::RcSignal<#orig_ty>))
                 };
-                // Remove any `serde` attributes (Serde can't be used with the reactive version)
+                // Remove any `serde` attributes (Serde can't be used with the reactive version),
+                // and any of our own
                 let mut new_attrs = Vec::new();
                 for attr in field.attrs.iter() {
-                    if !(attr.path.segments.len() == 1
-                        && attr.path.segments.first().unwrap().ident == "serde")
-                    {
+                    if !(attr.path.is_ident("serde") || attr.path.is_ident("rx")) {
                         new_attrs.push(attr.clone());
                     }
                 }
@@ -192,7 +226,9 @@ pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream
                 // We know it has an identifier because it's a named field
                 let field_name = field.ident.as_ref().unwrap();
                 // Check if this field was registered as one to use nested reactivity
-                if nested_fields_map.contains_key(field.ident.as_ref().unwrap()) {
+                if nested_fields_map.contains_key(field_name)
+                    || rx_nested_fields.contains(field_name)
+                {
                     field_assignments.extend(quote! {
                         #field_name: self.#field_name.make_rx(),
                     })
@@ -250,7 +286,9 @@ pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream
                 // We know it has an identifier because it's a named field
                 let field_name = field.ident.as_ref().unwrap();
                 // Check if this field was registered as one to use nested reactivity
-                if nested_fields_map.contains_key(field.ident.as_ref().unwrap()) {
+                if nested_fields_map.contains_key(field_name)
+                    || rx_nested_fields.contains(field_name)
+                {
                     field_assignments.extend(quote! {
                         #field_name: self.#field_name.clone().make_unrx(),
                     })
@@ -273,16 +311,168 @@ pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream
         _ => unreachable!(),
     };
 
+    // Create a list of fields for the unreactive version that will actually be
+    // frozen, which is the same as the above, except for fields that have been
+    // marked to be skipped or frozen differently
+    let make_freeze_fields = match orig_struct.fields {
+        syn::Fields::Named(ref mut fields) => {
+            let mut field_assignments = quote!();
+            for field in fields.named.iter_mut() {
+                // We know it has an identifier because it's a named field
+                let field_name = field.ident.as_ref().unwrap();
+                let is_nested = nested_fields_map.contains_key(field_name)
+                    || rx_nested_fields.contains(field_name);
+                // Nested state is frozen with its own `Freeze` implementation, so that any of
+                // its fields that shouldn't be frozen aren't
+                let frozen_nested = quote! {
+                    ::serde_json::from_str(&::perseus::state::Freeze::freeze(&self.#field_name)).unwrap()
+                };
+                let field_val = match freeze_fields_map.get(field_name) {
+                    // This will be restored as the default value when the state is thawed
+                    Some(FieldFreeze::Skip) => quote!(::std::default::Default::default()),
+                    Some(FieldFreeze::With { freeze_fn, .. }) if is_nested => {
+                        quote!(#freeze_fn(&#frozen_nested))
+                    }
+                    Some(FieldFreeze::With { freeze_fn, .. }) => {
+                        quote!(#freeze_fn(&*self.#field_name.get_untracked()))
+                    }
+                    None if is_nested => frozen_nested,
+                    None => quote!((*self.#field_name.get_untracked()).clone()),
+                };
+                field_assignments.extend(quote! {
+                    #field_name: #field_val,
+                });
+            }
+            quote! {
+                #orig_name {
+                    #field_assignments
+                }
+            }
+        }
+        syn::Fields::Unit => quote!(#orig_name),
+        // We filtered out the other types before
+        _ => unreachable!(),
+    };
+
+    // Create the body of the method that restores anything that was changed when the
+    // state was frozen, once it's been thawed
+    let thaw_body = match orig_struct.fields {
+        syn::Fields::Named(ref fields) => {
+            let mut field_assignments = quote!();
+            for field in fields.named.iter() {
+                // We know it has an identifier because it's a named field
+                let field_name = field.ident.as_ref().unwrap();
+                let is_nested = nested_fields_map.contains_key(field_name)
+                    || rx_nested_fields.contains(field_name);
+                let field_val = thaw_field(
+                    quote!(self.#field_name),
+                    freeze_fields_map.get(field_name),
+                    is_nested,
+                );
+                field_assignments.extend(quote! {
+                    #field_name: #field_val,
+                });
+            }
+            quote! {
+                #orig_name {
+                    #field_assignments
+                }
+            }
+        }
+        _ => quote!(self),
+    };
+
+    // Create a list of statements that subscribe to every signal in the `struct`
+    let track_fields = match orig_struct.fields {
+        syn::Fields::Named(ref fields) => {
+            let mut track_stmts = quote!();
+            for field in fields.named.iter() {
+                // We know it has an identifier because it's a named field
+                let field_name = field.ident.as_ref().unwrap();
+                if nested_fields_map.contains_key(field_name)
+                    || rx_nested_fields.contains(field_name)
+                {
+                    track_stmts.extend(quote! {
+                        ::perseus::state::Freeze::track(&self.#field_name);
+                    });
+                } else {
+                    track_stmts.extend(quote! {
+                        self.#field_name.track();
+                    });
+                }
+            }
+            track_stmts
+        }
+        _ => quote!(),
+    };
+    // Create the body of the method that merges a patch into the `struct` in place
+    let merge_body = match orig_struct.fields {
+        syn::Fields::Named(ref fields) => {
+            let mut merge_stmts = quote!();
+            for field in fields.named.iter() {
+                // We know it has an identifier because it's a named field
+                let field_name = field.ident.as_ref().unwrap();
+                let is_nested = nested_fields_map.contains_key(field_name)
+                    || rx_nested_fields.contains(field_name);
+                merge_stmts.extend(match freeze_fields_map.get(field_name) {
+                    Some(FieldFreeze::With { freeze_fn, thaw_fn }) => merge_frozen_field_stmts(
+                        quote!(&self.#field_name),
+                        quote!(__perseus_new_frozen.#field_name),
+                        is_nested,
+                        freeze_fn,
+                        thaw_fn,
+                        quote!(return false),
+                    ),
+                    _ => merge_field_stmts(
+                        quote!(&self.#field_name),
+                        quote!(__perseus_new.#field_name),
+                        is_nested,
+                        quote!(return false),
+                    ),
+                });
+            }
+            // Fields that are frozen differently have to be patched as they would be frozen
+            let new_frozen = if freeze_fields_map
+                .values()
+                .any(|freeze| matches!(freeze, FieldFreeze::With { .. }))
+            {
+                merge_frozen_backend(&orig_name, &generics)
+            } else {
+                quote!()
+            };
+            quote! {
+                use ::perseus::state::MakeUnrx;
+                #new_frozen
+                let __perseus_new = match ::perseus::state::merge_macro_backend::<#orig_name #generics>(
+                    self.clone().make_unrx(),
+                    patch,
+                ) {
+                    ::std::option::Option::Some(new) => new,
+                    ::std::option::Option::None => return false,
+                };
+                #merge_stmts
+                true
+            }
+        }
+        // There's nothing to merge into a unit `struct`
+        _ => quote!(true),
+    };
+
     quote! {
         // We add a Serde derivation because it will always be necessary for Perseus on the original `struct`, and it's really difficult and brittle to filter it out
         #[derive(::serde::Serialize, ::serde::Deserialize, ::std::clone::Clone)]
         #orig_struct
         impl #generics ::perseus::state::MakeRx for #orig_name #generics {
             type Rx = #mid_name #generics;
+            #state_version
+            #migrate_fn
             fn make_rx(self) -> #mid_name #generics {
                 use ::perseus::state::MakeRx;
                 #make_rx_fields
             }
+            fn thaw(self) -> Self {
+                #thaw_body
+            }
         }
         #[derive(::std::clone::Clone)]
         #mid_struct
@@ -296,10 +486,20 @@ pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream
         impl #generics ::perseus::state::Freeze for #mid_name #generics {
             fn freeze(&self) -> ::std::string::String {
                 use ::perseus::state::MakeUnrx;
-                let unrx = #make_unrx_fields;
+                let unrx = #make_freeze_fields;
                 // TODO Is this `.unwrap()` safe?
                 ::serde_json::to_string(&unrx).unwrap()
             }
+            fn state_version(&self) -> u32 {
+                <#orig_name #generics as ::perseus::state::MakeRx>::STATE_VERSION
+            }
+            fn track(&self) {
+                #track_fields
+            }
+            #[allow(unused_variables)]
+            fn merge(&self, patch: ::serde_json::Value) -> bool {
+                #merge_body
+            }
         }
         #[derive(::std::clone::Clone)]
         #ref_struct
@@ -310,3 +510,758 @@ pub fn make_rx_impl(mut orig_struct: ItemStruct, name_raw: Ident) -> TokenStream
         }
     }
 }
+
+/// The ways in which a field can be frozen other than the default.
+enum FieldFreeze {
+    /// The field won't be frozen, and its default value will be used instead.
+    Skip,
+    /// The field's value will be passed through the first of the given
+    /// functions before it's frozen, and through the second after it's thawed.
+    With {
+        freeze_fn: syn::Path,
+        thaw_fn: syn::Path,
+    },
+}
+
+/// Our own attributes on a single field.
+#[derive(Default)]
+struct FieldAttrs {
+    /// Whether or not the field should use nested reactivity through its type's
+    /// own `MakeRx` implementation.
+    nested: bool,
+    /// How the field should be frozen, if it shouldn't be frozen as usual.
+    freeze: Option<FieldFreeze>,
+}
+
+/// Parses any attributes of the form `#[rx(nested)]`, `#[rx(skip_freeze)]`, or
+/// `#[rx(freeze_with = "path::to::fn", thaw_with = "path::to::fn")]` on a
+/// field, removing them from the given list of its attributes.
+fn take_field_attrs(attrs: &mut Vec<Attribute>) -> Result<FieldAttrs> {
+    let err_msg = "expected `#[rx(nested)]`, `#[rx(skip_freeze)]`, or `#[rx(freeze_with = \"path::to::fn\", thaw_with = \"path::to::fn\")]`";
+    let mut field_attrs = FieldAttrs::default();
+    let mut new_attrs = Vec::new();
+    let mut freeze_fn = None;
+    let mut thaw_fn = None;
+    for attr in attrs.iter() {
+        if !attr.path.is_ident("rx") {
+            new_attrs.push(attr.clone());
+            continue;
+        }
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested,
+            _ => return Err(syn::Error::new_spanned(attr, err_msg)),
+        };
+        for meta in list.iter() {
+            match meta {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("nested") => {
+                    field_attrs.nested = true
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip_freeze") => {
+                    field_attrs.freeze = Some(FieldFreeze::Skip)
+                }
+                NestedMeta::Meta(Meta::NameValue(name_val))
+                    if name_val.path.is_ident("freeze_with")
+                        || name_val.path.is_ident("thaw_with") =>
+                {
+                    let path: syn::Path =
+                        match &name_val.lit {
+                            Lit::Str(s) => s.parse()?,
+                            lit => return Err(syn::Error::new_spanned(
+                                lit,
+                                "freeze and thaw functions must be given as string literal paths",
+                            )),
+                        };
+                    if name_val.path.is_ident("freeze_with") {
+                        freeze_fn = Some((path, name_val.clone()));
+                    } else {
+                        thaw_fn = Some((path, name_val.clone()));
+                    }
+                }
+                meta => return Err(syn::Error::new_spanned(meta, err_msg)),
+            }
+        }
+    }
+    *attrs = new_attrs;
+    // Anything frozen differently has to be restored when it's thawed, otherwise thawing
+    // would replace live values with whatever was frozen
+    match (freeze_fn, thaw_fn) {
+        (Some((freeze_fn, _)), Some((thaw_fn, _))) => {
+            field_attrs.freeze = Some(FieldFreeze::With { freeze_fn, thaw_fn })
+        }
+        (Some((_, meta)), None) => {
+            return Err(syn::Error::new_spanned(
+                meta,
+                "`freeze_with` must be paired with `thaw_with`",
+            ))
+        }
+        (None, Some((_, meta))) => {
+            return Err(syn::Error::new_spanned(
+                meta,
+                "`thaw_with` must be paired with `freeze_with`",
+            ))
+        }
+        (None, None) => (),
+    }
+
+    Ok(field_attrs)
+}
+
+/// Parses the `#[rx::version(n)]` and `#[rx::migrate(my_fn)]` attributes on a
+/// type, returning the items that should be added to its `MakeRx`
+/// implementation (if either is missing, the default will be used).
+fn parse_version_attrs(attrs: &[Attribute]) -> Result<(Option<TokenStream>, Option<TokenStream>)> {
+    let mut state_version = None;
+    let mut migrate_fn = None;
+    for attr in attrs.iter() {
+        if !(attr.path.segments.len() == 2 && attr.path.segments.first().unwrap().ident == "rx") {
+            continue;
+        }
+        let attr_name = &attr.path.segments.last().unwrap().ident;
+        if attr_name == "version" {
+            match attr.parse_args::<syn::LitInt>() {
+                Ok(version) => state_version = Some(version),
+                Err(_) => {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "expected integer version (e.g. `#[rx::version(1)]`)",
+                    ))
+                }
+            }
+        } else if attr_name == "migrate" {
+            match attr.parse_args::<syn::Path>() {
+                Ok(path) => migrate_fn = Some(path),
+                Err(_) => return Err(syn::Error::new_spanned(
+                    attr,
+                    "expected path to migration function (e.g. `#[rx::migrate(migrate_state)]`)",
+                )),
+            }
+        }
+    }
+    let state_version = state_version.map(|version| {
+        quote! {
+            const STATE_VERSION: u32 = #version;
+        }
+    });
+    let migrate_fn = migrate_fn.map(|migrate_fn| {
+        quote! {
+            fn migrate(
+                from_version: u32,
+                state: ::serde_json::Value,
+            ) -> ::std::result::Result<::serde_json::Value, ::std::string::String> {
+                #migrate_fn(from_version, state)
+            }
+        }
+    });
+
+    Ok((state_version, migrate_fn))
+}
+
+/// Checks if the given attribute is one of our own type-level attributes
+/// (e.g. `#[rx::nested(..)]`), which need to be removed before the type is
+/// output.
+fn is_rx_type_attr(attr: &Attribute) -> bool {
+    attr.path.segments.len() == 2
+        && attr.path.segments.first().unwrap().ident == "rx"
+        && ["nested", "version", "migrate"]
+            .iter()
+            .any(|name| attr.path.segments.last().unwrap().ident == name)
+}
+
+/// Processes the given `enum` to create reactive versions of it, in the same
+/// way as `make_rx_impl()` does for `struct`s. The reactive versions have
+/// exactly the same variants as the original, but with their fields made
+/// reactive, and the current variant is itself held in a signal, so that
+/// merging state can switch it.
+pub fn make_rx_enum_impl(mut orig_enum: ItemEnum, name_raw: Ident) -> TokenStream {
+    if orig_enum.variants.is_empty() {
+        return syn::Error::new_spanned(orig_enum, "empty enums can't be made reactive")
+            .to_compile_error();
+    }
+    if let Some(attr) = orig_enum.attrs.iter().find(|attr| {
+        attr.path.segments.len() == 2
+            && attr.path.segments.first().unwrap().ident == "rx"
+            && attr.path.segments.last().unwrap().ident == "nested"
+    }) {
+        return syn::Error::new_spanned(
+            attr,
+            "`#[rx::nested(..)]` isn't supported on enums (use `#[rx(nested)]` on fields instead)",
+        )
+        .to_compile_error();
+    }
+    let (state_version, migrate_fn) = match parse_version_attrs(&orig_enum.attrs) {
+        Ok(res) => res,
+        Err(err) => return err.to_compile_error(),
+    };
+    orig_enum.attrs.retain(|attr| !is_rx_type_attr(attr));
+    // Get our own attributes on every field of every variant (in order)
+    let mut variants_attrs = Vec::new();
+    for variant in orig_enum.variants.iter_mut() {
+        let mut fields_attrs = Vec::new();
+        for field in variant.fields.iter_mut() {
+            match take_field_attrs(&mut field.attrs) {
+                Ok(field_attrs) => fields_attrs.push(field_attrs),
+                Err(err) => return err.to_compile_error(),
+            }
+        }
+        variants_attrs.push(fields_attrs);
+    }
+
+    let orig_name = orig_enum.ident.clone();
+    let generics = orig_enum.generics.clone();
+    let vis = orig_enum.vis.clone();
+    let ref_name = name_raw.clone();
+    let mid_name = Ident::new(
+        &(name_raw.to_string() + "PerseusRxIntermediary"),
+        Span::call_site(),
+    );
+    // The active variant is held in a signal, so it can be changed reactively, and this is
+    // what users will match on
+    let variant_name = Ident::new(&(name_raw.to_string() + "Variant"), Span::call_site());
+    // Serde can't be used with the reactive versions
+    let mut variant_enum = orig_enum.clone();
+    variant_enum.ident = variant_name.clone();
+    variant_enum
+        .attrs
+        .retain(|attr| !attr.path.is_ident("serde"));
+    for variant in variant_enum.variants.iter_mut() {
+        variant.attrs.retain(|attr| !attr.path.is_ident("serde"));
+        for field in variant.fields.iter_mut() {
+            field.attrs.retain(|attr| !attr.path.is_ident("serde"));
+        }
+    }
+    for (variant, fields_attrs) in variant_enum.variants.iter_mut().zip(variants_attrs.iter()) {
+        for (field, field_attrs) in variant.fields.iter_mut().zip(fields_attrs.iter()) {
+            let orig_ty = &field.ty;
+            field.ty = if field_attrs.nested {
+                syn::Type::Verbatim(quote!(<#orig_ty as ::perseus::state::MakeRx>::Rx))
+            } else {
+                syn::Type::Verbatim(quote!(::sycamore::prelude::RcSignal<#orig_ty>))
+            };
+        }
+    }
+    // The reference version is just a reference to the intermediary (there's nothing to
+    // convert, since the fields of each variant are in their own signals anyway)
+    let mut ref_generics = generics.clone();
+    ref_generics.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeDef::new(Lifetime::new("'rx", Span::call_site()))),
+    );
+
+    let orig_path = quote!(#orig_name);
+    let variant_path = quote!(#variant_name);
+    let make_rx_arms = variant_arms(
+        &orig_enum,
+        &orig_path,
+        &variant_name,
+        &variants_attrs,
+        |field, attrs| {
+            if attrs.nested {
+                quote!(#field.make_rx())
+            } else {
+                quote!(::sycamore::prelude::create_rc_signal(#field))
+            }
+        },
+    );
+    // These are matched on a reference, so every field will be a reference
+    let make_unrx_arms = variant_arms(
+        &orig_enum,
+        &variant_path,
+        &orig_name,
+        &variants_attrs,
+        |field, attrs| {
+            if attrs.nested {
+                quote!(#field.clone().make_unrx())
+            } else {
+                quote!((*#field.get_untracked()).clone())
+            }
+        },
+    );
+    let make_freeze_arms = variant_arms(
+        &orig_enum,
+        &variant_path,
+        &orig_name,
+        &variants_attrs,
+        |field, attrs| {
+            let frozen_nested = quote! {
+                ::serde_json::from_str(&::perseus::state::Freeze::freeze(#field)).unwrap()
+            };
+            match &attrs.freeze {
+                Some(FieldFreeze::Skip) => quote!(::std::default::Default::default()),
+                Some(FieldFreeze::With { freeze_fn, .. }) if attrs.nested => {
+                    quote!(#freeze_fn(&#frozen_nested))
+                }
+                Some(FieldFreeze::With { freeze_fn, .. }) => {
+                    quote!(#freeze_fn(&*#field.get_untracked()))
+                }
+                None if attrs.nested => frozen_nested,
+                None => quote!((*#field.get_untracked()).clone()),
+            }
+        },
+    );
+    let thaw_arms = variant_arms(
+        &orig_enum,
+        &orig_path,
+        &orig_name,
+        &variants_attrs,
+        |field, attrs| thaw_field(quote!(#field), attrs.freeze.as_ref(), attrs.nested),
+    );
+    let track_arms = variant_stmt_arms(
+        &orig_enum,
+        &variant_path,
+        &variants_attrs,
+        |field, attrs| {
+            if attrs.nested {
+                quote!(::perseus::state::Freeze::track(#field);)
+            } else {
+                quote!(#field.track();)
+            }
+        },
+    );
+    let has_frozen_fields = variants_attrs
+        .iter()
+        .flatten()
+        .any(|attrs| matches!(attrs.freeze, Some(FieldFreeze::With { .. })));
+    let merge_arms = variant_merge_arms(
+        &orig_enum,
+        &variant_path,
+        &orig_name,
+        &variants_attrs,
+        has_frozen_fields,
+    );
+    // Fields that are frozen differently have to be patched as they would be frozen
+    let (new_frozen, merge_target) = if has_frozen_fields {
+        (
+            merge_frozen_backend(&orig_name, &generics),
+            quote!((
+                &*__perseus_current,
+                __perseus_new.clone(),
+                __perseus_new_frozen
+            )),
+        )
+    } else {
+        (
+            quote!(),
+            quote!((&*__perseus_current, __perseus_new.clone())),
+        )
+    };
+
+    quote! {
+        #[derive(::serde::Serialize, ::serde::Deserialize, ::std::clone::Clone)]
+        #orig_enum
+        impl #generics ::perseus::state::MakeRx for #orig_name #generics {
+            type Rx = #mid_name #generics;
+            #state_version
+            #migrate_fn
+            fn make_rx(self) -> #mid_name #generics {
+                use ::perseus::state::MakeRx;
+                #mid_name(::sycamore::prelude::create_rc_signal(match self {
+                    #make_rx_arms
+                }))
+            }
+            fn thaw(self) -> Self {
+                match self {
+                    #thaw_arms
+                }
+            }
+        }
+        #[derive(::std::clone::Clone)]
+        #variant_enum
+        #[derive(::std::clone::Clone)]
+        #vis struct #mid_name #generics(::sycamore::prelude::RcSignal<#variant_name #generics>);
+        impl #generics ::std::ops::Deref for #mid_name #generics {
+            type Target = ::sycamore::prelude::RcSignal<#variant_name #generics>;
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+        impl #generics ::perseus::state::MakeUnrx for #mid_name #generics {
+            type Unrx = #orig_name #generics;
+            fn make_unrx(self) -> #orig_name #generics {
+                use ::perseus::state::MakeUnrx;
+                match &*self.0.get_untracked() {
+                    #make_unrx_arms
+                }
+            }
+        }
+        impl #generics ::perseus::state::Freeze for #mid_name #generics {
+            // Fields that aren't frozen won't be used
+            #[allow(unused_variables)]
+            fn freeze(&self) -> ::std::string::String {
+                let unrx = match &*self.0.get_untracked() {
+                    #make_freeze_arms
+                };
+                ::serde_json::to_string(&unrx).unwrap()
+            }
+            fn state_version(&self) -> u32 {
+                <#orig_name #generics as ::perseus::state::MakeRx>::STATE_VERSION
+            }
+            fn track(&self) {
+                self.0.track();
+                match &*self.0.get_untracked() {
+                    #track_arms
+                }
+            }
+            #[allow(unused_variables)]
+            fn merge(&self, patch: ::serde_json::Value) -> bool {
+                use ::perseus::state::{MakeRx, MakeUnrx};
+                #new_frozen
+                let __perseus_new = match ::perseus::state::merge_macro_backend::<#orig_name #generics>(
+                    self.clone().make_unrx(),
+                    patch,
+                ) {
+                    ::std::option::Option::Some(new) => new,
+                    ::std::option::Option::None => return false,
+                };
+                let __perseus_current = self.0.get_untracked();
+                #[allow(unreachable_patterns)]
+                let merged = 'perseus_merge: {
+                    match #merge_target {
+                        #merge_arms
+                        _ => false,
+                    }
+                };
+                // If the variant has changed (or its fields couldn't be merged), we'll replace
+                // it wholesale, which will only update what depends on the variant itself
+                if !merged {
+                    self.0.set(match __perseus_new {
+                        #make_rx_arms
+                    });
+                }
+                true
+            }
+        }
+        #vis type #ref_name #ref_generics = &'rx #mid_name #generics;
+        impl #generics #mid_name #generics {
+            pub fn to_ref_struct(self, cx: ::sycamore::prelude::Scope) -> #ref_name #generics {
+                ::sycamore::prelude::create_ref(cx, self)
+            }
+        }
+    }
+}
+
+/// Gets the names of the variables the fields of the given variant will be
+/// bound to when it's matched on, which are the names of the fields
+/// themselves, or `__field_n` for unnamed fields.
+fn variant_bindings(variant: &Variant) -> Vec<Ident> {
+    match &variant.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| field.ident.clone().unwrap())
+            .collect(),
+        Fields::Unnamed(fields) => (0..fields.unnamed.len())
+            .map(|i| Ident::new(&format!("__field_{}", i), Span::call_site()))
+            .collect(),
+        Fields::Unit => Vec::new(),
+    }
+}
+
+/// Creates a pattern that matches the given variant of the `enum` at the given
+/// path, binding its fields to the given names.
+fn variant_pattern(from: &TokenStream, variant: &Variant, bindings: &[Ident]) -> TokenStream {
+    let variant_name = &variant.ident;
+    match &variant.fields {
+        Fields::Named(_) => quote!(#from::#variant_name { #(#bindings),* }),
+        Fields::Unnamed(_) => quote!(#from::#variant_name(#(#bindings),*)),
+        Fields::Unit => quote!(#from::#variant_name),
+    }
+}
+
+/// Creates the arms of a `match` on the `enum` at the given path (which should
+/// be the given `enum` or one of its reactive versions) that convert each
+/// variant into the same variant of the `enum` with the given name, using the
+/// given function to convert each field (which will be bound to the names from
+/// `variant_bindings()`).
+fn variant_arms(
+    item: &ItemEnum,
+    from: &TokenStream,
+    to: &Ident,
+    variants_attrs: &[Vec<FieldAttrs>],
+    convert_field: impl Fn(&Ident, &FieldAttrs) -> TokenStream,
+) -> TokenStream {
+    let mut arms = quote!();
+    for (variant, fields_attrs) in item.variants.iter().zip(variants_attrs.iter()) {
+        let variant_name = &variant.ident;
+        let field_names = variant_bindings(variant);
+        let pattern = variant_pattern(from, variant, &field_names);
+        let field_vals = field_names
+            .iter()
+            .zip(fields_attrs.iter())
+            .map(|(field_name, field_attrs)| convert_field(field_name, field_attrs));
+        arms.extend(match &variant.fields {
+            Fields::Named(_) => quote! {
+                #pattern => #to::#variant_name { #(#field_names: #field_vals),* },
+            },
+            Fields::Unnamed(_) => quote! {
+                #pattern => #to::#variant_name(#(#field_vals),*),
+            },
+            Fields::Unit => quote! {
+                #pattern => #to::#variant_name,
+            },
+        });
+    }
+
+    arms
+}
+
+/// Creates the arms of a `match` on the `enum` at the given path that run the
+/// statements produced by the given function for each field of each variant.
+fn variant_stmt_arms(
+    item: &ItemEnum,
+    from: &TokenStream,
+    variants_attrs: &[Vec<FieldAttrs>],
+    field_stmt: impl Fn(&Ident, &FieldAttrs) -> TokenStream,
+) -> TokenStream {
+    let mut arms = quote!();
+    for (variant, fields_attrs) in item.variants.iter().zip(variants_attrs.iter()) {
+        let field_names = variant_bindings(variant);
+        let pattern = variant_pattern(from, variant, &field_names);
+        let stmts = field_names
+            .iter()
+            .zip(fields_attrs.iter())
+            .map(|(field_name, field_attrs)| field_stmt(field_name, field_attrs));
+        arms.extend(quote! {
+            #pattern => { #(#stmts)* }
+        });
+    }
+
+    arms
+}
+
+/// Creates the statements that merge the new value of a field (taken from a
+/// patched unreactive version of the state) into a reference to its reactive
+/// version. Plain fields will only be set if their values have changed, and
+/// nested fields will be merged recursively. If the merge fails, these will
+/// run the given `fail` expression (which should diverge).
+fn merge_field_stmts(
+    field: TokenStream,
+    new_field: TokenStream,
+    nested: bool,
+    fail: TokenStream,
+) -> TokenStream {
+    let stmts = if nested {
+        quote! {
+            match (
+                ::serde_json::to_value(&__perseus_field.clone().make_unrx()),
+                ::serde_json::to_value(&#new_field),
+            ) {
+                (::std::result::Result::Ok(old), ::std::result::Result::Ok(new)) => {
+                    if old != new
+                        && !::perseus::state::Freeze::merge(
+                            __perseus_field,
+                            ::perseus::state::create_merge_patch(&old, &new),
+                        )
+                    {
+                        #fail;
+                    }
+                }
+                _ => #fail,
+            }
+        }
+    } else {
+        // We compare the serialized values to avoid requiring `PartialEq`
+        quote! {
+            let changed = match (
+                ::serde_json::to_value(&*__perseus_field.get_untracked()),
+                ::serde_json::to_value(&#new_field),
+            ) {
+                (::std::result::Result::Ok(old), ::std::result::Result::Ok(new)) => old != new,
+                _ => true,
+            };
+            if changed {
+                ::sycamore::prelude::Signal::set(__perseus_field, #new_field);
+            }
+        }
+    };
+
+    // The field is bound first so that method calls on it can't be parsed as applying to
+    // anything inside it
+    quote! {{
+        let __perseus_field = #field;
+        #stmts
+    }}
+}
+
+/// Creates the arms of a `match` on `(&current, orig)` (where `current` is the
+/// current variant, from the `enum` at the given path, and `orig` is a new
+/// value of the given original `enum`) that merge the fields of `orig` into
+/// `current` if they're the same variant, evaluating to whether or not that
+/// worked. These need to be inside a block labelled `'perseus_merge`.
+fn variant_merge_arms(
+    item: &ItemEnum,
+    from: &TokenStream,
+    orig_name: &Ident,
+    variants_attrs: &[Vec<FieldAttrs>],
+    has_frozen_fields: bool,
+) -> TokenStream {
+    let mut arms = quote!();
+    for (variant, fields_attrs) in item.variants.iter().zip(variants_attrs.iter()) {
+        let variant_name = &variant.ident;
+        let field_names = variant_bindings(variant);
+        let pattern = variant_pattern(from, variant, &field_names);
+        let new_names = field_names
+            .iter()
+            .map(|field_name| {
+                Ident::new(&format!("__perseus_new_{}", field_name), Span::call_site())
+            })
+            .collect::<Vec<_>>();
+        // Fields that are frozen differently are taken from a version patched as it would be
+        // frozen, and everything else is ignored there
+        let frozen_names = field_names
+            .iter()
+            .zip(fields_attrs.iter())
+            .map(|(field_name, field_attrs)| match field_attrs.freeze {
+                Some(FieldFreeze::With { .. }) => {
+                    let name = Ident::new(
+                        &format!("__perseus_new_frozen_{}", field_name),
+                        Span::call_site(),
+                    );
+                    quote!(#name)
+                }
+                _ => quote!(_),
+            })
+            .collect::<Vec<_>>();
+        let new_pattern = match &variant.fields {
+            Fields::Named(_) => quote!(#orig_name::#variant_name { #(#field_names: #new_names),* }),
+            Fields::Unnamed(_) => quote!(#orig_name::#variant_name(#(#new_names),*)),
+            Fields::Unit => quote!(#orig_name::#variant_name),
+        };
+        let pattern = if has_frozen_fields {
+            let new_frozen_pattern = match &variant.fields {
+                Fields::Named(_) => {
+                    quote!(#orig_name::#variant_name { #(#field_names: #frozen_names),* })
+                }
+                Fields::Unnamed(_) => quote!(#orig_name::#variant_name(#(#frozen_names),*)),
+                Fields::Unit => quote!(#orig_name::#variant_name),
+            };
+            quote!((#pattern, #new_pattern, #new_frozen_pattern))
+        } else {
+            quote!((#pattern, #new_pattern))
+        };
+        let stmts = field_names
+            .iter()
+            .zip(new_names.iter())
+            .zip(frozen_names.iter())
+            .zip(fields_attrs.iter())
+            .map(|(((field_name, new_name), frozen_name), field_attrs)| {
+                match &field_attrs.freeze {
+                    Some(FieldFreeze::With { freeze_fn, thaw_fn }) => merge_frozen_field_stmts(
+                        quote!(#field_name),
+                        frozen_name.clone(),
+                        field_attrs.nested,
+                        freeze_fn,
+                        thaw_fn,
+                        quote!(break 'perseus_merge false),
+                    ),
+                    _ => merge_field_stmts(
+                        quote!(#field_name),
+                        quote!(#new_name),
+                        field_attrs.nested,
+                        quote!(break 'perseus_merge false),
+                    ),
+                }
+            });
+        arms.extend(quote! {
+            #pattern => {
+                #(#stmts)*
+                true
+            }
+        });
+    }
+
+    arms
+}
+
+/// Creates the expression that restores a single field of some freshly thawed
+/// state (given as an expression that moves it), which will pass it through
+/// its thaw function if it has one, and through its own type's `MakeRx::thaw()`
+/// if it uses nested reactivity.
+fn thaw_field(field: TokenStream, freeze: Option<&FieldFreeze>, nested: bool) -> TokenStream {
+    let field = match freeze {
+        Some(FieldFreeze::With { thaw_fn, .. }) => quote!(#thaw_fn(#field)),
+        _ => field,
+    };
+    if nested {
+        quote!(::perseus::state::MakeRx::thaw(#field))
+    } else {
+        field
+    }
+}
+
+/// Creates the statement that patches the state as it would be frozen,
+/// binding the result to `__perseus_new_frozen`. This needs to come before
+/// `patch` is used for anything else.
+fn merge_frozen_backend(orig_name: &Ident, generics: &syn::Generics) -> TokenStream {
+    quote! {
+        let __perseus_new_frozen = match ::serde_json::from_str::<#orig_name #generics>(
+            &::perseus::state::Freeze::freeze(self),
+        )
+        .ok()
+        .and_then(|frozen| {
+            ::perseus::state::merge_macro_backend::<#orig_name #generics>(frozen, patch.clone())
+        }) {
+            ::std::option::Option::Some(new) => new,
+            ::std::option::Option::None => return false,
+        };
+    }
+}
+
+/// Creates the statements that merge the new value of a field that's frozen
+/// with the given functions (taken from a version of the state that was
+/// patched as it would be frozen) into a reference to its reactive version.
+/// The field will only be updated if its frozen value has changed, in which
+/// case the new value will be passed through the thaw function first. Like
+/// `merge_field_stmts()`, these will run `fail` if the merge fails.
+fn merge_frozen_field_stmts(
+    field: TokenStream,
+    new_frozen_field: TokenStream,
+    nested: bool,
+    freeze_fn: &syn::Path,
+    thaw_fn: &syn::Path,
+    fail: TokenStream,
+) -> TokenStream {
+    let stmts = if nested {
+        // Nested state is compared as it's frozen too, so that any of its own fields that
+        // aren't frozen are left alone
+        quote! {
+            match (
+                ::serde_json::from_str::<::serde_json::Value>(
+                    &::perseus::state::Freeze::freeze(__perseus_field),
+                ),
+                ::serde_json::to_value(&#thaw_fn(#new_frozen_field)),
+            ) {
+                (::std::result::Result::Ok(old), ::std::result::Result::Ok(new)) => {
+                    if old != new
+                        && !::perseus::state::Freeze::merge(
+                            __perseus_field,
+                            ::perseus::state::create_merge_patch(&old, &new),
+                        )
+                    {
+                        #fail;
+                    }
+                }
+                _ => #fail,
+            }
+        }
+    } else {
+        quote! {
+            let changed = match (
+                ::serde_json::to_value(&#freeze_fn(&*__perseus_field.get_untracked())),
+                ::serde_json::to_value(&#new_frozen_field),
+            ) {
+                (::std::result::Result::Ok(old), ::std::result::Result::Ok(new)) => old != new,
+                _ => true,
+            };
+            if changed {
+                ::sycamore::prelude::Signal::set(__perseus_field, #thaw_fn(#new_frozen_field));
+            }
+        }
+    };
+
+    // The field is bound first so that method calls on it can't be parsed as applying to
+    // anything inside it
+    quote! {{
+        let __perseus_field = #field;
+        #stmts
+    }}
+}
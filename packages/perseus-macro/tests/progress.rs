@@ -0,0 +1,6 @@
+#[test]
+fn rx_state() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/rx_state/pass/*.rs");
+    t.compile_fail("tests/rx_state/fail/*.rs");
+}
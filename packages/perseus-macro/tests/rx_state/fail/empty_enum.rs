@@ -0,0 +1,6 @@
+use perseus::prelude::*;
+
+#[make_rx(NothingRx)]
+enum Nothing {}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use perseus::prelude::*;
+
+#[make_rx(StatusRx)]
+#[rx::nested("draft", DraftRx)]
+enum Status {
+    Draft(Draft),
+    Archived,
+}
+
+#[make_rx(DraftRx)]
+struct Draft {
+    title: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use perseus::prelude::*;
+
+#[make_rx(EditorRx)]
+struct Editor {
+    #[rx(freeze_with = "recent")]
+    history: Vec<String>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use perseus::prelude::*;
+
+#[make_rx(EditorRx)]
+struct Editor {
+    #[rx(freeze_with = 5, thaw_with = "restore")]
+    history: Vec<String>,
+}
+
+fn main() {}
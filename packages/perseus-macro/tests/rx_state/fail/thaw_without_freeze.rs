@@ -0,0 +1,9 @@
+use perseus::prelude::*;
+
+#[make_rx(EditorRx)]
+struct Editor {
+    #[rx(thaw_with = "restore")]
+    history: Vec<String>,
+}
+
+fn main() {}
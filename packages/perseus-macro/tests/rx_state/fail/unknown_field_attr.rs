@@ -0,0 +1,9 @@
+use perseus::prelude::*;
+
+#[make_rx(EditorRx)]
+struct Editor {
+    #[rx(skip)]
+    history: Vec<String>,
+}
+
+fn main() {}
@@ -0,0 +1,64 @@
+use perseus::prelude::*;
+use perseus::state::{Freeze, MakeRx, MakeUnrx, RxHashMap, RxVec};
+use serde_json::json;
+use std::collections::HashMap;
+
+#[make_rx(TodosRx)]
+struct Todos {
+    #[rx(nested)]
+    list: RxVec<Todo>,
+    #[rx(nested)]
+    by_id: RxHashMap<u32, Todo>,
+}
+
+#[make_rx(TodoRx)]
+struct Todo {
+    title: String,
+    done: bool,
+}
+
+fn todo(title: &str) -> Todo {
+    Todo {
+        title: title.to_string(),
+        done: false,
+    }
+}
+
+fn main() {
+    let todos = Todos {
+        list: vec![todo("a"), todo("b")].into(),
+        by_id: HashMap::from([(1, todo("c"))]).into(),
+    }
+    .make_rx();
+
+    // Every element gets its own signals
+    let first = todos.list.get()[0].clone();
+    first.done.set(true);
+    todos.by_id.get()[&1].title.set("d".to_string());
+    let unrx = todos.clone().make_unrx();
+    assert!(unrx.list[0].done);
+    assert!(!unrx.list[1].done);
+    assert_eq!(unrx.by_id[&1].title, "d");
+
+    // Map entries are patched in place, and `null` removes them
+    let entry = todos.by_id.get()[&1].clone();
+    assert!(todos.merge(json!({ "by_id": { "1": { "done": true }, "2": { "title": "e", "done": false } } })));
+    assert!(*entry.done.get());
+    assert_eq!(*entry.title.get(), "d");
+    assert!(todos.merge(json!({ "by_id": { "2": null } })));
+    assert!(!todos.by_id.get().contains_key(&2));
+
+    // Invalid patches are rejected without applying any of their entries
+    assert!(!todos.merge(json!({ "by_id": { "1": { "done": false }, "3": { "done": "yes" } } })));
+    assert!(*entry.done.get());
+    assert!(!todos.by_id.get().contains_key(&3));
+
+    // Lists of the same length are patched element by element, keeping their signals
+    assert!(todos.merge(json!({ "list": [{ "title": "a", "done": false }, { "title": "b", "done": true }] })));
+    assert!(!*first.done.get());
+    assert!(*todos.list.get()[1].done.get());
+    // Otherwise, they're replaced wholesale
+    assert!(todos.merge(json!({ "list": [{ "title": "f", "done": false }] })));
+    assert_eq!(*todos.list.get()[0].title.get(), "f");
+    assert_eq!(todos.make_unrx().list.len(), 1);
+}
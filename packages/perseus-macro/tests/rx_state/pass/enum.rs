@@ -0,0 +1,67 @@
+use perseus::prelude::*;
+use perseus::state::{create_merge_patch, Freeze, MakeRx, MakeUnrx};
+use serde_json::{json, Value};
+
+#[make_rx(StatusRx)]
+enum Status {
+    Draft {
+        title: String,
+        #[rx(skip_freeze)]
+        autosave: String,
+    },
+    Published(String, u32),
+    Archived,
+}
+
+fn frozen(state: &impl Freeze) -> Value {
+    serde_json::from_str(&state.freeze()).unwrap()
+}
+
+fn main() {
+    let status = Status::Draft {
+        title: "Hello".to_string(),
+        autosave: "Hel".to_string(),
+    }
+    .make_rx();
+    match &*status.get() {
+        StatusRxVariant::Draft { title, .. } => title.set("Hello!".to_string()),
+        _ => unreachable!(),
+    }
+    assert_eq!(
+        frozen(&status),
+        json!({ "Draft": { "title": "Hello!", "autosave": "" } })
+    );
+
+    // A patch within the current variant updates its fields in place
+    let title = match &*status.get() {
+        StatusRxVariant::Draft { title, .. } => title.clone(),
+        _ => unreachable!(),
+    };
+    assert!(status.merge(create_merge_patch(
+        &frozen(&status),
+        &json!({ "Draft": { "title": "Hi", "autosave": "" } })
+    )));
+    assert_eq!(*title.get(), "Hi");
+    match &*status.get() {
+        StatusRxVariant::Draft { autosave, .. } => assert_eq!(*autosave.get(), "Hel"),
+        _ => unreachable!(),
+    }
+
+    // And a patch to another variant replaces the variant
+    assert!(status.merge(create_merge_patch(
+        &frozen(&status),
+        &json!({ "Published": ["Hi", 3] })
+    )));
+    match &*status.get() {
+        StatusRxVariant::Published(title, views) => {
+            assert_eq!(*title.get(), "Hi");
+            assert_eq!(*views.get(), 3);
+        }
+        _ => unreachable!(),
+    }
+
+    // Invalid patches leave the state untouched
+    assert!(!status.merge(json!({ "Published": ["Hi", "many"] })));
+    assert!(status.merge(json!("Archived")));
+    assert!(matches!(status.make_unrx(), Status::Archived));
+}
@@ -0,0 +1,51 @@
+use perseus::prelude::*;
+use perseus::state::{create_merge_patch, Freeze, MakeRx, MakeUnrx};
+use serde_json::{json, Value};
+
+#[make_rx(EditorRx)]
+struct Editor {
+    content: String,
+    #[rx(freeze_with = "recent", thaw_with = "restore")]
+    history: Vec<String>,
+}
+
+#[allow(clippy::ptr_arg)]
+fn recent(history: &Vec<String>) -> Vec<String> {
+    history.iter().rev().take(2).rev().cloned().collect()
+}
+fn restore(mut history: Vec<String>) -> Vec<String> {
+    history.insert(0, "...".to_string());
+    history
+}
+
+fn strings(strs: &[&str]) -> Vec<String> {
+    strs.iter().map(|s| s.to_string()).collect()
+}
+
+fn main() {
+    let editor = Editor {
+        content: "x".to_string(),
+        history: strings(&["a", "b", "c"]),
+    }
+    .make_rx();
+    let frozen = editor.freeze();
+    let old: Value = serde_json::from_str(&frozen).unwrap();
+    assert_eq!(old, json!({ "content": "x", "history": ["b", "c"] }));
+
+    // Thawing passes the frozen value through the thaw function
+    let thawed = serde_json::from_str::<Editor>(&frozen).unwrap().thaw();
+    assert_eq!(thawed.history, strings(&["...", "b", "c"]));
+
+    // Merging only thaws the field again if its frozen value has changed
+    assert!(editor.merge(create_merge_patch(
+        &old,
+        &json!({ "content": "y", "history": ["b", "c"] })
+    )));
+    assert_eq!(*editor.content.get(), "y");
+    assert_eq!(*editor.history.get(), strings(&["a", "b", "c"]));
+    assert!(editor.merge(create_merge_patch(
+        &json!({ "content": "y", "history": ["b", "c"] }),
+        &json!({ "content": "y", "history": ["c", "d"] })
+    )));
+    assert_eq!(editor.make_unrx().history, strings(&["...", "c", "d"]));
+}
@@ -0,0 +1,23 @@
+// This deliberately doesn't enable Serde's `rc` feature, which apps won't have
+use perseus::prelude::*;
+use perseus::state::{Freeze, MakeRx, MakeUnrx};
+
+#[make_rx(StateRx)]
+struct State {
+    name: String,
+    count: u32,
+}
+
+fn main() {
+    let state = State {
+        name: "foo".to_string(),
+        count: 1,
+    }
+    .make_rx();
+    assert!(state.merge(serde_json::json!({ "count": 2 })));
+    assert_eq!(*state.name.get(), "foo");
+    assert_eq!(*state.count.get(), 2);
+    // A patch that doesn't fit the type shouldn't change anything
+    assert!(!state.merge(serde_json::json!({ "count": "3" })));
+    assert_eq!(state.make_unrx().count, 2);
+}
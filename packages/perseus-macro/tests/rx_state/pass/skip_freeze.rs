@@ -0,0 +1,57 @@
+use perseus::prelude::*;
+use perseus::state::{create_merge_patch, Freeze, MakeRx, MakeUnrx};
+use serde_json::{json, Value};
+
+#[make_rx(SessionRx)]
+struct Session {
+    user: String,
+    #[rx(skip_freeze)]
+    token: String,
+    #[rx(nested)]
+    prefs: Prefs,
+}
+
+#[make_rx(PrefsRx)]
+struct Prefs {
+    theme: String,
+    #[rx(skip_freeze)]
+    draft: String,
+}
+
+fn frozen(state: &impl Freeze) -> Value {
+    serde_json::from_str(&state.freeze()).unwrap()
+}
+
+fn main() {
+    let session = Session {
+        user: "alice".to_string(),
+        token: "secret".to_string(),
+        prefs: Prefs {
+            theme: "dark".to_string(),
+            draft: "hello".to_string(),
+        },
+    }
+    .make_rx();
+    let old = frozen(&session);
+    assert_eq!(
+        old,
+        json!({ "user": "alice", "token": "", "prefs": { "theme": "dark", "draft": "" } })
+    );
+
+    // Merging frozen state (e.g. from another tab) leaves skipped fields alone
+    let other = Session {
+        user: "bob".to_string(),
+        token: String::new(),
+        prefs: Prefs {
+            theme: "light".to_string(),
+            draft: String::new(),
+        },
+    }
+    .make_rx();
+    assert!(session.merge(create_merge_patch(&old, &frozen(&other))));
+    let session = session.make_unrx();
+    assert_eq!(session.user, "bob");
+    assert_eq!(session.token, "secret");
+    assert_eq!(session.prefs.theme, "light");
+    assert_eq!(session.prefs.draft, "hello");
+}
@@ -0,0 +1,38 @@
+use perseus::prelude::*;
+use perseus::state::{Freeze, MakeRx};
+
+#[make_rx(StateRx)]
+#[rx::version(1)]
+#[rx::migrate(migrate)]
+struct State {
+    name: String,
+}
+
+// Version 0 called `name` `title`
+fn migrate(from_version: u32, state: serde_json::Value) -> Result<serde_json::Value, String> {
+    match from_version {
+        0 => Ok(serde_json::json!({ "name": state["title"] })),
+        _ => Err("unsupported version".to_string()),
+    }
+}
+
+#[make_rx(UnversionedRx)]
+struct Unversioned {
+    name: String,
+}
+
+fn main() {
+    assert_eq!(State::STATE_VERSION, 1);
+    let state = State {
+        name: "foo".to_string(),
+    }
+    .make_rx();
+    assert_eq!(state.state_version(), 1);
+    assert_eq!(
+        State::migrate(0, serde_json::json!({ "title": "foo" })),
+        Ok(serde_json::json!({ "name": "foo" }))
+    );
+
+    assert_eq!(Unversioned::STATE_VERSION, 0);
+    assert!(Unversioned::migrate(0, serde_json::json!({})).is_err());
+}
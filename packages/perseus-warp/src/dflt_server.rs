@@ -1,10 +1,18 @@
 use crate::perseus_routes;
-use perseus::{i18n::TranslationsManager, server::ServerProps, stores::MutableStore};
+use perseus::{
+    i18n::TranslationsManager,
+    server::{ServerProps, TlsConfig},
+    stores::MutableStore,
+};
 use std::net::SocketAddr;
 
 /// Creates and starts the default Perseus server with Warp. This should be run
 /// in a `main` function annotated with `#[tokio::main]` (which requires the
 /// `macros` and `rt-multi-thread` features on the `tokio` dependency).
+///
+/// If the `PERSEUS_TLS_CERT` and `PERSEUS_TLS_KEY` environment variables are
+/// set, this will serve over HTTPS, which requires the `tls` feature (see
+/// [`TlsConfig`] for details).
 pub async fn dflt_server<M: MutableStore + 'static, T: TranslationsManager + 'static>(
     props: ServerProps<M, T>,
     (host, port): (String, u16),
@@ -13,5 +21,57 @@ pub async fn dflt_server<M: MutableStore + 'static, T: TranslationsManager + 'st
         .parse()
         .expect("Invalid address provided to bind to.");
     let routes = perseus_routes(props).await;
-    warp::serve(routes).run(addr).await;
+    match TlsConfig::from_env() {
+        #[cfg(feature = "tls")]
+        Some(tls) => {
+            let server = warp::serve(routes)
+                .tls()
+                .cert_path(&tls.cert_path)
+                .key_path(&tls.key_path)
+                .run(addr);
+            match tls.redirect_port {
+                Some(redirect_port) => {
+                    let redirect_addr = SocketAddr::new(addr.ip(), redirect_port);
+                    let redirect_server =
+                        warp::serve(redirect_filter(host, port)).run(redirect_addr);
+                    futures::future::join(server, redirect_server).await;
+                }
+                None => server.await,
+            }
+        }
+        #[cfg(not(feature = "tls"))]
+        Some(_) => {
+            panic!("TLS has been configured, but the `tls` feature of `perseus-warp` isn't enabled")
+        }
+        None => warp::serve(routes).run(addr).await,
+    }
+}
+
+/// A filter that redirects every request to the HTTPS server on the given
+/// host and port.
+#[cfg(feature = "tls")]
+fn redirect_filter(
+    host: String,
+    port: u16,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    use warp::{http::Uri, path::FullPath, Filter};
+
+    warp::path::full()
+        .and(
+            warp::query::raw()
+                .map(|query: String| format!("?{}", query))
+                .or(warp::any().map(String::new))
+                .unify(),
+        )
+        .and(warp::header::optional::<String>("host"))
+        .map(
+            move |path: FullPath, query: String, host_header: Option<String>| {
+                let url = perseus::server::get_https_redirect_url(
+                    host_header.as_deref(),
+                    &format!("{}{}", path.as_str(), query),
+                    (&host, port),
+                );
+                warp::redirect::permanent(url.parse::<Uri>().expect("Invalid redirect URL."))
+            },
+        )
 }
@@ -4,15 +4,18 @@ use perseus::{
     i18n::{TranslationsManager, Translator},
     router::{match_route_atomic, RouteInfoAtomic, RouteVerdictAtomic},
     server::{
-        build_error_page, get_page_for_template, get_path_slice, GetPageProps, HtmlShell,
-        ServerOptions,
+        build_error_page, generate_nonce, get_page_for_template, get_path_slice, GetPageProps,
+        HtmlShell, ServerOptions,
     },
     stores::{ImmutableStore, MutableStore},
     utils::get_path_prefix_server,
     ErrorPages, SsrNode,
 };
 use std::{collections::HashMap, rc::Rc, sync::Arc};
-use warp::{http::Response, path::FullPath};
+use warp::{
+    http::{header, response::Builder, Response},
+    path::FullPath,
+};
 
 /// Builds on the internal Perseus primitives to provide a utility function that
 /// returns a `Response` automatically.
@@ -24,9 +27,21 @@ fn return_error_page(
     translator: Option<Rc<Translator>>,
     error_pages: &ErrorPages<SsrNode>,
     html_shell: &HtmlShell,
+    csp_header: &Option<String>,
 ) -> Response<String> {
     let html = build_error_page(url, status, err, translator, error_pages, html_shell);
-    Response::builder().status(status).body(html).unwrap()
+    add_csp_header(Response::builder().status(status), csp_header)
+        .body(html)
+        .unwrap()
+}
+
+/// Adds the `Content-Security-Policy` header for this response, if the app
+/// has one.
+fn add_csp_header(http_res: Builder, csp_header: &Option<String>) -> Builder {
+    match csp_header {
+        Some(csp_header) => http_res.header(header::CONTENT_SECURITY_POLICY, csp_header),
+        None => http_res,
+    }
 }
 
 /// The handler for calls to any actual pages (first-time visits), which will
@@ -47,10 +62,30 @@ pub async fn initial_load_handler<M: MutableStore, T: TranslationsManager>(
     let templates = &opts.templates_map;
     let error_pages = &opts.error_pages;
     let path_slice = get_path_slice(path);
+    // If the app has a CSP, every initial load gets a fresh nonce for its inline
+    // scripts
+    let (html_shell, csp_header) = match &opts.csp {
+        Some(csp) => {
+            let nonce = generate_nonce();
+            (
+                html_shell.as_ref().clone().with_nonce(&nonce),
+                Some(csp.get_header(&nonce)),
+            )
+        }
+        None => (html_shell.as_ref().clone(), None),
+    };
     // Create a closure to make returning error pages easier (most have the same
     // data)
     let html_err = |status: u16, err: &str| {
-        return return_error_page(path, status, err, None, error_pages, html_shell.as_ref());
+        return return_error_page(
+            path,
+            status,
+            err,
+            None,
+            error_pages,
+            &html_shell,
+            &csp_header,
+        );
     };
 
     // Run the routing algorithms on the path to figure out which template we need
@@ -101,8 +136,8 @@ pub async fn initial_load_handler<M: MutableStore, T: TranslationsManager>(
             };
 
             let final_html = html_shell
-                .as_ref()
                 .clone()
+                .for_template(template)
                 .page_data(&page_data, &global_state, &translations)
                 .to_string();
 
@@ -112,6 +147,7 @@ pub async fn initial_load_handler<M: MutableStore, T: TranslationsManager>(
             for (key, val) in template.get_headers(page_data.state) {
                 http_res = http_res.header(key.unwrap(), val);
             }
+            http_res = add_csp_header(http_res, &csp_header);
 
             http_res.body(final_html).unwrap()
         }
@@ -121,11 +157,10 @@ pub async fn initial_load_handler<M: MutableStore, T: TranslationsManager>(
             // We use a `302 Found` status code to indicate a redirect
             // We 'should' generate a `Location` field for the redirect, but it's not
             // RFC-mandated, so we can use the app shell
-            Response::builder()
-                .status(302) // NOTE: Changed this from 200 (I think that was a mistake...)
+            let http_res = Response::builder().status(302); // NOTE: Changed this from 200 (I think that was a mistake...)
+            add_csp_header(http_res, &csp_header)
                 .body(
                     html_shell
-                        .as_ref()
                         .clone()
                         .locale_redirection_fallback(
                             // We'll redirect the user to the default locale
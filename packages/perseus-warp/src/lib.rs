@@ -14,6 +14,7 @@ mod dflt_server;
 mod initial_load;
 mod page_data;
 mod perseus_routes;
+mod state_push;
 mod static_content;
 mod translations;
 
@@ -3,7 +3,7 @@ use perseus::{
     errors::err_to_status_code,
     i18n::TranslationsManager,
     internal::PageDataPartial,
-    server::{get_page_for_template, GetPageProps, ServerOptions},
+    server::{get_page_for_template, restore_page_uri, GetPageProps, ServerOptions},
     stores::{ImmutableStore, MutableStore},
 };
 use serde::Deserialize;
@@ -28,7 +28,7 @@ pub async fn page_handler<M: MutableStore, T: TranslationsManager>(
         template_name,
         was_incremental_match,
     }: PageDataReq,
-    http_req: perseus::http::Request<()>,
+    mut http_req: perseus::http::Request<()>,
     opts: Arc<ServerOptions>,
     immutable_store: Arc<ImmutableStore>,
     mutable_store: Arc<M>,
@@ -54,6 +54,9 @@ pub async fn page_handler<M: MutableStore, T: TranslationsManager>(
                     .unwrap();
             }
         };
+        // Make sure request state functions see the page's own URI (with its query
+        // parameters)
+        restore_page_uri(&mut http_req, &locale, path);
         let page_data = get_page_for_template(
             GetPageProps::<M, T> {
                 raw_path: path,
@@ -74,6 +77,7 @@ pub async fn page_handler<M: MutableStore, T: TranslationsManager>(
                 let partial_page_data = PageDataPartial {
                     state: page_data.state,
                     head: page_data.head,
+                    layout_states: page_data.layout_states,
                 };
                 let mut http_res = Response::builder().status(200);
                 // http_res.content_type("text/html");
@@ -3,10 +3,11 @@ use crate::page_data::page_handler;
 use crate::{
     conv_req::get_http_req,
     page_data::PageDataReq,
+    state_push::{state_push_handler, StatePushReq},
     static_content::{serve_file, static_aliases_filter},
     translations::translations_handler,
 };
-use perseus::server::{get_render_cfg, ServerProps};
+use perseus::server::{get_render_cfg, ServerProps, IMMUTABLE_CACHE_CONTROL};
 use perseus::{i18n::TranslationsManager, stores::MutableStore};
 use std::sync::Arc;
 use warp::Filter;
@@ -46,6 +47,41 @@ pub async fn perseus_routes<M: MutableStore + 'static, T: TranslationsManager +
     // Handle JS interop snippets (which need to be served as separate files)
     let snippets =
         warp::path!(".perseus" / "snippets" / ..).and(warp::fs::dir(opts.snippets.clone()));
+    // If we know the hashes of the bundles, we can also serve them from URLs that
+    // change whenever they do, which means they can be cached forever (if we don't,
+    // this will never match, since IDs are never empty)
+    let bundle_id = Arc::new(
+        opts.bundles
+            .as_ref()
+            .map(|bundles| bundles.id.clone())
+            .unwrap_or_default(),
+    );
+    let hashed_bundles = warp::path!(".perseus" / "bundles" / String / ..)
+        .and_then(move |id: String| {
+            let bundle_id = bundle_id.clone();
+            async move {
+                if !bundle_id.is_empty() && id == *bundle_id {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            }
+        })
+        .untuple_one()
+        .and(
+            warp::path!("bundle.js")
+                .and(warp::fs::file(opts.js_bundle.clone()))
+                .or(warp::path!("bundle.wasm").and(warp::fs::file(opts.wasm_bundle.clone())))
+                .unify()
+                .or(warp::path!("bundle.wasm.js").and(warp::fs::file(opts.wasm_js_bundle.clone())))
+                .unify()
+                .or(warp::path!("snippets" / ..).and(warp::fs::dir(opts.snippets.clone())))
+                .unify(),
+        )
+        .with(warp::reply::with::header(
+            "Cache-Control",
+            IMMUTABLE_CACHE_CONTROL,
+        ));
     // Handle static content in the user-set directories (this will all be under
     // `/.perseus/static`) We only set this if the user is using a static
     // content directory
@@ -70,6 +106,16 @@ pub async fn perseus_routes<M: MutableStore + 'static, T: TranslationsManager +
         .and(static_aliases_filter(opts.static_aliases.clone()))
         .and_then(serve_file);
 
+    // Handle state pushes, if the app can publish them
+    let state_publisher = opts.state_publisher.clone();
+    let state_push = warp::path!(".perseus" / "state-push")
+        .and_then(move || {
+            let state_publisher = state_publisher.clone();
+            async move { state_publisher.ok_or_else(warp::reject::not_found) }
+        })
+        .and(warp::query::<StatePushReq>())
+        .map(state_push_handler);
+
     // Define some filters to handle all the data we want to pass through
     let opts = Arc::new(opts);
     let opts = warp::any().map(move || opts.clone());
@@ -121,9 +167,11 @@ pub async fn perseus_routes<M: MutableStore + 'static, T: TranslationsManager +
         .or(wasm_bundle)
         .or(wasm_js_bundle)
         .or(snippets)
+        .or(hashed_bundles)
         .or(static_dir)
         .or(static_aliases)
         .or(translations)
         .or(page_data)
+        .or(state_push)
         .or(initial_loads)
 }
@@ -0,0 +1,24 @@
+use futures::StreamExt;
+use perseus::state::StatePublisher;
+use serde::Deserialize;
+use std::convert::Infallible;
+use warp::sse::Event;
+
+#[derive(Deserialize)]
+pub struct StatePushReq {
+    pub url: String,
+}
+
+/// The handler for `/.perseus/state-push`, which streams any state published
+/// on the server for the page with the given URL to the browser as server-sent
+/// events.
+pub fn state_push_handler(
+    publisher: StatePublisher,
+    StatePushReq { url }: StatePushReq,
+) -> impl warp::Reply {
+    let stream = publisher
+        .subscribe(&url)
+        .map(|msg| Ok::<_, Infallible>(Event::default().data(msg)));
+    // Proxies will often close connections that have been idle for too long
+    warp::sse::reply(warp::sse::keep_alive().stream(stream))
+}
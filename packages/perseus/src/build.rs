@@ -4,11 +4,11 @@ use crate::errors::*;
 use crate::i18n::{Locales, TranslationsManager};
 use crate::stores::{ImmutableStore, MutableStore};
 use crate::template::Template;
-use crate::template::{PageProps, TemplateMap};
+use crate::template::{LayoutStates, PageProps, TemplateMap};
 use crate::translator::Translator;
-use crate::utils::minify;
+use crate::utils::{minify, QueryParams};
 use futures::future::try_join_all;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use sycamore::prelude::SsrNode;
 
 /// Builds a template, writing static data as appropriate. This should be used
@@ -22,6 +22,7 @@ pub async fn build_template(
     translator: &Translator,
     (immutable_store, mutable_store): (&ImmutableStore, &impl MutableStore),
     global_state: &Option<String>,
+    layout_states: &LayoutStates,
     exporting: bool,
 ) -> Result<(Vec<String>, bool), ServerError> {
     let mut single_page = false;
@@ -74,6 +75,7 @@ pub async fn build_template(
             translator,
             (immutable_store, mutable_store),
             global_state,
+            layout_states,
         );
         futs.push(fut);
     }
@@ -90,6 +92,7 @@ async fn gen_state_for_path(
     translator: &Translator,
     (immutable_store, mutable_store): (&ImmutableStore, &impl MutableStore),
     global_state: &Option<String>,
+    layout_states: &LayoutStates,
 ) -> Result<(), ServerError> {
     let template_path = template.get_path();
     // If needed, we'll construct a full path that's URL encoded so we can easily
@@ -143,10 +146,11 @@ async fn gen_state_for_path(
             path: full_path_with_locale.clone(),
             state: Some(initial_state),
             global_state: global_state.clone(),
+            query: QueryParams::new(),
         };
         // Prerender the template using that state
         let prerendered = sycamore::render_to_string(|cx| {
-            template.render_for_template_server(page_props.clone(), cx, translator)
+            template.render_for_template_server(page_props.clone(), cx, translator, layout_states)
         });
         minify(&prerendered, true)?;
         // Write that prerendered HTML to a static file
@@ -182,10 +186,11 @@ async fn gen_state_for_path(
             path: full_path_with_locale.clone(),
             state: Some(initial_state),
             global_state: global_state.clone(),
+            query: QueryParams::new(),
         };
         // Prerender the template using that state
         let prerendered = sycamore::render_to_string(|cx| {
-            template.render_for_template_server(page_props.clone(), cx, translator)
+            template.render_for_template_server(page_props.clone(), cx, translator, layout_states)
         });
         minify(&prerendered, true)?;
         // Write that prerendered HTML to a static file
@@ -238,9 +243,10 @@ async fn gen_state_for_path(
             path: full_path_with_locale,
             state: None,
             global_state: global_state.clone(),
+            query: QueryParams::new(),
         };
         let prerendered = sycamore::render_to_string(|cx| {
-            template.render_for_template_server(page_props.clone(), cx, translator)
+            template.render_for_template_server(page_props.clone(), cx, translator, layout_states)
         });
         let head_str = template.render_head_str(page_props, translator);
         // Write that prerendered HTML to a static file
@@ -265,6 +271,7 @@ pub async fn build_template_and_get_cfg(
     translator: &Translator,
     (immutable_store, mutable_store): (&ImmutableStore, &impl MutableStore),
     global_state: &Option<String>,
+    layout_states: &LayoutStates,
     exporting: bool,
 ) -> Result<HashMap<String, String>, ServerError> {
     let mut render_cfg = HashMap::new();
@@ -276,6 +283,7 @@ pub async fn build_template_and_get_cfg(
         translator,
         (immutable_store, mutable_store),
         global_state,
+        layout_states,
         exporting,
     )
     .await?;
@@ -307,6 +315,36 @@ pub async fn build_template_and_get_cfg(
     Ok(render_cfg)
 }
 
+/// Generates the build states of all the layouts used by the given templates
+/// for a single locale, writing them to the immutable store. Since layouts can
+/// be shared between many templates, each one will only be built once.
+///
+/// This returns the states of all the layouts that generated one.
+pub async fn build_layouts_for_locale(
+    templates: &TemplateMap<SsrNode>,
+    translator: &Translator,
+    immutable_store: &ImmutableStore,
+) -> Result<LayoutStates, ServerError> {
+    let locale = translator.get_locale();
+    let mut layout_states = LayoutStates::new();
+    let mut built = HashSet::new();
+    for template in templates.values() {
+        for layout in template.get_layouts() {
+            // Layouts are identified by their names
+            if !layout.uses_build_state() || !built.insert(layout.get_name()) {
+                continue;
+            }
+            let state = layout.get_build_state(locale.clone()).await?;
+            immutable_store
+                .write(&layout.get_state_file(&locale), &state)
+                .await?;
+            layout_states.insert(layout.get_name(), state);
+        }
+    }
+
+    Ok(layout_states)
+}
+
 /// Runs the build process of building many different templates for a single
 /// locale. If you're not using i18n, provide a `Translator::empty()`
 /// for this. You should only build the most commonly used locales here (the
@@ -318,6 +356,9 @@ pub async fn build_templates_for_locale(
     global_state: &Option<String>,
     exporting: bool,
 ) -> Result<(), ServerError> {
+    // Layouts have to be built first, since they'll be rendered around the
+    // templates
+    let layout_states = build_layouts_for_locale(templates, translator, immutable_store).await?;
     // The render configuration stores a list of pages to the root paths of their
     // templates
     let mut render_cfg = HashMap::new();
@@ -329,6 +370,7 @@ pub async fn build_templates_for_locale(
             translator,
             (immutable_store, mutable_store),
             global_state,
+            &layout_states,
             exporting,
         ));
     }
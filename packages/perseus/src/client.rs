@@ -2,7 +2,9 @@ use crate::{
     checkpoint,
     plugins::PluginAction,
     router::{perseus_router, PerseusRouterProps},
+    service_worker::SERVICE_WORKER_FILE,
     template::TemplateNodeType,
+    utils::get_path_prefix_client,
 };
 use std::collections::HashMap;
 use wasm_bindgen::JsValue;
@@ -45,6 +47,19 @@ pub fn run_client<M: MutableStore, T: TranslationsManager>(
         .unwrap()
         .unwrap();
 
+    // If the app has a service worker, make sure it's registered (the browser will
+    // check for updates to it itself)
+    if app.get_service_worker_settings().is_some() {
+        let service_worker_url = format!("{}/{}", get_path_prefix_client(), SERVICE_WORKER_FILE);
+        // Older browsers won't have this at all, and they'll just have to stay online
+        let navigator = web_sys::window().unwrap().navigator();
+        if sycamore::rt::Reflect::has(&navigator, &JsValue::from_str("serviceWorker"))
+            .unwrap_or(false)
+        {
+            let _ = navigator.service_worker().register(&service_worker_url);
+        }
+    }
+
     // Set up the properties we'll pass to the router
     let router_props = PerseusRouterProps {
         locales: app.get_locales(),
@@ -52,6 +67,18 @@ pub fn run_client<M: MutableStore, T: TranslationsManager>(
         templates: app.get_templates_map(),
         render_cfg: get_render_cfg().expect("render configuration invalid or not injected"),
         pss_max_size: app.get_pss_max_size(),
+        pss_memory_policy: app.get_pss_memory_policy(),
+        plugins: plugins.clone(),
+        prefetch: app.get_prefetch_settings(),
+        #[cfg(all(feature = "hydrate", debug_assertions))]
+        hydration_check: None,
+        #[cfg(all(feature = "hsr", debug_assertions))]
+        hsr_store: app.get_hsr_store(),
+        #[cfg(feature = "global-state-sync")]
+        global_state_sync: app.get_global_state_sync(),
+        #[cfg(feature = "state-history")]
+        state_history_len: app.get_state_history_len(),
+        subscribe_to_state_pushes: app.get_state_publisher().is_some(),
     };
 
     // At this point, the user can already see something from the server-side
@@ -62,7 +89,36 @@ pub fn run_client<M: MutableStore, T: TranslationsManager>(
     // This top-level context is what we use for everything, allowing page state to
     // be registered and stored for the lifetime of the app
     #[cfg(feature = "hydrate")]
-    sycamore::hydrate_to(move |cx| perseus_router(cx, router_props), &root);
+    {
+        // In development, we check that the initial view is hydrated correctly, and we
+        // might have been asked to render it from scratch if it wasn't last time
+        #[cfg(debug_assertions)]
+        {
+            use crate::router::{take_hydration_fallback, HydrationCheck};
+
+            if take_hydration_fallback() {
+                root.set_inner_html("");
+                let root = sycamore::web::from_web_sys::<TemplateNodeType>(root.into());
+                // Without a hydration context, this will be rendered as usual (and we
+                // deliberately leak the scope, as `render_to` would)
+                let _ = sycamore::reactive::create_scope(|cx| {
+                    let view = perseus_router(cx, router_props);
+                    sycamore::utils::render::insert(cx, &root, view, None, None, false);
+                });
+                return Ok(());
+            }
+            let router_props = PerseusRouterProps {
+                hydration_check: Some(HydrationCheck::new(
+                    &root,
+                    app.get_hydration_mismatch_fallback(),
+                )),
+                ..router_props
+            };
+            sycamore::hydrate_to(move |cx| perseus_router(cx, router_props), &root);
+        }
+        #[cfg(not(debug_assertions))]
+        sycamore::hydrate_to(move |cx| perseus_router(cx, router_props), &root);
+    }
     #[cfg(not(feature = "hydrate"))]
     {
         // We have to delete the existing content before we can render the new stuff
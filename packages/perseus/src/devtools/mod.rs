@@ -0,0 +1,27 @@
+#[cfg(target_arch = "wasm32")]
+mod overlay;
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) use overlay::mount_devtools;
+
+/// The ID of the element the devtools overlay is rendered into, which the
+/// engine injects into the HTML shell.
+pub(crate) const DEVTOOLS_ID: &str = "__perseus_devtools";
+/// The styles for the devtools overlay, which the engine injects into the HTML
+/// shell. Everything is scoped to the overlay's container, so these can't
+/// affect the app.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const DEVTOOLS_CSS: &str = r#"
+#__perseus_devtools { position: fixed; bottom: 1rem; right: 1rem; z-index: 2147483647; font: 12px/1.4 monospace; color: #e5e7eb; }
+#__perseus_devtools button, #__perseus_devtools .__perseus_devtools_file { display: inline-block; font: inherit; cursor: pointer; color: inherit; background: #374151; border: 1px solid #4b5563; border-radius: 4px; padding: 2px 8px; margin: 2px 4px 2px 0; }
+#__perseus_devtools input[type="file"] { display: none; }
+#__perseus_devtools label { cursor: pointer; margin-right: 4px; }
+#__perseus_devtools .__perseus_devtools_toggle { float: right; }
+#__perseus_devtools .__perseus_devtools_hidden { display: none; }
+#__perseus_devtools .__perseus_devtools_panel { clear: both; width: 36rem; max-width: calc(100vw - 2rem); max-height: 70vh; overflow: auto; box-sizing: border-box; margin-bottom: 0.5rem; padding: 0.5rem; background: #111827; border: 1px solid #4b5563; border-radius: 6px; }
+#__perseus_devtools h2 { font-size: 1em; margin: 0.75rem 0 0.25rem; }
+#__perseus_devtools summary { cursor: pointer; }
+#__perseus_devtools pre { margin: 0; white-space: pre-wrap; word-break: break-all; }
+#__perseus_devtools textarea { display: block; width: 100%; min-height: 6rem; box-sizing: border-box; font: inherit; color: inherit; background: #1f2937; border: 1px solid #4b5563; }
+#__perseus_devtools .__perseus_devtools_status { color: #fbbf24; }
+"#;
@@ -0,0 +1,349 @@
+use super::DEVTOOLS_ID;
+use crate::router::RouteVerdict;
+use crate::state::{Freeze, FrozenApp, PageThawPrefs, PssState, ThawPrefs};
+use crate::template::{RenderCtx, TemplateNodeType};
+use fmterr::fmt_err;
+use std::cell::RefCell;
+use sycamore::prelude::*;
+use sycamore::rt::{JsCast, JsValue};
+use sycamore::utils::hydrate::with_no_hydration_context;
+use sycamore_futures::spawn_local_scoped;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, BlobPropertyBag, Event, HtmlAnchorElement, HtmlInputElement, Url};
+
+/// The name of the file snapshots of the app's state are downloaded as.
+const SNAPSHOT_FILE: &str = "perseus-snapshot.json";
+
+/// Everything the devtools overlay shows, as of a single point in time.
+#[derive(Clone, PartialEq)]
+struct Snapshot {
+    /// The router's load state.
+    load_state: String,
+    /// The last route verdict the router reached.
+    last_verdict: String,
+    /// The frozen global state, prettified, if there is any yet.
+    global_state: Option<String>,
+    /// The pages in the PSS, sorted by URL.
+    pages: Vec<PageSnapshot>,
+    /// The URLs of the pages in the PSS that can be evicted, from the oldest
+    /// to the newest.
+    order: Vec<String>,
+    /// The URLs of the pages that will never be evicted from the PSS.
+    keep_list: Vec<String>,
+    /// The URLs of the pages that have been preloaded globally.
+    preloaded: Vec<String>,
+    /// The URLs of the pages that have been preloaded for the current route.
+    route_preloaded: Vec<String>,
+}
+/// A single page in the PSS, as the devtools overlay shows it.
+#[derive(Clone, PartialEq)]
+struct PageSnapshot {
+    /// The page's URL.
+    url: String,
+    /// A summary of the page's template and what the PSS has for it.
+    summary: String,
+    /// The page's frozen state, prettified, if it has any.
+    state: Option<String>,
+}
+impl Snapshot {
+    /// Takes a snapshot of the app's state, without subscribing to any of it.
+    fn take(render_ctx: &RenderCtx) -> Self {
+        let load_state = format!(
+            "{:?}",
+            *render_ctx.router.get_load_state_rc().get_untracked()
+        );
+        let last_verdict = match render_ctx.router.get_last_verdict() {
+            Some(RouteVerdict::Found(info)) => format!(
+                "Found {{ path: {:?}, template: {:?}, locale: {:?}, was_incremental_match: {} }}",
+                info.path,
+                info.template.get_path(),
+                info.locale,
+                info.was_incremental_match
+            ),
+            Some(RouteVerdict::NotFound) => "NotFound".to_string(),
+            Some(RouteVerdict::LocaleDetection(dest)) => format!("LocaleDetection({:?})", dest),
+            None => "None".to_string(),
+        };
+        let global_state = render_ctx.global_state.0.borrow().freeze();
+        // This is what the global state freezes to before it's been initialized
+        let global_state = (global_state != "None").then(|| prettify(&global_state));
+
+        let pss = render_ctx.page_state_store.inspect();
+        let pages = pss
+            .pages
+            .into_iter()
+            .map(|page| {
+                let (state, state_summary) = match page.state {
+                    PssState::Some(state) => (Some(prettify(&state)), "state"),
+                    PssState::None => (None, "no state yet"),
+                    PssState::Never => (None, "takes no state"),
+                };
+                let summary = format!(
+                    "{} ({}; {}; {})",
+                    page.url,
+                    page.template.as_deref().unwrap_or("unknown template"),
+                    state_summary,
+                    if page.has_head {
+                        "head cached"
+                    } else {
+                        "head not cached"
+                    }
+                );
+                PageSnapshot {
+                    url: page.url,
+                    summary,
+                    state,
+                }
+            })
+            .collect();
+
+        Self {
+            load_state,
+            last_verdict,
+            global_state,
+            pages,
+            order: pss.order,
+            keep_list: pss.keep_list,
+            preloaded: pss.preloaded,
+            route_preloaded: pss.route_preloaded,
+        }
+    }
+}
+
+/// Mounts the devtools overlay into the container the engine injected into
+/// the HTML shell. This should be called once, when the router is created.
+pub(crate) fn mount_devtools<'a>(cx: Scope<'a>, render_ctx: &'a RenderCtx) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    // If the engine was built without devtools, there won't be anywhere to put them
+    let container = match document.get_element_by_id(DEVTOOLS_ID) {
+        Some(container) => container,
+        None => return,
+    };
+    // The overlay isn't part of what the engine rendered, so there's nothing to
+    // hydrate
+    let view = with_no_hydration_context(|| devtools_overlay(cx, render_ctx));
+    for node in view.flatten() {
+        container.append_child(&node.inner_element()).unwrap();
+    }
+}
+
+/// The devtools overlay itself, which lists everything in the app's state and
+/// lets it be edited, saved, and loaded.
+fn devtools_overlay<'a>(cx: Scope<'a>, render_ctx: &'a RenderCtx) -> View<TemplateNodeType> {
+    let is_open = create_signal(cx, false);
+    let is_live = create_signal(cx, true);
+    let status = create_signal(cx, String::new());
+    let snapshot = create_signal(cx, Snapshot::take(render_ctx));
+    let global_state = create_signal(
+        cx,
+        snapshot
+            .get_untracked()
+            .global_state
+            .clone()
+            .unwrap_or_default(),
+    );
+    // Changing this makes us subscribe to every page state again
+    let retrack = create_signal(cx, ());
+    let tracked_urls = create_ref(cx, RefCell::new(Vec::new()));
+
+    let refresh = move || {
+        let new_snapshot = Snapshot::take(render_ctx);
+        // If pages have been added to the PSS since we last subscribed to it, we need to
+        // subscribe to them too
+        let urls = new_snapshot
+            .pages
+            .iter()
+            .map(|page| page.url.clone())
+            .collect::<Vec<_>>();
+        if *is_live.get_untracked() && urls != *tracked_urls.borrow() {
+            retrack.set(());
+        }
+        // We don't want to wipe out edits to the global state unless it's actually changed
+        if new_snapshot.global_state != snapshot.get_untracked().global_state {
+            global_state.set(new_snapshot.global_state.clone().unwrap_or_default());
+        }
+        snapshot.set(new_snapshot);
+    };
+    create_effect(cx, move || {
+        retrack.track();
+        if !*is_open.get() || !*is_live.get() {
+            return;
+        }
+        render_ctx.router.get_load_state_rc().track();
+        render_ctx.global_state.0.borrow().track();
+        *tracked_urls.borrow_mut() = render_ctx.page_state_store.track();
+        // Pages register their states while they're being rendered, which happens just
+        // after the load state changes, so we wait until that's done
+        spawn_local_scoped(cx, async move { refresh() });
+    });
+
+    let has_global_state = create_memo(cx, || snapshot.get().global_state.is_some());
+    let pages = create_memo(cx, || snapshot.get().pages.clone());
+
+    let save_snapshot = move |_| {
+        let res = download_snapshot(&render_ctx.freeze());
+        status.set(match res {
+            Ok(()) => "Saved snapshot.".to_string(),
+            Err(err) => format!("Couldn't save snapshot: {:?}", err),
+        });
+    };
+    let load_snapshot = move |ev: Event| {
+        let input: HtmlInputElement = ev.target().unwrap().unchecked_into();
+        let file = match input.files().and_then(|files| files.get(0)) {
+            Some(file) => file,
+            None => return,
+        };
+        // This lets the same file be loaded again
+        input.set_value("");
+        spawn_local_scoped(cx, async move {
+            let res = match JsFuture::from(file.text()).await {
+                Ok(frozen_app) => render_ctx
+                    .thaw(
+                        &frozen_app.as_string().unwrap_or_default(),
+                        ThawPrefs {
+                            page: PageThawPrefs::IncludeAll,
+                            global_prefer_frozen: true,
+                        },
+                    )
+                    .map_err(|err| fmt_err(&err)),
+                Err(err) => Err(format!("{:?}", err)),
+            };
+            status.set(match res {
+                Ok(()) => "Loaded snapshot.".to_string(),
+                Err(err) => format!("Couldn't load snapshot: {}", err),
+            });
+        });
+    };
+
+    view! { cx,
+        div(class = if *is_open.get() { "__perseus_devtools_panel" } else { "__perseus_devtools_panel __perseus_devtools_hidden" }) {
+            div {
+                button(on:click = move |_| refresh()) { "Refresh" }
+                label {
+                    input(type = "checkbox", bind:checked = is_live)
+                    "Live"
+                }
+                button(on:click = save_snapshot) { "Save snapshot" }
+                label(class = "__perseus_devtools_file") {
+                    "Load snapshot"
+                    input(type = "file", accept = ".json,application/json", on:change = load_snapshot)
+                }
+            }
+            p(class = "__perseus_devtools_status") { (status.get()) }
+            h2 { "Router" }
+            pre { "Load state: " (snapshot.get().load_state.clone()) }
+            pre { "Last verdict: " (snapshot.get().last_verdict.clone()) }
+            h2 { "Global state" }
+            (if *has_global_state.get() {
+                view! { cx,
+                    textarea(bind:value = global_state)
+                    button(on:click = move |_| {
+                        let res = apply_edit(render_ctx, None, &global_state.get());
+                        status.set(edit_status(res));
+                    }) { "Apply" }
+                }
+            } else {
+                view! { cx, pre { "The global state hasn't been used yet." } }
+            })
+            h2 { (format!("Page state store ({} pages)", snapshot.get().pages.len())) }
+            Indexed(
+                iterable = pages,
+                view = move |cx, page| {
+                    let PageSnapshot {
+                        url,
+                        summary: description,
+                        state,
+                    } = page;
+                    match state {
+                        Some(state) => {
+                            let state = create_signal(cx, state);
+                            view! { cx,
+                                details {
+                                    summary { (description) }
+                                    textarea(bind:value = state)
+                                    button(on:click = move |_| {
+                                        let res = apply_edit(render_ctx, Some(&url), &state.get());
+                                        status.set(edit_status(res));
+                                    }) { "Apply" }
+                                }
+                            }
+                        }
+                        None => view! { cx, pre { (description) } },
+                    }
+                }
+            )
+            pre { "Eviction order (oldest first): " (snapshot.get().order.join(", ")) }
+            pre { "Keep list: " (snapshot.get().keep_list.join(", ")) }
+            pre { "Preloaded: " (snapshot.get().preloaded.join(", ")) }
+            pre { "Preloaded for this route: " (snapshot.get().route_preloaded.join(", ")) }
+        }
+        button(class = "__perseus_devtools_toggle", on:click = move |_| is_open.set(!*is_open.get())) { "Perseus" }
+    }
+}
+
+/// Thaws the given edited state into the app, as the state of the given page,
+/// or as the global state if no page is given. The current page will be
+/// re-rendered to use it, and any other page will use it when it's next
+/// rendered. As with any thawing, state that doesn't fit the page's state type
+/// will be silently ignored.
+fn apply_edit(render_ctx: &RenderCtx, url: Option<&str>, state: &str) -> Result<(), String> {
+    // This is the only problem we can catch before thawing
+    let state = serde_json::from_str::<serde_json::Value>(state)
+        .map_err(|err| format!("invalid JSON: {}", err))?
+        .to_string();
+    let mut frozen_app = serde_json::from_str::<FrozenApp>(&render_ctx.freeze()).unwrap();
+    let thaw_prefs = match url {
+        Some(url) => {
+            frozen_app.page_state_store.insert(url.to_string(), state);
+            ThawPrefs {
+                page: PageThawPrefs::Include(vec![url.to_string()]),
+                global_prefer_frozen: false,
+            }
+        }
+        None => {
+            frozen_app.global_state = state;
+            ThawPrefs {
+                page: PageThawPrefs::Include(Vec::new()),
+                global_prefer_frozen: true,
+            }
+        }
+    };
+
+    render_ctx
+        .thaw(&serde_json::to_string(&frozen_app).unwrap(), thaw_prefs)
+        .map_err(|err| fmt_err(&err))
+}
+
+/// Describes the result of applying an edit, for the status line.
+fn edit_status(res: Result<(), String>) -> String {
+    match res {
+        Ok(()) => "Applied (if the state doesn't fit the page's state type, it'll be ignored)."
+            .to_string(),
+        Err(err) => format!("Couldn't apply: {}", err),
+    }
+}
+
+/// Has the browser download the given frozen app as a file.
+fn download_snapshot(frozen_app: &str) -> Result<(), JsValue> {
+    let parts = js_sys::Array::of1(&JsValue::from_str(frozen_app));
+    let opts = BlobPropertyBag::new();
+    opts.set_type("application/json");
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &opts)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let document = web_sys::window().unwrap().document().unwrap();
+    let anchor: HtmlAnchorElement = document.create_element("a")?.unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(SNAPSHOT_FILE);
+    anchor.click();
+
+    Url::revoke_object_url(&url)
+}
+
+/// Prettifies the given frozen state, if it's valid JSON.
+fn prettify(state: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(state)
+        .ok()
+        .and_then(|state| serde_json::to_string_pretty(&state).ok())
+        .unwrap_or_else(|| state.to_string())
+}
@@ -3,9 +3,13 @@ use crate::{
     errors::{EngineError, ServerError},
     i18n::TranslationsManager,
     plugins::PluginAction,
-    stores::MutableStore,
+    server::{get_render_cfg, BundleManifest},
+    service_worker::{write_service_worker, ServiceWorkerProps},
+    stores::{ImmutableStore, MutableStore},
+    utils::get_path_prefix_server,
     PerseusAppBase, SsrNode,
 };
+use std::collections::HashSet;
 use std::rc::Rc;
 
 /// Builds the app, calling all necessary plugin opportunities. This works
@@ -46,6 +50,13 @@ pub async fn build<M: MutableStore, T: TranslationsManager>(
     // All these parameters can be modified by `PerseusApp` and plugins, so there's
     // no point in having a plugin opportunity here
     let templates_map = app.get_templates_map();
+    // These are needed to generate the service worker
+    let service_worker = app.get_service_worker_settings();
+    let index_view_str = app.get_index_view_str();
+    let root_id = app.get_root();
+    let error_pages = app.get_error_pages();
+    let static_dir = app.get_static_dir();
+    let static_aliases = app.get_static_aliases();
 
     // We have to get the translations manager last, because it consumes everything
     let translations_manager = app.get_translations_manager().await;
@@ -60,6 +71,50 @@ pub async fn build<M: MutableStore, T: TranslationsManager>(
         exporting: false,
     })
     .await;
+    let res = async {
+        res?;
+        // The CLI builds the Wasm bundle before it gets here, so we can hash it for
+        // the server now
+        let bundles = write_bundle_manifest(&immutable_store).await?;
+        // The service worker has to know about the bundles, so it can only be
+        // generated now
+        if let Some(settings) = &service_worker {
+            let index_view = PerseusAppBase::<SsrNode, M, T>::get_html_shell(
+                index_view_str,
+                &root_id,
+                &immutable_store,
+                &plugins,
+                bundles.as_ref(),
+            )
+            .await;
+            // Pages that use request state mustn't be cached
+            let request_state_templates = templates_map
+                .iter()
+                .filter(|(_, template)| template.uses_request_state())
+                .map(|(path, _)| path.clone())
+                .collect::<HashSet<_>>();
+            let render_cfg = get_render_cfg(&immutable_store).await?;
+            write_service_worker(
+                ServiceWorkerProps {
+                    settings,
+                    render_cfg: &render_cfg,
+                    request_state_templates: &request_state_templates,
+                    locales: &locales,
+                    error_pages: &error_pages,
+                    html_shell: &index_view,
+                    bundles: bundles.as_ref(),
+                    static_dir: &static_dir,
+                    static_aliases: &static_aliases,
+                    path_prefix: &get_path_prefix_server(),
+                },
+                &immutable_store,
+            )
+            .await?;
+        }
+
+        Ok::<(), ServerError>(())
+    }
+    .await;
     if let Err(err) = res {
         let err: Rc<EngineError> = Rc::new(err.into());
         plugins
@@ -79,3 +134,29 @@ pub async fn build<M: MutableStore, T: TranslationsManager>(
         Ok(())
     }
 }
+
+/// Hashes the app's bundles and records them in the immutable store, so the
+/// server can serve them from content-hashed URLs. If the bundles haven't been
+/// built (e.g. if we're only checking the app's page generation), we'll remove
+/// any old manifest instead, and the server will serve the bundles without
+/// content hashes.
+async fn write_bundle_manifest(
+    immutable_store: &ImmutableStore,
+) -> Result<Option<BundleManifest>, ServerError> {
+    let pkg = format!("{}/pkg", immutable_store.get_path());
+    match BundleManifest::new(
+        &format!("{}/perseus_engine.js", pkg),
+        &format!("{}/perseus_engine_bg.wasm", pkg),
+        &format!("{}/perseus_engine_bg.wasm.js", pkg),
+        &format!("{}/snippets", pkg),
+    ) {
+        Ok(bundles) => {
+            bundles.write(immutable_store).await?;
+            Ok(Some(bundles))
+        }
+        Err(_) => {
+            BundleManifest::remove(immutable_store).await?;
+            Ok(None)
+        }
+    }
+}
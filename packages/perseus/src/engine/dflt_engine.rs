@@ -109,13 +109,21 @@ where
         EngineOperation::Serve => {
             // To reduce friction for default servers and user-made servers, we
             // automatically do the boilerplate that all servers would have to do
-            let props = get_props(app());
+            let props = match get_props(app()) {
+                Ok(props) => props,
+                Err(err) => {
+                    eprintln!("{}", fmt_err(&err));
+                    return 1;
+                }
+            };
             // This returns a `(String, u16)` of the host and port for maximum compatibility
             let addr = get_host_and_port();
             // In production, give the user a heads up that something's actually happening
             #[cfg(not(debug_assertions))]
             println!(
-                "Your production app is now live on <http://{host}:{port}>! To change this, re-run this command with different settings for the `PERSEUS_HOST` and `PERSEUS_PORT` environment variables.\nNote that the above address will not reflect any domains configured.",
+                "Your production app is now live on <{scheme}://{host}:{port}>! To change this, re-run this command with different settings for the `PERSEUS_HOST` and `PERSEUS_PORT` environment variables.\nNote that the above address will not reflect any domains configured.",
+                // The default servers will serve over HTTPS if TLS has been configured
+                scheme = if crate::server::TlsConfig::from_env().is_some() { "https" } else { "http" },
                 host = &addr.0,
                 port = &addr.1
             );
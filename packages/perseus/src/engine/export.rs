@@ -3,11 +3,13 @@ use crate::errors::ServerError;
 use crate::export::{export_app, ExportProps};
 use crate::{
     plugins::{PluginAction, Plugins},
+    server::{get_render_cfg, BundleManifest},
+    service_worker::{write_service_worker, ServiceWorkerProps},
     utils::get_path_prefix_server,
     PerseusApp, SsrNode,
 };
 use fs_extra::dir::{copy as copy_dir, CopyOptions};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
 use std::rc::Rc;
@@ -80,6 +82,10 @@ async fn build_and_export<M: MutableStore, T: TranslationsManager>(
     let templates_map = app.get_templates_map();
     let index_view_str = app.get_index_view_str();
     let root_id = app.get_root();
+    let service_worker = app.get_service_worker_settings();
+    let error_pages = app.get_error_pages();
+    let static_dir = app.get_static_dir();
+    let static_aliases = app.get_static_aliases();
     // This consumes `self`, so we get it finally
     let translations_manager = app.get_translations_manager().await;
 
@@ -114,8 +120,82 @@ async fn build_and_export<M: MutableStore, T: TranslationsManager>(
     // (which needs access to the render config, generated in the above build step)
     // It doesn't matter if the type parameters here are wrong, this function
     // doesn't use them
-    let index_view =
-        PerseusApp::get_html_shell(index_view_str, &root_id, &immutable_store, &plugins).await;
+    //
+    // We also hash the bundles here (the CLI makes sure they've been built by now), and
+    // record that for the CLI so it can put them in the right places
+    let manifest_res = match BundleManifest::new(
+        &format!("{}/pkg/perseus_engine.js", immutable_store.get_path()),
+        &format!("{}/pkg/perseus_engine_bg.wasm", immutable_store.get_path()),
+        &format!(
+            "{}/pkg/perseus_engine_bg.wasm.js",
+            immutable_store.get_path()
+        ),
+        &format!("{}/pkg/snippets", immutable_store.get_path()),
+    ) {
+        Ok(bundles) => bundles.write(&immutable_store).await.map(|_| bundles),
+        Err(err) => Err(err.into()),
+    };
+    let bundles = match manifest_res {
+        Ok(bundles) => bundles,
+        Err(err) => {
+            let err: Rc<EngineError> = Rc::new(err.into());
+            plugins
+                .functional_actions
+                .export_actions
+                .after_failed_export
+                .run(err.clone(), plugins.get_plugin_data());
+            return Err(err);
+        }
+    };
+    let index_view = PerseusApp::get_html_shell(
+        index_view_str,
+        &root_id,
+        &immutable_store,
+        &plugins,
+        Some(&bundles),
+    )
+    .await;
+    // The service worker will be copied into the exported files with the other
+    // static aliases
+    if let Some(settings) = &service_worker {
+        // Exporting will have failed if any templates used request state, but we check
+        // anyway
+        let request_state_templates = templates_map
+            .iter()
+            .filter(|(_, template)| template.uses_request_state())
+            .map(|(path, _)| path.clone())
+            .collect::<HashSet<_>>();
+        let sw_res = match get_render_cfg(&immutable_store).await {
+            Ok(render_cfg) => {
+                write_service_worker(
+                    ServiceWorkerProps {
+                        settings,
+                        render_cfg: &render_cfg,
+                        request_state_templates: &request_state_templates,
+                        locales: &locales,
+                        error_pages: &error_pages,
+                        html_shell: &index_view,
+                        bundles: Some(&bundles),
+                        static_dir: &static_dir,
+                        static_aliases: &static_aliases,
+                        path_prefix: &get_path_prefix_server(),
+                    },
+                    &immutable_store,
+                )
+                .await
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = sw_res {
+            let err: Rc<EngineError> = Rc::new(err.into());
+            plugins
+                .functional_actions
+                .export_actions
+                .after_failed_export
+                .run(err.clone(), plugins.get_plugin_data());
+            return Err(err);
+        }
+    }
     // Turn the build artifacts into self-contained static files
     let export_res = export_app(ExportProps {
         templates: &templates_map,
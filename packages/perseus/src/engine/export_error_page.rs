@@ -1,6 +1,10 @@
 use crate::{
-    errors::EngineError, i18n::TranslationsManager, plugins::PluginAction,
-    server::build_error_page, stores::MutableStore, PerseusApp, PerseusAppBase, SsrNode,
+    errors::EngineError,
+    i18n::TranslationsManager,
+    plugins::PluginAction,
+    server::{build_error_page, BundleManifest},
+    stores::MutableStore,
+    PerseusApp, PerseusAppBase, SsrNode,
 };
 use std::{fs, rc::Rc};
 
@@ -27,8 +31,16 @@ pub async fn export_error_page(
     // We assume the app has already been built before running this (so the render
     // config must be available) It doesn't matter if the type parameters here
     // are wrong, this function doesn't use them
-    let html_shell =
-        PerseusApp::get_html_shell(index_view_str, &root_id, &immutable_store, &plugins).await;
+    // If the app was exported, we'll use the same bundle URLs as the rest of it
+    let bundles = BundleManifest::read(&immutable_store).await;
+    let html_shell = PerseusApp::get_html_shell(
+        index_view_str,
+        &root_id,
+        &immutable_store,
+        &plugins,
+        bundles.as_ref(),
+    )
+    .await;
 
     plugins
         .functional_actions
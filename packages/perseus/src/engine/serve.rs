@@ -1,6 +1,7 @@
+use crate::errors::ServeError;
 use crate::i18n::TranslationsManager;
 use crate::plugins::PluginAction;
-use crate::server::{ServerOptions, ServerProps};
+use crate::server::{BundleManifest, ServerOptions, ServerProps};
 use crate::stores::MutableStore;
 use crate::PerseusAppBase;
 use futures::executor::block_on;
@@ -26,7 +27,9 @@ pub(crate) fn get_host_and_port() -> (String, u16) {
 }
 
 /// Gets the properties to pass to the server, invoking plugin opportunities as
-/// necessary. This is entirely engine-agnostic.
+/// necessary. This is entirely engine-agnostic. This will fail if the app's
+/// configuration can't be served (e.g. if its content security policy is
+/// invalid).
 ///
 /// WARNING: in production, this will automatically set the working directory
 /// to be the parent of the actual binary! This means that disabling
@@ -34,7 +37,7 @@ pub(crate) fn get_host_and_port() -> (String, u16) {
 /// errors! You have been warned!
 pub(crate) fn get_props<M: MutableStore, T: TranslationsManager>(
     app: PerseusAppBase<SsrNode, M, T>,
-) -> ServerProps<M, T> {
+) -> Result<ServerProps<M, T>, ServeError> {
     if !cfg!(debug_assertions) {
         let binary_loc = env::current_exe().unwrap();
         let binary_dir = binary_loc.parent().unwrap(); // It's a file, there's going to be a parent if we're working on anything close
@@ -55,6 +58,21 @@ pub(crate) fn get_props<M: MutableStore, T: TranslationsManager>(
     let app_root = app.get_root();
     let immutable_store = app.get_immutable_store();
     let index_view_str = app.get_index_view_str();
+
+    let js_bundle = "dist/pkg/perseus_engine.js".to_string();
+    // Our crate has the same name, so this will be predictable
+    let wasm_bundle = "dist/pkg/perseus_engine_bg.wasm".to_string();
+    let wasm_js_bundle = "dist/pkg/perseus_engine_bg.wasm.js".to_string();
+    let snippets = "dist/pkg/snippets".to_string();
+    // The bundles were hashed when the app was built, so they can be served from
+    // URLs that change with their contents (if they weren't, we'll just serve them
+    // from the usual places, but we'll let the user know, since that means browsers
+    // can't cache them properly)
+    let bundles = block_on(BundleManifest::read(&immutable_store));
+    if bundles.is_none() {
+        eprintln!("no bundle manifest found, so bundles will be served without content hashes (has the app been built with `perseus build`?)");
+    }
+
     // By the time this binary is being run, the app has already been built be the
     // CLI (hopefully!), so we can depend on access to the render config
     let index_view = block_on(PerseusAppBase::<SsrNode, M, T>::get_html_shell(
@@ -62,37 +80,48 @@ pub(crate) fn get_props<M: MutableStore, T: TranslationsManager>(
         &app_root,
         &immutable_store,
         &plugins,
+        bundles.as_ref(),
     ));
 
+    let error_pages = app.get_error_pages();
+    let static_aliases = app.get_static_aliases();
+    // We check this once here so that an invalid policy can't fail every request
+    let csp = app.get_csp();
+    if let Some(csp) = &csp {
+        csp.validate()?;
+    }
+
     let opts = ServerOptions {
         // We don't support setting some attributes from `wasm-pack` through plugins/`PerseusApp`
         // because that would require CLI changes as well (a job for an alternative engine)
         html_shell: index_view,
-        js_bundle: "dist/pkg/perseus_engine.js".to_string(),
-        // Our crate has the same name, so this will be predictable
-        wasm_bundle: "dist/pkg/perseus_engine_bg.wasm".to_string(),
+        js_bundle,
+        wasm_bundle,
         // This probably won't exist, but on the off chance that the user needs to support older
         // browsers, we'll provide it anyway
-        wasm_js_bundle: "dist/pkg/perseus_engine_bg.wasm.js".to_string(),
+        wasm_js_bundle,
         templates_map: app.get_atomic_templates_map(),
         locales: app.get_locales(),
         root_id: app_root,
-        snippets: "dist/pkg/snippets".to_string(),
-        error_pages: Arc::new(app.get_error_pages()),
+        snippets,
+        bundles,
+        error_pages: Arc::new(error_pages),
         // This will be available directly at `/.perseus/static`
         static_dir: if fs::metadata(&static_dir_path).is_ok() {
             Some(static_dir_path)
         } else {
             None
         },
-        static_aliases: app.get_static_aliases(),
+        static_aliases,
+        csp,
+        state_publisher: app.get_state_publisher(),
     };
 
-    ServerProps {
+    Ok(ServerProps {
         opts,
         immutable_store,
         mutable_store: app.get_mutable_store(),
         global_state_creator: app.get_global_state_creator(),
         translations_manager: block_on(app.get_translations_manager()),
-    }
+    })
 }
@@ -80,6 +80,23 @@ pub enum ClientError {
     PreloadLocaleDetection,
     #[error("the given path for preloading was not found")]
     PreloadNotFound,
+    #[error(transparent)]
+    FrozenStateStoreError(#[from] FrozenStateStoreError),
+}
+
+/// Errors that can occur while interacting with a frozen state store.
+#[derive(Error, Debug)]
+pub enum FrozenStateStoreError {
+    #[cfg(all(feature = "idb-freezing", target_arch = "wasm32"))]
+    #[error(transparent)]
+    IdbError(#[from] crate::state::IdbError),
+    #[error("web storage is unavailable (it may have been disabled by the user)")]
+    StorageUnavailable,
+    /// This converts from a `JsValue` or the like.
+    #[error("the following error occurred while interfacing with JavaScript: {0}")]
+    Js(String),
+    #[error(transparent)]
+    FetchError(#[from] FetchError),
 }
 
 /// Errors that can occur in the build process or while the server is running.
@@ -97,6 +114,13 @@ pub enum ServerError {
         #[source]
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+    #[error("render function '{fn_name}' in template '{template_name}' timed out")]
+    StateFnTimedOut {
+        fn_name: String,
+        template_name: String,
+        // The status code to respond with, which depends on the template's timeout fallback
+        status: u16,
+    },
     // We should only get a failure to minify if the user has given invalid HTML, or if Sycamore
     // stuffed up somewhere
     #[error("failed to minify html (you can disable the `minify` flag to avoid this; this is very likely a Sycamore bug, unless you've provided invalid custom HTML)")]
@@ -127,6 +151,7 @@ pub fn err_to_status_code(err: &ServerError) -> u16 {
             ErrorCause::Client(code) => code.unwrap_or(400),
             ErrorCause::Server(code) => code.unwrap_or(500),
         },
+        ServerError::StateFnTimedOut { status, .. } => *status,
         // Any other errors go to a 500, they'll be misconfigurations or internal server errors
         _ => 500,
     }
@@ -161,6 +186,12 @@ pub enum StoreError {
         #[source]
         source: Box<dyn std::error::Error + Send + Sync>,
     },
+    #[error("asset '{name}' couldn't be deleted from store")]
+    DeleteFailed {
+        name: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
 }
 
 /// Errors that can occur while fetching a resource from the server.
@@ -205,6 +236,12 @@ pub enum BuildError {
         "invalid indicator '{indicator}' in time string (must be one of: s, m, h, d, w, M, y)"
     )]
     InvalidDatetimeIntervalIndicator { indicator: String },
+    #[error("bundle couldn't be read from '{path}' (have you built your app?)")]
+    ReadBundleFailed {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
     #[error("asset 'render_cfg.json' invalid or corrupted (try cleaning all assets)")]
     RenderCfgInvalid {
         #[from]
@@ -229,6 +266,8 @@ pub enum ServeError {
     PageNotFound { path: String },
     #[error("both build and request states were defined for a template when only one or fewer were expected (should it be able to amalgamate states?)")]
     BothStatesDefined,
+    #[error("content security policy '{header}' isn't a valid header value")]
+    InvalidCsp { header: String },
     #[cfg(not(target_arch = "wasm32"))]
     #[error("couldn't parse revalidation datetime (try cleaning all assets)")]
     BadRevalidate {
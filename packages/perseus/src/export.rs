@@ -3,7 +3,7 @@ use crate::i18n::{Locales, TranslationsManager};
 use crate::page_data::PageDataPartial;
 use crate::server::{get_render_cfg, HtmlShell};
 use crate::stores::ImmutableStore;
-use crate::template::TemplateMap;
+use crate::template::{LayoutStates, TemplateMap};
 use crate::{page_data::PageData, SsrNode};
 use futures::future::{try_join, try_join_all};
 
@@ -11,6 +11,7 @@ use futures::future::{try_join, try_join_all};
 pub async fn get_static_page_data(
     path: &str,
     has_state: bool,
+    layout_states: LayoutStates,
     immutable_store: &ImmutableStore,
 ) -> Result<PageData, ServerError> {
     // Get the partial HTML content and a state to go with it (if applicable)
@@ -33,6 +34,7 @@ pub async fn get_static_page_data(
         content,
         state,
         head,
+        layout_states,
     })
 }
 
@@ -194,6 +196,7 @@ pub async fn export_path(
             let page_data = get_static_page_data(
                 &format!("{}-{}", locale, &path_encoded),
                 has_state,
+                template.get_layout_states(locale, immutable_store).await?,
                 immutable_store,
             )
             .await?;
@@ -206,6 +209,7 @@ pub async fn export_path(
             // not using i18n
             let full_html = html_shell
                 .clone()
+                .for_template(template)
                 .page_data(&page_data, global_state, &translations)
                 .to_string();
             immutable_store
@@ -220,6 +224,7 @@ pub async fn export_path(
             let partial_page_data = PageDataPartial {
                 state: page_data.state,
                 head: page_data.head,
+                layout_states: page_data.layout_states,
             };
             let partial = serde_json::to_string(&partial_page_data).unwrap();
             immutable_store
@@ -233,6 +238,9 @@ pub async fn export_path(
         let page_data = get_static_page_data(
             &format!("{}-{}", locales.default, &path_encoded),
             has_state,
+            template
+                .get_layout_states(&locales.default, immutable_store)
+                .await?,
             immutable_store,
         )
         .await?;
@@ -241,6 +249,7 @@ pub async fn export_path(
         // not using i18n
         let full_html = html_shell
             .clone()
+            .for_template(template)
             .page_data(&page_data, global_state, "")
             .to_string();
         // We don't add an extension because this will be queried directly by the
@@ -254,6 +263,7 @@ pub async fn export_path(
         let partial_page_data = PageDataPartial {
             state: page_data.state,
             head: page_data.head,
+            layout_states: page_data.layout_states,
         };
         let partial = serde_json::to_string(&partial_page_data).unwrap();
         immutable_store
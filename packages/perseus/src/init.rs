@@ -1,12 +1,18 @@
 #[cfg(not(target_arch = "wasm32"))]
-use crate::server::{get_render_cfg, HtmlShell};
+use crate::server::{get_render_cfg, BundleManifest, ContentSecurityPolicy, HtmlShell};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::service_worker::SERVICE_WORKER_FILE;
+#[cfg(feature = "global-state-sync")]
+use crate::state::GlobalStateSync;
 use crate::stores::ImmutableStore;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::utils::get_path_prefix_server;
 use crate::{
     i18n::{Locales, TranslationsManager},
     plugins::{PluginAction, Plugins},
-    state::GlobalStateCreator,
+    router::PrefetchSettings,
+    service_worker::ServiceWorkerSettings,
+    state::{FrozenStateStore, GlobalStateCreator, PssMemoryPolicy, StatePublisher},
     stores::MutableStore,
     template::TemplateMap,
     ErrorPages, Html, SsrNode, Template,
@@ -119,6 +125,30 @@ pub struct PerseusAppBase<G: Html, M: MutableStore, T: TranslationsManager> {
     error_pages: ErrorPagesGetter<G>,
     /// The maximum size for the page state store.
     pss_max_size: usize,
+    /// The policy for evicting pages from the page state store based on their
+    /// estimated sizes, if there is one.
+    pss_memory_policy: Option<PssMemoryPolicy>,
+    /// The settings for automatically prefetching linked pages, if that's
+    /// enabled.
+    prefetch: Option<PrefetchSettings>,
+    /// The settings for the app's service worker, if it should have one.
+    service_worker: Option<ServiceWorkerSettings>,
+    /// Whether or not pages that are hydrated incorrectly in development should
+    /// be rendered again from scratch.
+    hydration_mismatch_fallback: bool,
+    /// The frozen state store hot state reloading should use, if it shouldn't
+    /// use the default.
+    hsr_store: Option<Rc<dyn FrozenStateStore>>,
+    /// The settings for synchronizing global state across tabs, if that's
+    /// enabled.
+    #[cfg(feature = "global-state-sync")]
+    global_state_sync: Option<GlobalStateSync>,
+    /// The maximum number of entries to record in each timeline of the state
+    /// history, if that's enabled.
+    #[cfg(feature = "state-history")]
+    state_history_len: Option<usize>,
+    /// The publisher for pushing state to the browser, if the app uses one.
+    state_publisher: Option<StatePublisher>,
     /// The global state creator for the app.
     // This is wrapped in an `Arc` so we can pass it around on the engine-side (which is solely for
     // Actix's benefit...)
@@ -154,6 +184,9 @@ pub struct PerseusAppBase<G: Html, M: MutableStore, T: TranslationsManager> {
     /// here will only be used if it exists.
     #[cfg(not(target_arch = "wasm32"))]
     static_dir: String,
+    /// The Content Security Policy the app will be served with, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    csp: Option<Arc<ContentSecurityPolicy>>,
     // We need this on the client-side to account for the unused type parameters
     #[cfg(target_arch = "wasm32")]
     _marker: PhantomData<(M, T)>,
@@ -282,6 +315,16 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
             // building
             error_pages: ErrorPagesGetter(Box::new(ErrorPages::default)),
             pss_max_size: DFLT_PSS_MAX_SIZE,
+            pss_memory_policy: None,
+            prefetch: None,
+            service_worker: None,
+            hydration_mismatch_fallback: false,
+            hsr_store: None,
+            #[cfg(feature = "global-state-sync")]
+            global_state_sync: None,
+            #[cfg(feature = "state-history")]
+            state_history_len: None,
+            state_publisher: None,
             #[cfg(not(target_arch = "wasm32"))]
             global_state_creator: Arc::new(GlobalStateCreator::default()),
             // By default, we'll disable i18n (as much as I may want more websites to support more
@@ -306,6 +349,9 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
             index_view: DFLT_INDEX_VIEW.to_string(),
             #[cfg(not(target_arch = "wasm32"))]
             static_dir: "./static".to_string(),
+            // Apps that want a CSP have to opt in, since it's very easy to break things with one
+            #[cfg(not(target_arch = "wasm32"))]
+            csp: None,
             #[cfg(target_arch = "wasm32")]
             _marker: PhantomData,
         }
@@ -324,6 +370,16 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
             // building
             error_pages: ErrorPagesGetter(Box::new(ErrorPages::default)),
             pss_max_size: DFLT_PSS_MAX_SIZE,
+            pss_memory_policy: None,
+            prefetch: None,
+            service_worker: None,
+            hydration_mismatch_fallback: false,
+            hsr_store: None,
+            #[cfg(feature = "global-state-sync")]
+            global_state_sync: None,
+            #[cfg(feature = "state-history")]
+            state_history_len: None,
+            state_publisher: None,
             // By default, we'll disable i18n (as much as I may want more websites to support more
             // languages...)
             locales: Locales {
@@ -394,6 +450,34 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
         }
         self
     }
+    /// Sets a function that will produce the `Content-Security-Policy` header
+    /// for initial loads from a per-request nonce. Once this is set, every
+    /// inline script Perseus injects into the HTML shell will carry that
+    /// nonce, so your policy won't need `unsafe-inline`. See
+    /// `perseus::server::default_csp` for a sensible starting point.
+    ///
+    /// Note that exported apps can't use nonces, since there are no requests
+    /// to generate them for.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn content_security_policy(
+        mut self,
+        val: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.csp = Some(Arc::new(ContentSecurityPolicy::new(val)));
+        self
+    }
+    /// Sets a function that will produce the `Content-Security-Policy` header
+    /// for initial loads from a per-request nonce. Once this is set, every
+    /// inline script Perseus injects into the HTML shell will carry that
+    /// nonce, so your policy won't need `unsafe-inline`. See
+    /// `perseus::server::default_csp` for a sensible starting point.
+    ///
+    /// Note that exported apps can't use nonces, since there are no requests
+    /// to generate them for.
+    #[cfg(target_arch = "wasm32")]
+    pub fn content_security_policy(self, _val: impl Fn(&str) -> String + 'static) -> Self {
+        self
+    }
     /// Sets the locales information for the app. The first argument is the
     /// default locale (used as a fallback for users with no locale preferences
     /// set in their browsers), and the second is a list of other locales
@@ -562,6 +646,83 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
         self.pss_max_size = val;
         self
     }
+    /// Sets a policy for evicting pages from the page state store based on
+    /// how much memory their states are likely to take up, with optional
+    /// per-template weights and caps. This applies on top of the page count
+    /// limit set by `.pss_max_size()`, and is useful if some of your pages have
+    /// very large states. See [`PssMemoryPolicy`] for the details.
+    ///
+    /// By default, no such policy is applied.
+    pub fn pss_memory_policy(mut self, val: PssMemoryPolicy) -> Self {
+        self.pss_memory_policy = Some(val);
+        self
+    }
+    /// Enables automatic prefetching of the pages that links in your app point
+    /// to, when they're hovered, focused, or scrolled into view (depending on
+    /// the given settings). This makes navigation feel near-instant, at the
+    /// cost of some extra requests. Individual links can opt out of this with
+    /// the `data-perseus-prefetch="false"` attribute.
+    pub fn prefetch_links(mut self, val: PrefetchSettings) -> Self {
+        self.prefetch = Some(val);
+        self
+    }
+    /// Gives your app a service worker, which will let it work offline. This
+    /// is generated whenever your app is built or exported, and registered
+    /// automatically once your app has loaded in the browser. See
+    /// [`ServiceWorkerSettings`] for details.
+    pub fn service_worker(mut self, val: ServiceWorkerSettings) -> Self {
+        self.service_worker = Some(val);
+        self
+    }
+    /// Sets whether or not pages should be rendered again from scratch in the
+    /// browser if hydrating them produces different HTML to what the engine
+    /// rendered. With the `hydrate` feature enabled, development builds will
+    /// always check for and report such mismatches, but, by default, they'll
+    /// leave the page as hydrated. If this is set to `true`, the page will be
+    /// reloaded and rendered without hydration instead, which can be useful
+    /// for telling hydration bugs apart from bugs in your own code.
+    ///
+    /// This has no effect in production builds.
+    pub fn hydration_mismatch_fallback(mut self, val: bool) -> Self {
+        self.hydration_mismatch_fallback = val;
+        self
+    }
+    /// Sets the frozen state store that hot state reloading will use to keep
+    /// your app's state across reloads in development. By default, this is a
+    /// dedicated IndexedDB database, which is separate from anything you might
+    /// be freezing state to yourself.
+    pub fn hsr_store(mut self, val: impl FrozenStateStore + 'static) -> Self {
+        self.hsr_store = Some(Rc::new(val));
+        self
+    }
+    /// Enables synchronizing your app's global state across all the tabs of it
+    /// the user has open, using the given settings. Whenever the global state
+    /// changes in one tab, the others will thaw the new state and re-render
+    /// their current pages. See [`GlobalStateSync`] for the details.
+    #[cfg(feature = "global-state-sync")]
+    pub fn sync_global_state(mut self, val: GlobalStateSync) -> Self {
+        self.global_state_sync = Some(val);
+        self
+    }
+    /// Enables recording the history of your app's page and global state,
+    /// keeping up to the given number of entries for each page and for the
+    /// global state. Changes can then be undone and redone through
+    /// `.history` on the render context. See
+    /// [`StateHistory`](crate::state::StateHistory) for the details.
+    #[cfg(feature = "state-history")]
+    pub fn record_state_history(mut self, max_len: usize) -> Self {
+        self.state_history_len = Some(max_len);
+        self
+    }
+    /// Sets the publisher that can be used to push new state to the pages
+    /// users have open in their browsers. On the engine-side, the server will
+    /// send browsers anything published with this (or any of its clones), and,
+    /// in the browser, setting this will make the app subscribe to those
+    /// updates. See [`StatePublisher`] for the details.
+    pub fn state_publisher(mut self, val: StatePublisher) -> Self {
+        self.state_publisher = Some(val);
+        self
+    }
     // Getters
     /// Gets the HTML ID of the `<div>` at which to insert Perseus.
     pub fn get_root(&self) -> String {
@@ -598,12 +759,16 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
     /// the translations manager, consuming `self`). As inconvenient as this
     /// is, it's necessitated, otherwise exporting would try to access the built
     /// app before it had actually been built.
+    ///
+    /// If a [`BundleManifest`] is provided, the shell will load the bundles
+    /// from their content-hashed URLs.
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn get_html_shell(
         index_view_str: String,
         root: &str,
         immutable_store: &ImmutableStore,
         plugins: &Plugins<G>,
+        bundles: Option<&BundleManifest>,
     ) -> HtmlShell {
         // Construct an HTML shell
         let mut html_shell = HtmlShell::new(
@@ -615,6 +780,7 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
                 .await
                 .expect("Couldn't get render configuration!"),
             &get_path_prefix_server(),
+            bundles,
         );
 
         // Apply the myriad plugin actions to the HTML shell (replacing the whole thing
@@ -770,12 +936,59 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
     pub fn get_pss_max_size(&self) -> usize {
         self.pss_max_size
     }
+    /// Gets the policy for evicting pages from the page state store based on
+    /// their estimated sizes, if there is one.
+    pub fn get_pss_memory_policy(&self) -> Option<PssMemoryPolicy> {
+        self.pss_memory_policy.clone()
+    }
+    /// Gets the settings for automatically prefetching linked pages, if that's
+    /// enabled.
+    pub fn get_prefetch_settings(&self) -> Option<PrefetchSettings> {
+        self.prefetch.clone()
+    }
+    /// Gets the settings for the app's service worker, if it should have one.
+    pub fn get_service_worker_settings(&self) -> Option<ServiceWorkerSettings> {
+        self.service_worker.clone()
+    }
+    /// Gets whether or not pages that are hydrated incorrectly in development
+    /// should be rendered again from scratch.
+    pub fn get_hydration_mismatch_fallback(&self) -> bool {
+        self.hydration_mismatch_fallback
+    }
+    /// Gets the frozen state store hot state reloading should use, if it
+    /// shouldn't use the default.
+    pub fn get_hsr_store(&self) -> Option<Rc<dyn FrozenStateStore>> {
+        self.hsr_store.clone()
+    }
+    /// Gets the settings for synchronizing global state across tabs, if that's
+    /// enabled.
+    #[cfg(feature = "global-state-sync")]
+    pub fn get_global_state_sync(&self) -> Option<GlobalStateSync> {
+        self.global_state_sync.clone()
+    }
+    /// Gets the maximum number of entries to record in each timeline of the
+    /// state history, if that's enabled.
+    #[cfg(feature = "state-history")]
+    pub fn get_state_history_len(&self) -> Option<usize> {
+        self.state_history_len
+    }
+    /// Gets the publisher for pushing state to the browser, if the app uses
+    /// one.
+    pub fn get_state_publisher(&self) -> Option<StatePublisher> {
+        self.state_publisher.clone()
+    }
     /// Gets the [`GlobalStateCreator`]. This can't be directly modified by
     /// plugins because of reactive type complexities.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn get_global_state_creator(&self) -> Arc<GlobalStateCreator> {
         self.global_state_creator.clone()
     }
+    /// Gets the Content Security Policy the app should be served with, if one
+    /// has been set.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_csp(&self) -> Option<Arc<ContentSecurityPolicy>> {
+        self.csp.clone()
+    }
     /// Gets the locales information.
     pub fn get_locales(&self) -> Locales {
         let locales = self.locales.clone();
@@ -870,6 +1083,18 @@ impl<G: Html, M: MutableStore, T: TranslationsManager> PerseusAppBase<G, M, T> {
 
             scoped_static_aliases.insert(url, new_path);
         }
+        // The service worker is generated into the immutable store, but it has to be
+        // served from the root of the app to be able to control it
+        if self.service_worker.is_some() {
+            scoped_static_aliases.insert(
+                format!("/{}", SERVICE_WORKER_FILE),
+                format!(
+                    "{}/{}",
+                    self.get_immutable_store().get_path(),
+                    SERVICE_WORKER_FILE
+                ),
+            );
+        }
 
         scoped_static_aliases
     }
@@ -42,6 +42,8 @@ pub mod router;
 /// are intended for use by those developing new server integrations.
 #[cfg(not(target_arch = "wasm32"))]
 pub mod server;
+/// Utilities for generating a service worker that lets your app work offline.
+pub mod service_worker;
 /// Utilities for working with Perseus' state platform.
 pub mod state;
 /// Utilities for working with immutable and mutable stores. See
@@ -57,6 +59,8 @@ pub mod utils;
 mod build;
 #[cfg(all(feature = "client-helpers", target_arch = "wasm32"))]
 mod client;
+#[cfg(all(feature = "devtools", debug_assertions))]
+mod devtools;
 #[cfg(not(target_arch = "wasm32"))]
 mod export;
 mod init;
@@ -91,7 +95,7 @@ pub use crate::{
     error_pages::ErrorPages,
     errors::{ErrorCause, GenericErrorWithCause},
     init::*,
-    template::{RenderFnResult, RenderFnResultWithCause, Template},
+    template::{Layout, RenderFnResult, RenderFnResultWithCause, Template},
 };
 // Browser-side only
 #[cfg(target_arch = "wasm32")]
@@ -129,7 +133,7 @@ pub mod prelude {
     #[cfg(feature = "i18n")]
     pub use crate::{link, t};
     pub use crate::{
-        ErrorCause, ErrorPages, GenericErrorWithCause, PerseusApp, PerseusRoot, RenderFnResult,
-        RenderFnResultWithCause, Template,
+        ErrorCause, ErrorPages, GenericErrorWithCause, Layout, PerseusApp, PerseusRoot,
+        RenderFnResult, RenderFnResultWithCause, Template,
     };
 }
@@ -1,3 +1,4 @@
+use crate::template::LayoutStates;
 use serde::{Deserialize, Serialize};
 
 /// Represents the data necessary to render a page, including document metadata.
@@ -11,6 +12,10 @@ pub struct PageData {
     pub state: Option<String>,
     /// The string to interpolate into the document's `<head>`.
     pub head: String,
+    /// The states of the layouts the page is rendered inside, if they have
+    /// any.
+    #[serde(default, skip_serializing_if = "LayoutStates::is_empty")]
+    pub layout_states: LayoutStates,
 }
 
 /// A version of [`PageData`] that doesn't contain the HTML content of the page.
@@ -26,4 +31,8 @@ pub struct PageDataPartial {
     pub state: Option<String>,
     /// The string to interpolate into the document's `<head>`.
     pub head: String,
+    /// The states of the layouts the page is rendered inside, if they have
+    /// any.
+    #[serde(default, skip_serializing_if = "LayoutStates::is_empty")]
+    pub layout_states: LayoutStates,
 }
@@ -1,6 +1,8 @@
 use super::*;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::errors::EngineError;
+use crate::router::{NavigationDecision, NavigationError, RouteVerdict};
+use crate::template::TemplateNodeType;
 use crate::Html;
 use std::any::Any;
 use std::collections::HashMap;
@@ -257,4 +259,17 @@ pub struct FunctionalPluginClientActions {
     /// Runs before anything else in the browser. Note that this runs after
     /// panics have been set to go to the console.
     pub start: FunctionalPluginAction<(), ()>,
+    /// Runs before the router navigates to a new page, with the route it's
+    /// about to navigate to. If any plugin decides to cancel or redirect the
+    /// navigation, that decision will be followed (though there's no
+    /// guarantee of which plugin's decision will win if several don't want
+    /// the navigation to proceed).
+    pub before_navigate: FunctionalPluginAction<RouteVerdict<TemplateNodeType>, NavigationDecision>,
+    /// Runs after the router has successfully navigated to a new page, with
+    /// the route that was navigated to.
+    pub after_navigate: FunctionalPluginAction<RouteVerdict<TemplateNodeType>, ()>,
+    /// Runs after the router has failed to navigate to a new page, with the
+    /// route that was being navigated to and details of the error.
+    pub on_navigation_error:
+        FunctionalPluginAction<(RouteVerdict<TemplateNodeType>, NavigationError), ()>,
 }
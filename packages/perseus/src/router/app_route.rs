@@ -15,6 +15,8 @@ pub(crate) struct PerseusRoute<'cx> {
     /// completely irrelevant (it will be overridden immediately by the internal
     /// routing logic).
     pub verdict: RouteVerdict<TemplateNodeType>,
+    /// The path that was matched, without any query string or fragment.
+    pub path: String,
     /// The Sycamore scope that allows us to access the render context.
     ///
     /// This will *always* be `Some(_)` in actual applications.
@@ -28,6 +30,7 @@ impl<'cx> Default for PerseusRoute<'cx> {
     fn default() -> Self {
         Self {
             verdict: RouteVerdict::NotFound,
+            path: String::new(),
             // Again, this will never be accessed
             cx: None,
         }
@@ -40,6 +43,16 @@ impl<'cx> PerseusRoute<'cx> {
     }
 }
 impl<'cx> Route for PerseusRoute<'cx> {
+    fn match_path(&self, path: &str) -> Self {
+        // Sycamore will give us the query string and fragment too if the user called
+        // `navigate()` with them, but they're irrelevant to routing
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let segments = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+        self.match_route(&segments)
+    }
     fn match_route(&self, path: &[&str]) -> Self {
         let render_ctx = RenderCtx::from_ctx(self.cx.unwrap()); // We know the scope will always exist
         let verdict = match_route(
@@ -50,6 +63,7 @@ impl<'cx> Route for PerseusRoute<'cx> {
         );
         Self {
             verdict,
+            path: path.join("/"),
             cx: self.cx,
         }
     }
@@ -3,8 +3,8 @@ use crate::errors::*;
 use crate::i18n::detect_locale;
 use crate::router::match_route;
 use crate::router::{RouteInfo, RouteVerdict, RouterLoadState};
-use crate::template::{PageProps, RenderCtx, TemplateNodeType};
-use crate::utils::checkpoint;
+use crate::template::{LayoutStates, PageProps, RenderCtx, TemplateNodeType};
+use crate::utils::{checkpoint, parse_query};
 use fmterr::fmt_err;
 use sycamore::prelude::*;
 use sycamore::rt::Reflect; // We can piggyback off Sycamore to avoid bringing in `js_sys`
@@ -61,6 +61,8 @@ pub(crate) fn get_initial_view(
                 path: path_with_locale.clone(),
             });
             router_state.set_last_verdict(verdict.clone());
+            let query = parse_query(&web_sys::window().unwrap().location().search().unwrap());
+            router_state.set_query(query.clone());
 
             // Get the initial state and decide what to do from that
             let initial_state = get_initial_state();
@@ -130,6 +132,7 @@ pub(crate) fn get_initial_view(
                         path: path_with_locale.clone(),
                         state,
                         global_state,
+                        query,
                     };
                     // Pre-emptively declare the page interactive since all we do from this point
                     // is hydrate
@@ -139,8 +142,14 @@ pub(crate) fn get_initial_view(
                         template_name: path,
                         path: path_with_locale,
                     });
-                    // Return the actual template, for rendering/hydration
-                    template.render_for_template_client(page_props, cx, translator)
+                    // Render the actual template, for hydration, inside any layouts it needs (we
+                    // can't have any mounted yet, so this will always produce a view)
+                    render_ctx.layouts.add_states(locale, &get_layout_states());
+                    let view = template.render_for_template_client(page_props, cx, translator);
+                    render_ctx
+                        .layouts
+                        .mount(cx, template, locale, view)
+                        .unwrap_or_else(View::empty)
                 }
                 // We have an error that the server sent down, so we should just return that error
                 // view
@@ -291,6 +300,19 @@ pub(crate) fn get_global_state() -> Option<String> {
     }
 }
 
+/// Gets the states of the layouts the initial page was rendered inside, which
+/// were injected by the server. If there are errors in this, we'll just
+/// assume there aren't any.
+fn get_layout_states() -> LayoutStates {
+    let val_opt = web_sys::window().unwrap().get("__PERSEUS_LAYOUT_STATES");
+    let state_str = match val_opt.and_then(|js_obj| js_obj.as_string()) {
+        Some(state_str) => state_str,
+        None => return LayoutStates::new(),
+    };
+
+    serde_json::from_str(&state_str).unwrap_or_default()
+}
+
 /// Gets the translations injected by the server, if there was any. If there are
 /// errors in this, we can return `None` and not worry about it, they'll be
 /// handled by the initial state.
@@ -2,10 +2,11 @@ use crate::errors::*;
 use crate::page_data::PageDataPartial;
 use crate::router::{get_global_state, RouteVerdict, RouterLoadState};
 use crate::state::PssContains;
-use crate::template::{PageProps, RenderCtx, Template, TemplateNodeType};
+use crate::template::{LayoutStates, PageProps, RenderCtx, Template, TemplateNodeType};
 use crate::utils::checkpoint;
 use crate::utils::fetch;
 use crate::utils::get_path_prefix_client;
+use crate::utils::parse_query;
 use crate::utils::replace_head;
 use fmterr::fmt_err;
 use std::rc::Rc;
@@ -41,6 +42,9 @@ pub(crate) struct GetSubsequentViewProps<'a> {
 /// Note that this will automatically update the router state just before it
 /// returns, meaning that any errors that may occur after this function has been
 /// called need to reset the router state to be an error.
+///
+/// If the page was rendered inside layouts that were already mounted, this
+/// will return `None`, since the router's view doesn't need to be replaced.
 // TODO Eliminate all panics in this function
 pub(crate) async fn get_subsequent_view(
     GetSubsequentViewProps {
@@ -51,7 +55,7 @@ pub(crate) async fn get_subsequent_view(
         locale,
         route_verdict,
     }: GetSubsequentViewProps<'_>,
-) -> View<TemplateNodeType> {
+) -> Option<View<TemplateNodeType>> {
     let render_ctx = RenderCtx::from_ctx(cx);
     let router_state = &render_ctx.router;
     let translations_manager = &render_ctx.translations_manager;
@@ -68,11 +72,27 @@ pub(crate) async fn get_subsequent_view(
         path: path_with_locale.clone(),
     });
     router_state.set_last_verdict(route_verdict.clone());
+    // By the time this runs, the browser's URL will have been updated
+    let search = web_sys::window().unwrap().location().search().unwrap();
+    let query = parse_query(&search);
+    router_state.set_query(query.clone());
 
     checkpoint("initial_state_not_present");
     // Before we fetch anything, first check if there's an entry in the PSS already
-    // (if there is, we can avoid a network request)
-    let page_data: Result<PageDataPartial, View<TemplateNodeType>> = match pss.contains(&path) {
+    // (if there is, we can avoid a network request). That's keyed by path alone
+    // though, and request state can depend on the query string, so, if there is one,
+    // we'll always fetch the page afresh, getting rid of whatever we had for it so that
+    // the macros don't prefer that over what we fetch
+    let contains = match search.strip_prefix('?').unwrap_or(&search).is_empty() {
+        true => pss.contains(&path),
+        false => {
+            pss.force_remove(&path);
+            pss.force_remove(&path_with_locale);
+            pss.get_preloaded(&path);
+            PssContains::None
+        }
+    };
+    let page_data: Result<PageDataPartial, View<TemplateNodeType>> = match contains {
         // We only have one part of the puzzle (or nothing at all), and no guarantee that the other
         // doesn't exist, so we'll have to check with the server to be safe
         PssContains::State | PssContains::Head | PssContains::None => {
@@ -84,7 +104,7 @@ pub(crate) async fn get_subsequent_view(
                 false => path.to_string(),
             };
             // Get the static page data (head and state)
-            let asset_url = format!(
+            let mut asset_url = format!(
                 "{}/.perseus/page/{}/{}.json?template_name={}&was_incremental_match={}",
                 get_path_prefix_client(),
                 locale,
@@ -92,6 +112,10 @@ pub(crate) async fn get_subsequent_view(
                 template.get_path(),
                 was_incremental_match
             );
+            // Forward the page's query string so request state functions can see it
+            if let Some(search) = search.strip_prefix('?').filter(|search| !search.is_empty()) {
+                asset_url.push_str(&format!("&query={}", urlencoding::encode(search)));
+            }
             // If this doesn't exist, then it's a 404 (we went here by explicit navigation,
             // but it may be an unservable ISR page or the like)
             let page_data_str = fetch(&asset_url).await;
@@ -121,6 +145,7 @@ pub(crate) async fn get_subsequent_view(
                         router_state.set_load_state(RouterLoadState::ErrorLoaded {
                             path: path_with_locale.clone(),
                         });
+                        router_state.report_navigation_error(&asset_url, 404);
                         Err(error_pages.get_view_and_render_head(
                             cx,
                             &asset_url,
@@ -137,6 +162,7 @@ pub(crate) async fn get_subsequent_view(
                     match &err {
                         // No translators ready yet
                         ClientError::FetchError(FetchError::NotOk { url, status, .. }) => {
+                            router_state.report_navigation_error(url, *status);
                             Err(error_pages.get_view_and_render_head(
                                 cx,
                                 url,
@@ -156,11 +182,13 @@ pub(crate) async fn get_subsequent_view(
             state: Some("PSS".to_string()), /* The macros will preferentially use the PSS state,
                                              * so this will never be parsed */
             head: pss.get_head(&path).unwrap(),
+            layout_states: LayoutStates::new(),
         }),
         // We only have document metadata, but the page definitely takes no state, so we're fine
         PssContains::HeadNoState => Ok(PageDataPartial {
             state: None,
             head: pss.get_head(&path).unwrap(),
+            layout_states: LayoutStates::new(),
         }),
         // The page's data has been preloaded at some other time
         PssContains::Preloaded => {
@@ -175,9 +203,15 @@ pub(crate) async fn get_subsequent_view(
     // Any errors will be prepared error pages ready for return
     let page_data = match page_data {
         Ok(page_data) => page_data,
-        Err(view) => return view,
+        Err(view) => return render_ctx.layouts.clear_with(view),
     };
 
+    // Keep any layout states around in case the layouts need to be rendered (data
+    // from the PSS won't have them, but then we'll already have seen them)
+    render_ctx
+        .layouts
+        .add_states(&locale, &page_data.layout_states);
+
     // Interpolate the metadata directly into the document's `<head>`
     replace_head(&page_data.head);
 
@@ -192,14 +226,17 @@ pub(crate) async fn get_subsequent_view(
             router_state.set_load_state(RouterLoadState::ErrorLoaded {
                 path: path_with_locale.clone(),
             });
-            match &err {
+            let (url, status) = match &err {
                 // These errors happen because we couldn't get a translator, so they certainly don't get one
-                ClientError::FetchError(FetchError::NotOk { url, status, .. }) => return error_pages.get_view_and_render_head(cx, url, *status, &fmt_err(&err), None),
-                ClientError::FetchError(FetchError::SerFailed { url, .. }) => return error_pages.get_view_and_render_head(cx, url, 500, &fmt_err(&err), None),
-                ClientError::LocaleNotSupported { locale } => return error_pages.get_view_and_render_head(cx, &format!("/{}/...", locale), 404, &fmt_err(&err), None),
+                ClientError::FetchError(FetchError::NotOk { url, status, .. }) => (url.to_string(), *status),
+                ClientError::FetchError(FetchError::SerFailed { url, .. }) => (url.to_string(), 500),
+                ClientError::LocaleNotSupported { locale } => (format!("/{}/...", locale), 404),
                 // No other errors should be returned
                 _ => panic!("expected 'AssetNotOk'/'AssetSerFailed'/'LocaleNotSupported' error, found other unacceptable error")
-            }
+            };
+            router_state.report_navigation_error(&url, status);
+            let view = error_pages.get_view_and_render_head(cx, &url, status, &fmt_err(&err), None);
+            return render_ctx.layouts.clear_with(view);
         }
     };
 
@@ -209,6 +246,7 @@ pub(crate) async fn get_subsequent_view(
         // This will probably be overridden by the already-set version (unless no
         // page has used global state yet)
         global_state: get_global_state(),
+        query,
     };
     let template_name = template.get_path();
     // Pre-emptively update the router state
@@ -218,6 +256,7 @@ pub(crate) async fn get_subsequent_view(
         template_name,
         path: path_with_locale,
     });
-    // Now return the view that should be rendered
-    template.render_for_template_client(page_props, cx, translator)
+    // Now render the page, and put it inside any layouts it needs
+    let view = template.render_for_template_client(page_props, cx, translator);
+    render_ctx.layouts.mount(cx, &template, &locale, view)
 }
@@ -0,0 +1,204 @@
+use crate::router::{RouterLoadState, RouterState};
+use sycamore::rt::{JsCast, JsValue};
+use web_sys::{Element, Node};
+
+/// The key in session storage under which we record the path of a page that
+/// should be rendered from scratch on its next load, because hydrating it
+/// didn't work.
+const FALLBACK_KEY: &str = "__perseus_hydration_fallback";
+/// The maximum number of differences that will be reported for a single page,
+/// since one mismatch can cascade into many more.
+const MAX_REPORTED_DIFFS: usize = 10;
+/// The maximum length of the snippets of HTML that will be shown in reports.
+const MAX_SNIPPET_LEN: usize = 100;
+
+/// A check that the browser hydrated the initial view into the same DOM that
+/// the engine prerendered. If hydration goes wrong, Sycamore will quietly
+/// patch up the DOM it was given, so we keep a copy of it from before
+/// hydration and compare the two afterward. This is only done in development.
+#[derive(Debug)]
+pub(crate) struct HydrationCheck {
+    /// The root element the app is rendered into.
+    root: Element,
+    /// A copy of the root element as the engine rendered it.
+    snapshot: Node,
+    /// Whether or not the page should be rendered from scratch if there's a
+    /// mismatch.
+    fallback: bool,
+}
+impl HydrationCheck {
+    /// Takes a copy of the given root element before it's hydrated.
+    pub(crate) fn new(root: &Element, fallback: bool) -> Self {
+        Self {
+            root: root.clone(),
+            snapshot: root.clone_node_with_deep(true).unwrap(),
+            fallback,
+        }
+    }
+    /// Compares the hydrated root element with the copy taken before
+    /// hydration, reporting any differences to the console and the reload
+    /// server. This should be run once hydration is complete. If we're
+    /// falling back on a mismatch, this will reload the page.
+    pub(crate) fn run(&self, router_state: &RouterState) {
+        let load_state = router_state.get_load_state_rc().get_untracked();
+        let (template_name, path) = match (*load_state).clone() {
+            RouterLoadState::Loaded {
+                template_name,
+                path,
+            } => (template_name, path),
+            RouterLoadState::ErrorLoaded { path } => ("<error page>".to_string(), path),
+            // Redirects don't render anything
+            _ => return,
+        };
+
+        let mut diffs = Vec::new();
+        let root_path = format!("#{}", self.root.id());
+        diff_children(&self.snapshot, &self.root, &root_path, &mut diffs);
+        if diffs.is_empty() {
+            return;
+        }
+
+        let mut report = format!(
+            "[Hydration Mismatch]: the page at `/{}` (template `{}`) was hydrated into different HTML to what the engine rendered. Paths below give the index of each node among its parent's children.",
+            path, template_name
+        );
+        for diff in diffs.iter().take(MAX_REPORTED_DIFFS) {
+            report.push_str("\n    ");
+            report.push_str(diff);
+        }
+        if diffs.len() > MAX_REPORTED_DIFFS {
+            report.push_str(&format!(
+                "\n    ...and {} more",
+                diffs.len() - MAX_REPORTED_DIFFS
+            ));
+        }
+        web_sys::console::error_1(&JsValue::from(&report));
+        #[cfg(feature = "live-reload")]
+        crate::state::send_to_reload_server(report);
+
+        if self.fallback {
+            let window = web_sys::window().unwrap();
+            if let Ok(Some(storage)) = window.session_storage() {
+                let pathname = window.location().pathname().unwrap();
+                if storage.set_item(FALLBACK_KEY, &pathname).is_ok() {
+                    let _ = window.location().reload();
+                }
+            }
+        }
+    }
+}
+
+/// Checks if the current page should be rendered from scratch, rather than
+/// hydrated, because hydrating it last time led to a mismatch. This will only
+/// return `true` once for each mismatch.
+pub(crate) fn take_hydration_fallback() -> bool {
+    let window = web_sys::window().unwrap();
+    let storage = match window.session_storage() {
+        Ok(Some(storage)) => storage,
+        _ => return false,
+    };
+    let flagged = storage.get_item(FALLBACK_KEY).ok().flatten();
+    let _ = storage.remove_item(FALLBACK_KEY);
+
+    flagged.is_some() && flagged == window.location().pathname().ok()
+}
+
+/// Compares the children of the given nodes, which are at the given path.
+fn diff_children(engine: &Node, browser: &Node, path: &str, diffs: &mut Vec<String>) {
+    let engine_children = engine.child_nodes();
+    let browser_children = browser.child_nodes();
+    let len = engine_children.length().max(browser_children.length());
+    for i in 0..len {
+        match (engine_children.item(i), browser_children.item(i)) {
+            (Some(engine_child), Some(browser_child)) => {
+                let child_path = format!(
+                    "{} > {}[{}]",
+                    path,
+                    engine_child.node_name().to_lowercase(),
+                    i
+                );
+                diff_nodes(&engine_child, &browser_child, &child_path, diffs);
+            }
+            (Some(engine_child), None) => diffs.push(format!(
+                "at `{}`: the engine rendered `{}`, but the browser rendered nothing",
+                path,
+                describe(&engine_child)
+            )),
+            (None, Some(browser_child)) => diffs.push(format!(
+                "at `{}`: the engine rendered nothing, but the browser rendered `{}`",
+                path,
+                describe(&browser_child)
+            )),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+/// Compares the given nodes, which are at the given path.
+fn diff_nodes(engine: &Node, browser: &Node, path: &str, diffs: &mut Vec<String>) {
+    if engine.is_equal_node(Some(browser)) {
+        return;
+    }
+    if engine.node_type() != browser.node_type() || engine.node_name() != browser.node_name() {
+        diffs.push(format!(
+            "at `{}`: the engine rendered `{}`, but the browser rendered `{}`",
+            path,
+            describe(engine),
+            describe(browser)
+        ));
+        return;
+    }
+
+    if engine.node_type() == Node::ELEMENT_NODE {
+        let engine: &Element = engine.unchecked_ref();
+        let browser: &Element = browser.unchecked_ref();
+        let mut attr_names = get_attribute_names(engine);
+        for name in get_attribute_names(browser) {
+            if !attr_names.contains(&name) {
+                attr_names.push(name);
+            }
+        }
+        for name in attr_names {
+            let engine_val = engine.get_attribute(&name);
+            let browser_val = browser.get_attribute(&name);
+            if engine_val != browser_val {
+                diffs.push(format!(
+                    "at `{}`: the engine set the `{}` attribute to {:?}, but the browser set it to {:?}",
+                    path, name, engine_val, browser_val
+                ));
+            }
+        }
+        diff_children(engine, browser, path, diffs);
+    } else {
+        // Text and comments have no children, so they must just have different content
+        diffs.push(format!(
+            "at `{}`: the engine rendered `{}`, but the browser rendered `{}`",
+            path,
+            describe(engine),
+            describe(browser)
+        ));
+    }
+}
+
+/// Gets the names of all the attributes on the given element.
+fn get_attribute_names(elem: &Element) -> Vec<String> {
+    let names = elem.get_attribute_names();
+    (0..names.length())
+        .filter_map(|i| names.get(i).as_string())
+        .collect()
+}
+
+/// Describes the given node as a short snippet of HTML.
+fn describe(node: &Node) -> String {
+    let snippet = match node.node_type() {
+        Node::ELEMENT_NODE => node.unchecked_ref::<Element>().outer_html(),
+        Node::COMMENT_NODE => format!("<!--{}-->", node.node_value().unwrap_or_default()),
+        _ => node.text_content().unwrap_or_default(),
+    };
+    if snippet.chars().count() > MAX_SNIPPET_LEN {
+        let truncated = snippet.chars().take(MAX_SNIPPET_LEN).collect::<String>();
+        format!("{}...", truncated)
+    } else {
+        snippet
+    }
+}
@@ -0,0 +1,124 @@
+use crate::template::{LayoutStates, Template, TemplateNodeType};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use sycamore::prelude::{create_rc_signal, RcSignal, Scope, View};
+
+/// A layout that's currently mounted in the router's view.
+struct MountedLayout {
+    /// The identifier of the layout, which combines its locale and name (since
+    /// a layout will usually need to be rendered again if the locale
+    /// changes).
+    id: String,
+    /// The content currently rendered inside the layout, which can be updated
+    /// without rendering the layout again.
+    content: RcSignal<View<TemplateNodeType>>,
+}
+
+/// A record of the layouts the router currently has mounted, which allows
+/// layouts to persist as the user navigates between pages that share them.
+/// This also keeps the states of any layouts the app has seen, since those are
+/// only sent from the server along with pages.
+#[derive(Clone, Default)]
+pub(crate) struct MountedLayouts {
+    /// The currently mounted layouts, from outermost to innermost.
+    mounted: Rc<RefCell<Vec<MountedLayout>>>,
+    /// The states of all the layouts we know of, indexed by their identifiers.
+    states: Rc<RefCell<HashMap<String, String>>>,
+}
+impl std::fmt::Debug for MountedLayouts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mounted = self
+            .mounted
+            .borrow()
+            .iter()
+            .map(|layout| layout.id.clone())
+            .collect::<Vec<_>>();
+        f.debug_struct("MountedLayouts")
+            .field("mounted", &mounted)
+            .finish()
+    }
+}
+impl MountedLayouts {
+    /// Records the given layout states, which were sent from the server for the
+    /// given locale.
+    pub(crate) fn add_states(&self, locale: &str, layout_states: &LayoutStates) {
+        let mut states = self.states.borrow_mut();
+        for (name, state) in layout_states {
+            states.insert(get_id(locale, name), state.to_string());
+        }
+    }
+    /// Mounts the given page view inside the layouts of the given template,
+    /// reusing as many of the currently mounted layouts as possible.
+    ///
+    /// If the router needs to replace its entire view, this will return the new
+    /// view, but, if the outermost layout was already mounted, the new content
+    /// will be rendered into the layouts that already exist, and this will
+    /// return `None`.
+    pub(crate) fn mount(
+        &self,
+        cx: Scope,
+        template: &Template<TemplateNodeType>,
+        locale: &str,
+        view: View<TemplateNodeType>,
+    ) -> Option<View<TemplateNodeType>> {
+        let layouts = template.get_layouts();
+        let ids = layouts
+            .iter()
+            .map(|layout| get_id(locale, &layout.get_name()))
+            .collect::<Vec<_>>();
+        let mut mounted = self.mounted.borrow_mut();
+        // Work out how many of the outer layouts we can keep
+        let num_kept = mounted
+            .iter()
+            .zip(ids.iter())
+            .take_while(|(mounted, id)| &mounted.id == *id)
+            .count();
+
+        // Render any layouts that aren't already mounted around the page, from the
+        // innermost outwards
+        let states = self.states.borrow();
+        let mut new_mounts = Vec::new();
+        let view = layouts[num_kept..]
+            .iter()
+            .zip(ids[num_kept..].iter())
+            .rev()
+            .fold(view, |view, (layout, id)| {
+                let content = create_rc_signal(view);
+                new_mounts.push(MountedLayout {
+                    id: id.to_string(),
+                    content: content.clone(),
+                });
+                layout.render(cx, states.get(id).cloned(), content)
+            });
+        new_mounts.reverse();
+
+        mounted.truncate(num_kept);
+        let view = match mounted.last() {
+            // We can render straight into the innermost layout we kept
+            Some(innermost) => {
+                innermost.content.set(view);
+                None
+            }
+            None => Some(view),
+        };
+        mounted.extend(new_mounts);
+
+        view
+    }
+    /// Unmounts all layouts, returning the given view, which should replace the
+    /// router's entire view. This should be used for views that aren't rendered
+    /// inside layouts, like error pages.
+    pub(crate) fn clear_with(
+        &self,
+        view: View<TemplateNodeType>,
+    ) -> Option<View<TemplateNodeType>> {
+        self.mounted.borrow_mut().clear();
+        Some(view)
+    }
+}
+
+/// Gets the identifier for the layout with the given name in the given locale.
+fn get_id(locale: &str, name: &str) -> String {
+    format!("{}-{}", locale, name)
+}
@@ -4,23 +4,41 @@ mod app_route;
 mod get_initial_view;
 #[cfg(target_arch = "wasm32")]
 mod get_subsequent_view;
+#[cfg(all(feature = "hydrate", debug_assertions, target_arch = "wasm32"))]
+mod hydration_check;
+#[cfg(target_arch = "wasm32")]
+mod layouts;
 mod match_route;
+mod navigation;
+mod prefetch;
 mod route_verdict;
 #[cfg(target_arch = "wasm32")]
 mod router_component;
 mod router_state;
+mod scroll;
 
 #[cfg(target_arch = "wasm32")]
 pub(crate) use app_route::PerseusRoute;
 pub use match_route::{
     get_template_for_path, get_template_for_path_atomic, match_route, match_route_atomic,
 };
+pub use navigation::{NavigationDecision, NavigationError, NavigationHookId};
+pub use prefetch::{PrefetchSettings, PREFETCH_OPT_OUT_ATTR};
 pub use route_verdict::{RouteInfo, RouteInfoAtomic, RouteVerdict, RouteVerdictAtomic};
 #[cfg(target_arch = "wasm32")]
 pub(crate) use router_component::{perseus_router, PerseusRouterProps};
 pub use router_state::{RouterLoadState, RouterState};
+pub use scroll::{ScrollBehavior, ScrollInfo};
 
 #[cfg(target_arch = "wasm32")]
 pub(crate) use get_initial_view::{get_global_state, get_initial_view, InitialView};
 #[cfg(target_arch = "wasm32")]
 pub(crate) use get_subsequent_view::{get_subsequent_view, GetSubsequentViewProps};
+#[cfg(all(feature = "hydrate", debug_assertions, target_arch = "wasm32"))]
+pub(crate) use hydration_check::{take_hydration_fallback, HydrationCheck};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use layouts::MountedLayouts;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use prefetch::LinkPrefetcher;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use scroll::ScrollManager;
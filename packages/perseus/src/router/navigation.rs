@@ -0,0 +1,310 @@
+use super::RouteVerdict;
+#[cfg(target_arch = "wasm32")]
+use crate::plugins::{PluginAction, Plugins};
+use crate::template::TemplateNodeType;
+use std::rc::Rc;
+
+/// What the router should do with a navigation, as decided by a
+/// `before_navigate` hook.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NavigationDecision {
+    /// Let the navigation go ahead.
+    Proceed,
+    /// Cancel the navigation, leaving the user on the current page (e.g. if
+    /// they have unsaved changes they don't want to lose).
+    Cancel,
+    /// Send the user to the given URL instead. This will replace the
+    /// navigation that was cancelled in the browser's history.
+    Redirect(String),
+}
+
+/// An error that occurred while the router was navigating to a new page, which
+/// will be given to `on_navigation_error` hooks. By the time these hooks are
+/// run, the appropriate error page will already have been rendered.
+#[derive(Clone, Debug)]
+pub struct NavigationError {
+    /// The URL at which the error occurred. This may be the URL of an asset
+    /// the router was fetching, rather than the page itself.
+    pub url: String,
+    /// The HTTP status code of the error.
+    pub status: u16,
+}
+
+/// An identifier for a navigation hook, which can be used to remove it before
+/// the user navigates away from the page that registered it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NavigationHookId(usize);
+
+type BeforeNavigateHook = Rc<dyn Fn(&RouteVerdict<TemplateNodeType>) -> NavigationDecision>;
+type AfterNavigateHook = Rc<dyn Fn(&RouteVerdict<TemplateNodeType>)>;
+type NavigationErrorHook = Rc<dyn Fn(&RouteVerdict<TemplateNodeType>, &NavigationError)>;
+
+/// A registered navigation hook.
+struct Hook<F> {
+    /// The hook's identifier.
+    id: NavigationHookId,
+    /// The page generation in which this hook was registered. Hooks from
+    /// previous generations are dropped once a navigation away from their
+    /// page has completed.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    generation: usize,
+    /// The hook itself.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    hook: F,
+}
+
+/// The navigation hooks that pages have registered with the router. Hooks from
+/// plugins are run alongside these, but they're kept in the plugin actions.
+#[derive(Default)]
+pub(crate) struct NavigationHooks {
+    /// The next identifier that will be given to a hook.
+    next_id: usize,
+    /// The current page generation, which is incremented every time the router
+    /// starts rendering a new page.
+    generation: usize,
+    /// Hooks that run before the router navigates anywhere.
+    before_navigate: Vec<Hook<BeforeNavigateHook>>,
+    /// Hooks that run after the router has successfully navigated.
+    after_navigate: Vec<Hook<AfterNavigateHook>>,
+    /// Hooks that run after the router has failed to navigate.
+    on_navigation_error: Vec<Hook<NavigationErrorHook>>,
+    /// The error that occurred in the navigation currently in progress, if
+    /// there was one.
+    #[cfg(target_arch = "wasm32")]
+    pending_error: Option<NavigationError>,
+}
+impl std::fmt::Debug for NavigationHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NavigationHooks")
+            .field("generation", &self.generation)
+            .field("before_navigate", &self.before_navigate.len())
+            .field("after_navigate", &self.after_navigate.len())
+            .field("on_navigation_error", &self.on_navigation_error.len())
+            .finish()
+    }
+}
+impl NavigationHooks {
+    /// Gets a new hook identifier.
+    fn new_id(&mut self) -> NavigationHookId {
+        let id = NavigationHookId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+    /// Registers a hook that runs before each navigation.
+    pub(crate) fn add_before_navigate(&mut self, hook: BeforeNavigateHook) -> NavigationHookId {
+        let id = self.new_id();
+        self.before_navigate.push(Hook {
+            id,
+            generation: self.generation,
+            hook,
+        });
+        id
+    }
+    /// Registers a hook that runs after each successful navigation.
+    pub(crate) fn add_after_navigate(&mut self, hook: AfterNavigateHook) -> NavigationHookId {
+        let id = self.new_id();
+        self.after_navigate.push(Hook {
+            id,
+            generation: self.generation,
+            hook,
+        });
+        id
+    }
+    /// Registers a hook that runs after each failed navigation.
+    pub(crate) fn add_on_navigation_error(
+        &mut self,
+        hook: NavigationErrorHook,
+    ) -> NavigationHookId {
+        let id = self.new_id();
+        self.on_navigation_error.push(Hook {
+            id,
+            generation: self.generation,
+            hook,
+        });
+        id
+    }
+    /// Removes the hook with the given identifier, if it's still registered.
+    pub(crate) fn remove(&mut self, id: NavigationHookId) {
+        self.before_navigate.retain(|hook| hook.id != id);
+        self.after_navigate.retain(|hook| hook.id != id);
+        self.on_navigation_error.retain(|hook| hook.id != id);
+    }
+    /// Records an error that occurred while navigating, which will be given to
+    /// the error hooks once the navigation has finished.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn set_error(&mut self, err: NavigationError) {
+        self.pending_error = Some(err);
+    }
+    /// Starts a new page generation. Hooks registered from here on belong to
+    /// the page that's about to be rendered.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) fn start_generation(&mut self) {
+        self.generation += 1;
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.pending_error = None;
+        }
+    }
+    /// Drops any hooks that were registered by pages before the current one.
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub(crate) fn drop_old_generations(&mut self) {
+        let generation = self.generation;
+        self.before_navigate
+            .retain(|hook| hook.generation == generation);
+        self.after_navigate
+            .retain(|hook| hook.generation == generation);
+        self.on_navigation_error
+            .retain(|hook| hook.generation == generation);
+    }
+}
+
+// These take the hooks behind a `RefCell` so that we don't hold a borrow while
+// running user code (which might well register more hooks)
+#[cfg(target_arch = "wasm32")]
+impl NavigationHooks {
+    /// Runs all the `before_navigate` hooks, including those from plugins,
+    /// returning the first decision that isn't to proceed.
+    pub(crate) fn run_before_navigate(
+        this: &std::cell::RefCell<Self>,
+        verdict: &RouteVerdict<TemplateNodeType>,
+        plugins: &Plugins<TemplateNodeType>,
+    ) -> NavigationDecision {
+        let hooks = this
+            .borrow()
+            .before_navigate
+            .iter()
+            .map(|hook| hook.hook.clone())
+            .collect::<Vec<_>>();
+        for hook in hooks {
+            match hook(verdict) {
+                NavigationDecision::Proceed => continue,
+                decision => return decision,
+            }
+        }
+        plugins
+            .functional_actions
+            .client_actions
+            .before_navigate
+            .run(verdict.clone(), plugins.get_plugin_data())
+            .into_values()
+            .find(|decision| decision != &NavigationDecision::Proceed)
+            .unwrap_or(NavigationDecision::Proceed)
+    }
+    /// Runs the `after_navigate` hooks, or the `on_navigation_error` hooks if
+    /// an error occurred, including those from plugins.
+    pub(crate) fn run_after_navigate(
+        this: &std::cell::RefCell<Self>,
+        verdict: &RouteVerdict<TemplateNodeType>,
+        plugins: &Plugins<TemplateNodeType>,
+    ) {
+        let err = this.borrow_mut().pending_error.take();
+        match err {
+            Some(err) => {
+                let hooks = this
+                    .borrow()
+                    .on_navigation_error
+                    .iter()
+                    .map(|hook| hook.hook.clone())
+                    .collect::<Vec<_>>();
+                for hook in hooks {
+                    hook(verdict, &err);
+                }
+                plugins
+                    .functional_actions
+                    .client_actions
+                    .on_navigation_error
+                    .run((verdict.clone(), err), plugins.get_plugin_data());
+            }
+            None => {
+                let hooks = this
+                    .borrow()
+                    .after_navigate
+                    .iter()
+                    .map(|hook| hook.hook.clone())
+                    .collect::<Vec<_>>();
+                for hook in hooks {
+                    hook(verdict);
+                }
+                plugins
+                    .functional_actions
+                    .client_actions
+                    .after_navigate
+                    .run(verdict.clone(), plugins.get_plugin_data());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn before() -> BeforeNavigateHook {
+        Rc::new(|_| NavigationDecision::Proceed)
+    }
+    fn after() -> AfterNavigateHook {
+        Rc::new(|_| ())
+    }
+    fn on_error() -> NavigationErrorHook {
+        Rc::new(|_, _| ())
+    }
+    fn ids<F>(hooks: &[Hook<F>]) -> Vec<NavigationHookId> {
+        hooks.iter().map(|hook| hook.id).collect()
+    }
+
+    #[test]
+    fn gives_unique_ids_across_kinds() {
+        let mut hooks = NavigationHooks::default();
+        let a = hooks.add_before_navigate(before());
+        let b = hooks.add_after_navigate(after());
+        let c = hooks.add_on_navigation_error(on_error());
+        let d = hooks.add_before_navigate(before());
+        assert_ne!(a, b);
+        assert_ne!(b, c);
+        assert_ne!(a, d);
+        assert_eq!(ids(&hooks.before_navigate), vec![a, d]);
+        assert_eq!(ids(&hooks.after_navigate), vec![b]);
+        assert_eq!(ids(&hooks.on_navigation_error), vec![c]);
+    }
+    #[test]
+    fn removes_only_the_given_hook() {
+        let mut hooks = NavigationHooks::default();
+        let a = hooks.add_before_navigate(before());
+        let b = hooks.add_before_navigate(before());
+        let c = hooks.add_after_navigate(after());
+        hooks.remove(a);
+        assert_eq!(ids(&hooks.before_navigate), vec![b]);
+        assert_eq!(ids(&hooks.after_navigate), vec![c]);
+        // Removing a hook twice is fine
+        hooks.remove(a);
+        assert_eq!(ids(&hooks.before_navigate), vec![b]);
+    }
+    #[test]
+    fn drops_hooks_from_previous_pages() {
+        let mut hooks = NavigationHooks::default();
+        hooks.add_before_navigate(before());
+        hooks.add_on_navigation_error(on_error());
+        // The router starts rendering a new page, which registers its own hooks
+        hooks.start_generation();
+        let a = hooks.add_before_navigate(before());
+        let b = hooks.add_after_navigate(after());
+        hooks.drop_old_generations();
+        assert_eq!(ids(&hooks.before_navigate), vec![a]);
+        assert_eq!(ids(&hooks.after_navigate), vec![b]);
+        assert!(hooks.on_navigation_error.is_empty());
+    }
+    #[test]
+    fn keeps_hooks_until_navigation_finishes() {
+        let mut hooks = NavigationHooks::default();
+        let a = hooks.add_before_navigate(before());
+        // The old page's hooks should stay around while the new one renders
+        hooks.start_generation();
+        assert_eq!(ids(&hooks.before_navigate), vec![a]);
+        // And dropping old generations twice shouldn't affect the current page's
+        // hooks
+        let b = hooks.add_before_navigate(before());
+        hooks.drop_old_generations();
+        hooks.drop_old_generations();
+        assert_eq!(ids(&hooks.before_navigate), vec![b]);
+    }
+}
@@ -0,0 +1,309 @@
+#[cfg(target_arch = "wasm32")]
+use crate::{
+    i18n::Locales,
+    router::{match_route, RouteVerdict},
+    state::PageStateStore,
+    template::{RenderCtx, TemplateMap, TemplateNodeType},
+    utils::get_path_prefix_client,
+};
+#[cfg(target_arch = "wasm32")]
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
+#[cfg(target_arch = "wasm32")]
+use sycamore::rt::{JsCast, JsValue, Reflect};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::closure::Closure;
+#[cfg(target_arch = "wasm32")]
+use web_sys::{Element, HtmlAnchorElement, IntersectionObserver, IntersectionObserverEntry};
+
+/// The attribute that can be set to `"false"` on a link to stop it from being
+/// prefetched automatically (e.g. if it's to a page with expensive request
+/// state).
+pub const PREFETCH_OPT_OUT_ATTR: &str = "data-perseus-prefetch";
+
+/// Settings for automatically prefetching the pages that links in your app
+/// point to, so that navigating to them is near-instant. Prefetched pages are
+/// placed in the page state store's preload cache, just as if they'd been
+/// preloaded manually.
+///
+/// Links can opt out of this individually by setting the
+/// `data-perseus-prefetch="false"` attribute.
+#[derive(Clone, Debug)]
+pub struct PrefetchSettings {
+    /// Whether or not links should be prefetched when the user hovers over
+    /// them.
+    pub on_hover: bool,
+    /// Whether or not links should be prefetched when they're focused (e.g.
+    /// by keyboard navigation).
+    pub on_focus: bool,
+    /// Whether or not links should be prefetched as soon as they're scrolled
+    /// into view. This is the most aggressive option, and will lead to many
+    /// more requests on pages with many links.
+    pub on_viewport: bool,
+    /// The maximum number of pages that will be prefetched at once. Any more
+    /// will be queued until earlier requests complete. If this is `0`, it
+    /// will be treated as `1`.
+    pub max_concurrent: usize,
+    /// Whether or not to disable prefetching if the user has asked their
+    /// browser to reduce data usage (with the `Save-Data` preference).
+    pub respect_save_data: bool,
+}
+impl Default for PrefetchSettings {
+    fn default() -> Self {
+        Self {
+            on_hover: true,
+            on_focus: true,
+            on_viewport: false,
+            max_concurrent: 4,
+            respect_save_data: true,
+        }
+    }
+}
+
+/// The system that prefetches pages as the user interacts with links to them.
+/// This can be cheaply cloned.
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone)]
+pub(crate) struct LinkPrefetcher {
+    inner: Rc<PrefetcherInner>,
+}
+#[cfg(target_arch = "wasm32")]
+struct PrefetcherInner {
+    /// The settings for prefetching.
+    settings: PrefetchSettings,
+    /// The render configuration, for routing.
+    render_cfg: Rc<HashMap<String, String>>,
+    /// The app's templates, for routing.
+    templates: TemplateMap<TemplateNodeType>,
+    /// The app's locales, for routing.
+    locales: Locales,
+    /// The page state store, into which prefetched pages will be preloaded.
+    pss: PageStateStore,
+    /// The number of prefetch requests currently in flight.
+    in_flight: Cell<usize>,
+    /// The paths waiting to be prefetched.
+    queue: RefCell<VecDeque<String>>,
+    /// The paths that are either queued or being prefetched, so we don't
+    /// request anything twice.
+    pending: RefCell<HashSet<String>>,
+    /// The observer that watches for links being scrolled into view, if we're
+    /// doing that.
+    observer: RefCell<Option<IntersectionObserver>>,
+}
+#[cfg(target_arch = "wasm32")]
+impl std::fmt::Debug for LinkPrefetcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkPrefetcher")
+            .field("settings", &self.inner.settings)
+            .field("in_flight", &self.inner.in_flight.get())
+            .finish()
+    }
+}
+#[cfg(target_arch = "wasm32")]
+impl LinkPrefetcher {
+    /// Creates a new prefetcher with the given settings and sets up the event
+    /// listeners it needs. Links that are scrolled into view will only be
+    /// prefetched once `.observe_links()` has been called.
+    pub(crate) fn new(settings: PrefetchSettings, render_ctx: &RenderCtx) -> Self {
+        let this = Self {
+            inner: Rc::new(PrefetcherInner {
+                settings,
+                render_cfg: render_ctx.render_cfg.clone(),
+                templates: render_ctx.templates.clone(),
+                locales: render_ctx.locales.clone(),
+                pss: render_ctx.page_state_store.clone(),
+                in_flight: Cell::new(0),
+                queue: RefCell::new(VecDeque::new()),
+                pending: RefCell::new(HashSet::new()),
+                observer: RefCell::new(None),
+            }),
+        };
+        this.attach();
+        this
+    }
+    /// Sets up event listeners for hovering over and focusing links (which we
+    /// can do once for the whole document), and creates an observer for links
+    /// being scrolled into view.
+    fn attach(&self) {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let settings = &self.inner.settings;
+
+        let mut events = Vec::new();
+        if settings.on_hover {
+            events.push("mouseover");
+        }
+        if settings.on_focus {
+            events.push("focusin");
+        }
+        for event in events {
+            let this = self.clone();
+            let closure = Closure::wrap(Box::new(move |ev: web_sys::Event| {
+                let anchor = ev
+                    .target()
+                    .and_then(|target| target.dyn_into::<Element>().ok())
+                    .and_then(|elem| elem.closest("a[href]").ok().flatten());
+                if let Some(anchor) = anchor {
+                    this.maybe_prefetch(anchor.unchecked_into());
+                }
+            }) as Box<dyn FnMut(web_sys::Event)>);
+            document
+                .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+                .unwrap();
+            // The router lives as long as the app, so this is never removed
+            closure.forget();
+        }
+
+        if settings.on_viewport {
+            let this = self.clone();
+            let closure = Closure::wrap(Box::new(
+                move |entries: JsValue, observer: IntersectionObserver| {
+                    // We can piggyback off Sycamore's `Reflect` to avoid bringing in `js_sys`
+                    let len = Reflect::get(&entries, &JsValue::from_str("length"))
+                        .ok()
+                        .and_then(|len| len.as_f64())
+                        .unwrap_or(0.0) as u32;
+                    for i in 0..len {
+                        let entry: IntersectionObserverEntry = match Reflect::get_u32(&entries, i) {
+                            Ok(entry) => entry.unchecked_into(),
+                            Err(_) => continue,
+                        };
+                        if entry.is_intersecting() {
+                            let target = entry.target();
+                            // We only need to know about each link once
+                            observer.unobserve(&target);
+                            this.maybe_prefetch(target.unchecked_into());
+                        }
+                    }
+                },
+            )
+                as Box<dyn FnMut(JsValue, IntersectionObserver)>);
+            if let Ok(observer) = IntersectionObserver::new(closure.as_ref().unchecked_ref()) {
+                *self.inner.observer.borrow_mut() = Some(observer);
+            }
+            closure.forget();
+        }
+    }
+    /// Starts observing all the links currently in the document, so that they
+    /// can be prefetched when they're scrolled into view. This should be
+    /// called whenever a new page has been rendered. If we aren't prefetching
+    /// links on viewport entry, this will do nothing.
+    pub(crate) fn observe_links(&self) {
+        let observer = self.inner.observer.borrow();
+        let observer = match &*observer {
+            Some(observer) => observer,
+            None => return,
+        };
+        let document = web_sys::window().unwrap().document().unwrap();
+        let links = match document.query_selector_all("a[href]") {
+            Ok(links) => links,
+            Err(_) => return,
+        };
+        for i in 0..links.length() {
+            if let Some(link) = links.item(i) {
+                // Observing the same element twice does nothing
+                observer.observe(link.unchecked_ref());
+            }
+        }
+    }
+    /// Prefetches the page the given link points to, unless it shouldn't be.
+    fn maybe_prefetch(&self, anchor: HtmlAnchorElement) {
+        if anchor.get_attribute(PREFETCH_OPT_OUT_ATTR).as_deref() == Some("false")
+            || anchor.rel() == "external"
+            || (self.inner.settings.respect_save_data && save_data_enabled())
+        {
+            return;
+        }
+        // Pages are preloaded without their query strings, which request state could
+        // depend on, so links with query strings can't be prefetched
+        let location = web_sys::window().unwrap().location();
+        if location.origin().ok() != Some(anchor.origin())
+            || location.pathname().ok() == Some(anchor.pathname())
+            || !anchor.search().is_empty()
+        {
+            return;
+        }
+
+        // Get the path within the app, in the same way the router does
+        let pathname = anchor.pathname();
+        let path_prefix = get_path_prefix_client();
+        let path = pathname
+            .strip_prefix(&path_prefix)
+            .unwrap_or(&pathname)
+            .trim_matches('/')
+            .to_string();
+        if !self.inner.pending.borrow_mut().insert(path.clone()) {
+            return;
+        }
+        self.inner.queue.borrow_mut().push_back(path);
+        self.pump();
+    }
+    /// Starts prefetching queued paths, as long as we have capacity for more
+    /// requests.
+    fn pump(&self) {
+        // If this were zero, nothing would ever be prefetched
+        let max_concurrent = self.inner.settings.max_concurrent.max(1);
+        while self.inner.in_flight.get() < max_concurrent {
+            let path = match self.inner.queue.borrow_mut().pop_front() {
+                Some(path) => path,
+                None => return,
+            };
+            self.inner.in_flight.set(self.inner.in_flight.get() + 1);
+            let this = self.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                this.prefetch(&path).await;
+                this.inner.pending.borrow_mut().remove(&path);
+                this.inner.in_flight.set(this.inner.in_flight.get() - 1);
+                this.pump();
+            });
+        }
+    }
+    /// Prefetches the page at the given path into the page state store.
+    /// Prefetching is best-effort, so any errors are ignored (the user will
+    /// see them properly if they actually navigate to the page).
+    async fn prefetch(&self, path: &str) {
+        let path_segments = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<&str>>();
+        let verdict = match_route(
+            &path_segments,
+            &self.inner.render_cfg,
+            &self.inner.templates,
+            &self.inner.locales,
+        );
+        // Static-only pages will be loaded in full, so there's no point in prefetching
+        // their data
+        if let RouteVerdict::Found(route_info) = verdict {
+            if route_info.template.is_static_only() {
+                return;
+            }
+            let _ = self
+                .inner
+                .pss
+                .preload(
+                    &route_info.path,
+                    &route_info.locale,
+                    &route_info.template.get_path(),
+                    route_info.was_incremental_match,
+                    false,
+                )
+                .await;
+        }
+    }
+}
+
+/// Checks if the user has asked their browser to reduce data usage.
+#[cfg(target_arch = "wasm32")]
+fn save_data_enabled() -> bool {
+    let navigator = web_sys::window().unwrap().navigator();
+    // This isn't supported by all browsers, so it isn't in `web_sys`
+    Reflect::get(&navigator, &JsValue::from_str("connection"))
+        .ok()
+        .filter(|connection| connection.is_object())
+        .and_then(|connection| Reflect::get(&connection, &JsValue::from_str("saveData")).ok())
+        .and_then(|save_data| save_data.as_bool())
+        .unwrap_or(false)
+}
@@ -1,26 +1,36 @@
+#[cfg(all(feature = "hydrate", debug_assertions))]
+use crate::router::HydrationCheck;
 use crate::{
     checkpoint,
     i18n::detect_locale,
     i18n::Locales,
+    plugins::Plugins,
     router::{
         get_initial_view, get_subsequent_view, GetSubsequentViewProps, InitialView, RouterLoadState,
     },
-    router::{PerseusRoute, RouteInfo, RouteVerdict},
+    router::{
+        match_route, navigation::NavigationHooks, LinkPrefetcher, NavigationDecision, PerseusRoute,
+        PrefetchSettings, RouteInfo, RouteVerdict, RouterState, ScrollBehavior,
+    },
     template::{RenderCtx, TemplateMap, TemplateNodeType},
     utils::get_path_prefix_client,
     ErrorPages,
 };
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::rc::Rc;
+use sycamore::rt::JsCast;
 use sycamore::{
     prelude::{
-        component, create_effect, create_signal, on_mount, view, ReadSignal, Scope, Signal, View,
+        component, create_effect, create_ref, create_signal, on_mount, view, ReadSignal, Scope,
+        Signal, View,
     },
     Prop,
 };
 use sycamore_futures::spawn_local_scoped;
 use sycamore_router::{HistoryIntegration, RouterBase};
-use web_sys::Element;
+use wasm_bindgen::closure::Closure;
+use web_sys::{Element, HtmlAnchorElement};
 
 // We don't want to bring in a styling library, so we do this the old-fashioned
 // way! We're particularly comprehensive with these because the user could
@@ -49,10 +59,13 @@ const ROUTE_ANNOUNCER_STYLES: &str = r#"
 ///
 /// If the page needs to redirect to a particular locale, then this function
 /// will imperatively do so.
+///
+/// If the new page was rendered inside layouts that were already mounted, this
+/// will return `None`, and the current view should be left alone.
 async fn get_view(
     cx: Scope<'_>,
     verdict: RouteVerdict<TemplateNodeType>,
-) -> View<TemplateNodeType> {
+) -> Option<View<TemplateNodeType>> {
     checkpoint("router_entry");
     match &verdict {
         RouteVerdict::Found(RouteInfo {
@@ -61,6 +74,13 @@ async fn get_view(
             locale,
             was_incremental_match,
         }) => {
+            // Static-only pages don't load the app, so they can only be navigated to with
+            // a full page load (the URL has already been updated)
+            if template.is_static_only() {
+                checkpoint("static_only");
+                let _ = web_sys::window().unwrap().location().reload();
+                return None;
+            }
             get_subsequent_view(GetSubsequentViewProps {
                 cx,
                 path: path.clone(),
@@ -79,20 +99,187 @@ async fn get_view(
             // This shouldn't be a replacement navigation, since the user has deliberately
             // navigated here
             sycamore_router::navigate(&dest);
-            View::empty()
+            render_ctx.layouts.clear_with(View::empty())
         }
         RouteVerdict::NotFound => {
             let render_ctx = RenderCtx::from_ctx(cx);
             checkpoint("not_found");
+            render_ctx
+                .router
+                .report_navigation_error(&get_current_location(), 404);
             // TODO Update the router state here (we need a path though...)
             // This function only handles subsequent loads, so this is all we have
-            render_ctx
-                .error_pages
-                .get_view_and_render_head(cx, "", 404, "not found", None)
+            let view =
+                render_ctx
+                    .error_pages
+                    .get_view_and_render_head(cx, "", 404, "not found", None);
+            render_ctx.layouts.clear_with(view)
         }
     }
 }
 
+/// Gets the current path and query string from the browser, which is what we
+/// restore the URL to if a navigation is cancelled.
+fn get_current_location() -> String {
+    let location = web_sys::window().unwrap().location();
+    format!(
+        "{}{}",
+        location.pathname().unwrap(),
+        location.search().unwrap()
+    )
+}
+
+/// Navigates to the page described by the given route verdict, which should
+/// already have been approved by any `before_navigate` hooks, running the
+/// appropriate hooks and restoring the scroll position afterward. If
+/// `is_navigation` is `false`, the page will just be rendered, without any of
+/// that (which is used for reloading the current page).
+async fn navigate_to<'a>(
+    cx: Scope<'a>,
+    verdict: RouteVerdict<TemplateNodeType>,
+    curr_view: &'a Signal<View<TemplateNodeType>>,
+    plugins: &Plugins<TemplateNodeType>,
+    prefetcher: &Option<LinkPrefetcher>,
+    is_navigation: bool,
+) {
+    let router_state = &RenderCtx::from_ctx(cx).router;
+    // Any hooks the new page registers will belong to it
+    router_state.hooks.borrow_mut().start_generation();
+    if let Some(new_view) = get_view(cx, verdict.clone()).await {
+        curr_view.set(new_view);
+    }
+    *router_state.last_location.borrow_mut() = get_current_location();
+    if let Some(prefetcher) = prefetcher {
+        prefetcher.observe_links();
+    }
+
+    if is_navigation {
+        // The new page has been rendered now, so anything it links to by fragment should
+        // exist
+        let scroll_behavior = match &verdict {
+            RouteVerdict::Found(RouteInfo { template, .. }) => template.get_scroll_behavior(),
+            _ => ScrollBehavior::default(),
+        };
+        router_state.scroll.restore(scroll_behavior);
+        NavigationHooks::run_after_navigate(&router_state.hooks, &verdict, plugins);
+    }
+    // Now we've left the old page behind, so its hooks can go too
+    router_state.hooks.borrow_mut().drop_old_generations();
+}
+
+/// Sets up a listener that handles clicks on links before Sycamore's router
+/// does. For links to other pages, this runs the `before_navigate` hooks
+/// before the browser's history is updated, so that cancelled navigations
+/// never make it into the history at all.
+///
+/// This also handles links whose URLs have query strings, which Sycamore's
+/// router would otherwise drop. Links to other pages are navigated to as usual
+/// (keeping their query strings), while links that only change the current
+/// page's query string just update the URL and the router's query parameters,
+/// without re-rendering anything.
+fn intercept_links(
+    router_state: RouterState,
+    render_ctx: &RenderCtx,
+    plugins: Rc<Plugins<TemplateNodeType>>,
+) {
+    let render_cfg = render_ctx.render_cfg.clone();
+    let templates = render_ctx.templates.clone();
+    let locales = render_ctx.locales.clone();
+    let document = web_sys::window().unwrap().document().unwrap();
+    let closure = Closure::wrap(Box::new(move |ev: web_sys::MouseEvent| {
+        let anchor = ev
+            .target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .and_then(|elem| elem.closest("a[href]").ok().flatten());
+        let anchor: HtmlAnchorElement = match anchor {
+            Some(anchor) => anchor.unchecked_into(),
+            None => return,
+        };
+        // This mirrors the checks Sycamore's router makes before handling a link itself
+        if anchor.rel() == "external"
+            || ev.meta_key()
+            || ev.ctrl_key()
+            || ev.shift_key()
+            || ev.alt_key()
+        {
+            return;
+        }
+        let location = web_sys::window().unwrap().location();
+        if location.origin().ok() != Some(anchor.origin()) {
+            // The browser can handle this itself
+            return;
+        }
+        let search = anchor.search();
+        let pathname = anchor.pathname();
+        let hash = anchor.hash();
+
+        // Links to other pages are navigations, which the page (or a plugin) might want
+        // to stop
+        if location.pathname().ok() != Some(pathname.clone()) {
+            let path_prefix = get_path_prefix_client();
+            let segments = pathname
+                .strip_prefix(&path_prefix)
+                .unwrap_or(&pathname)
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .collect::<Vec<_>>();
+            let verdict = match_route(&segments, &render_cfg, &templates, &locales);
+            match NavigationHooks::run_before_navigate(&router_state.hooks, &verdict, &plugins) {
+                NavigationDecision::Proceed => {
+                    *router_state.approved_path.borrow_mut() = Some(segments.join("/"));
+                    // Sycamore's router will have handled the click by the time this runs, so,
+                    // if it didn't navigate (e.g. because the link was outside the app), the
+                    // approval shouldn't apply to a later navigation
+                    let approved_path = router_state.approved_path.clone();
+                    crate::spawn_local(async move {
+                        approved_path.borrow_mut().take();
+                    });
+                }
+                NavigationDecision::Cancel => {
+                    ev.prevent_default();
+                    ev.stop_propagation();
+                    return;
+                }
+                NavigationDecision::Redirect(dest) => {
+                    ev.prevent_default();
+                    ev.stop_propagation();
+                    sycamore_router::navigate(&dest);
+                    return;
+                }
+            }
+        }
+
+        if search.is_empty() && location.search().unwrap().is_empty() {
+            // Sycamore can handle this itself
+            return;
+        }
+        if location.pathname().ok() == Some(pathname.clone())
+            && location.search().ok() == Some(search.clone())
+        {
+            // This is a fragment link (or a link to the current URL), which Sycamore
+            // can also handle itself
+            return;
+        }
+
+        // We'll take it from here (stopping Sycamore's router from dropping the
+        // query string)
+        ev.prevent_default();
+        ev.stop_propagation();
+        let url = format!("{}{}{}", pathname, search, hash);
+        if location.pathname().ok() == Some(pathname) {
+            router_state.push_location(&url);
+        } else {
+            sycamore_router::navigate(&url);
+        }
+    }) as Box<dyn FnMut(web_sys::MouseEvent)>);
+    // We use the capture phase so this runs before Sycamore's own handler
+    document
+        .add_event_listener_with_callback_and_bool("click", closure.as_ref().unchecked_ref(), true)
+        .unwrap();
+    // The router lives as long as the app, so this is never removed
+    closure.forget();
+}
+
 /// The properties that the router takes.
 #[derive(Debug, Prop)]
 pub(crate) struct PerseusRouterProps {
@@ -108,6 +295,33 @@ pub(crate) struct PerseusRouterProps {
     /// The maximum size of the page state store, before pages are evicted
     /// to save memory in the browser.
     pub pss_max_size: usize,
+    /// The policy for evicting pages from the PSS based on their estimated
+    /// sizes, if there is one.
+    pub pss_memory_policy: Option<crate::state::PssMemoryPolicy>,
+    /// The plugins the app is using, which may have navigation hooks.
+    pub plugins: Rc<Plugins<TemplateNodeType>>,
+    /// The settings for automatically prefetching linked pages, if that's
+    /// enabled.
+    pub prefetch: Option<PrefetchSettings>,
+    /// The check that the initial view was hydrated correctly, if we're
+    /// hydrating it.
+    #[cfg(all(feature = "hydrate", debug_assertions))]
+    pub hydration_check: Option<HydrationCheck>,
+    /// The frozen state store to use for hot state reloading, if the default
+    /// shouldn't be used.
+    #[cfg(all(feature = "hsr", debug_assertions))]
+    pub hsr_store: Option<Rc<dyn crate::state::FrozenStateStore>>,
+    /// The settings for synchronizing global state across tabs, if that's
+    /// enabled.
+    #[cfg(feature = "global-state-sync")]
+    pub global_state_sync: Option<crate::state::GlobalStateSync>,
+    /// The maximum number of entries to record in each timeline of the state
+    /// history, if that's enabled.
+    #[cfg(feature = "state-history")]
+    pub state_history_len: Option<usize>,
+    /// Whether or not the app should subscribe to state pushed from the
+    /// server.
+    pub subscribe_to_state_pushes: bool,
 }
 
 /// The Perseus router. This is used internally in the Perseus engine, and you
@@ -128,6 +342,18 @@ pub(crate) fn perseus_router(
         templates,
         render_cfg,
         pss_max_size,
+        pss_memory_policy,
+        plugins,
+        prefetch,
+        #[cfg(all(feature = "hydrate", debug_assertions))]
+        hydration_check,
+        #[cfg(all(feature = "hsr", debug_assertions))]
+        hsr_store,
+        #[cfg(feature = "global-state-sync")]
+        global_state_sync,
+        #[cfg(feature = "state-history")]
+        state_history_len,
+        subscribe_to_state_pushes,
     }: PerseusRouterProps,
 ) -> View<TemplateNodeType> {
     // Now create an instance of `RenderCtx`, which we'll insert into context and
@@ -135,12 +361,45 @@ pub(crate) fn perseus_router(
     // needs in terms of infrastructure)
     let render_ctx = RenderCtx::new(
         pss_max_size,
+        pss_memory_policy,
         locales,   // Pretty light
         templates, // Already has `Rc`s
         Rc::new(render_cfg),
         Rc::new(error_pages),
+        #[cfg(feature = "global-state-sync")]
+        global_state_sync,
+        #[cfg(feature = "state-history")]
+        state_history_len,
     )
     .set_ctx(cx);
+    let plugins = create_ref(cx, plugins);
+    let prefetcher = create_ref(
+        cx,
+        prefetch.map(|settings| LinkPrefetcher::new(settings, render_ctx)),
+    );
+    // Links on the initial page can only be observed once it's been hydrated
+    on_mount(cx, move || {
+        if let Some(prefetcher) = prefetcher {
+            prefetcher.observe_links();
+        }
+    });
+    #[cfg(all(feature = "devtools", debug_assertions))]
+    crate::devtools::mount_devtools(cx, render_ctx);
+    #[cfg(feature = "state-history")]
+    render_ctx.history.start_recording(cx);
+    if subscribe_to_state_pushes {
+        crate::state::subscribe_to_state_pushes(
+            cx,
+            render_ctx.page_state_store.clone(),
+            render_ctx.router.clone(),
+            render_ctx.frozen_app.clone(),
+        );
+    }
+    // Similarly, we can only check the hydrated view once hydration is done
+    #[cfg(all(feature = "hydrate", debug_assertions))]
+    if let Some(hydration_check) = hydration_check {
+        on_mount(cx, move || hydration_check.run(&render_ctx.router));
+    }
 
     // Get the current path, removing any base paths to avoid relative path locale
     // redirection loops (in previous versions of Perseus, we used Sycamore to
@@ -158,6 +417,8 @@ pub(crate) fn perseus_router(
     // Prepare the initial view for hydration (because we have everything we need in
     // global window variables, this can be synchronous)
     let initial_view = get_initial_view(cx, path.to_string());
+    *render_ctx.router.last_location.borrow_mut() = get_current_location();
+    render_ctx.router.scroll.init();
     let initial_view = match initial_view {
         InitialView::View(initial_view) => initial_view,
         // if we need to redirect, then we'll create a fake view that will just execute that code
@@ -184,6 +445,7 @@ pub(crate) fn perseus_router(
     // Create a `Route` to pass through Sycamore with the information we need
     let route = PerseusRoute {
         verdict: RouteVerdict::NotFound,
+        path: String::new(),
         cx: Some(cx),
     };
 
@@ -263,15 +525,18 @@ pub(crate) fn perseus_router(
                 // If the first page hasn't loaded yet, terminate now
                 None => return,
             };
-            spawn_local_scoped(cx, async move {
-                let new_view = get_view(cx, verdict.clone()).await;
-                curr_view.set(new_view);
-            });
+            // Reloading isn't a navigation, so we don't run any hooks
+            spawn_local_scoped(
+                cx,
+                navigate_to(cx, verdict, curr_view, plugins, prefetcher, false),
+            );
         }
     });
 
     // This section handles live reloading and HSR freezing
     // We used to have an indicator shared to the macros, but that's no longer used
+    #[cfg(all(feature = "hsr", debug_assertions))]
+    let hsr_store = create_ref(cx, hsr_store);
     #[cfg(all(feature = "live-reload", debug_assertions))]
     {
         use crate::state::Freeze;
@@ -288,7 +553,7 @@ pub(crate) fn perseus_router(
                     #[cfg(all(feature = "hsr"))]
                     {
                         let frozen_state = render_ctx.freeze();
-                        crate::state::hsr_freeze(frozen_state).await;
+                        crate::state::hsr_freeze(frozen_state, hsr_store.clone()).await;
                     }
                     crate::state::force_reload();
                     // We shouldn't ever get here unless there was an error, the
@@ -314,11 +579,13 @@ pub(crate) fn perseus_router(
             // initial load
             if render_ctx.is_first.get() {
                 render_ctx.is_first.set(false);
-                crate::state::hsr_thaw(&render_ctx).await;
+                crate::state::hsr_thaw(&render_ctx, hsr_store.clone()).await;
             }
         });
     };
 
+    intercept_links(router_state.clone(), render_ctx, plugins.clone());
+
     // Append the route announcer to the end of the document body
     let document = web_sys::window().unwrap().document().unwrap();
     let announcer = document.create_element("p").unwrap();
@@ -350,13 +617,78 @@ pub(crate) fn perseus_router(
 
                     if *is_initial.get_untracked() {
                         is_initial.set(false);
+                        *router_state.curr_path.borrow_mut() = route.get_untracked().path.clone();
                     } else {
-                        spawn_local_scoped(cx, async move {
-                            let route = route.get();
-                            let verdict = route.get_verdict();
-                            let new_view = get_view(cx, verdict.clone()).await;
-                            curr_view.set(new_view);
-                        });
+                        // If we've just restored the URL after cancelling a navigation, the right
+                        // page is already being displayed
+                        if router_state.suppress_next.replace(false) {
+                            return;
+                        }
+
+                        // This has to happen before the browser is scrolled for the new page
+                        router_state.scroll.save();
+                        // If only the query string has changed (e.g. the user has gone back after
+                        // clicking a link to the same page with a different query string), there's
+                        // nothing to render, we just need to update the query parameters once the
+                        // browser's history has been updated
+                        let new_path = route.get_untracked().path.clone();
+                        if *router_state.curr_path.borrow() == new_path {
+                            spawn_local_scoped(cx, async move {
+                                router_state.scroll.restore(ScrollBehavior::Manual);
+                                router_state.sync_query();
+                            });
+                            return;
+                        }
+                        let verdict = route.get_untracked().get_verdict().clone();
+                        // Link clicks will already have been checked by the hooks
+                        let approved = router_state.approved_path.borrow_mut().take().as_ref() == Some(&new_path);
+                        let decision = match approved {
+                            true => NavigationDecision::Proceed,
+                            false => NavigationHooks::run_before_navigate(&router_state.hooks, &verdict, plugins),
+                        };
+                        // This runs before the browser's history has been updated for `navigate()`
+                        // calls, so anything that changes the URL for those has to wait until
+                        // afterward
+                        match decision {
+                            NavigationDecision::Proceed => {
+                                *router_state.curr_path.borrow_mut() = new_path;
+                                spawn_local_scoped(cx, navigate_to(cx, verdict, curr_view, plugins, prefetcher, true))
+                            }
+                            // We undo the navigation in the browser's history, rather than adding a
+                            // new entry for the page the user's still on
+                            NavigationDecision::Cancel => match router_state.scroll.compare_history_entry() {
+                                // The user has gone back or forward, so we go the other way
+                                Some(Ordering::Less) => {
+                                    router_state.suppress_next.set(true);
+                                    let _ = web_sys::window().unwrap().history().unwrap().go_with_delta(1);
+                                }
+                                Some(Ordering::Greater) => {
+                                    router_state.suppress_next.set(true);
+                                    let _ = web_sys::window().unwrap().history().unwrap().go_with_delta(-1);
+                                }
+                                // This is a `navigate()` call, which will push a new entry (and scroll
+                                // to the top) once we're done, so we go back from that, and then put
+                                // the user back where they were
+                                Some(Ordering::Equal) => spawn_local_scoped(cx, async move {
+                                    router_state.suppress_next.set(true);
+                                    let _ = web_sys::window().unwrap().history().unwrap().back();
+                                    router_state.scroll.restore_current();
+                                }),
+                                // If we can't tell where the browser is, the best we can do is put the
+                                // last URL back
+                                None => {
+                                    let last_location = router_state.last_location.borrow().clone();
+                                    spawn_local_scoped(cx, async move {
+                                        router_state.suppress_next.set(true);
+                                        sycamore_router::navigate_replace(&last_location);
+                                        router_state.scroll.restore_current();
+                                    })
+                                }
+                            },
+                            NavigationDecision::Redirect(dest) => spawn_local_scoped(cx, async move {
+                                sycamore_router::navigate_replace(&dest);
+                            }),
+                        }
                     }
                 });
 
@@ -1,5 +1,9 @@
+use super::navigation::{NavigationDecision, NavigationError, NavigationHookId, NavigationHooks};
 use super::RouteVerdict;
 use crate::template::TemplateNodeType;
+use crate::utils::QueryParams;
+#[cfg(target_arch = "wasm32")]
+use std::cell::Cell;
 use std::cell::RefCell;
 use std::rc::Rc;
 use sycamore::prelude::{create_rc_signal, create_ref, RcSignal, Scope};
@@ -19,6 +23,32 @@ pub struct RouterState {
     /// should rarely ever need to do this, but it's used internally in the
     /// thawing process.
     pub(crate) reload_commander: RcSignal<bool>,
+    /// The query parameters of the current URL. This is in an `RcSignal` so
+    /// that pages can react to changes in it.
+    query: RcSignal<QueryParams>,
+    /// The navigation hooks pages have registered.
+    pub(crate) hooks: Rc<RefCell<NavigationHooks>>,
+    /// The path (and query string) the user was at after the last navigation
+    /// the router let go ahead. If a navigation is cancelled, the URL will be
+    /// restored to this.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) last_location: Rc<RefCell<String>>,
+    /// The path (without the query string) of the page the router is currently
+    /// on, which lets us tell when only the query string has changed.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) curr_path: Rc<RefCell<String>>,
+    /// Whether or not the router should ignore the next route change, which is
+    /// used when restoring the URL after a navigation is cancelled.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) suppress_next: Rc<Cell<bool>>,
+    /// The path of a navigation that's been triggered by a link the user
+    /// clicked, whose `before_navigate` hooks have already been run (before
+    /// the browser's history was updated), so that they aren't run again.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) approved_path: Rc<RefCell<Option<String>>>,
+    /// The scroll positions of the pages the user has visited.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) scroll: super::ScrollManager,
 }
 impl Default for RouterState {
     /// Creates a default instance of the router state intended for server-side
@@ -29,6 +59,18 @@ impl Default for RouterState {
             last_verdict: Rc::new(RefCell::new(None)),
             // It doesn't matter what we initialize this as, it's just for signalling
             reload_commander: create_rc_signal(true),
+            query: create_rc_signal(QueryParams::new()),
+            hooks: Rc::new(RefCell::new(NavigationHooks::default())),
+            #[cfg(target_arch = "wasm32")]
+            last_location: Rc::new(RefCell::new(String::new())),
+            #[cfg(target_arch = "wasm32")]
+            curr_path: Rc::new(RefCell::new(String::new())),
+            #[cfg(target_arch = "wasm32")]
+            suppress_next: Rc::new(Cell::new(false)),
+            #[cfg(target_arch = "wasm32")]
+            approved_path: Rc::new(RefCell::new(None)),
+            #[cfg(target_arch = "wasm32")]
+            scroll: super::ScrollManager::default(),
         }
     }
 }
@@ -61,6 +103,74 @@ impl RouterState {
         let mut last_verdict = self.last_verdict.borrow_mut();
         *last_verdict = Some(new);
     }
+    /// Gets the query parameters of the current URL. You'll still need to call
+    /// `.get()` after this (this just returns a `&'a RcSignal` to derive
+    /// other state from in a `create_memo` or the like).
+    ///
+    /// This will be updated whenever the query string changes, including when
+    /// the user clicks a link that only changes the query string, which won't
+    /// lead to the page being re-rendered. That means pages like search
+    /// results can react to `?q=...` changes without a full navigation.
+    ///
+    /// On the engine-side, this will only contain anything for pages rendered
+    /// at request-time.
+    pub fn get_query<'a>(&self, cx: Scope<'a>) -> &'a RcSignal<QueryParams> {
+        create_ref(cx, self.query.clone())
+    }
+    /// Sets the query parameters of the current URL, without changing the URL
+    /// itself.
+    pub(crate) fn set_query(&self, query: QueryParams) {
+        self.query.set(query);
+    }
+    /// Changes the query string of the current URL to the given query
+    /// parameters, adding a new entry to the browser's history. This will
+    /// update the signal from `.get_query()`, but it won't re-render the
+    /// current page or run any navigation hooks.
+    #[cfg(target_arch = "wasm32")]
+    pub fn push_query(&self, query: &QueryParams) {
+        // We sort these so the same parameters always produce the same URL
+        let mut pairs = query
+            .iter()
+            .map(|(key, val)| format!("{}={}", urlencoding::encode(key), urlencoding::encode(val)))
+            .collect::<Vec<String>>();
+        pairs.sort();
+        let location = web_sys::window().unwrap().location();
+        let search = match pairs.is_empty() {
+            true => String::new(),
+            false => format!("?{}", pairs.join("&")),
+        };
+        self.push_location(&format!(
+            "{}{}{}",
+            location.pathname().unwrap(),
+            search,
+            location.hash().unwrap()
+        ));
+    }
+    /// Moves the browser to the given URL, which must only differ from the
+    /// current one in its query string (and fragment), without rendering
+    /// anything, and updates the query parameters.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn push_location(&self, url: &str) {
+        self.scroll.save();
+        let window = web_sys::window().unwrap();
+        let _ = window.history().unwrap().push_state_with_url(
+            &wasm_bindgen::JsValue::UNDEFINED,
+            "",
+            Some(url),
+        );
+        // This just gives the new history entry a key for saving its scroll position
+        self.scroll.restore(super::ScrollBehavior::Manual);
+        self.sync_query();
+    }
+    /// Updates the query parameters (and the location a cancelled navigation
+    /// would return to) from the browser's current URL.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn sync_query(&self) {
+        let location = web_sys::window().unwrap().location();
+        let search = location.search().unwrap();
+        self.query.set(crate::utils::parse_query(&search));
+        *self.last_location.borrow_mut() = format!("{}{}", location.pathname().unwrap(), search);
+    }
     /// Orders the router to reload the current page as if you'd called
     /// `navigate()` to it (but that would do nothing). This
     /// enables reloading in an SPA style (but you should almost never need it).
@@ -71,6 +181,69 @@ impl RouterState {
         self.reload_commander
             .set(!*self.reload_commander.get_untracked())
     }
+    /// Registers a hook that will be run before the router navigates to a new
+    /// page, whether from a link click, a call to `navigate()`, or the
+    /// browser's back/forward buttons. This is given the route the router is
+    /// about to navigate to, and can let the navigation proceed, cancel it
+    /// (e.g. if the user has unsaved changes), or redirect the user somewhere
+    /// else.
+    ///
+    /// Hooks registered by a page will stay active until the user has
+    /// navigated away from it, so they should be registered in the page's
+    /// render function. If you need a hook that lasts for the lifetime of
+    /// the app, use a plugin instead.
+    ///
+    /// Note that, by the time this is run, the browser has usually already
+    /// added the target URL to its history, so cancelling a navigation will
+    /// restore the previous URL by replacing that entry.
+    pub fn before_navigate(
+        &self,
+        hook: impl Fn(&RouteVerdict<TemplateNodeType>) -> NavigationDecision + 'static,
+    ) -> NavigationHookId {
+        self.hooks.borrow_mut().add_before_navigate(Rc::new(hook))
+    }
+    /// Registers a hook that will be run after the router has successfully
+    /// navigated to a new page, once that page has been rendered. This is
+    /// given the route that was navigated to.
+    ///
+    /// Hooks registered by a page will stay active until the user has
+    /// navigated away from it (which means they'll be run for that navigation
+    /// as well).
+    pub fn after_navigate(
+        &self,
+        hook: impl Fn(&RouteVerdict<TemplateNodeType>) + 'static,
+    ) -> NavigationHookId {
+        self.hooks.borrow_mut().add_after_navigate(Rc::new(hook))
+    }
+    /// Registers a hook that will be run if the router fails to navigate to a
+    /// new page, after the appropriate error page has been rendered. This is
+    /// given the route that was being navigated to and details of the error.
+    ///
+    /// Hooks registered by a page will stay active until the user has
+    /// navigated away from it (which means they'll be run for that navigation
+    /// as well).
+    pub fn on_navigation_error(
+        &self,
+        hook: impl Fn(&RouteVerdict<TemplateNodeType>, &NavigationError) + 'static,
+    ) -> NavigationHookId {
+        self.hooks
+            .borrow_mut()
+            .add_on_navigation_error(Rc::new(hook))
+    }
+    /// Removes the navigation hook with the given identifier. This does nothing
+    /// if the hook has already been removed.
+    pub fn remove_navigation_hook(&self, id: NavigationHookId) {
+        self.hooks.borrow_mut().remove(id);
+    }
+    /// Records an error that occurred while navigating, so that it can be
+    /// given to any `on_navigation_error` hooks.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn report_navigation_error(&self, url: &str, status: u16) {
+        self.hooks.borrow_mut().set_error(NavigationError {
+            url: url.to_string(),
+            status,
+        });
+    }
 }
 
 /// The current load state of the router. You can use this to be warned of when
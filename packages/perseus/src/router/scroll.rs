@@ -0,0 +1,229 @@
+#[cfg(target_arch = "wasm32")]
+use serde::{Deserialize, Serialize};
+#[cfg(target_arch = "wasm32")]
+use std::cell::{Cell, RefCell};
+#[cfg(target_arch = "wasm32")]
+use std::collections::HashMap;
+#[cfg(target_arch = "wasm32")]
+use std::rc::Rc;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsValue;
+
+/// How the router should handle the scroll position when the user navigates to
+/// a page.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ScrollBehavior {
+    /// Restore the scroll position the user was at when they left the page if
+    /// they come back to it with the browser's back/forward buttons.
+    /// Otherwise, scroll to the element targeted by the URL's fragment (e.g.
+    /// `#some-heading`), or to the top of the page if there isn't one.
+    #[default]
+    Restore,
+    /// Always scroll to the element targeted by the URL's fragment, or to the
+    /// top of the page, even when the user comes back to the page with the
+    /// browser's back/forward buttons.
+    Top,
+    /// Leave the scroll position alone. Note that the browser will still be
+    /// scrolled to the top when the user clicks a link, before the new page
+    /// is rendered.
+    Manual,
+    /// Call the given function to scroll the page, once it has been rendered.
+    /// This will be given the scroll position the router would have restored,
+    /// and the URL's fragment.
+    Custom(fn(ScrollInfo)),
+}
+
+/// The information the router has about where a page should be scrolled to,
+/// which is given to custom scrolling functions.
+#[derive(Clone, Debug)]
+pub struct ScrollInfo {
+    /// The scroll position (`x`, `y`) the user was at when they last left this
+    /// page, if they've come back to it with the browser's back/forward
+    /// buttons.
+    pub saved_position: Option<(f64, f64)>,
+    /// The fragment in the URL (without the `#`), if there is one.
+    pub fragment: Option<String>,
+}
+
+/// The key in session storage under which scroll positions are saved, which
+/// allows them to survive reloads.
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY: &str = "__perseus_scroll_positions";
+/// The maximum number of history entries we'll keep scroll positions for.
+#[cfg(target_arch = "wasm32")]
+const MAX_SAVED_POSITIONS: usize = 100;
+
+/// The scroll positions of the history entries we've seen, which are
+/// identified by keys we put in their `history.state`.
+#[cfg(target_arch = "wasm32")]
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct SavedPositions {
+    /// The key that will be given to the next new history entry.
+    next_key: u32,
+    /// The saved scroll positions, indexed by history entry key.
+    positions: HashMap<u32, (f64, f64)>,
+}
+
+/// The router's record of scroll positions, which is used to restore them when
+/// the user navigates back and forward.
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Default, Debug)]
+pub(crate) struct ScrollManager {
+    /// The key of the history entry the user is currently on.
+    curr_key: Rc<Cell<Option<u32>>>,
+    /// The scroll positions we know of.
+    saved: Rc<RefCell<SavedPositions>>,
+}
+#[cfg(target_arch = "wasm32")]
+impl ScrollManager {
+    /// Sets up scroll restoration for the initial page. This takes over
+    /// scroll restoration from the browser, which would otherwise restore
+    /// positions before pages have been rendered.
+    pub(crate) fn init(&self) {
+        let window = web_sys::window().unwrap();
+        let history = window.history().unwrap();
+        let _ = history.set_scroll_restoration(web_sys::ScrollRestoration::Manual);
+
+        let saved = window
+            .session_storage()
+            .ok()
+            .flatten()
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten())
+            .and_then(|saved| serde_json::from_str::<SavedPositions>(&saved).ok())
+            .unwrap_or_default();
+        *self.saved.borrow_mut() = saved;
+        self.tag_entry();
+    }
+    /// Saves the current scroll position for the history entry the user is
+    /// on. This must be called before the browser is scrolled for the new
+    /// page.
+    pub(crate) fn save(&self) {
+        let curr_key = match self.curr_key.get() {
+            Some(key) => key,
+            None => return,
+        };
+        let window = web_sys::window().unwrap();
+        let position = (
+            window.scroll_x().unwrap_or(0.0),
+            window.scroll_y().unwrap_or(0.0),
+        );
+        let mut saved = self.saved.borrow_mut();
+        saved.positions.insert(curr_key, position);
+        // Evict the oldest entries if we have too many
+        while saved.positions.len() > MAX_SAVED_POSITIONS {
+            let oldest = *saved.positions.keys().min().unwrap();
+            saved.positions.remove(&oldest);
+        }
+        drop(saved);
+        self.persist();
+    }
+    /// Scrolls the page appropriately after the user has navigated to it (and
+    /// it has been rendered), according to the given behavior.
+    pub(crate) fn restore(&self, behavior: ScrollBehavior) {
+        let saved_position = self.tag_entry();
+        let hash = web_sys::window()
+            .unwrap()
+            .location()
+            .hash()
+            .unwrap_or_default();
+        let fragment = hash
+            .strip_prefix('#')
+            .filter(|fragment| !fragment.is_empty())
+            .map(|fragment| fragment.to_string());
+
+        match behavior {
+            ScrollBehavior::Restore => match saved_position {
+                Some((x, y)) => scroll_to(x, y),
+                None => scroll_to_fragment(fragment),
+            },
+            ScrollBehavior::Top => scroll_to_fragment(fragment),
+            ScrollBehavior::Manual => (),
+            ScrollBehavior::Custom(scroll_fn) => scroll_fn(ScrollInfo {
+                saved_position,
+                fragment,
+            }),
+        }
+    }
+    /// Scrolls back to the position last saved for the history entry the user
+    /// is on. This is used when a navigation away from it has been cancelled,
+    /// since Sycamore will already have scrolled to the top for the new page.
+    pub(crate) fn restore_current(&self) {
+        let position = self
+            .curr_key
+            .get()
+            .and_then(|key| self.saved.borrow().positions.get(&key).copied());
+        if let Some((x, y)) = position {
+            scroll_to(x, y);
+        }
+    }
+    /// Compares the browser's current history entry with the one the user was
+    /// on when the current page was rendered, which tells us if they've since
+    /// gone back (`Less`) or forward (`Greater`), or if the browser hasn't
+    /// moved yet (`Equal`). This will return `None` if we can't tell.
+    ///
+    /// This works because we give new history entries increasing keys, and
+    /// the browser discards any entries ahead of the current one whenever a
+    /// new one is pushed.
+    pub(crate) fn compare_history_entry(&self) -> Option<std::cmp::Ordering> {
+        let history = web_sys::window().unwrap().history().unwrap();
+        let entry_key = history
+            .state()
+            .ok()
+            .and_then(|state| state.as_f64())
+            .map(|key| key as u32)?;
+        Some(entry_key.cmp(&self.curr_key.get()?))
+    }
+    /// Writes the saved scroll positions to session storage. This is
+    /// best-effort, since session storage may be unavailable.
+    fn persist(&self) {
+        let storage = web_sys::window().unwrap().session_storage();
+        if let (Ok(Some(storage)), Ok(saved)) =
+            (storage, serde_json::to_string(&*self.saved.borrow()))
+        {
+            let _ = storage.set_item(STORAGE_KEY, &saved);
+        }
+    }
+    /// Makes sure the current history entry has a key, returning its saved
+    /// scroll position if it was already known.
+    fn tag_entry(&self) -> Option<(f64, f64)> {
+        let history = web_sys::window().unwrap().history().unwrap();
+        let existing_key = history
+            .state()
+            .ok()
+            .and_then(|state| state.as_f64())
+            .map(|key| key as u32);
+        match existing_key {
+            Some(key) => {
+                self.curr_key.set(Some(key));
+                self.saved.borrow().positions.get(&key).copied()
+            }
+            None => {
+                let mut saved = self.saved.borrow_mut();
+                let key = saved.next_key;
+                saved.next_key += 1;
+                drop(saved);
+                let _ = history.replace_state(&JsValue::from(key), "");
+                self.curr_key.set(Some(key));
+                self.persist();
+                None
+            }
+        }
+    }
+}
+
+/// Scrolls the window to the given position.
+#[cfg(target_arch = "wasm32")]
+fn scroll_to(x: f64, y: f64) {
+    web_sys::window().unwrap().scroll_to_with_x_and_y(x, y);
+}
+/// Scrolls to the element with the given fragment as its ID, or to the top of
+/// the page if there isn't one.
+#[cfg(target_arch = "wasm32")]
+fn scroll_to_fragment(fragment: Option<String>) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let elem = fragment.and_then(|fragment| document.get_element_by_id(&fragment));
+    match elem {
+        Some(elem) => elem.scroll_into_view(),
+        None => scroll_to(0.0, 0.0),
+    }
+}
@@ -0,0 +1,258 @@
+use crate::errors::*;
+use crate::stores::ImmutableStore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384};
+use std::fs;
+use std::path::Path;
+
+/// The `Cache-Control` header that integrations should send with bundles
+/// served from content-hashed URLs. Since the URL changes whenever the
+/// contents do, browsers can keep these forever.
+pub const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// The number of hex characters of the combined hash to use as the bundle ID.
+const BUNDLE_ID_LENGTH: usize = 16;
+
+/// A record of the content hashes of the app's bundles, which allows them to
+/// be served from URLs that change whenever they do (and can therefore be
+/// cached indefinitely), and to be loaded with [Subresource Integrity](https://developer.mozilla.org/en-US/docs/Web/Security/Subresource_Integrity)
+/// checks.
+///
+/// All bundles (including JS snippets) are served from under
+/// `/.perseus/bundles/<id>/`, so that the relative imports of snippets in the
+/// JS bundle are cache-busted along with everything else.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BundleManifest {
+    /// A hash of all the bundles together, which will be used in their URLs.
+    pub id: String,
+    /// The integrity metadata for the JS bundle (e.g. `sha384-...`).
+    pub js_integrity: String,
+    /// The integrity metadata for the Wasm bundle (e.g. `sha384-...`).
+    pub wasm_integrity: String,
+    /// The integrity metadata for the JS version of the Wasm bundle produced
+    /// for the `wasm2js` feature, if there is one.
+    #[serde(default)]
+    pub wasm_js_integrity: Option<String>,
+}
+impl BundleManifest {
+    /// Creates a new manifest by hashing the given JS and Wasm bundles, along
+    /// with the JS version of the Wasm bundle (which will only exist if the app
+    /// has been built for `wasm2js`) and everything in the given snippets
+    /// directory (which may not exist).
+    pub fn new(
+        js_bundle: &str,
+        wasm_bundle: &str,
+        wasm_js_bundle: &str,
+        snippets: &str,
+    ) -> Result<Self, BuildError> {
+        let js = read_bundle(js_bundle)?;
+        let wasm = read_bundle(wasm_bundle)?;
+        let wasm_js = match fs::read(wasm_js_bundle) {
+            Ok(contents) => Some(contents),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(BuildError::ReadBundleFailed {
+                    path: wasm_js_bundle.to_string(),
+                    source: err,
+                })
+            }
+        };
+
+        let mut id_hasher = Sha256::new();
+        id_hasher.update(&js);
+        id_hasher.update(&wasm);
+        if let Some(wasm_js) = &wasm_js {
+            id_hasher.update(wasm_js);
+        }
+        hash_snippets(Path::new(snippets), &mut id_hasher)?;
+        let id = id_hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        Ok(Self {
+            id: id[..BUNDLE_ID_LENGTH].to_string(),
+            js_integrity: get_integrity(&js),
+            wasm_integrity: get_integrity(&wasm),
+            wasm_js_integrity: wasm_js.as_deref().map(get_integrity),
+        })
+    }
+    /// Gets the URL under which all the bundles in this manifest should be
+    /// served, with the given path prefix. This has no trailing slash.
+    pub fn get_url_root(&self, path_prefix: &str) -> String {
+        format!("{}/.perseus/bundles/{}", path_prefix, self.id)
+    }
+    /// Reads a previously written manifest from the given immutable store,
+    /// returning `None` if there isn't one.
+    pub async fn read(immutable_store: &ImmutableStore) -> Option<Self> {
+        let contents = immutable_store.read("bundle_manifest.json").await.ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+    /// Writes this manifest to the given immutable store, where the server can
+    /// find it to serve the bundles, and the CLI can find it to lay out
+    /// exported bundles.
+    pub async fn write(&self, immutable_store: &ImmutableStore) -> Result<(), ServerError> {
+        let contents = serde_json::to_string(self).map_err(|err| {
+            let build_err: BuildError = err.into();
+            build_err
+        })?;
+        immutable_store
+            .write("bundle_manifest.json", &contents)
+            .await?;
+
+        Ok(())
+    }
+    /// Removes any previously written manifest from the given immutable store,
+    /// so that the hashes of old bundles can't outlive them.
+    pub async fn remove(immutable_store: &ImmutableStore) -> Result<(), ServerError> {
+        immutable_store.delete("bundle_manifest.json").await?;
+
+        Ok(())
+    }
+}
+
+/// Reads the bundle at the given path into memory.
+fn read_bundle(path: &str) -> Result<Vec<u8>, BuildError> {
+    fs::read(path).map_err(|err| BuildError::ReadBundleFailed {
+        path: path.to_string(),
+        source: err,
+    })
+}
+
+/// Produces integrity metadata for the given bundle contents.
+fn get_integrity(contents: &[u8]) -> String {
+    format!("sha384-{}", base64::encode(Sha384::digest(contents)))
+}
+
+/// Recursively feeds every snippet file in the given directory into the given
+/// hasher, in a deterministic order. A directory that doesn't exist is fine,
+/// since many apps have no snippets.
+fn hash_snippets(dir: &Path, hasher: &mut Sha256) -> Result<(), BuildError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| BuildError::ReadBundleFailed {
+            path: dir.to_string_lossy().to_string(),
+            source: err,
+        })?;
+        paths.push(entry.path());
+    }
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            hash_snippets(&path, hasher)?;
+        } else {
+            // The path is included so that moving a snippet changes the hash too
+            hasher.update(path.to_string_lossy().as_bytes());
+            hasher.update(read_bundle(&path.to_string_lossy())?);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Creates a fresh directory for a test's bundles, which won't clash with
+    /// any other test's.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "perseus-bundles-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("snippets/a")).unwrap();
+        fs::write(dir.join("bundle.js"), "js").unwrap();
+        fs::write(dir.join("bundle.wasm"), "wasm").unwrap();
+        fs::write(dir.join("snippets/a/snippet.js"), "snippet").unwrap();
+        dir
+    }
+    fn manifest(dir: &Path) -> Result<BundleManifest, BuildError> {
+        let path = |file: &str| dir.join(file).to_string_lossy().to_string();
+        BundleManifest::new(
+            &path("bundle.js"),
+            &path("bundle.wasm"),
+            &path("bundle.wasm.js"),
+            &path("snippets"),
+        )
+    }
+
+    #[test]
+    fn produces_integrity_metadata() {
+        let dir = test_dir("integrity");
+        let manifest = manifest(&dir).unwrap();
+        assert_eq!(manifest.js_integrity, get_integrity(b"js"));
+        assert_eq!(manifest.wasm_integrity, get_integrity(b"wasm"));
+        assert!(manifest.js_integrity.starts_with("sha384-"));
+        assert_eq!(manifest.wasm_js_integrity, None);
+        assert_eq!(manifest.id.len(), BUNDLE_ID_LENGTH);
+        let _ = fs::remove_dir_all(dir);
+    }
+    #[test]
+    fn id_is_stable() {
+        let dir = test_dir("stable");
+        assert_eq!(manifest(&dir).unwrap().id, manifest(&dir).unwrap().id);
+        let _ = fs::remove_dir_all(dir);
+    }
+    #[test]
+    fn id_changes_with_bundles() {
+        let dir = test_dir("bundles");
+        let original = manifest(&dir).unwrap().id;
+        fs::write(dir.join("bundle.wasm"), "new wasm").unwrap();
+        assert_ne!(manifest(&dir).unwrap().id, original);
+        let _ = fs::remove_dir_all(dir);
+    }
+    #[test]
+    fn id_changes_with_snippets() {
+        let dir = test_dir("snippets");
+        let original = manifest(&dir).unwrap().id;
+        fs::write(dir.join("snippets/a/snippet.js"), "new snippet").unwrap();
+        let changed = manifest(&dir).unwrap().id;
+        assert_ne!(changed, original);
+        // Moving a snippet should change the ID too
+        fs::create_dir_all(dir.join("snippets/b")).unwrap();
+        fs::rename(
+            dir.join("snippets/a/snippet.js"),
+            dir.join("snippets/b/snippet.js"),
+        )
+        .unwrap();
+        assert_ne!(manifest(&dir).unwrap().id, changed);
+        let _ = fs::remove_dir_all(dir);
+    }
+    #[test]
+    fn covers_wasm2js_bundle() {
+        let dir = test_dir("wasm2js");
+        let original = manifest(&dir).unwrap().id;
+        fs::write(dir.join("bundle.wasm.js"), "wasm js").unwrap();
+        let manifest = manifest(&dir).unwrap();
+        assert_ne!(manifest.id, original);
+        assert_eq!(manifest.wasm_js_integrity, Some(get_integrity(b"wasm js")));
+        let _ = fs::remove_dir_all(dir);
+    }
+    #[test]
+    fn fails_on_missing_bundle() {
+        let dir = test_dir("missing");
+        fs::remove_file(dir.join("bundle.js")).unwrap();
+        assert!(matches!(
+            manifest(&dir),
+            Err(BuildError::ReadBundleFailed { .. })
+        ));
+        let _ = fs::remove_dir_all(dir);
+    }
+    #[test]
+    fn works_without_snippets() {
+        let dir = test_dir("no-snippets");
+        fs::remove_dir_all(dir.join("snippets")).unwrap();
+        assert!(manifest(&dir).is_ok());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::errors::ServeError;
+use rand::{distributions::Alphanumeric, Rng};
+
+/// The length of the nonces generated for each request. This gives well over
+/// the 128 bits of entropy recommended by the CSP specification.
+const NONCE_LENGTH: usize = 32;
+
+/// The type of functions that produce the value of a `Content-Security-Policy`
+/// header from the nonce generated for a request.
+pub type CspFn = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// A user-provided function that creates the `Content-Security-Policy` header
+/// for initial loads. Perseus injects a number of inline `<script>`s into the
+/// HTML shell (for things like the render configuration and the initial
+/// state), and, when this is set, the server integrations will generate a
+/// fresh nonce for every initial load, attach it to all those scripts, and
+/// then call this function with the same nonce to produce a matching header.
+///
+/// Your policy should include `'nonce-<nonce>'` in its `script-src`
+/// directive, and, since the inline loader imports the app bundle and then
+/// instantiates Wasm, you'll usually want `'strict-dynamic'` and
+/// `'wasm-unsafe-eval'` as well. See [`default_csp`] for a sensible
+/// starting point.
+pub struct ContentSecurityPolicy {
+    policy_fn: CspFn,
+}
+impl std::fmt::Debug for ContentSecurityPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContentSecurityPolicy").finish()
+    }
+}
+impl ContentSecurityPolicy {
+    /// Creates a new policy from the given function, which will be provided
+    /// the nonce for each request.
+    pub fn new(policy_fn: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        Self {
+            policy_fn: Box::new(policy_fn),
+        }
+    }
+    /// Gets the value of the `Content-Security-Policy` header for a request
+    /// that used the given nonce.
+    pub fn get_header(&self, nonce: &str) -> String {
+        (self.policy_fn)(nonce)
+    }
+    /// Checks that this policy produces a valid header value. Since nonces
+    /// are always alphanumeric, this is checked once with a sample nonce when
+    /// the server starts, rather than on every request.
+    pub fn validate(&self) -> Result<(), ServeError> {
+        let header = self.get_header(&generate_nonce());
+        match http::HeaderValue::from_str(&header) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ServeError::InvalidCsp { header }),
+        }
+    }
+}
+
+/// A minimal policy that allows only the scripts Perseus (and plugins) inject
+/// into the HTML shell, along with anything they load. This doesn't restrict
+/// styles, images, etc., so you'll probably want to extend it.
+pub fn default_csp(nonce: &str) -> String {
+    format!(
+        "script-src 'nonce-{nonce}' 'strict-dynamic' 'wasm-unsafe-eval'; object-src 'none'; base-uri 'self'",
+        nonce = nonce
+    )
+}
+
+/// Generates a new random nonce for use in a single response. This is used by
+/// the server integrations for every initial load if the app has a
+/// [`ContentSecurityPolicy`].
+pub fn generate_nonce() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(NONCE_LENGTH)
+        .map(char::from)
+        .collect()
+}
@@ -1,8 +1,11 @@
 use fmterr::fmterr;
 
+use super::BundleManifest;
 use crate::error_pages::ErrorPageData;
 use crate::page_data::PageData;
+use crate::template::Template;
 use crate::utils::minify;
+use crate::Html;
 use std::collections::HashMap;
 use std::{env, fmt};
 
@@ -45,21 +48,44 @@ pub struct HtmlShell {
     pub after_content: Vec<String>,
     /// The ID of the element into which we'll interpolate content.
     root_id: String,
-    /// The path prefix to use.
+    /// The URL under which the bundles are served (including the path prefix).
     #[cfg_attr(not(feature = "preload-wasm-on-redirect"), allow(dead_code))]
-    path_prefix: String,
+    bundle_root: String,
+    /// The integrity metadata of the Wasm bundle, if we have a bundle
+    /// manifest.
+    #[cfg_attr(not(feature = "preload-wasm-on-redirect"), allow(dead_code))]
+    wasm_integrity: Option<String>,
+    /// The scripts Perseus needs to set up the app in the browser (including
+    /// the one that loads the Wasm bundle), which go before the interpolation
+    /// boundary.
+    app_scripts: Vec<String>,
+    /// The `<link>` that preloads the JS bundle so that the browser checks its
+    /// integrity, if we know it. This goes right after the `<base>` element.
+    bundle_preload: Option<String>,
+    /// Whether or not this shell is for a page that shouldn't load the app at
+    /// all (see `Template::static_only`).
+    static_only: bool,
+    /// The nonce to attach to all inline scripts, if the app is using a
+    /// Content Security Policy. This should be different for every response.
+    nonce: Option<String>,
 }
 impl HtmlShell {
     /// Initializes the HTML shell by interpolating necessary scripts into it
     /// and adding the render configuration.
+    ///
+    /// If a [`BundleManifest`] is provided, the bundles will be loaded from
+    /// their content-hashed URLs, with integrity checks. Otherwise, they'll be
+    /// loaded from `/.perseus/bundle.js` and the like.
     pub fn new(
         shell: String,
         root_id: &str,
         render_cfg: &HashMap<String, String>,
         path_prefix: &str,
+        bundles: Option<&BundleManifest>,
     ) -> Self {
         let mut head_before_boundary = Vec::new();
-        let mut scripts_before_boundary = Vec::new();
+        // These are only needed if the page is actually going to load the app
+        let mut app_scripts = Vec::new();
 
         // Define the render config as a global variable
         let render_cfg = format!(
@@ -68,12 +94,12 @@ impl HtmlShell {
             // case
             render_cfg = serde_json::to_string(render_cfg).unwrap()
         );
-        scripts_before_boundary.push(render_cfg);
+        app_scripts.push(render_cfg);
 
         // Inject a global variable to identify whether we are testing (picked up by app
         // shell to trigger helper DOM events)
         if env::var("PERSEUS_TESTING").is_ok() {
-            scripts_before_boundary.push("window.__PERSEUS_TESTING = true;".into());
+            app_scripts.push("window.__PERSEUS_TESTING = true;".into());
         }
 
         // Define the script that will load the Wasm bundle (inlined to avoid
@@ -83,29 +109,49 @@ impl HtmlShell {
         //
         // Note: because we're using binary bundles, we don't need to import
         // a `main` function or the like, `init()` just works
+        let bundle_root = match bundles {
+            Some(bundles) => bundles.get_url_root(path_prefix),
+            None => format!("{}/.perseus", path_prefix),
+        };
         #[cfg(not(feature = "wasm2js"))]
-        let load_wasm_bundle = format!(
-            r#"
-        import init from "{path_prefix}/.perseus/bundle.js";
+        let load_wasm_bundle = match bundles {
+            // We fetch the Wasm bundle ourselves so we can check its integrity (the JS
+            // bundle's integrity is checked through the `modulepreload` below, which the
+            // import will use)
+            Some(bundles) => format!(
+                r#"
+        import init from "{bundle_root}/bundle.js";
         async function main() {{
-            await init("{path_prefix}/.perseus/bundle.wasm");
+            await init(fetch("{bundle_root}/bundle.wasm", {{ integrity: "{wasm_integrity}" }}));
         }}
         main();
         "#,
-            path_prefix = path_prefix
-        );
+                bundle_root = bundle_root,
+                wasm_integrity = bundles.wasm_integrity
+            ),
+            None => format!(
+                r#"
+        import init from "{bundle_root}/bundle.js";
+        async function main() {{
+            await init("{bundle_root}/bundle.wasm");
+        }}
+        main();
+        "#,
+                bundle_root = bundle_root
+            ),
+        };
         #[cfg(feature = "wasm2js")]
         let load_wasm_bundle = format!(
             r#"
-        import init from "{path_prefix}/.perseus/bundle.js";
+        import init from "{bundle_root}/bundle.js";
         async function main() {{
-            await init("{path_prefix}/.perseus/bundle.wasm.js");
+            await init("{bundle_root}/bundle.wasm.js");
         }}
         main();
         "#,
-            path_prefix = path_prefix
+            bundle_root = bundle_root
         );
-        scripts_before_boundary.push(load_wasm_bundle);
+        app_scripts.push(load_wasm_bundle);
 
         // If we're in development, pass through the host/port of the reload server if
         // we're using it We'll depend on the `PERSEUS_USE_RELOAD_SERVER`
@@ -118,10 +164,8 @@ impl HtmlShell {
                 env::var("PERSEUS_RELOAD_SERVER_HOST").unwrap_or_else(|_| "localhost".to_string());
             let port =
                 env::var("PERSEUS_RELOAD_SERVER_PORT").unwrap_or_else(|_| "3100".to_string());
-            scripts_before_boundary
-                .push(format!("window.__PERSEUS_RELOAD_SERVER_HOST = '{}';", host));
-            scripts_before_boundary
-                .push(format!("window.__PERSEUS_RELOAD_SERVER_PORT = '{}';", port));
+            app_scripts.push(format!("window.__PERSEUS_RELOAD_SERVER_HOST = '{}';", host));
+            app_scripts.push(format!("window.__PERSEUS_RELOAD_SERVER_PORT = '{}';", port));
         }
 
         // Add in the `<base>` element at the very top so that it applies to everything
@@ -130,21 +174,56 @@ impl HtmlShell {
         // Note that it's already had any pre-existing ones stripped away
         let base = format!(r#"<base href="{}/" />"#, path_prefix);
         head_before_boundary.push(base);
+        // If we know the JS bundle's integrity, we preload it as a module so that the
+        // browser checks it before the loader's `import` uses it
+        let bundle_preload = bundles.map(|bundles| {
+            format!(
+                r#"<link rel="modulepreload" href="{}/bundle.js" integrity="{}" />"#,
+                bundle_root, bundles.js_integrity
+            )
+        });
 
         Self {
             shell,
             head_before_boundary,
-            scripts_before_boundary,
+            scripts_before_boundary: Vec::new(),
             head_after_boundary: Vec::new(),
             scripts_after_boundary: Vec::new(),
             before_content: Vec::new(),
             after_content: Vec::new(),
             content: "".into(),
             root_id: root_id.into(),
-            path_prefix: path_prefix.into(),
+            bundle_root,
+            wasm_integrity: bundles.map(|bundles| bundles.wasm_integrity.clone()),
+            app_scripts,
+            bundle_preload,
+            static_only: false,
+            nonce: None,
         }
     }
 
+    /// Sets the shell up for a page using the given template. If the template
+    /// is static-only (see [`Template::static_only`]), the shell won't load
+    /// the app at all. This should be called for every page the shell is used
+    /// for, before `.page_data()`.
+    pub fn for_template<G: Html>(mut self, template: &Template<G>) -> Self {
+        self.static_only = template.is_static_only();
+        self
+    }
+
+    /// Sets the nonce that will be attached to every inline `<script>` in the
+    /// shell, including those added by plugins and those interpolated by
+    /// `.page_data()`, `.error_page()`, and `.locale_redirection_fallback()`.
+    /// This allows the shell to be served under a Content Security Policy
+    /// that doesn't permit `unsafe-inline`.
+    ///
+    /// This must be called separately for every response, with a freshly
+    /// generated nonce (see [`generate_nonce`](super::generate_nonce)).
+    pub fn with_nonce(mut self, nonce: &str) -> Self {
+        self.nonce = Some(nonce.to_string());
+        self
+    }
+
     /// Interpolates page data, global state, and translations into the shell.
     ///
     /// The translations provided should be the source string from which a
@@ -157,6 +236,16 @@ impl HtmlShell {
         global_state: &Option<String>,
         translations: &str,
     ) -> Self {
+        // Interpolate the document `<head>` (this should of course be removed between
+        // page loads)
+        self.head_after_boundary.push((&page_data.head).into());
+        // And set the content
+        self.content = (&page_data.content).into();
+        // Without the app, nothing would ever read the state
+        if self.static_only {
+            return self;
+        }
+
         // Interpolate a global variable of the state so the app shell doesn't have to
         // make any more trips The app shell will unset this after usage so it
         // doesn't contaminate later non-initial loads Error pages (above) will
@@ -189,11 +278,14 @@ impl HtmlShell {
         // known from the URL
         let translations = format!("window.__PERSEUS_TRANSLATIONS = `{}`;", translations);
         self.scripts_after_boundary.push(translations);
-        // Interpolate the document `<head>` (this should of course be removed between
-        // page loads)
-        self.head_after_boundary.push((&page_data.head).into());
-        // And set the content
-        self.content = (&page_data.content).into();
+        // Layouts are only rendered once in the browser, so their states are only needed
+        // for the first page that uses them
+        if !page_data.layout_states.is_empty() {
+            let layout_states =
+                escape_page_data(&serde_json::to_string(&page_data.layout_states).unwrap());
+            let layout_states = format!("window.__PERSEUS_LAYOUT_STATES = `{}`;", layout_states);
+            self.scripts_after_boundary.push(layout_states);
+        }
 
         self
     }
@@ -259,10 +351,18 @@ impl HtmlShell {
             // Note that this has to come before the code that actually loads the Wasm
             // bundle The aim of this is to make the time loading increase so
             // that the time blanking decreases
-            let wasm_preload = format!(
-                r#"<link rel="preload" href="{path_prefix}/.perseus/bundle.wasm" as="fetch" />"#,
-                path_prefix = self.path_prefix
-            );
+            let wasm_preload = match &self.wasm_integrity {
+                // This has to match the `fetch()` in the loader exactly to be used
+                Some(integrity) => format!(
+                    r#"<link rel="preload" href="{bundle_root}/bundle.wasm" as="fetch" crossorigin integrity="{integrity}" />"#,
+                    bundle_root = self.bundle_root,
+                    integrity = integrity
+                ),
+                None => format!(
+                    r#"<link rel="preload" href="{bundle_root}/bundle.wasm" as="fetch" />"#,
+                    bundle_root = self.bundle_root
+                ),
+            };
             self.head_before_boundary.push(wasm_preload.into());
         }
 
@@ -299,24 +399,56 @@ impl HtmlShell {
 // unexpected ways
 impl fmt::Display for HtmlShell {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let head_start = self.head_before_boundary.join("\n");
+        // Static-only pages don't load the app at all
+        let app_scripts = if self.static_only {
+            String::new()
+        } else {
+            self.app_scripts.join("\n")
+        };
+        let mut head_before_boundary = self.head_before_boundary.clone();
+        if let (Some(bundle_preload), false) = (&self.bundle_preload, self.static_only) {
+            // The `<base>` element is always first, and this should come straight after it
+            head_before_boundary.insert(1.min(head_before_boundary.len()), bundle_preload.clone());
+        }
+        let head_start = head_before_boundary.join("\n");
+        // If we have a nonce, every inline script needs it for the browser to execute
+        // it under a CSP
+        let nonce_attr = match &self.nonce {
+            Some(nonce) => format!(r#" nonce="{}""#, nonce),
+            None => String::new(),
+        };
         // We also inject a delimiter dummy `<meta>` tag that will be used to wall off
         // the constant document head from the interpolated document head
         // There's another one of these for denoting the end so we can semi-reliably
         // extract the head of initially loaded pages for PSS caching
         let head_end = format!(
             r#"
-            <script type="module">{scripts_before_boundary}</script>
+            <script type="module"{nonce_attr}>{app_scripts}
+{scripts_before_boundary}</script>
             <meta itemprop="__perseus_head_boundary" content="">
             {head_after_boundary}
-            <script>{scripts_after_boundary}</script>
+            <script{nonce_attr}>{scripts_after_boundary}</script>
             <meta itemprop="__perseus_head_end" content="">
-            <script>//</script>
+            <script{nonce_attr}>//</script>
             "#,
+            nonce_attr = nonce_attr,
             scripts_before_boundary = self.scripts_before_boundary.join("\n"),
+            app_scripts = app_scripts,
             head_after_boundary = self.head_after_boundary.join("\n"),
             scripts_after_boundary = self.scripts_after_boundary.join("\n"),
         );
+        // In development, the devtools overlay's styles go in the constant part of the
+        // head
+        #[cfg(all(feature = "devtools", debug_assertions))]
+        let head_end = match self.static_only {
+            false => format!(
+                "<style{}>{}</style>{}",
+                nonce_attr,
+                crate::devtools::DEVTOOLS_CSS,
+                head_end
+            ),
+            true => head_end,
+        };
 
         let shell_with_head = self
             .shell
@@ -325,6 +457,17 @@ impl fmt::Display for HtmlShell {
 
         let body_start = self.before_content.join("\n");
         let body_end = self.after_content.join("\n");
+        // And the app will render the overlay itself into a container at the end of the
+        // body
+        #[cfg(all(feature = "devtools", debug_assertions))]
+        let body_end = match self.static_only {
+            false => format!(
+                r#"{}<div id="{}"></div>"#,
+                body_end,
+                crate::devtools::DEVTOOLS_ID
+            ),
+            true => body_end,
+        };
         let shell_with_body = shell_with_head
             .replace("<body>", &format!("<body>{}", body_start))
             .replace("</body>", &format!("{}</body>", body_end));
@@ -5,16 +5,22 @@
 //! need to use this module (though some plugins may need types in here).
 
 mod build_error_page;
+mod bundles;
+mod csp;
 mod get_render_cfg;
 mod html_shell;
 mod options;
 mod render;
+mod tls;
 
 pub use build_error_page::build_error_page;
+pub use bundles::{BundleManifest, IMMUTABLE_CACHE_CONTROL};
+pub use csp::{default_csp, generate_nonce, ContentSecurityPolicy, CspFn};
 pub use get_render_cfg::get_render_cfg;
 pub use html_shell::HtmlShell;
 pub use options::{ServerOptions, ServerProps};
-pub use render::{get_page, get_page_for_template, GetPageProps};
+pub use render::{get_page, get_page_for_template, restore_page_uri, GetPageProps};
+pub use tls::{get_https_redirect_url, TlsConfig};
 
 /// Removes empty elements from a path, which is important due to double
 /// slashes. This returns a vector of the path's components;
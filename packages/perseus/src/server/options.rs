@@ -1,18 +1,20 @@
 use crate::error_pages::ErrorPages;
 use crate::i18n::Locales;
 use crate::i18n::TranslationsManager;
-use crate::state::GlobalStateCreator;
+use crate::state::{GlobalStateCreator, StatePublisher};
 use crate::stores::{ImmutableStore, MutableStore};
 use crate::template::ArcTemplateMap;
 use crate::SsrNode;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use super::HtmlShell;
+use super::{BundleManifest, ContentSecurityPolicy, HtmlShell};
 
 /// The options for setting up all server integrations. This should be literally
-/// constructed, as nothing is optional. If integrations need further
-/// properties, they should expose their own options in addition to these.
+/// constructed, as nothing is optional (though the `Option` fields for features
+/// your app doesn't use, like `csp`, `bundles`, and `state_publisher`, can just
+/// be set to `None`). If integrations need further properties, they should
+/// expose their own options in addition to these.
 #[derive(Debug, Clone)]
 pub struct ServerOptions {
     /// The location on the filesystem of your JavaScript bundle.
@@ -37,6 +39,12 @@ pub struct ServerOptions {
     pub root_id: String,
     /// The location of the JS interop snippets to be served as static files.
     pub snippets: String,
+    /// The content hashes of the bundles, if they've been computed. When this
+    /// is set, integrations should serve the bundles and snippets under
+    /// `/.perseus/bundles/<id>/` with immutable caching headers (see
+    /// [`IMMUTABLE_CACHE_CONTROL`](super::IMMUTABLE_CACHE_CONTROL)), since
+    /// that's where the HTML shell will look for them.
+    pub bundles: Option<BundleManifest>,
     /// The error pages for the app. These will be server-rendered if an initial
     /// load fails.
     pub error_pages: Arc<ErrorPages<SsrNode>>,
@@ -48,6 +56,16 @@ pub struct ServerOptions {
     /// favicons, which should be stored in a static directory, but need to be
     /// aliased at a path like `/favicon.ico`.
     pub static_aliases: HashMap<String, String>,
+    /// The Content Security Policy to send with initial loads, if any. When
+    /// this is set, integrations should generate a nonce for each initial
+    /// load, attach it to the HTML shell, and send the header this produces.
+    pub csp: Option<Arc<ContentSecurityPolicy>>,
+    /// The publisher for pushing state to browsers, if the app uses one. When
+    /// this is set, integrations should serve a stream of server-sent events
+    /// at `/.perseus/state-push`, sending every message from
+    /// `.subscribe()` (given the `url` query parameter) as the data of an
+    /// event.
+    pub state_publisher: Option<StatePublisher>,
 }
 
 /// The full set of properties that all server integrations take.
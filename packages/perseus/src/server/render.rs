@@ -1,9 +1,11 @@
 use crate::errors::*;
 use crate::i18n::TranslationsManager;
 use crate::page_data::PageData;
+use crate::state::SubsequentLoad;
 use crate::stores::{ImmutableStore, MutableStore};
-use crate::template::{PageProps, States, Template, TemplateMap};
+use crate::template::{LayoutStates, PageProps, States, Template, TemplateMap};
 use crate::translator::Translator;
+use crate::utils::{parse_query, QueryParams};
 use crate::Request;
 use crate::SsrNode;
 use chrono::{DateTime, Utc};
@@ -27,6 +29,35 @@ fn clone_req(raw: &Request) -> Request {
         .unwrap() // This should never fail...
 }
 
+/// Restores the URI of a request for a page's data (made by the browser on a
+/// subsequent load) to the URI of the page itself, including the query string
+/// the browser forwarded in the `query` parameter. This means request state
+/// functions will see the same URI whether a page is being loaded for the first
+/// time or not (except for any path prefix). Integrations should call this on
+/// requests for page data before passing them to [`get_page_for_template`].
+///
+/// The given path should not have a `.json` extension.
+pub fn restore_page_uri<B>(req: &mut http::Request<B>, locale: &str, path: &str) {
+    let query = req
+        .uri()
+        .query()
+        .and_then(|query| parse_query(query).remove("query"));
+    let path = match (locale, path) {
+        ("xx-XX", "index") => "/".to_string(),
+        ("xx-XX", path) => format!("/{}", path),
+        (locale, "index") => format!("/{}", locale),
+        (locale, path) => format!("/{}/{}", locale, path),
+    };
+    let uri = match query {
+        Some(query) if !query.is_empty() => format!("{}?{}", path, query),
+        _ => path,
+    };
+    // If the path can't be made into a valid URI, we'll just leave the request alone
+    if let Ok(uri) = uri.parse() {
+        *req.uri_mut() = uri;
+    }
+}
+
 /// Gets the path with the locale, returning it without if i18n isn't being
 /// used.
 fn get_path_with_locale(path_without_locale: &str, translator: &Translator) -> String {
@@ -103,25 +134,67 @@ async fn render_build_state_for_mutable(
 }
 /// Renders a template that generated its state at request-time. Note that
 /// revalidation and incremental generation have no impact on SSR-rendered
-/// pages. This does everything at request-time, and so doesn't need a mutable
-/// or immutable store.
+/// pages. This does everything at request-time, and so only needs a mutable
+/// store if the template caches its request states there (in which case a
+/// fresh cached state will be used instead of generating a new one).
 ///
 /// As this involves state computation, this only returns the state.
 async fn get_request_state(
     template: &Template<SsrNode>,
     translator: &Translator,
     path: &str,
+    path_encoded: &str,
+    mutable_store: &impl MutableStore,
     req: Request,
 ) -> Result<Option<String>, ServerError> {
+    let cache = template.get_request_state_cache();
+    let cache_key = cache.map(|cache| cache.get_key(path_encoded, &req));
+    if let (Some(cache), Some(cache_key)) = (cache, &cache_key) {
+        if let Some(state) = cache.get(cache_key, mutable_store).await {
+            return Ok(Some(state));
+        }
+    }
+
     // Generate the initial state (this may generate an error, but there's no file
     // that can't exist)
-    let state = Some(
-        template
-            .get_request_state(path.to_string(), translator.get_locale(), req)
-            .await?,
-    );
+    //
+    // The last successful state can only be shared between requests that would share a
+    // cached state, since request states might be specific to the user, so this fallback
+    // needs the cache key
+    let last_state_name = match &cache_key {
+        Some(cache_key) if template.falls_back_to_last_state() => {
+            Some(format!("request_cache/{}.last.json", cache_key))
+        }
+        _ => None,
+    };
+    let state = match template
+        .get_request_state(path.to_string(), translator.get_locale(), req)
+        .await
+    {
+        Ok(state) => {
+            // Keep this around in case a later request times out (this only happens on cache
+            // misses, so it's not on every request)
+            if let Some(last_state_name) = &last_state_name {
+                mutable_store.write(last_state_name, &state).await?;
+            }
+            state
+        }
+        Err(err @ ServerError::StateFnTimedOut { .. }) => match &last_state_name {
+            Some(last_state_name) => match mutable_store.read(last_state_name).await {
+                // This is stale, so we certainly shouldn't cache it as if it were fresh
+                Ok(state) => return Ok(Some(state)),
+                // There's no last state, so we have to fail
+                Err(_) => return Err(err),
+            },
+            None => return Err(err),
+        },
+        Err(err) => return Err(err),
+    };
+    if let (Some(cache), Some(cache_key)) = (cache, &cache_key) {
+        cache.set(cache_key, &state, mutable_store).await?;
+    }
 
-    Ok(state)
+    Ok(Some(state))
 }
 /// Renders a template that wants to amalgamate build state with request state.
 /// This does everything at request-time, and so doesn't need a mutable or
@@ -129,11 +202,14 @@ async fn get_request_state(
 ///
 /// As this is always the final item, this returns a body and head along with
 /// the state.
+#[allow(clippy::too_many_arguments)]
 async fn render_amalgamated_state(
     template: &Template<SsrNode>,
     translator: &Translator,
     path: &str,
     global_state: &Option<String>,
+    layout_states: &LayoutStates,
+    query: &QueryParams,
     build_state: String,
     request_state: String,
     render_html: bool,
@@ -157,10 +233,11 @@ async fn render_amalgamated_state(
         path: path_with_locale,
         state: state.clone(),
         global_state: global_state.clone(),
+        query: query.clone(),
     };
     let html = if render_html {
         sycamore::render_to_string(|cx| {
-            template.render_for_template_server(page_props.clone(), cx, translator)
+            template.render_for_template_server(page_props.clone(), cx, translator, layout_states)
         })
     } else {
         String::new()
@@ -258,33 +335,47 @@ async fn should_revalidate(
 ///
 /// This receives no directive about not rendering content HTML, since it
 /// has to for future caching anyway.
+///
+/// If the build state function times out and the template falls back to the
+/// last successful state, this will return what's already in the mutable
+/// store.
 async fn revalidate(
     template: &Template<SsrNode>,
     translator: &Translator,
     path: &str,
     path_encoded: &str,
     global_state: &Option<String>,
+    layout_states: &LayoutStates,
     mutable_store: &impl MutableStore,
 ) -> Result<(String, String, Option<String>), ServerError> {
     let path_with_locale = get_path_with_locale(path, translator);
     // We need to regenerate and cache this page for future usage (until the next
     // revalidation)
-    let state = Some(
-        template
-            .get_build_state(
-                format!("{}/{}", template.get_path(), path),
-                translator.get_locale(),
-            )
-            .await?,
-    );
+    let state = match template
+        .get_build_state(
+            format!("{}/{}", template.get_path(), path),
+            translator.get_locale(),
+        )
+        .await
+    {
+        Ok(state) => Some(state),
+        // If the state function timed out, we can keep serving what's already in the mutable
+        // store (without updating the revalidation time, so the next request will try again)
+        Err(ServerError::StateFnTimedOut { .. }) if template.falls_back_to_last_state() => {
+            return render_build_state_for_mutable(path_encoded, mutable_store, true).await
+        }
+        Err(err) => return Err(err),
+    };
     // Assemble the page properties
     let page_props = PageProps {
         path: path_with_locale,
         state: state.clone(),
         global_state: global_state.clone(),
+        // This will be cached, so it can't depend on any particular request
+        query: QueryParams::new(),
     };
     let html = sycamore::render_to_string(|cx| {
-        template.render_for_template_server(page_props.clone(), cx, translator)
+        template.render_for_template_server(page_props.clone(), cx, translator, layout_states)
     });
     let head = template.render_head_str(page_props, translator);
     // Handle revalidation, we need to parse any given time strings into datetimes
@@ -370,7 +461,15 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
     // Since `Request` is not actually `Clone`able, we hack our way around needing
     // it twice An `Rc` won't work because of future constraints, and an `Arc`
     // seems a little unnecessary
-    let req_2 = clone_req(&req);
+    let mut req_2 = clone_req(&req);
+    // Request state functions need to know if this is a subsequent load, so that they can leave
+    // resources for the browser to load itself
+    if !render_html {
+        req_2.extensions_mut().insert(SubsequentLoad);
+    }
+    // Pages rendered at request-time can depend on the query parameters (for subsequent loads,
+    // the integration should have restored the page's own URI)
+    let query = parse_query(req.uri().query().unwrap_or(""));
     // Get a translator for this locale (for sanity we hope the manager is caching)
     let translator = translations_manager
         .get_translator_for_locale(locale.to_string())
@@ -385,6 +484,9 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
     // add the locale
     let path_encoded = format!("{}-{}", locale, urlencoding::encode(path));
     let path_with_locale = get_path_with_locale(path, &translator);
+    // Every render of the page will be wrapped in the template's layouts, which
+    // have their own states from build time
+    let layout_states = template.get_layout_states(locale, immutable_store).await?;
 
     // Only a single string of HTML is needed, and it will be overridden if
     // necessary (priorities system)
@@ -426,6 +528,7 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
                             path,
                             &path_encoded,
                             global_state,
+                            &layout_states,
                             mutable_store,
                         )
                         .await?;
@@ -468,9 +571,16 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
                         path: path_with_locale.clone(),
                         state: state.clone(),
                         global_state: global_state.clone(),
+                        // This will be cached, so it can't depend on any particular request
+                        query: QueryParams::new(),
                     };
                     let html_val = sycamore::render_to_string(|cx| {
-                        template.render_for_template_server(page_props.clone(), cx, &translator)
+                        template.render_for_template_server(
+                            page_props.clone(),
+                            cx,
+                            &translator,
+                            &layout_states,
+                        )
                     });
                     let head_val = template.render_head_str(page_props, &translator);
                     // Handle revalidation, we need to parse any given time strings into datetimes
@@ -534,6 +644,7 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
                     path,
                     &path_encoded,
                     global_state,
+                    &layout_states,
                     mutable_store,
                 )
                 .await?;
@@ -572,7 +683,15 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
         // page will be built soon If we're not, and there's no build state,
         // then we still need to build, which we'll do after we've checked for
         // amalgamation
-        let state = get_request_state(template, &translator, path, req_2).await?;
+        let state = get_request_state(
+            template,
+            &translator,
+            path,
+            &path_encoded,
+            mutable_store,
+            req_2,
+        )
+        .await?;
         states.request_state = state;
     }
 
@@ -594,13 +713,14 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
             path: path_with_locale,
             state: state.clone(),
             global_state: global_state.clone(),
+            query: query.clone(),
         };
         let head_val = template.render_head_str(page_props.clone(), &translator);
         head = head_val;
         // We should only render the HTML if necessary, since we're not caching
         if render_html {
             let html_val = sycamore::render_to_string(|cx| {
-                template.render_for_template_server(page_props, cx, &translator)
+                template.render_for_template_server(page_props, cx, &translator, &layout_states)
             });
             html = html_val;
         }
@@ -618,6 +738,8 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
             &translator,
             path,
             global_state,
+            &layout_states,
+            &query,
             states.build_state.unwrap(),
             states.request_state.unwrap(),
             render_html,
@@ -636,12 +758,13 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
             path: path_with_locale,
             state: state.clone(),
             global_state: global_state.clone(),
+            query: query.clone(),
         };
         let head_val = template.render_head_str(page_props.clone(), &translator);
         // We should only render the HTML if necessary, since we're not caching
         if render_html {
             let html_val = sycamore::render_to_string(|cx| {
-                template.render_for_template_server(page_props, cx, &translator)
+                template.render_for_template_server(page_props, cx, &translator, &layout_states)
             });
             html = html_val;
         }
@@ -659,12 +782,14 @@ pub async fn get_page_for_template<M: MutableStore, T: TranslationsManager>(
             content: html,
             state,
             head,
+            layout_states,
         }
     } else {
         PageData {
             content: String::new(),
             state,
             head,
+            layout_states,
         }
     };
 
@@ -0,0 +1,124 @@
+use std::env;
+
+/// The TLS configuration for the default servers, which is read from
+/// environment variables so that it can be set at deploy-time without
+/// rebuilding the app:
+///
+/// - `PERSEUS_TLS_CERT`: the path to a PEM-encoded certificate (chain)
+/// - `PERSEUS_TLS_KEY`: the path to the PEM-encoded private key for that
+///   certificate
+/// - `PERSEUS_TLS_REDIRECT_PORT` (optional): a port on which to listen for
+///   plain HTTP requests, all of which will be redirected to HTTPS (usually
+///   `80`)
+///
+/// For local testing, you can generate a self-signed certificate with
+/// something like `openssl req -x509 -newkey rsa:4096 -nodes -keyout key.pem
+/// -out cert.pem -days 365 -subj '/CN=localhost'`.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// The path to the certificate.
+    pub cert_path: String,
+    /// The path to the private key.
+    pub key_path: String,
+    /// The port on which to redirect HTTP requests to HTTPS, if any.
+    pub redirect_port: Option<u16>,
+}
+impl TlsConfig {
+    /// Gets the TLS configuration from the environment, returning `None` if
+    /// TLS hasn't been configured.
+    ///
+    /// # Panics
+    /// This will panic if only one of the certificate and key is set, or if
+    /// the redirection port is invalid, since both are almost certainly
+    /// mistakes that shouldn't silently lead to a site being served over
+    /// plain HTTP.
+    pub fn from_env() -> Option<Self> {
+        let cert_path = env::var("PERSEUS_TLS_CERT").ok();
+        let key_path = env::var("PERSEUS_TLS_KEY").ok();
+        let (cert_path, key_path) = match (cert_path, key_path) {
+            (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+            (None, None) => return None,
+            _ => panic!("both `PERSEUS_TLS_CERT` and `PERSEUS_TLS_KEY` must be set to use TLS"),
+        };
+        let redirect_port = env::var("PERSEUS_TLS_REDIRECT_PORT").ok().map(|port| {
+            port.parse::<u16>()
+                .expect("TLS redirection port must be a number.")
+        });
+
+        Some(Self {
+            cert_path,
+            key_path,
+            redirect_port,
+        })
+    }
+}
+
+/// Gets the HTTPS URL to redirect a plain HTTP request to, given the request's
+/// `Host` header (if it had one), its path and query, and the host and port
+/// the HTTPS server is listening on. This is used by the default servers for
+/// HTTP to HTTPS redirection.
+pub fn get_https_redirect_url(
+    host_header: Option<&str>,
+    path_and_query: &str,
+    (host, port): (&str, u16),
+) -> String {
+    // We prefer the domain the user actually requested, but we have to swap out any
+    // port they used
+    let host = match host_header {
+        Some(host_header) => host_header
+            .rsplit_once(':')
+            // Make sure we don't break IPv6 addresses (which contain colons)
+            .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+            .map(|(host, _)| host)
+            .unwrap_or(host_header),
+        None => host,
+    };
+    if port == 443 {
+        format!("https://{}{}", host, path_and_query)
+    } else {
+        format!("https://{}:{}{}", host, port, path_and_query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn omits_default_port() {
+        let url = get_https_redirect_url(Some("example.com"), "/about", ("0.0.0.0", 443));
+        assert_eq!(url, "https://example.com/about")
+    }
+    #[test]
+    fn uses_https_port() {
+        let url = get_https_redirect_url(Some("localhost"), "/", ("0.0.0.0", 8443));
+        assert_eq!(url, "https://localhost:8443/")
+    }
+    #[test]
+    fn replaces_requested_port() {
+        let url = get_https_redirect_url(Some("localhost:8080"), "/about", ("0.0.0.0", 8443));
+        assert_eq!(url, "https://localhost:8443/about");
+        let url = get_https_redirect_url(Some("example.com:80"), "/about", ("0.0.0.0", 443));
+        assert_eq!(url, "https://example.com/about")
+    }
+    #[test]
+    fn preserves_ipv6_hosts() {
+        let url = get_https_redirect_url(Some("[::1]"), "/", ("::", 8443));
+        assert_eq!(url, "https://[::1]:8443/");
+        let url = get_https_redirect_url(Some("[::1]:8080"), "/", ("::", 8443));
+        assert_eq!(url, "https://[::1]:8443/")
+    }
+    #[test]
+    fn falls_back_to_server_host() {
+        let url = get_https_redirect_url(None, "/about", ("example.com", 443));
+        assert_eq!(url, "https://example.com/about")
+    }
+    #[test]
+    fn preserves_path_prefix_and_query() {
+        let url = get_https_redirect_url(
+            Some("example.com"),
+            "/my-app/about?foo=bar",
+            ("0.0.0.0", 443),
+        );
+        assert_eq!(url, "https://example.com/my-app/about?foo=bar")
+    }
+}
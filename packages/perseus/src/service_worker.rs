@@ -0,0 +1,477 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    errors::ServerError,
+    i18n::Locales,
+    server::{build_error_page, BundleManifest, HtmlShell},
+    stores::ImmutableStore,
+    ErrorPages, SsrNode,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use sha2::{Digest, Sha256};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the service worker file, which is written to the root of the
+/// immutable store, and served from the root of the app (service workers can
+/// only control pages at or below the URL they're served from).
+pub const SERVICE_WORKER_FILE: &str = "service-worker.js";
+
+/// The number of hex characters of the content hash to use in the service
+/// worker's cache name.
+#[cfg(not(target_arch = "wasm32"))]
+const VERSION_LENGTH: usize = 16;
+
+/// The pages whose data a service worker should download when it's installed,
+/// so that they can be navigated to offline even if the user hasn't visited
+/// them yet.
+#[derive(Clone, Debug, Default)]
+pub enum PrecachedPages {
+    /// Don't precache any pages (apart from the landing page). Pages will
+    /// still be cached as the user visits them.
+    #[default]
+    None,
+    /// Precache every page rendered at build-time (i.e. every page in the
+    /// render configuration, except for those generated incrementally).
+    All,
+    /// Precache the pages at the given paths, which should be given without
+    /// locales or leading slashes (e.g. `about` or `posts/first`), and with an
+    /// empty string for the landing page. With i18n, these will be precached
+    /// in every locale.
+    Selected(Vec<String>),
+}
+
+/// Settings for the service worker Perseus can generate to make your app work
+/// offline. When this is enabled, a service worker will be generated when your
+/// app is built or exported, and registered once your app has loaded in the
+/// browser.
+///
+/// That service worker will precache the app shell, your bundles, your static
+/// assets, and the data of the pages you select, and will keep page data
+/// fresh with a stale-while-revalidate strategy. Only pages rendered at
+/// build-time are ever cached, since pages that use request state might be
+/// specific to the user (or to the moment they were requested). If the user
+/// navigates to a page that hasn't been cached while they're offline, the
+/// error page for `offline_status` will be shown.
+#[derive(Clone, Debug)]
+pub struct ServiceWorkerSettings {
+    /// The pages whose data should be precached.
+    pub precache: PrecachedPages,
+    /// The HTTP status code whose error page will be shown to users who try to
+    /// load something that hasn't been cached while they're offline.
+    pub offline_status: u16,
+}
+impl Default for ServiceWorkerSettings {
+    fn default() -> Self {
+        Self {
+            precache: PrecachedPages::default(),
+            offline_status: 503,
+        }
+    }
+}
+
+/// The properties necessary to generate a service worker.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct ServiceWorkerProps<'a> {
+    /// The settings for the service worker.
+    pub settings: &'a ServiceWorkerSettings,
+    /// The app's render configuration, which lists every page rendered at
+    /// build-time.
+    pub render_cfg: &'a HashMap<String, String>,
+    /// The paths of the templates that use request state, whose pages will
+    /// never be cached.
+    pub request_state_templates: &'a HashSet<String>,
+    /// The locales data for the app.
+    pub locales: &'a Locales,
+    /// The app's error pages, from which the offline page will be rendered.
+    pub error_pages: &'a ErrorPages<SsrNode>,
+    /// The HTML shell the app is served with.
+    pub html_shell: &'a HtmlShell,
+    /// The content hashes of the bundles, if they've been computed.
+    pub bundles: Option<&'a BundleManifest>,
+    /// The directory static assets are served from (which may not exist).
+    pub static_dir: &'a str,
+    /// The app's static aliases, as URLs mapped to filesystem paths.
+    pub static_aliases: &'a HashMap<String, String>,
+    /// The server-side path prefix.
+    pub path_prefix: &'a str,
+}
+
+/// Generates a service worker for the app and writes it to
+/// [`SERVICE_WORKER_FILE`] in the given immutable store. This must be run
+/// after the app has been built (and, ideally, after its bundles have been
+/// built, so that their hashes are known).
+///
+/// The generated service worker is deterministic, so rebuilding an app that
+/// hasn't changed will produce the same one, and browsers will only install a
+/// new version when something has actually changed.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn write_service_worker(
+    ServiceWorkerProps {
+        settings,
+        render_cfg,
+        request_state_templates,
+        locales,
+        error_pages,
+        html_shell,
+        bundles,
+        static_dir,
+        static_aliases,
+        path_prefix,
+    }: ServiceWorkerProps<'_>,
+    immutable_store: &ImmutableStore,
+) -> Result<(), ServerError> {
+    // Everything that goes into the service worker goes into its version, so that
+    // caches are replaced whenever anything changes
+    let mut version_hasher = Sha256::new();
+    let mut precache = Vec::new();
+
+    // Only pages that were rendered at build-time are the same for everyone, so
+    // they're the only ones we can cache (these are the URLs for both initial and
+    // subsequent loads, without query strings, in a deterministic order)
+    let page_locales = match locales.using_i18n {
+        true => locales.get_all(),
+        false => vec![&locales.default],
+    };
+    let mut cacheable_pages = Vec::new();
+    // With i18n, the landing page just redirects to a locale
+    if locales.using_i18n {
+        cacheable_pages.push(format!("{}/", path_prefix));
+    }
+    let mut build_time_paths = render_cfg
+        .iter()
+        // Pages generated incrementally are only known by their templates
+        .filter(|(path, template_path)| {
+            !path.ends_with('*') && !request_state_templates.contains(*template_path)
+        })
+        .collect::<Vec<_>>();
+    build_time_paths.sort();
+    for (path, _) in &build_time_paths {
+        let path_norm = match path.is_empty() {
+            true => "index",
+            false => path,
+        };
+        for locale in &page_locales {
+            cacheable_pages.push(get_page_url(path_prefix, locales, locale, path));
+            cacheable_pages.push(format!(
+                "{}/.perseus/page/{}/{}.json",
+                path_prefix, locale, path_norm
+            ));
+        }
+    }
+
+    // The landing page works as an app shell for anything else (unless it's specific
+    // to the user)
+    let landing_page = format!("{}/", path_prefix);
+    if cacheable_pages.contains(&landing_page) {
+        precache.push(landing_page);
+    }
+    let bundle_root = match bundles {
+        Some(bundles) => {
+            version_hasher.update(&bundles.id);
+            bundles.get_url_root(path_prefix)
+        }
+        None => {
+            // These aren't in the URLs, so they have to go into the version directly
+            // (anything that's missing will be missing from the precache too)
+            for bundle in [
+                "perseus_engine.js",
+                "perseus_engine_bg.wasm",
+                "perseus_engine_bg.wasm.js",
+            ] {
+                if let Ok(contents) =
+                    fs::read(format!("{}/pkg/{}", immutable_store.get_path(), bundle))
+                {
+                    version_hasher.update(&contents);
+                }
+            }
+            format!("{}/.perseus", path_prefix)
+        }
+    };
+    #[cfg(not(feature = "wasm2js"))]
+    precache.push(format!("{}/bundle.wasm", bundle_root));
+    #[cfg(feature = "wasm2js")]
+    precache.push(format!("{}/bundle.wasm.js", bundle_root));
+    precache.push(format!("{}/bundle.js", bundle_root));
+    let snippets_dir = PathBuf::from(format!("{}/pkg/snippets", immutable_store.get_path()));
+    for (url, contents) in list_files(&snippets_dir, &format!("{}/snippets", bundle_root)) {
+        version_hasher.update(&contents);
+        precache.push(url);
+    }
+
+    // Static assets
+    for (url, contents) in list_files(
+        Path::new(static_dir),
+        &format!("{}/.perseus/static", path_prefix),
+    ) {
+        version_hasher.update(&contents);
+        precache.push(url);
+    }
+    // These have to be in a deterministic order, or the version would change
+    let mut static_aliases = static_aliases.iter().collect::<Vec<_>>();
+    static_aliases.sort();
+    for (alias_url, path) in static_aliases {
+        // We can't cache ourselves!
+        if alias_url == &format!("/{}", SERVICE_WORKER_FILE) {
+            continue;
+        }
+        for (url, contents) in list_files(Path::new(path), &format!("{}{}", path_prefix, alias_url))
+        {
+            version_hasher.update(&contents);
+            precache.push(url);
+        }
+    }
+
+    // Translations are only fetched when the user switches locales, but they'd
+    // better be there if they do that offline
+    if locales.using_i18n {
+        for locale in locales.get_all() {
+            precache.push(format!("{}/.perseus/translations/{}", path_prefix, locale));
+        }
+    }
+
+    // Pages, both for initial loads and subsequent loads (these URLs have to match
+    // what the browser will request exactly)
+    let mut paths = match &settings.precache {
+        PrecachedPages::None => Vec::new(),
+        PrecachedPages::All => render_cfg
+            .keys()
+            // Pages generated incrementally are only known by their templates
+            .filter(|path| !path.ends_with('*'))
+            .cloned()
+            .collect(),
+        PrecachedPages::Selected(paths) => paths
+            .iter()
+            .map(|path| path.trim_matches('/').to_string())
+            .collect(),
+    };
+    paths.sort();
+    for path in paths {
+        // Pages that don't exist (or that haven't been built yet) can't be precached, and
+        // nor can pages that use request state
+        let template_path = match render_cfg.get(&path) {
+            Some(template_path) if !request_state_templates.contains(template_path) => {
+                template_path
+            }
+            _ => continue,
+        };
+        let path_norm = match path.is_empty() {
+            true => "index",
+            false => &path,
+        };
+        for locale in &page_locales {
+            precache.push(get_page_url(path_prefix, locales, locale, &path));
+            precache.push(format!(
+                "{}/.perseus/page/{}/{}.json?template_name={}&was_incremental_match=false",
+                path_prefix, locale, path_norm, template_path
+            ));
+        }
+    }
+    // The landing page may well have been added twice
+    let mut seen = HashSet::new();
+    precache.retain(|url| seen.insert(url.clone()));
+
+    let offline_page = build_error_page(
+        "",
+        settings.offline_status,
+        "network unavailable",
+        None,
+        error_pages,
+        html_shell,
+    );
+    // Unlike everything else, the render configuration isn't always reflected in
+    // the URLs, so we hash it separately (in a deterministic order)
+    let mut render_cfg = render_cfg.iter().collect::<Vec<_>>();
+    render_cfg.sort();
+    for (path, template_path) in render_cfg {
+        version_hasher.update(path);
+        version_hasher.update(template_path);
+    }
+    let precache = serde_json::to_string(&precache).unwrap();
+    let cacheable_pages = serde_json::to_string(&cacheable_pages).unwrap();
+    let offline_page = serde_json::to_string(&offline_page).unwrap();
+    version_hasher.update(&precache);
+    version_hasher.update(&cacheable_pages);
+    version_hasher.update(&offline_page);
+    version_hasher.update(settings.offline_status.to_string());
+    let version = version_hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    let service_worker = format!(
+        r#"
+const CACHE = "perseus-{version}";
+const PRECACHE = {precache};
+// Pages that use request state might be specific to the user, so only these are cached
+const CACHEABLE_PAGES = new Set({cacheable_pages});
+const OFFLINE_PAGE = {offline_page};
+const OFFLINE_STATUS = {offline_status};
+const PAGE_DATA_PREFIX = "{path_prefix}/.perseus/page/";
+const TRANSLATIONS_PREFIX = "{path_prefix}/.perseus/translations/";
+const BUNDLES_PREFIX = "{path_prefix}/.perseus/bundles/";
+
+self.addEventListener("install", (ev) => {{
+    ev.waitUntil(
+        caches
+            .open(CACHE)
+            // A single missing asset shouldn't stop everything else from working offline
+            .then((cache) => Promise.all(PRECACHE.map((url) => cache.add(url).catch(() => {{}}))))
+            .then(() => self.skipWaiting())
+    );
+}});
+
+self.addEventListener("activate", (ev) => {{
+    ev.waitUntil(
+        caches
+            .keys()
+            .then((keys) =>
+                Promise.all(
+                    keys
+                        .filter((key) => key.startsWith("perseus-") && key !== CACHE)
+                        .map((key) => caches.delete(key))
+                )
+            )
+            .then(() => self.clients.claim())
+    );
+}});
+
+self.addEventListener("fetch", (ev) => {{
+    const req = ev.request;
+    const url = new URL(req.url);
+    if (req.method !== "GET" || url.origin !== self.location.origin) return;
+
+    const cacheable = CACHEABLE_PAGES.has(url.pathname);
+    if (req.mode === "navigate") {{
+        ev.respondWith(
+            cacheable ? networkFirst(req) : networkOnly(req, OFFLINE_PAGE, "text/html; charset=utf-8")
+        );
+    }} else if (url.pathname.startsWith(PAGE_DATA_PREFIX)) {{
+        ev.respondWith(
+            cacheable ? staleWhileRevalidate(ev, req) : networkOnly(req, "network unavailable", "text/plain")
+        );
+    }} else if (url.pathname.startsWith(TRANSLATIONS_PREFIX)) {{
+        ev.respondWith(staleWhileRevalidate(ev, req));
+    }} else {{
+        ev.respondWith(cacheFirst(req, url));
+    }}
+}});
+
+function offlineResponse(body, contentType) {{
+    return new Response(body, {{
+        status: OFFLINE_STATUS,
+        headers: {{ "Content-Type": contentType }},
+    }});
+}}
+
+// Pages that can't be cached are only ever loaded from the network
+async function networkOnly(req, offlineBody, contentType) {{
+    try {{
+        return await fetch(req);
+    }} catch (err) {{
+        return offlineResponse(offlineBody, contentType);
+    }}
+}}
+
+// Pages are loaded from the network if possible, so users see the latest version
+async function networkFirst(req) {{
+    const cache = await caches.open(CACHE);
+    try {{
+        const res = await fetch(req);
+        if (res.ok) cache.put(req, res.clone());
+        return res;
+    }} catch (err) {{
+        const cached = await cache.match(req);
+        return cached || offlineResponse(OFFLINE_PAGE, "text/html; charset=utf-8");
+    }}
+}}
+
+async function staleWhileRevalidate(ev, req) {{
+    const cache = await caches.open(CACHE);
+    const cached = await cache.match(req);
+    const fresh = fetch(req).then((res) => {{
+        if (res.ok) cache.put(req, res.clone());
+        return res;
+    }});
+    if (cached) {{
+        ev.waitUntil(fresh.catch(() => {{}}));
+        return cached;
+    }}
+    // The app will show the appropriate error page for this status
+    return fresh.catch(() => offlineResponse("network unavailable", "text/plain"));
+}}
+
+async function cacheFirst(req, url) {{
+    const cache = await caches.open(CACHE);
+    const cached = await cache.match(req);
+    if (cached) return cached;
+    const res = await fetch(req);
+    // Bundles are served from content-hashed URLs, so they can be cached safely
+    if (res.ok && url.pathname.startsWith(BUNDLES_PREFIX)) cache.put(req, res.clone());
+    return res;
+}}
+"#,
+        version = &version[..VERSION_LENGTH],
+        precache = precache,
+        cacheable_pages = cacheable_pages,
+        offline_page = offline_page,
+        offline_status = settings.offline_status,
+        path_prefix = path_prefix,
+    );
+    immutable_store
+        .write(SERVICE_WORKER_FILE, &service_worker)
+        .await?;
+
+    Ok(())
+}
+
+/// Gets the URL the browser will request for an initial load of the page at the
+/// given path (without a locale) in the given locale.
+#[cfg(not(target_arch = "wasm32"))]
+fn get_page_url(path_prefix: &str, locales: &Locales, locale: &str, path: &str) -> String {
+    match (locales.using_i18n, path.is_empty()) {
+        (false, _) => format!("{}/{}", path_prefix, path),
+        (true, true) => format!("{}/{}", path_prefix, locale),
+        (true, false) => format!("{}/{}/{}", path_prefix, locale, path),
+    }
+}
+
+/// Lists all the files at the given path (which may be a single file or a
+/// directory), returning the URLs they'll be served at (given the URL of the
+/// path itself) and their contents, in a deterministic order. Anything that
+/// can't be read is skipped.
+#[cfg(not(target_arch = "wasm32"))]
+fn list_files(path: &Path, url: &str) -> Vec<(String, Vec<u8>)> {
+    if path.is_file() {
+        return match fs::read(path) {
+            Ok(contents) => vec![(url.to_string(), contents)],
+            Err(_) => Vec::new(),
+        };
+    }
+    let mut entries = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>(),
+        Err(_) => return Vec::new(),
+    };
+    entries.sort();
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let name = match entry.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        files.extend(list_files(
+            &entry,
+            &format!("{}/{}", url, urlencoding::encode(&name)),
+        ));
+    }
+    files
+}
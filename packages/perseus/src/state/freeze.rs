@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use super::MakeRx;
+#[cfg(target_arch = "wasm32")]
+use super::{create_merge_patch, Freeze};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// A representation of a frozen app.
@@ -15,6 +18,126 @@ pub struct FrozenApp {
     /// The frozen page state store. We store this as a `HashMap` as this level
     /// so that we can avoid another deserialization.
     pub page_state_store: HashMap<String, String>,
+    /// The schema version of the frozen global state. Frozen apps from before
+    /// state was versioned will have this set to `0`.
+    #[serde(default)]
+    pub global_state_version: u32,
+    /// The schema versions of the states in the frozen page state store, by
+    /// URL. Any pages not in here are assumed to be on version `0`.
+    #[serde(default)]
+    pub page_state_versions: HashMap<String, u32>,
+}
+
+/// Deserializes the given frozen state, which was created with the given
+/// version of its type's schema, migrating it to the current version if
+/// necessary. This will return `None` if the state is invalid, if it can't be
+/// migrated, or if it comes from a newer version of the schema than the
+/// current one (which we can't downgrade). Anything that was changed when the
+/// state was frozen will be restored with `MakeRx::thaw()`.
+pub(crate) fn deserialize_frozen_state<U>(state_str: &str, version: u32) -> Option<U>
+where
+    U: MakeRx + DeserializeOwned,
+{
+    let state: U = if version == U::STATE_VERSION {
+        serde_json::from_str(state_str).ok()?
+    } else if version < U::STATE_VERSION {
+        let state = serde_json::from_str(state_str).ok()?;
+        let state = U::migrate(version, state).ok()?;
+        serde_json::from_value(state).ok()?
+    } else {
+        return None;
+    };
+
+    Some(state.thaw())
+}
+
+/// The frozen state of an app, and the preferences for thawing it, as they're
+/// stored in the render context.
+#[cfg(target_arch = "wasm32")]
+type FrozenAppCell = std::cell::RefCell<Option<(FrozenApp, ThawPrefs)>>;
+
+/// Thaws the given frozen global state alone, leaving any page states that
+/// have already been thawed alone. The new state will be used the next time
+/// the global state is requested, so the current page should be re-rendered
+/// after this.
+#[cfg(all(
+    target_arch = "wasm32",
+    any(feature = "global-state-sync", feature = "state-history")
+))]
+pub(crate) fn thaw_global_state(frozen_app: &FrozenAppCell, state: String, version: u32) {
+    let mut frozen_app = frozen_app.borrow_mut();
+    let (frozen_app, thaw_prefs) = frozen_app.get_or_insert_with(empty_frozen_app);
+    frozen_app.global_state = state;
+    frozen_app.global_state_version = version;
+    thaw_prefs.global_prefer_frozen = true;
+}
+/// Thaws the given frozen state for the page at the given URL alone, leaving
+/// the global state and any other page states that have already been thawed
+/// alone. The new state will be used the next time the page is rendered.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn thaw_page_state(frozen_app: &FrozenAppCell, url: &str, state: String, version: u32) {
+    let mut frozen_app = frozen_app.borrow_mut();
+    let (frozen_app, thaw_prefs) = frozen_app.get_or_insert_with(empty_frozen_app);
+    frozen_app.page_state_store.insert(url.to_string(), state);
+    frozen_app
+        .page_state_versions
+        .insert(url.to_string(), version);
+    // Make sure this page will prefer the frozen state we've just given it
+    match &mut thaw_prefs.page {
+        PageThawPrefs::Include(pages) => {
+            if !pages.iter().any(|page| page == url) {
+                pages.push(url.to_string());
+            }
+        }
+        PageThawPrefs::IncludeAll => (),
+        PageThawPrefs::Exclude(pages) => pages.retain(|page| page != url),
+    }
+}
+/// Merges the given frozen state, which was created with the given version of
+/// its type's schema, into the given live state in place, so that only the
+/// signals whose frozen values have changed will be updated, and anything
+/// that isn't frozen (e.g. fields with `#[rx(skip_freeze)]`) will be left
+/// alone. This returns `false`, having changed nothing, if the frozen state
+/// can't be merged (e.g. because it comes from a different version of the
+/// schema), in which case it should be thawed instead.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn merge_frozen_state<S: Freeze + ?Sized>(
+    state: &S,
+    frozen: &str,
+    version: u32,
+) -> bool {
+    if version != state.state_version() {
+        return false;
+    }
+    let (old, new) = match (
+        serde_json::from_str::<serde_json::Value>(&state.freeze()),
+        serde_json::from_str::<serde_json::Value>(frozen),
+    ) {
+        (Ok(old), Ok(new)) => (old, new),
+        _ => return false,
+    };
+
+    old == new || state.merge(create_merge_patch(&old, &new))
+}
+/// Creates a frozen app with nothing in it, which will have no effect when
+/// it's thawed.
+#[cfg(target_arch = "wasm32")]
+fn empty_frozen_app() -> (FrozenApp, ThawPrefs) {
+    (
+        FrozenApp {
+            // See `rx_state.rs` for why this would be the default value
+            global_state: "None".to_string(),
+            global_state_version: 0,
+            // This is only used when thawing through the render context
+            route: String::new(),
+            page_state_store: HashMap::new(),
+            page_state_versions: HashMap::new(),
+        },
+        ThawPrefs {
+            page: PageThawPrefs::Include(Vec::new()),
+            global_prefer_frozen: false,
+        },
+    )
 }
 
 /// The user's preferences on state thawing.
@@ -62,3 +185,89 @@ impl PageThawPrefs {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MakeUnrx;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct State {
+        name: String,
+        count: u32,
+        #[serde(skip)]
+        thawed: bool,
+    }
+    impl MakeRx for State {
+        type Rx = Self;
+        const STATE_VERSION: u32 = 2;
+        fn make_rx(self) -> Self {
+            self
+        }
+        fn thaw(mut self) -> Self {
+            self.thawed = true;
+            self
+        }
+        // Version 1 called `name` `title`, and didn't have `count`, and nothing can be
+        // migrated from version 0
+        fn migrate(
+            from_version: u32,
+            mut state: serde_json::Value,
+        ) -> Result<serde_json::Value, String> {
+            if from_version != 1 {
+                return Err("unsupported version".to_string());
+            }
+            let state = state.as_object_mut().ok_or("state isn't an object")?;
+            let title = state.remove("title").ok_or("state has no title")?;
+            state.insert("name".to_string(), title);
+            state.insert("count".to_string(), 0.into());
+            Ok(serde_json::Value::Object(state.clone()))
+        }
+    }
+    impl MakeUnrx for State {
+        type Unrx = Self;
+        fn make_unrx(self) -> Self {
+            self
+        }
+    }
+
+    #[test]
+    fn deserializes_current_version() {
+        assert_eq!(
+            deserialize_frozen_state::<State>(r#"{"name":"foo","count":3}"#, 2),
+            Some(State {
+                name: "foo".to_string(),
+                count: 3,
+                thawed: true,
+            })
+        );
+        assert_eq!(
+            deserialize_frozen_state::<State>(r#"{"name":"foo"}"#, 2),
+            None
+        );
+    }
+
+    #[test]
+    fn migrates_older_versions() {
+        assert_eq!(
+            deserialize_frozen_state::<State>(r#"{"title":"foo"}"#, 1),
+            Some(State {
+                name: "foo".to_string(),
+                count: 0,
+                thawed: true,
+            })
+        );
+        assert_eq!(
+            deserialize_frozen_state::<State>(r#"{"title":"foo"}"#, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        assert_eq!(
+            deserialize_frozen_state::<State>(r#"{"name":"foo","count":3}"#, 3),
+            None
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::errors::*;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{JsCast, JsValue};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_futures::JsFuture;
+
+/// A trait for places that frozen app state (as produced by `.freeze()` on the
+/// render context) can be persisted to and restored from. This is used by hot
+/// state reloading in development, but you can also use it to save your
+/// users' state yourself, whether in the browser (e.g. with
+/// [`WebStorageFrozenStateStore`]) or on your own server with
+/// [`HttpFrozenStateStore`] (allowing users to pick up where they left off on
+/// another device).
+///
+/// Stores only need to hold a single frozen state at a time, and setting a new
+/// one may replace any previous one. Like the mutable store, this is written
+/// as a trait with an isolated error type (see [`FrozenStateStoreError`]), so
+/// you can write your own implementations.
+///
+/// Note that these stores will only do anything in the browser.
+#[async_trait::async_trait(?Send)]
+pub trait FrozenStateStore: std::fmt::Debug {
+    /// Gets the stored frozen state, if there is one. Be warned that this
+    /// could be arbitrarily old, and it could have been tampered with.
+    async fn get(&self) -> Result<Option<String>, FrozenStateStoreError>;
+    /// Stores the given frozen state, replacing any existing one.
+    async fn set(&self, frozen_state: &str) -> Result<(), FrozenStateStoreError>;
+    /// Removes the stored frozen state entirely.
+    async fn clear(&self) -> Result<(), FrozenStateStoreError>;
+}
+
+#[cfg(all(feature = "idb-freezing", target_arch = "wasm32"))]
+#[async_trait::async_trait(?Send)]
+impl FrozenStateStore for super::IdbFrozenStateStore {
+    async fn get(&self) -> Result<Option<String>, FrozenStateStoreError> {
+        Ok(super::IdbFrozenStateStore::get(self).await?)
+    }
+    async fn set(&self, frozen_state: &str) -> Result<(), FrozenStateStoreError> {
+        Ok(super::IdbFrozenStateStore::set(self, frozen_state).await?)
+    }
+    async fn clear(&self) -> Result<(), FrozenStateStoreError> {
+        Ok(super::IdbFrozenStateStore::clear(self).await?)
+    }
+}
+
+/// The kinds of web storage that [`WebStorageFrozenStateStore`] can use.
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WebStorageKind {
+    Local,
+    Session,
+}
+
+/// A frozen state store that uses the browser's `localStorage` or
+/// `sessionStorage`, under a single key. Unlike IndexedDB, these are
+/// synchronous and very widely supported, but browsers limit them to a few
+/// megabytes per origin, so they're best suited to apps with fairly small
+/// states. Anything in `sessionStorage` will be lost when the user closes the
+/// tab.
+#[derive(Clone, Debug)]
+pub struct WebStorageFrozenStateStore {
+    #[cfg(target_arch = "wasm32")]
+    kind: WebStorageKind,
+    #[cfg(target_arch = "wasm32")]
+    key: String,
+}
+impl WebStorageFrozenStateStore {
+    /// Creates a new store that uses `localStorage`, persisting state across
+    /// browser sessions, under the given key.
+    #[allow(unused_variables)]
+    pub fn local(key: &str) -> Self {
+        Self {
+            #[cfg(target_arch = "wasm32")]
+            kind: WebStorageKind::Local,
+            #[cfg(target_arch = "wasm32")]
+            key: key.to_string(),
+        }
+    }
+    /// Creates a new store that uses `sessionStorage`, persisting state only
+    /// until the user closes the tab, under the given key.
+    #[allow(unused_variables)]
+    pub fn session(key: &str) -> Self {
+        Self {
+            #[cfg(target_arch = "wasm32")]
+            kind: WebStorageKind::Session,
+            #[cfg(target_arch = "wasm32")]
+            key: key.to_string(),
+        }
+    }
+    /// Gets the underlying storage.
+    #[cfg(target_arch = "wasm32")]
+    fn get_storage(&self) -> Result<web_sys::Storage, FrozenStateStoreError> {
+        let window = web_sys::window().unwrap();
+        let storage = match self.kind {
+            WebStorageKind::Local => window.local_storage(),
+            WebStorageKind::Session => window.session_storage(),
+        };
+        // Browsers will throw errors here if the user has disabled storage
+        match storage {
+            Ok(Some(storage)) => Ok(storage),
+            _ => Err(FrozenStateStoreError::StorageUnavailable),
+        }
+    }
+}
+#[async_trait::async_trait(?Send)]
+impl FrozenStateStore for WebStorageFrozenStateStore {
+    #[cfg(target_arch = "wasm32")]
+    async fn get(&self) -> Result<Option<String>, FrozenStateStoreError> {
+        self.get_storage()?
+            .get_item(&self.key)
+            .map_err(|err| FrozenStateStoreError::Js(format!("{:?}", err)))
+    }
+    #[cfg(target_arch = "wasm32")]
+    async fn set(&self, frozen_state: &str) -> Result<(), FrozenStateStoreError> {
+        // This will fail if the state is too large for the browser's quota
+        self.get_storage()?
+            .set_item(&self.key, frozen_state)
+            .map_err(|err| FrozenStateStoreError::Js(format!("{:?}", err)))
+    }
+    #[cfg(target_arch = "wasm32")]
+    async fn clear(&self) -> Result<(), FrozenStateStoreError> {
+        self.get_storage()?
+            .remove_item(&self.key)
+            .map_err(|err| FrozenStateStoreError::Js(format!("{:?}", err)))
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn get(&self) -> Result<Option<String>, FrozenStateStoreError> {
+        Ok(None)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn set(&self, _frozen_state: &str) -> Result<(), FrozenStateStoreError> {
+        Ok(())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn clear(&self) -> Result<(), FrozenStateStoreError> {
+        Ok(())
+    }
+}
+
+/// A frozen state store that uses an HTTP endpoint of your own, which allows
+/// you to persist your users' state on your server, so they can resume it from
+/// another device. Frozen state will be fetched from the endpoint with a `GET`
+/// request (to which a `404` means that nothing has been stored), stored with
+/// a `PUT` request whose body is the frozen state, and cleared with a `DELETE`
+/// request. Any other response status outside the `200` range will be treated
+/// as an error.
+///
+/// Cookies will be sent with requests to the same origin as your app, and you
+/// can add extra headers (e.g. for authentication) with `.header()`. Note that
+/// Perseus has no way of knowing which user a frozen state belongs to, so your
+/// endpoint must work that out itself!
+#[derive(Clone, Debug)]
+pub struct HttpFrozenStateStore {
+    #[cfg(target_arch = "wasm32")]
+    url: String,
+    #[cfg(target_arch = "wasm32")]
+    headers: Vec<(String, String)>,
+}
+impl HttpFrozenStateStore {
+    /// Creates a new store that uses the endpoint at the given URL.
+    #[allow(unused_variables)]
+    pub fn new(url: &str) -> Self {
+        Self {
+            #[cfg(target_arch = "wasm32")]
+            url: url.to_string(),
+            #[cfg(target_arch = "wasm32")]
+            headers: Vec::new(),
+        }
+    }
+    /// Adds a header that will be sent with every request to the endpoint.
+    #[allow(unused_variables, unused_mut)]
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        #[cfg(target_arch = "wasm32")]
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    /// Sends a request to the endpoint with the given method and body,
+    /// returning the status code and the body of the response.
+    #[cfg(target_arch = "wasm32")]
+    async fn request(
+        &self,
+        method: &str,
+        body: Option<&str>,
+    ) -> Result<(u16, String), FrozenStateStoreError> {
+        use web_sys::{Request, RequestInit, Response};
+
+        let js_err_handler = |err: JsValue| FrozenStateStoreError::Js(format!("{:?}", err));
+        let opts = RequestInit::new();
+        opts.set_method(method);
+        if let Some(body) = body {
+            opts.set_body(&JsValue::from_str(body));
+        }
+        let request = Request::new_with_str_and_init(&self.url, &opts).map_err(js_err_handler)?;
+        let headers = request.headers();
+        if body.is_some() {
+            // Frozen state is always JSON
+            headers
+                .set("Content-Type", "application/json")
+                .map_err(js_err_handler)?;
+        }
+        for (name, value) in &self.headers {
+            headers.set(name, value).map_err(js_err_handler)?;
+        }
+
+        let window = web_sys::window().unwrap();
+        let res_value = JsFuture::from(window.fetch_with_request(&request))
+            .await
+            .map_err(js_err_handler)?;
+        let res: Response = res_value.dyn_into().unwrap();
+        let body = JsFuture::from(res.text().map_err(js_err_handler)?)
+            .await
+            .map_err(js_err_handler)?;
+        let body = match body.as_string() {
+            Some(body) => body,
+            None => {
+                return Err(FetchError::NotString {
+                    url: self.url.clone(),
+                }
+                .into())
+            }
+        };
+
+        Ok((res.status(), body))
+    }
+    /// Creates an error for an unexpected response from the endpoint.
+    #[cfg(target_arch = "wasm32")]
+    fn not_ok(&self, status: u16, body: String) -> FrozenStateStoreError {
+        FetchError::NotOk {
+            url: self.url.clone(),
+            status,
+            err: body,
+        }
+        .into()
+    }
+}
+#[async_trait::async_trait(?Send)]
+impl FrozenStateStore for HttpFrozenStateStore {
+    #[cfg(target_arch = "wasm32")]
+    async fn get(&self) -> Result<Option<String>, FrozenStateStoreError> {
+        match self.request("GET", None).await? {
+            (404, _) => Ok(None),
+            (200..=299, body) => Ok(Some(body)),
+            (status, body) => Err(self.not_ok(status, body)),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    async fn set(&self, frozen_state: &str) -> Result<(), FrozenStateStoreError> {
+        match self.request("PUT", Some(frozen_state)).await? {
+            (200..=299, _) => Ok(()),
+            (status, body) => Err(self.not_ok(status, body)),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    async fn clear(&self) -> Result<(), FrozenStateStoreError> {
+        match self.request("DELETE", None).await? {
+            // If there was nothing there, that's fine
+            (200..=299 | 404, _) => Ok(()),
+            (status, body) => Err(self.not_ok(status, body)),
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn get(&self) -> Result<Option<String>, FrozenStateStoreError> {
+        Ok(None)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn set(&self, _frozen_state: &str) -> Result<(), FrozenStateStoreError> {
+        Ok(())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn clear(&self) -> Result<(), FrozenStateStoreError> {
+        Ok(())
+    }
+}
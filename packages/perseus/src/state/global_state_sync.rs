@@ -0,0 +1,337 @@
+#[cfg(target_arch = "wasm32")]
+use super::{merge_frozen_state, thaw_global_state, FrozenApp, GlobalState, ThawPrefs};
+#[cfg(target_arch = "wasm32")]
+use crate::router::{RouterLoadState, RouterState};
+#[cfg(target_arch = "wasm32")]
+use serde::{Deserialize, Serialize};
+#[cfg(target_arch = "wasm32")]
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+#[cfg(target_arch = "wasm32")]
+use sycamore::reactive::{create_effect, create_ref, create_scope};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+#[cfg(target_arch = "wasm32")]
+use web_sys::{BroadcastChannel, MessageEvent, Storage, StorageEvent};
+
+/// The default name of the channel global state changes are broadcast on.
+#[cfg(target_arch = "wasm32")]
+const DFLT_CHANNEL: &str = "perseus_global_state";
+
+/// The type of functions that resolve conflicts between changes to global
+/// state made in different tabs. These are given the older and the newer of
+/// the two frozen states, in that order, and should return the frozen state
+/// to use.
+pub type GlobalStateConflictResolver = Rc<dyn Fn(&str, &str) -> String>;
+
+/// Settings for synchronizing global state across all the tabs of your app
+/// that the user has open (in the same browser). Whenever the global state is
+/// changed in one tab, it will be frozen and broadcast to all the others, which
+/// will merge it into their own global states in place, so only the parts of
+/// their pages that depend on what's changed will be updated (page state, and
+/// anything that isn't frozen, will be left alone). This uses a
+/// `BroadcastChannel`, or, in browsers that don't support that, `localStorage`
+/// events (the state is removed from `localStorage` as soon as it's been
+/// sent).
+///
+/// If two tabs change the global state at around the same time, the most
+/// recent change will win by default, but you can provide your own function
+/// to resolve such conflicts with `.resolve_conflicts()`.
+///
+/// Note that this only synchronizes *changes*, so a newly opened tab will start
+/// with the global state from the server as usual.
+#[derive(Clone)]
+pub struct GlobalStateSync {
+    #[cfg(target_arch = "wasm32")]
+    channel: String,
+    #[cfg(target_arch = "wasm32")]
+    resolver: Option<GlobalStateConflictResolver>,
+}
+impl std::fmt::Debug for GlobalStateSync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GlobalStateSync").finish()
+    }
+}
+impl Default for GlobalStateSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl GlobalStateSync {
+    /// Creates new settings for global state synchronization, using the
+    /// default channel and resolving conflicts in favor of the most recent
+    /// change.
+    pub fn new() -> Self {
+        Self {
+            #[cfg(target_arch = "wasm32")]
+            channel: DFLT_CHANNEL.to_string(),
+            #[cfg(target_arch = "wasm32")]
+            resolver: None,
+        }
+    }
+    /// Sets the name of the channel changes will be broadcast on. You only
+    /// need to change this if you have several Perseus apps on the same
+    /// origin.
+    #[allow(unused_variables, unused_mut)]
+    pub fn channel(mut self, name: &str) -> Self {
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.channel = name.to_string();
+        }
+        self
+    }
+    /// Sets a function to resolve conflicts when the global state has been
+    /// changed in this tab since the last change was received from another.
+    /// This will be given the older and the newer of the two frozen states,
+    /// in that order (so that every tab will reach the same result, as long
+    /// as this is deterministic), and should return the frozen global state
+    /// to use. Whatever this returns won't be broadcast to other tabs.
+    #[allow(unused_variables, unused_mut)]
+    pub fn resolve_conflicts(mut self, resolver: impl Fn(&str, &str) -> String + 'static) -> Self {
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.resolver = Some(Rc::new(resolver));
+        }
+        self
+    }
+}
+
+/// A message broadcast to other tabs when the global state changes.
+#[cfg(target_arch = "wasm32")]
+#[derive(Serialize, Deserialize)]
+struct SyncMessage {
+    /// The time the change was made, in milliseconds since the epoch.
+    time: f64,
+    /// The schema version of the frozen state.
+    version: u32,
+    /// The frozen global state.
+    state: String,
+}
+
+/// The ways global state changes can be sent to other tabs.
+#[cfg(target_arch = "wasm32")]
+enum SyncTransport {
+    Channel(BroadcastChannel),
+    /// Setting an item in storage will fire an event in every other tab. The
+    /// key to use is stored here too, and the item is removed again straight
+    /// after it's set.
+    Storage(Storage, String),
+}
+
+/// The system that synchronizes global state across tabs. This can be cheaply
+/// cloned.
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone)]
+pub(crate) struct GlobalStateSyncer {
+    inner: Rc<SyncerInner>,
+}
+#[cfg(target_arch = "wasm32")]
+struct SyncerInner {
+    /// The function to resolve conflicts with, if we aren't just using the
+    /// most recent change.
+    resolver: Option<GlobalStateConflictResolver>,
+    /// How we send changes to other tabs.
+    transport: SyncTransport,
+    /// The global state we're synchronizing.
+    global_state: GlobalState,
+    /// The router state, so that we can re-render the current page if we
+    /// can't merge a change from another tab.
+    router: RouterState,
+    /// The frozen state of the app, which we thaw other tabs' global states
+    /// into.
+    frozen_app: Rc<RefCell<Option<(FrozenApp, ThawPrefs)>>>,
+    /// The number of times the global state has been replaced, which lets
+    /// watchers on older states know to stop.
+    generation: Cell<usize>,
+    /// The time the global state was last changed in this tab.
+    last_local_change: Cell<f64>,
+    /// The time of the last change we received from another tab.
+    last_sync: Cell<f64>,
+    /// Whether or not we're currently merging a change from another tab into
+    /// the global state, which shouldn't be broadcast back.
+    is_merging: Cell<bool>,
+}
+#[cfg(target_arch = "wasm32")]
+impl std::fmt::Debug for GlobalStateSyncer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GlobalStateSyncer")
+            .field("generation", &self.inner.generation.get())
+            .finish()
+    }
+}
+#[cfg(target_arch = "wasm32")]
+impl GlobalStateSyncer {
+    /// Creates a new syncer and starts listening for changes from other tabs.
+    /// This will return `None` if there's no way to communicate with other
+    /// tabs in this browser.
+    pub(crate) fn new(
+        settings: GlobalStateSync,
+        global_state: GlobalState,
+        router: RouterState,
+        frozen_app: Rc<RefCell<Option<(FrozenApp, ThawPrefs)>>>,
+    ) -> Option<Self> {
+        let window = web_sys::window().unwrap();
+        let transport = match BroadcastChannel::new(&settings.channel) {
+            Ok(channel) => SyncTransport::Channel(channel),
+            // Older browsers don't have `BroadcastChannel`
+            Err(_) => match window.local_storage() {
+                Ok(Some(storage)) => {
+                    SyncTransport::Storage(storage, format!("__{}", settings.channel))
+                }
+                _ => return None,
+            },
+        };
+        let this = Self {
+            inner: Rc::new(SyncerInner {
+                resolver: settings.resolver,
+                transport,
+                global_state,
+                router,
+                frozen_app,
+                generation: Cell::new(0),
+                last_local_change: Cell::new(0.0),
+                last_sync: Cell::new(0.0),
+                is_merging: Cell::new(false),
+            }),
+        };
+
+        let listener_this = this.clone();
+        match &this.inner.transport {
+            SyncTransport::Channel(channel) => {
+                let closure = Closure::wrap(Box::new(move |ev: MessageEvent| {
+                    if let Some(msg) = ev.data().as_string() {
+                        listener_this.receive(&msg);
+                    }
+                }) as Box<dyn FnMut(MessageEvent)>);
+                channel.set_onmessage(Some(closure.as_ref().unchecked_ref()));
+                // The syncer lives as long as the app, so this is never removed
+                closure.forget();
+            }
+            SyncTransport::Storage(_, key) => {
+                let key = key.clone();
+                let closure = Closure::wrap(Box::new(move |ev: StorageEvent| {
+                    if ev.key().as_deref() == Some(&key) {
+                        if let Some(msg) = ev.new_value() {
+                            listener_this.receive(&msg);
+                        }
+                    }
+                }) as Box<dyn FnMut(StorageEvent)>);
+                window
+                    .add_event_listener_with_callback("storage", closure.as_ref().unchecked_ref())
+                    .unwrap();
+                closure.forget();
+            }
+        }
+
+        Some(this)
+    }
+    /// Starts watching the global state for changes, which will be broadcast
+    /// to other tabs. This must be called every time the global state is
+    /// replaced, since we watch the signals inside it.
+    pub(crate) fn watch(&self) {
+        let generation = self.inner.generation.get() + 1;
+        self.inner.generation.set(generation);
+
+        let this = self.clone();
+        // This scope is never disposed of, but the effect in it will do nothing once the
+        // global state has been replaced
+        let _ = create_scope(move |cx| {
+            let is_first = create_ref(cx, Cell::new(true));
+            create_effect(cx, move || {
+                this.inner.global_state.0.borrow().track();
+                // The first run is just to subscribe to everything
+                if is_first.replace(false)
+                    || this.inner.generation.get() != generation
+                    || this.inner.is_merging.get()
+                {
+                    return;
+                }
+                this.broadcast();
+            });
+        });
+    }
+    /// Broadcasts the current global state to other tabs.
+    fn broadcast(&self) {
+        let time = js_sys::Date::now();
+        self.inner.last_local_change.set(time);
+        let msg = {
+            let global_state = self.inner.global_state.0.borrow();
+            SyncMessage {
+                time,
+                version: global_state.state_version(),
+                state: global_state.freeze(),
+            }
+        };
+        let msg = serde_json::to_string(&msg).unwrap();
+        // There's nothing we can do if this fails (e.g. if storage is full)
+        let _ = match &self.inner.transport {
+            SyncTransport::Channel(channel) => channel.post_message(&JsValue::from_str(&msg)),
+            // Other tabs will already have been sent the event, and this stops the global
+            // state (which might have sensitive data in it) from sitting around in storage
+            SyncTransport::Storage(storage, key) => storage
+                .set_item(key, &msg)
+                .and_then(|_| storage.remove_item(key)),
+        };
+    }
+    /// Handles a change to the global state from another tab, applying it
+    /// here unless it conflicts with a more recent local change.
+    fn receive(&self, msg: &str) {
+        let msg: SyncMessage = match serde_json::from_str(msg) {
+            Ok(msg) => msg,
+            Err(_) => return,
+        };
+        let local_state = self.inner.global_state.0.borrow().freeze();
+        if msg.state == local_state {
+            self.inner.last_sync.set(msg.time);
+            return;
+        }
+
+        let last_local_change = self.inner.last_local_change.get();
+        // If we've changed the global state since we last heard from another tab, both
+        // tabs have made changes that the other hasn't seen
+        let is_conflict = last_local_change > self.inner.last_sync.get();
+        let remote_is_newer = msg.time >= last_local_change;
+        let new_state = match &self.inner.resolver {
+            Some(resolver) if is_conflict => {
+                if remote_is_newer {
+                    resolver(&local_state, &msg.state)
+                } else {
+                    resolver(&msg.state, &local_state)
+                }
+            }
+            _ if is_conflict && !remote_is_newer => {
+                // Our own change is more recent, and other tabs will have received that
+                self.inner.last_sync.set(last_local_change);
+                return;
+            }
+            _ => msg.state,
+        };
+        self.inner.last_sync.set(msg.time.max(last_local_change));
+
+        if new_state != local_state {
+            self.apply(new_state, msg.version);
+        }
+    }
+    /// Merges the given frozen global state into the current one in place, or,
+    /// if that isn't possible, thaws it and re-renders the current page so
+    /// that it's used.
+    fn apply(&self, state: String, version: u32) {
+        self.inner.is_merging.set(true);
+        let merged = merge_frozen_state(&**self.inner.global_state.0.borrow(), &state, version);
+        self.inner.is_merging.set(false);
+        if merged {
+            return;
+        }
+
+        thaw_global_state(&self.inner.frozen_app, state, version);
+
+        // If no page has been loaded yet, the new state will be picked up when one is
+        let is_loaded = matches!(
+            *self.inner.router.get_load_state_rc().get_untracked(),
+            RouterLoadState::Loaded { .. }
+        );
+        if is_loaded {
+            self.inner.router.reload();
+        }
+    }
+}
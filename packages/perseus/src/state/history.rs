@@ -0,0 +1,436 @@
+#[cfg(target_arch = "wasm32")]
+use super::{
+    merge_frozen_state, thaw_global_state, thaw_page_state, FrozenApp, GlobalState, PageStateStore,
+    ThawPrefs,
+};
+#[cfg(target_arch = "wasm32")]
+use crate::router::{RouterLoadState, RouterState};
+#[cfg(target_arch = "wasm32")]
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+#[cfg(target_arch = "wasm32")]
+use sycamore::prelude::{create_effect, create_rc_signal, RcSignal, Scope};
+
+/// The state a history operation should apply to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HistoryScope {
+    /// The global state.
+    Global,
+    /// The state of the page at the given URL (with no leading `/`, like the
+    /// `path` in a page's `PageProps`).
+    Page(String),
+    /// The state of whichever page the router currently has loaded.
+    CurrentPage,
+}
+
+/// A single state in a history timeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    /// The time this state was recorded, in milliseconds since the epoch.
+    pub time: f64,
+    /// The frozen state.
+    pub state: String,
+    /// The version of the state's schema it was frozen with.
+    pub version: u32,
+}
+
+/// The recorded history of a page's state, or of the global state.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistoryTimeline {
+    /// The recorded states, from oldest to newest.
+    pub entries: Vec<HistoryEntry>,
+    /// The index of the entry the state is currently at. Any entries after
+    /// this can be returned to with `.redo()`, until the state is changed
+    /// again, which will discard them.
+    pub current: usize,
+}
+
+/// A recorder of the history of your app's page and global state, providing
+/// undo and redo. This is available as `.history` on the render context, and
+/// will only record anything if it's been enabled with
+/// `PerseusApp::record_state_history()`.
+///
+/// Whenever the global state or the state of the current page changes, it'll
+/// be frozen and added to that state's timeline (each page has its own), so
+/// this works with any state made with `#[make_rx]`. Undoing or redoing will
+/// merge the recorded state into the live one in place, so only the parts of
+/// the page that depend on the signals that change will be updated, and
+/// anything that isn't frozen (like fields with `#[rx(skip_freeze)]`) will be
+/// left alone. If the recorded state can't be merged (e.g. because the page
+/// has since been evicted from the state store), it'll be thawed instead,
+/// re-rendering the current page if it's affected (states for other pages
+/// will be used when they're next visited).
+///
+/// All the getters here are reactive, so you can use them in your views (e.g.
+/// to disable an undo button).
+///
+/// On the engine-side, this will never record anything.
+#[derive(Clone, Default)]
+pub struct StateHistory {
+    #[cfg(target_arch = "wasm32")]
+    inner: Option<Rc<HistoryInner>>,
+}
+impl std::fmt::Debug for StateHistory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateHistory").finish()
+    }
+}
+impl StateHistory {
+    /// Returns the given state to the entry before the current one in its
+    /// timeline, returning whether or not there was one.
+    #[allow(unused_variables)]
+    pub fn undo(&self, scope: HistoryScope) -> bool {
+        #[cfg(target_arch = "wasm32")]
+        if let Some(inner) = &self.inner {
+            return inner.step(scope, false);
+        }
+        false
+    }
+    /// Returns the given state to the entry after the current one in its
+    /// timeline (i.e. undoes an undo), returning whether or not there was
+    /// one.
+    #[allow(unused_variables)]
+    pub fn redo(&self, scope: HistoryScope) -> bool {
+        #[cfg(target_arch = "wasm32")]
+        if let Some(inner) = &self.inner {
+            return inner.step(scope, true);
+        }
+        false
+    }
+    /// Returns the given state to the entry at the given index in its
+    /// timeline, returning whether or not there was one. This won't discard
+    /// any entries, so you can jump backward and forward freely until the
+    /// state is changed again.
+    #[allow(unused_variables)]
+    pub fn go_to(&self, scope: HistoryScope, index: usize) -> bool {
+        #[cfg(target_arch = "wasm32")]
+        if let Some(inner) = &self.inner {
+            return inner.go_to(scope, index);
+        }
+        false
+    }
+    /// Checks whether or not there's anything to undo for the given state.
+    pub fn can_undo(&self, scope: HistoryScope) -> bool {
+        let timeline = self.timeline(scope);
+        timeline.current > 0
+    }
+    /// Checks whether or not there's anything to redo for the given state.
+    pub fn can_redo(&self, scope: HistoryScope) -> bool {
+        let timeline = self.timeline(scope);
+        timeline.current + 1 < timeline.entries.len()
+    }
+    /// Gets the recorded timeline for the given state. This will be empty if
+    /// nothing has been recorded for it.
+    #[allow(unused_variables)]
+    pub fn timeline(&self, scope: HistoryScope) -> HistoryTimeline {
+        #[cfg(target_arch = "wasm32")]
+        if let Some(inner) = &self.inner {
+            return inner.timeline(scope);
+        }
+        HistoryTimeline::default()
+    }
+    /// Clears the recorded timeline for the given state, leaving only the
+    /// state it's currently in.
+    #[allow(unused_variables)]
+    pub fn clear(&self, scope: HistoryScope) {
+        #[cfg(target_arch = "wasm32")]
+        if let Some(inner) = &self.inner {
+            inner.clear(scope);
+        }
+    }
+    /// Notes that the state of the given page (or the global state, if this is
+    /// `None`) has been registered, which will happen when the page is
+    /// rendered with new state.
+    #[allow(unused_variables)]
+    pub(crate) fn registered(&self, url: Option<&str>) {
+        #[cfg(target_arch = "wasm32")]
+        if let Some(inner) = &self.inner {
+            inner.with_timeline(url, |timeline| timeline.registrations += 1);
+            inner.registered.set(());
+        }
+    }
+}
+#[cfg(target_arch = "wasm32")]
+impl StateHistory {
+    /// Creates a new history recorder, which will keep up to the given number
+    /// of entries in each timeline. This won't record anything until
+    /// `.start_recording()` is called.
+    pub(crate) fn new(
+        max_len: usize,
+        router: RouterState,
+        global_state: GlobalState,
+        page_state_store: PageStateStore,
+        frozen_app: Rc<RefCell<Option<(FrozenApp, ThawPrefs)>>>,
+    ) -> Self {
+        Self {
+            inner: Some(Rc::new(HistoryInner {
+                max_len: max_len.max(1),
+                global: RefCell::new(Timeline::default()),
+                pages: RefCell::new(HashMap::new()),
+                registered: create_rc_signal(()),
+                revision: create_rc_signal(()),
+                router,
+                global_state,
+                page_state_store,
+                frozen_app,
+                is_restoring: Cell::new(false),
+            })),
+        }
+    }
+    /// Starts recording changes to the global state and the state of the
+    /// current page in the given scope, which should live as long as the app.
+    pub(crate) fn start_recording(&self, cx: Scope) {
+        let inner = match &self.inner {
+            Some(inner) => inner.clone(),
+            None => return,
+        };
+        create_effect(cx, move || {
+            // Whenever a new state is registered, we need to subscribe to it
+            inner.registered.track();
+            inner.record_global();
+            if let RouterLoadState::Loaded { path, .. } = &*inner.router.get_load_state_rc().get() {
+                inner.record_page(path);
+            }
+        });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+struct HistoryInner {
+    /// The maximum number of entries in each timeline.
+    max_len: usize,
+    /// The timeline of the global state.
+    global: RefCell<Timeline>,
+    /// The timelines of page states, by URL.
+    pages: RefCell<HashMap<String, Timeline>>,
+    /// A signal that's updated whenever a new state is registered.
+    registered: RcSignal<()>,
+    /// A signal that's updated whenever any timeline changes, which makes the
+    /// getters reactive.
+    revision: RcSignal<()>,
+    router: RouterState,
+    global_state: GlobalState,
+    page_state_store: PageStateStore,
+    /// The frozen state of the app, which we thaw old states into.
+    frozen_app: Rc<RefCell<Option<(FrozenApp, ThawPrefs)>>>,
+    /// Whether or not we're currently merging an old state into a live one,
+    /// which will update its signals one at a time, and none of the
+    /// intermediate states should be recorded.
+    is_restoring: Cell<bool>,
+}
+#[cfg(target_arch = "wasm32")]
+impl HistoryInner {
+    /// Works out which timeline the given scope refers to. This will return
+    /// `None` if it's for the current page and none is loaded.
+    fn resolve(&self, scope: HistoryScope) -> Option<Option<String>> {
+        match scope {
+            HistoryScope::Global => Some(None),
+            HistoryScope::Page(url) => Some(Some(url)),
+            HistoryScope::CurrentPage => match &*self.router.get_load_state_rc().get() {
+                RouterLoadState::Loaded { path, .. } => Some(Some(path.to_string())),
+                _ => None,
+            },
+        }
+    }
+    /// Runs the given function on the timeline for the given page (or the
+    /// global state, if this is `None`), creating it if it doesn't exist.
+    fn with_timeline<T>(&self, url: Option<&str>, f: impl FnOnce(&mut Timeline) -> T) -> T {
+        match url {
+            Some(url) => f(self.pages.borrow_mut().entry(url.to_string()).or_default()),
+            None => f(&mut self.global.borrow_mut()),
+        }
+    }
+    /// Records the current global state, if it's changed.
+    fn record_global(&self) {
+        let (state, version) = {
+            let global_state = self.global_state.0.borrow();
+            global_state.track();
+            (global_state.freeze(), global_state.state_version())
+        };
+        // See `rx_state.rs` for why this would be the default value
+        if state == "None" || self.is_restoring.get() {
+            return;
+        }
+        if self
+            .global
+            .borrow_mut()
+            .record(state, version, self.max_len)
+        {
+            self.revision.set(());
+        }
+    }
+    /// Records the current state of the given page, if it's changed.
+    fn record_page(&self, url: &str) {
+        let (state, version) = match self.page_state_store.track_page(url) {
+            Some(state) => state,
+            None => return,
+        };
+        if self.is_restoring.get() {
+            return;
+        }
+        if self.with_timeline(Some(url), |timeline| {
+            timeline.record(state, version, self.max_len)
+        }) {
+            self.revision.set(());
+        }
+    }
+    fn step(&self, scope: HistoryScope, forward: bool) -> bool {
+        let url = match self.resolve(scope) {
+            Some(url) => url,
+            None => return false,
+        };
+        let current = self.with_timeline(url.as_deref(), |timeline| timeline.current);
+        let index = match (forward, current) {
+            (true, current) => current + 1,
+            (false, 0) => return false,
+            (false, current) => current - 1,
+        };
+        self.restore(url, index)
+    }
+    fn go_to(&self, scope: HistoryScope, index: usize) -> bool {
+        match self.resolve(scope) {
+            Some(url) => self.restore(url, index),
+            None => false,
+        }
+    }
+    /// Moves the timeline of the given page (or the global state, if this is
+    /// `None`) to the given entry, merging it into the live state, or thawing
+    /// it if that isn't possible.
+    fn restore(&self, url: Option<String>, index: usize) -> bool {
+        let entry = self.with_timeline(url.as_deref(), |timeline| {
+            if index >= timeline.entries.len() || index == timeline.current {
+                return None;
+            }
+            timeline.current = index;
+            Some(timeline.entries[index].clone())
+        });
+        let entry = match entry {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        self.is_restoring.set(true);
+        let merged = match &url {
+            Some(url) => self
+                .page_state_store
+                .with_state(url, |state| {
+                    merge_frozen_state(state, &entry.state, entry.version)
+                })
+                .unwrap_or(false),
+            None => {
+                merge_frozen_state(&**self.global_state.0.borrow(), &entry.state, entry.version)
+            }
+        };
+        self.is_restoring.set(false);
+        if merged {
+            self.revision.set(());
+            return true;
+        }
+
+        // We shouldn't record anything until the state we're thawing has been registered
+        self.with_timeline(url.as_deref(), |timeline| {
+            timeline.pending = Some(timeline.registrations)
+        });
+
+        let curr_path = match &*self.router.get_load_state_rc().get_untracked() {
+            RouterLoadState::Loaded { path, .. } => Some(path.to_string()),
+            _ => None,
+        };
+        let should_reload = match &url {
+            Some(url) => {
+                thaw_page_state(&self.frozen_app, url, entry.state, entry.version);
+                curr_path.as_ref() == Some(url)
+            }
+            None => {
+                thaw_global_state(&self.frozen_app, entry.state, entry.version);
+                curr_path.is_some()
+            }
+        };
+        self.revision.set(());
+        // Any other page will use its restored state when it's next visited
+        if should_reload {
+            self.router.reload();
+        }
+
+        true
+    }
+    fn timeline(&self, scope: HistoryScope) -> HistoryTimeline {
+        self.revision.track();
+        let url = match self.resolve(scope) {
+            Some(url) => url,
+            None => return HistoryTimeline::default(),
+        };
+        let timeline = match &url {
+            Some(url) => self.pages.borrow().get(url).cloned().unwrap_or_default(),
+            None => self.global.borrow().clone(),
+        };
+
+        HistoryTimeline {
+            entries: timeline.entries,
+            current: timeline.current,
+        }
+    }
+    fn clear(&self, scope: HistoryScope) {
+        let url = match self.resolve(scope) {
+            Some(url) => url,
+            None => return,
+        };
+        self.with_timeline(url.as_deref(), |timeline| {
+            let current = timeline.entries.get(timeline.current).cloned();
+            timeline.entries = current.into_iter().collect();
+            timeline.current = 0;
+        });
+        self.revision.set(());
+    }
+}
+
+/// The internal representation of a single timeline.
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Default)]
+struct Timeline {
+    entries: Vec<HistoryEntry>,
+    current: usize,
+    /// The number of times a new state has been registered for this timeline.
+    registrations: usize,
+    /// If we've restored an entry that hasn't been registered yet, the number
+    /// of registrations there had been when we did.
+    pending: Option<usize>,
+}
+#[cfg(target_arch = "wasm32")]
+impl Timeline {
+    /// Records the given state, returning whether or not it was actually new.
+    fn record(&mut self, state: String, version: u32, max_len: usize) -> bool {
+        // Until a restored entry is registered, the state we have is the one it's replacing
+        if let Some(registrations) = self.pending {
+            if registrations == self.registrations {
+                return false;
+            }
+            self.pending = None;
+        }
+        if self
+            .entries
+            .get(self.current)
+            .map(|entry| entry.state == state)
+            .unwrap_or(false)
+        {
+            return false;
+        }
+
+        // Recording a new state after undoing anything discards whatever was undone
+        self.entries.truncate(self.current + 1);
+        self.entries.push(HistoryEntry {
+            time: js_sys::Date::now(),
+            state,
+            version,
+        });
+        if self.entries.len() > max_len {
+            self.entries.remove(0);
+        }
+        self.current = self.entries.len() - 1;
+
+        true
+    }
+}
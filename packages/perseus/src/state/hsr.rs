@@ -1,17 +1,33 @@
-use super::IdbFrozenStateStore;
+use super::{FrozenStateStore, IdbFrozenStateStore};
+use crate::errors::FrozenStateStoreError;
 use crate::template::RenderCtx;
+use std::rc::Rc;
 use wasm_bindgen::JsValue;
 
-/// Freezes the app's state to IndexedDB to be accessed in future. This takes a
-/// pre-determined frozen state to avoid *really* annoying lifetime errors.
-pub(crate) async fn hsr_freeze(frozen_state: String) {
-    // We use a custom name so we don't interfere with any state freezing the user's
-    // doing independently
-    let idb_store = match IdbFrozenStateStore::new_with_name("perseus_hsr").await {
-        Ok(idb_store) => idb_store,
-        Err(err) => return log(&format!("IndexedDB setup error: {}.", err)),
+/// Gets the frozen state store that HSR should use, which will be the given
+/// one if the user provided one, or an IndexedDB store otherwise.
+async fn get_hsr_store(
+    hsr_store: Option<Rc<dyn FrozenStateStore>>,
+) -> Result<Rc<dyn FrozenStateStore>, FrozenStateStoreError> {
+    match hsr_store {
+        Some(hsr_store) => Ok(hsr_store),
+        // We use a custom name so we don't interfere with any state freezing the user's
+        // doing independently
+        None => Ok(Rc::new(
+            IdbFrozenStateStore::new_with_name("perseus_hsr").await?,
+        )),
+    }
+}
+
+/// Freezes the app's state to the HSR store to be accessed in future. This
+/// takes a pre-determined frozen state to avoid *really* annoying lifetime
+/// errors.
+pub(crate) async fn hsr_freeze(frozen_state: String, hsr_store: Option<Rc<dyn FrozenStateStore>>) {
+    let hsr_store = match get_hsr_store(hsr_store).await {
+        Ok(hsr_store) => hsr_store,
+        Err(err) => return log(&format!("Frozen state store setup error: {}.", err)),
     };
-    match idb_store.set(&frozen_state).await {
+    match hsr_store.set(&frozen_state).await {
         Ok(_) => log("State frozen."),
         Err(err) => log(&format!("State freezing error: {}.", err)),
     };
@@ -21,14 +37,14 @@ pub(crate) async fn hsr_freeze(frozen_state: String) {
 // This will be run at the beginning of every template function, which means it gets executed on the
 // server as well, so we have to Wasm-gate this
 #[cfg(target_arch = "wasm32")]
-pub(crate) async fn hsr_thaw(render_ctx: &RenderCtx) {
+pub(crate) async fn hsr_thaw(render_ctx: &RenderCtx, hsr_store: Option<Rc<dyn FrozenStateStore>>) {
     use super::{PageThawPrefs, ThawPrefs};
 
-    let idb_store = match IdbFrozenStateStore::new_with_name("perseus_hsr").await {
-        Ok(idb_store) => idb_store,
-        Err(err) => return log(&format!("IndexedDB setup error: {}.", err)),
+    let hsr_store = match get_hsr_store(hsr_store).await {
+        Ok(hsr_store) => hsr_store,
+        Err(err) => return log(&format!("Frozen state store setup error: {}.", err)),
     };
-    let frozen_state = match idb_store.get().await {
+    let frozen_state = match hsr_store.get().await {
         Ok(Some(frozen_state)) => frozen_state,
         // If there's no frozen state available, we'll proceed as usual
         Ok(None) => return,
@@ -46,9 +62,11 @@ pub(crate) async fn hsr_thaw(render_ctx: &RenderCtx) {
     // To be absolutely clear, this will NOT fail if the user has changed their data
     // model, it will be triggered if the state is actually corrupted
     // If that's the case, we'll log it and wait for the next freeze to override the
-    // invalid stuff If the user has updated their data model, the macros will
-    // fail with frozen state and switch to active or generated as necessary
-    // (meaning we lose the smallest amount of state possible!)
+    // invalid stuff If the user has updated their data model, the old state will be
+    // migrated if they've bumped its version and provided a migration, and
+    // otherwise the macros will fail with frozen state and switch to active or
+    // generated as necessary (meaning we lose the smallest amount of state
+    // possible!)
     match render_ctx.thaw(&frozen_state, thaw_prefs) {
         Ok(_) => log("State restored."),
         Err(_) => log("Stored state corrupted, waiting for next code change to override."),
@@ -56,7 +74,7 @@ pub(crate) async fn hsr_thaw(render_ctx: &RenderCtx) {
 
     // We don't want this old state to persist if the user manually reloads (they'd
     // be greeted with state that's probably out-of-date)
-    match idb_store.clear().await {
+    match hsr_store.clear().await {
         Ok(_) => (),
         Err(err) => log(&format!("Stale state clearing error: {}.", err)),
     }
@@ -64,7 +82,11 @@ pub(crate) async fn hsr_thaw(render_ctx: &RenderCtx) {
 
 /// Thaws a previous state frozen in development.
 #[cfg(not(target_arch = "wasm32"))]
-pub(crate) async fn hsr_thaw(_render_ctx: &RenderCtx) {}
+pub(crate) async fn hsr_thaw(
+    _render_ctx: &RenderCtx,
+    _hsr_store: Option<Rc<dyn FrozenStateStore>>,
+) {
+}
 
 /// An internal function for logging data about HSR.
 fn log(msg: &str) {
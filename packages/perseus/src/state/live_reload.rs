@@ -1,7 +1,15 @@
 use futures::channel::oneshot::Sender;
+use std::cell::RefCell;
 use wasm_bindgen::{closure::Closure, JsCast, JsValue};
 use web_sys::{ErrorEvent, MessageEvent, WebSocket};
 
+thread_local! {
+    /// The connection to the reload server, if we've made one.
+    static RELOAD_SERVER: RefCell<Option<WebSocket>> = const { RefCell::new(None) };
+    /// Messages waiting to be sent to the reload server once we're connected.
+    static PENDING_MESSAGES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
 /// Connects to the reload server if it's online. This takes a flip-flop
 /// `RcSignal` that it can use to signal other parts of the code to perform
 /// actual reloading (we can't do that here because we don't have access to the
@@ -59,9 +67,31 @@ pub(crate) fn connect_to_reload_server(live_reload_tx: Sender<()>) {
     // informational purposes
     let onopen_callback = Closure::wrap(Box::new(move |_| {
         log("Connected.");
+        // Send anything that's been waiting for the connection
+        RELOAD_SERVER.with(|ws| {
+            if let Some(ws) = &*ws.borrow() {
+                for msg in PENDING_MESSAGES.with(|pending| pending.take()) {
+                    let _ = ws.send_with_str(&msg);
+                }
+            }
+        });
     }) as Box<dyn FnMut(JsValue)>);
     ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
     onopen_callback.forget();
+
+    RELOAD_SERVER.with(|reload_server| *reload_server.borrow_mut() = Some(ws));
+}
+
+/// Sends the given message to the reload server, which will print it in the
+/// developer's terminal. If we aren't connected yet, this will be sent once we
+/// are (and it will never be sent if the reload server isn't online).
+pub(crate) fn send_to_reload_server(msg: String) {
+    RELOAD_SERVER.with(|ws| match &*ws.borrow() {
+        Some(ws) if ws.ready_state() == WebSocket::OPEN => {
+            let _ = ws.send_with_str(&msg);
+        }
+        _ => PENDING_MESSAGES.with(|pending| pending.borrow_mut().push(msg)),
+    });
 }
 
 fn get_window_var(name: &str) -> Option<String> {
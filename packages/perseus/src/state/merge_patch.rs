@@ -0,0 +1,193 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+/// Applies the given JSON merge patch (as defined in
+/// [RFC 7396](https://www.rfc-editor.org/rfc/rfc7396)) to the given value.
+/// Objects in the patch are merged into the value recursively, with `null`s
+/// removing whatever was at their keys, and anything else replaces what was
+/// there before.
+pub fn apply_merge_patch(target: &mut Value, patch: Value) {
+    match patch {
+        Value::Object(patch) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            if let Value::Object(target) = target {
+                for (key, val) in patch {
+                    if val.is_null() {
+                        target.remove(&key);
+                    } else {
+                        apply_merge_patch(target.entry(key).or_insert(Value::Null), val);
+                    }
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
+
+/// Creates a JSON merge patch that will turn `old` into `new` when it's
+/// applied with [`apply_merge_patch`], containing only the parts of `new`
+/// that are different. Arrays are always replaced wholesale.
+///
+/// Note that merge patches can't set anything inside an object to `null`, as
+/// that would remove it instead. For state, this is only a problem for types
+/// that can't be deserialized from a missing field (`Option`s can be).
+pub fn create_merge_patch(old: &Value, new: &Value) -> Value {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut patch = Map::new();
+            for key in old.keys() {
+                if !new.contains_key(key) {
+                    patch.insert(key.to_string(), Value::Null);
+                }
+            }
+            for (key, new_val) in new {
+                match old.get(key) {
+                    Some(old_val) if old_val == new_val => (),
+                    Some(old_val) => {
+                        patch.insert(key.to_string(), create_merge_patch(old_val, new_val));
+                    }
+                    None => {
+                        patch.insert(key.to_string(), new_val.clone());
+                    }
+                }
+            }
+
+            Value::Object(patch)
+        }
+        _ => new.clone(),
+    }
+}
+
+/// Applies the given merge patch to the given unreactive state, returning the
+/// result if it can still be deserialized as the same type. This is used by
+/// the `#[make_rx]` macro to implement `Freeze::merge()`.
+pub fn merge_macro_backend<T: Serialize + DeserializeOwned>(state: T, patch: Value) -> Option<T> {
+    let mut state = serde_json::to_value(&state).ok()?;
+    apply_merge_patch(&mut state, patch);
+    serde_json::from_value(state).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    fn apply(mut target: Value, patch: Value) -> Value {
+        apply_merge_patch(&mut target, patch);
+        target
+    }
+
+    #[test]
+    fn applies_rfc_examples() {
+        // These are the test cases from appendix A of RFC 7396
+        let cases = [
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (
+                json!({"a": "b"}),
+                json!({"b": "c"}),
+                json!({"a": "b", "b": "c"}),
+            ),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (
+                json!({"a": "b", "b": "c"}),
+                json!({"a": null}),
+                json!({"b": "c"}),
+            ),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (
+                json!({"a": {"b": "c"}}),
+                json!({"a": {"b": "d", "c": null}}),
+                json!({"a": {"b": "d"}}),
+            ),
+            (
+                json!({"a": [{"b": "c"}]}),
+                json!({"a": [1]}),
+                json!({"a": [1]}),
+            ),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"a": "foo"}), json!("bar"), json!("bar")),
+            (
+                json!({"e": null}),
+                json!({"a": 1}),
+                json!({"e": null, "a": 1}),
+            ),
+            (
+                json!([1, 2]),
+                json!({"a": "b", "c": null}),
+                json!({"a": "b"}),
+            ),
+            (
+                json!({}),
+                json!({"a": {"bb": {"ccc": null}}}),
+                json!({"a": {"bb": {}}}),
+            ),
+        ];
+        for (target, patch, expected) in cases {
+            assert_eq!(apply(target, patch), expected);
+        }
+    }
+
+    #[test]
+    fn created_patches_round_trip() {
+        let cases = [
+            (json!({"a": 1, "b": 2}), json!({"a": 1, "b": 3})),
+            (json!({"a": 1, "b": 2}), json!({"a": 1})),
+            (json!({"a": 1}), json!({"a": 1, "b": {"c": [1, 2]}})),
+            (
+                json!({"a": {"b": {"c": 1, "d": 2}}, "e": [1]}),
+                json!({"a": {"b": {"c": 1, "d": 3}}, "e": [1, 2]}),
+            ),
+            (json!({"a": {"b": 1}}), json!({"a": "b"})),
+            (json!({"a": "b"}), json!({"a": {"b": 1}})),
+            (json!([1, 2]), json!({"a": 1})),
+            (json!({"a": 1}), json!("a")),
+        ];
+        for (old, new) in cases {
+            let patch = create_merge_patch(&old, &new);
+            assert_eq!(apply(old, patch), new);
+        }
+    }
+
+    #[test]
+    fn created_patches_only_contain_changes() {
+        let old = json!({"a": 1, "b": {"c": 2, "d": 3}, "e": 4});
+        let new = json!({"a": 1, "b": {"c": 2, "d": 5}});
+        assert_eq!(
+            create_merge_patch(&old, &new),
+            json!({"b": {"d": 5}, "e": null})
+        );
+        assert_eq!(create_merge_patch(&old, &old), json!({}));
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct State {
+        name: String,
+        count: u32,
+        tag: Option<String>,
+    }
+
+    #[test]
+    fn macro_backend_checks_type() {
+        let state = || State {
+            name: "foo".to_string(),
+            count: 1,
+            tag: Some("bar".to_string()),
+        };
+        assert_eq!(
+            merge_macro_backend(state(), json!({"count": 2, "tag": null})),
+            Some(State {
+                name: "foo".to_string(),
+                count: 2,
+                tag: None,
+            })
+        );
+        assert_eq!(merge_macro_backend(state(), json!({"count": "2"})), None);
+        assert_eq!(merge_macro_backend(state(), json!({"name": null})), None);
+    }
+}
@@ -1,12 +1,53 @@
 mod freeze;
+mod freeze_store;
 mod global_state;
+mod merge_patch;
 mod page_state_store;
+mod resource;
+mod rx_collections;
 mod rx_state;
+mod state_push;
 
+pub(crate) use freeze::deserialize_frozen_state;
+#[cfg(all(
+    target_arch = "wasm32",
+    any(feature = "global-state-sync", feature = "state-history")
+))]
+pub(crate) use freeze::thaw_global_state;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use freeze::{merge_frozen_state, thaw_page_state};
 pub use freeze::{FrozenApp, PageThawPrefs, ThawPrefs};
+pub use freeze_store::{FrozenStateStore, HttpFrozenStateStore, WebStorageFrozenStateStore};
 pub use global_state::{GlobalState, GlobalStateCreator};
-pub use page_state_store::{PageStateStore, PssContains, PssEntry, PssState};
+// This is used by the `#[make_rx]` macro, hidden from the docs
+#[doc(hidden)]
+pub use merge_patch::merge_macro_backend;
+pub use merge_patch::{apply_merge_patch, create_merge_patch};
+pub use page_state_store::{
+    PageStateStore, PssContains, PssEntry, PssMemoryPolicy, PssState, PssUsage,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use resource::{is_subsequent_load, SubsequentLoad};
+pub use resource::{Resource, ResourceRx};
+pub use rx_collections::{
+    RxHashMap, RxHashMapPerseusRxIntermediary, RxVec, RxVecPerseusRxIntermediary,
+};
 pub use rx_state::{AnyFreeze, Freeze, MakeRx, MakeUnrx};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use state_push::subscribe_to_state_pushes;
+pub use state_push::StatePublisher;
+
+#[cfg(feature = "global-state-sync")]
+mod global_state_sync;
+#[cfg(all(feature = "global-state-sync", target_arch = "wasm32"))]
+pub(crate) use global_state_sync::GlobalStateSyncer;
+#[cfg(feature = "global-state-sync")]
+pub use global_state_sync::{GlobalStateConflictResolver, GlobalStateSync};
+
+#[cfg(feature = "state-history")]
+mod history;
+#[cfg(feature = "state-history")]
+pub use history::{HistoryEntry, HistoryScope, HistoryTimeline, StateHistory};
 
 #[cfg(all(feature = "idb-freezing", target_arch = "wasm32"))]
 mod freeze_idb;
@@ -21,6 +62,8 @@ mod live_reload;
 pub(crate) use live_reload::connect_to_reload_server;
 #[cfg(all(feature = "live-reload", debug_assertions, target_arch = "wasm32"))]
 pub(crate) use live_reload::force_reload;
+#[cfg(all(feature = "live-reload", debug_assertions, target_arch = "wasm32"))]
+pub(crate) use live_reload::send_to_reload_server;
 
 #[cfg(all(feature = "hsr", debug_assertions, target_arch = "wasm32"))]
 mod hsr;
@@ -36,11 +36,14 @@ pub struct PageStateStore {
     order: Rc<RefCell<Vec<String>>>,
     /// The maximum size of the store before pages are evicted, specified in
     /// terms of a number of pages. Note that this pays no attention to the
-    /// size in memory of individual pages (which should be dropped manually
-    /// if this is a concern).
+    /// size in memory of individual pages (a memory policy should be set if
+    /// this is a concern).
     ///
     /// Note: whatever you set here will impact HSR.
     max_size: usize,
+    /// The policy for evicting pages based on the estimated sizes of their
+    /// states, if there is one. This applies on top of `max_size`.
+    memory_policy: Option<PssMemoryPolicy>,
     /// A list of pages that will be kept in the store no matter what. This can
     /// be used to maintain the states of essential pages regardless of how
     /// much the user has travelled through the site. The *vast* majority of
@@ -53,6 +56,12 @@ pub struct PageStateStore {
     /// be globally preloaded; any pages that should only be preloaded for a
     /// specific route should be placed in `route_preloaded` instead.
     preloaded: Rc<RefCell<HashMap<String, PageDataPartial>>>,
+    /// The order in which pages were globally preloaded. Like the store
+    /// itself, the global preload cache is limited to `max_size` pages, with
+    /// the oldest being evicted first (which matters when links are
+    /// prefetched as they enter the viewport, which can preload a great many
+    /// pages that will never be visited).
+    preload_order: Rc<RefCell<Vec<String>>>,
     /// Pages that have been prelaoded for the current route, which should be
     /// cleared on a route change.
     route_preloaded: Rc<RefCell<HashMap<String, PageDataPartial>>>,
@@ -68,11 +77,20 @@ impl PageStateStore {
             map: Rc::default(),
             order: Rc::default(),
             max_size,
+            memory_policy: None,
             keep_list: Rc::default(),
             preloaded: Rc::default(),
+            preload_order: Rc::default(),
             route_preloaded: Rc::default(),
         }
     }
+    /// Sets a policy for evicting pages based on the estimated sizes of their
+    /// states, as well as on their number. See [`PssMemoryPolicy`] for the
+    /// details.
+    pub fn with_memory_policy(mut self, policy: PssMemoryPolicy) -> Self {
+        self.memory_policy = Some(policy);
+        self
+    }
     /// Gets an element out of the state by its type and URL. If the element
     /// stored for the given URL doesn't match the provided type, `None` will be
     /// returned.
@@ -126,23 +144,7 @@ impl PageStateStore {
             }
             map.insert(url.to_string(), new_entry);
         }
-        let mut order = self.order.borrow_mut();
-        // If we haven't been told to keep this page, enter it in the order list so it
-        // can be evicted later
-        if !self.keep_list.borrow().iter().any(|x| x == url) {
-            // Get rid of any previous mentions of this page in the order list
-            order.retain(|stored_url| stored_url != url);
-            order.push(url.to_string());
-            // If we've used up the maximum size yet, we should get rid of the oldest pages
-            if order.len() > self.max_size {
-                // Because this is called on every addition, we can safely assume that it's only
-                // one over
-                let old_url = order.remove(0);
-                map.remove(&old_url); // This will only occur for pages that
-                                      // aren't in the keep list, since those
-                                      // don't even appear in `order`
-            }
-        }
+        self.record_use(url, &mut map);
         // If we got to here, then there were no issues with not accepting state
         true
     }
@@ -161,23 +163,15 @@ impl PageStateStore {
             new_entry.set_head(head);
             map.insert(url.to_string(), new_entry);
         }
-        let mut order = self.order.borrow_mut();
-        // If we haven't been told to keep this page, enter it in the order list so it
-        // can be evicted later
-        if !self.keep_list.borrow().iter().any(|x| x == url) {
-            // Get rid of any previous mentions of this page in the order list
-            order.retain(|stored_url| stored_url != url);
-            order.push(url.to_string());
-            // If we've used up the maximum size yet, we should get rid of the oldest pages
-            if order.len() > self.max_size {
-                // Because this is called on every addition, we can safely assume that it's only
-                // one over
-                let old_url = order.remove(0);
-                map.remove(&old_url); // This will only occur for pages that
-                                      // aren't in the keep list, since those
-                                      // don't even appear in `order`
-            }
-        }
+        self.record_use(url, &mut map);
+    }
+    /// Records the template the page at the given URL was rendered with,
+    /// creating an entry for it if there isn't one yet. This is used to
+    /// apply per-template weights and caps from the memory policy, and should
+    /// be called before adding the page's state.
+    pub fn set_template(&self, url: &str, template_name: &str) {
+        let mut map = self.map.borrow_mut();
+        map.entry(url.to_string()).or_default().template = Some(template_name.to_string());
     }
     /// Sets the given entry as not being able to take any state. Any future
     /// attempt to register state for it will lead to silent failures and/or
@@ -286,12 +280,22 @@ impl PageStateStore {
                             source: err.into(),
                         }
                     })?;
-                let mut preloaded = if is_route_preload {
-                    self.preloaded.borrow_mut()
+                if is_route_preload {
+                    self.route_preloaded
+                        .borrow_mut()
+                        .insert(path.to_string(), page_data);
                 } else {
-                    self.route_preloaded.borrow_mut()
-                };
-                preloaded.insert(path.to_string(), page_data);
+                    let mut preloaded = self.preloaded.borrow_mut();
+                    let mut preload_order = self.preload_order.borrow_mut();
+                    preloaded.insert(path.to_string(), page_data);
+                    preload_order.retain(|url| url != path);
+                    preload_order.push(path.to_string());
+                    // Evict the oldest preloads if we've gone over the limit
+                    while preload_order.len() > self.max_size {
+                        let old_url = preload_order.remove(0);
+                        preloaded.remove(&old_url);
+                    }
+                }
                 Ok(())
             }
             None => Err(FetchError::NotFound {
@@ -310,6 +314,9 @@ impl PageStateStore {
         let mut preloaded = self.preloaded.borrow_mut();
         let mut route_preloaded = self.route_preloaded.borrow_mut();
         if let Some(page_data) = preloaded.remove(url) {
+            self.preload_order
+                .borrow_mut()
+                .retain(|preloaded_url| preloaded_url != url);
             Some(page_data)
         } else {
             route_preloaded.remove(url)
@@ -350,6 +357,122 @@ impl PageStateStore {
         let mut map = self.map.borrow_mut();
         map.remove(url)
     }
+    /// Gets the estimated memory usage of the store, in total, by page, and by
+    /// template. This will estimate the size of every page afresh (which
+    /// involves freezing their states), so it shouldn't be called too often
+    /// for large stores.
+    ///
+    /// This works whether or not a memory policy has been set, though
+    /// per-template weights will only be applied if there is one.
+    pub fn usage(&self) -> PssUsage {
+        let mut map = self.map.borrow_mut();
+        let mut usage = PssUsage {
+            total: 0,
+            pages: HashMap::new(),
+            templates: HashMap::new(),
+            max_bytes: self.memory_policy.as_ref().map(|policy| policy.max_bytes),
+        };
+        for (url, entry) in map.iter_mut() {
+            entry.size = self.estimate_size(entry);
+            usage.total += entry.size;
+            usage.pages.insert(url.to_string(), entry.size);
+            if let Some(template_name) = &entry.template {
+                *usage
+                    .templates
+                    .entry(template_name.to_string())
+                    .or_insert(0) += entry.size;
+            }
+        }
+
+        usage
+    }
+    /// Moves the given page to the end of the eviction order (unless it's been
+    /// marked to be kept) and evicts any pages that no longer fit in the
+    /// store.
+    fn record_use(&self, url: &str, map: &mut HashMap<String, PssEntry>) {
+        // If we've been told to keep this page, it won't appear in the order list, and
+        // can't be evicted (though its size still counts)
+        let is_kept = self.keep_list.borrow().iter().any(|x| x == url);
+        let mut order = self.order.borrow_mut();
+        if self.memory_policy.is_some() {
+            // Sizes are only estimated when pages are added, so we re-estimate the last page
+            // too, since that's the one the user is most likely to have been modifying
+            let last_url = order.last().filter(|last_url| *last_url != url).cloned();
+            for url in std::iter::once(url.to_string()).chain(last_url) {
+                if let Some(entry) = map.get_mut(&url) {
+                    entry.size = self.estimate_size(entry);
+                }
+            }
+        }
+        if is_kept {
+            return;
+        }
+        // Get rid of any previous mentions of this page in the order list
+        order.retain(|stored_url| stored_url != url);
+        order.push(url.to_string());
+        // If we've used up the maximum size yet, we should get rid of the oldest pages
+        while order.len() > self.max_size {
+            let old_url = order.remove(0);
+            map.remove(&old_url); // This will only occur for pages that aren't in
+                                  // the keep list, since those don't even
+                                  // appear in `order`
+        }
+
+        let policy = match &self.memory_policy {
+            Some(policy) => policy,
+            None => return,
+        };
+        // We never evict the newest page for size reasons, since it's almost certainly
+        // the one being rendered (and a page that's too big on its own should still work)
+        for (template_name, cap) in policy.template_caps.iter() {
+            let mut used: usize = map
+                .values()
+                .filter(|entry| entry.template.as_ref() == Some(template_name))
+                .map(|entry| entry.size)
+                .sum();
+            let mut idx = 0;
+            while used > *cap && idx + 1 < order.len() {
+                let is_template = map
+                    .get(&order[idx])
+                    .map(|entry| entry.template.as_ref() == Some(template_name))
+                    .unwrap_or(true);
+                if is_template {
+                    let old_url = order.remove(idx);
+                    if let Some(entry) = map.remove(&old_url) {
+                        used = used.saturating_sub(entry.size);
+                    }
+                } else {
+                    idx += 1;
+                }
+            }
+        }
+        let mut used: usize = map.values().map(|entry| entry.size).sum();
+        while used > policy.max_bytes && order.len() > 1 {
+            let old_url = order.remove(0);
+            if let Some(entry) = map.remove(&old_url) {
+                used = used.saturating_sub(entry.size);
+            }
+        }
+    }
+    /// Estimates the size of the given entry in bytes, from the sizes of its
+    /// frozen state and document metadata, weighted by its template.
+    fn estimate_size(&self, entry: &PssEntry) -> usize {
+        let state_size = match &entry.state {
+            PssState::Some(state) => state.freeze().len(),
+            _ => 0,
+        };
+        let head_size = entry.head.as_ref().map(|head| head.len()).unwrap_or(0);
+        let weight = match (&self.memory_policy, &entry.template) {
+            (Some(policy), Some(template_name)) => policy
+                .template_weights
+                .get(template_name)
+                .copied()
+                .unwrap_or(1.0),
+            _ => 1.0,
+        };
+
+        ((state_size + head_size) as f64 * weight) as usize
+    }
 }
 impl PageStateStore {
     /// Freezes the component entries into a new `HashMap` of `String`s to avoid
@@ -372,6 +495,104 @@ impl PageStateStore {
 
         str_map
     }
+    /// Gets the schema versions of all the states that would be frozen by
+    /// `.freeze_to_hash_map()`, so that they can be migrated when thawed.
+    pub fn state_versions(&self) -> HashMap<String, u32> {
+        let map = self.map.borrow();
+        let mut versions = HashMap::new();
+        for (k, entry) in map.iter() {
+            if let PssState::Some(state) = &entry.state {
+                versions.insert(k.to_string(), state.state_version());
+            }
+        }
+
+        versions
+    }
+}
+#[cfg(target_arch = "wasm32")]
+impl PageStateStore {
+    /// Runs the given function on the state of the given page, if it has one.
+    /// Note that the store can't be modified until the function has finished.
+    pub(crate) fn with_state<T>(
+        &self,
+        url: &str,
+        f: impl FnOnce(&dyn AnyFreeze) -> T,
+    ) -> Option<T> {
+        let map = self.map.borrow();
+        match &map.get(url)?.state {
+            PssState::Some(state) => Some(f(state.as_ref())),
+            _ => None,
+        }
+    }
+}
+#[cfg(all(feature = "state-history", target_arch = "wasm32"))]
+impl PageStateStore {
+    /// Subscribes to the state of the given page, returning it frozen, along
+    /// with the version of its schema, if it has one. This should be called in
+    /// a reactive scope.
+    pub(crate) fn track_page(&self, url: &str) -> Option<(String, u32)> {
+        let map = self.map.borrow();
+        match &map.get(url)?.state {
+            PssState::Some(state) => {
+                state.track();
+                Some((state.freeze(), state.state_version()))
+            }
+            _ => None,
+        }
+    }
+}
+#[cfg(all(feature = "devtools", debug_assertions, target_arch = "wasm32"))]
+impl PageStateStore {
+    /// Describes everything in the store, for the devtools overlay. Page
+    /// states are given in their frozen forms.
+    pub(crate) fn inspect(&self) -> PssInspection {
+        let map = self.map.borrow();
+        let mut pages = map
+            .iter()
+            .map(|(url, entry)| PssPageInspection {
+                url: url.to_string(),
+                template: entry.template.clone(),
+                state: match &entry.state {
+                    PssState::Some(state) => PssState::Some(state.freeze()),
+                    PssState::None => PssState::None,
+                    PssState::Never => PssState::Never,
+                },
+                has_head: entry.head.is_some(),
+            })
+            .collect::<Vec<_>>();
+        pages.sort_by(|a, b| a.url.cmp(&b.url));
+        let mut preloaded = self.preloaded.borrow().keys().cloned().collect::<Vec<_>>();
+        preloaded.sort();
+        let mut route_preloaded = self
+            .route_preloaded
+            .borrow()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        route_preloaded.sort();
+
+        PssInspection {
+            pages,
+            order: self.order.borrow().clone(),
+            keep_list: self.keep_list.borrow().clone(),
+            preloaded,
+            route_preloaded,
+        }
+    }
+    /// Subscribes to every page state in the store, returning the URLs of all
+    /// the pages in it, sorted. This should be called in a reactive scope.
+    pub(crate) fn track(&self) -> Vec<String> {
+        let map = self.map.borrow();
+        for entry in map.values() {
+            if let PssState::Some(state) = &entry.state {
+                state.track();
+            }
+        }
+        let mut urls = map.keys().cloned().collect::<Vec<_>>();
+        urls.sort();
+
+        urls
+    }
 }
 impl std::fmt::Debug for PageStateStore {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -391,6 +612,11 @@ pub struct PssEntry {
     /// The document metadata of the page, which can be cached to prevent future
     /// requests to the server.
     head: Option<String>,
+    /// The template the page was rendered with, if we know it.
+    template: Option<String>,
+    /// The estimated size of this entry in bytes, as of the last time it was
+    /// estimated. This is only kept up to date if there's a memory policy.
+    size: usize,
 }
 impl Default for PssEntry {
     fn default() -> Self {
@@ -398,6 +624,8 @@ impl Default for PssEntry {
             // There could be state later
             state: PssState::None,
             head: None,
+            template: None,
+            size: 0,
         }
     }
 }
@@ -432,6 +660,103 @@ impl PssEntry {
     }
 }
 
+/// A policy for evicting pages from the PSS based on how much memory their
+/// states are likely to take up, rather than just on how many of them there
+/// are. This is useful if some of your pages have much larger states than
+/// others, since the page count limit alone would let a few large pages
+/// balloon the browser's memory usage in a long session.
+///
+/// Sizes are estimated from the lengths of pages' frozen states and document
+/// metadata, which will usually be a fair bit smaller than their real sizes in
+/// memory, so you may want to set the budget conservatively. Each page is
+/// estimated when it's added, and again when the user navigates away from it.
+/// When the budget is exceeded, the least recently used pages will be evicted,
+/// except for the newest one and any that have been marked to be kept.
+#[derive(Clone, Debug)]
+pub struct PssMemoryPolicy {
+    /// The maximum total estimated size of the store, in bytes.
+    max_bytes: usize,
+    /// Factors by which the estimated sizes of the pages of certain templates
+    /// should be multiplied.
+    template_weights: HashMap<String, f64>,
+    /// The maximum total estimated sizes of the pages of certain templates, in
+    /// bytes.
+    template_caps: HashMap<String, usize>,
+}
+impl PssMemoryPolicy {
+    /// Creates a new memory policy that will keep the total estimated size of
+    /// the store under the given number of bytes.
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            template_weights: HashMap::new(),
+            template_caps: HashMap::new(),
+        }
+    }
+    /// Sets a factor by which the estimated sizes of pages rendered with the
+    /// given template should be multiplied. This can be used to account for
+    /// states that take up far more memory than their frozen forms suggest
+    /// (e.g. because they're made up of lots of small signals), or to make
+    /// pages that are cheap to regenerate be evicted sooner.
+    pub fn template_weight(mut self, template_name: &str, weight: f64) -> Self {
+        self.template_weights
+            .insert(template_name.to_string(), weight);
+        self
+    }
+    /// Sets the maximum total estimated size (after weighting) of the pages
+    /// rendered with the given template, in bytes. Once this is exceeded, the
+    /// least recently used pages of that template will be evicted, even if the
+    /// store as a whole is under budget.
+    pub fn template_cap(mut self, template_name: &str, max_bytes: usize) -> Self {
+        self.template_caps
+            .insert(template_name.to_string(), max_bytes);
+        self
+    }
+}
+
+/// The estimated memory usage of the PSS, in bytes.
+#[derive(Clone, Debug)]
+pub struct PssUsage {
+    /// The total estimated size of the store.
+    pub total: usize,
+    /// The estimated size of each page in the store, by URL.
+    pub pages: HashMap<String, usize>,
+    /// The total estimated sizes of the pages of each template. Pages whose
+    /// templates aren't known won't be counted here.
+    pub templates: HashMap<String, usize>,
+    /// The maximum total size allowed by the memory policy, if there is one.
+    pub max_bytes: Option<usize>,
+}
+
+/// A description of everything in the PSS, for the devtools overlay.
+#[cfg(all(feature = "devtools", debug_assertions, target_arch = "wasm32"))]
+pub(crate) struct PssInspection {
+    /// The pages in the store, sorted by URL.
+    pub(crate) pages: Vec<PssPageInspection>,
+    /// The URLs of the pages that can be evicted, from the oldest to the
+    /// newest.
+    pub(crate) order: Vec<String>,
+    /// The URLs of the pages that will never be evicted.
+    pub(crate) keep_list: Vec<String>,
+    /// The URLs of the pages that have been preloaded globally, sorted.
+    pub(crate) preloaded: Vec<String>,
+    /// The URLs of the pages that have been preloaded for the current route,
+    /// sorted.
+    pub(crate) route_preloaded: Vec<String>,
+}
+/// A description of a single page in the PSS, for the devtools overlay.
+#[cfg(all(feature = "devtools", debug_assertions, target_arch = "wasm32"))]
+pub(crate) struct PssPageInspection {
+    /// The page's URL.
+    pub(crate) url: String,
+    /// The template the page was rendered with, if we know it.
+    pub(crate) template: Option<String>,
+    /// The page's frozen state, if it has any.
+    pub(crate) state: PssState<String>,
+    /// Whether or not the page's document metadata has been cached.
+    pub(crate) has_head: bool,
+}
+
 /// The page state of a PSS entry. This is used to determine whether or not we
 /// need to request data from the server.
 pub enum PssState<T> {
@@ -465,3 +790,101 @@ pub enum PssContains {
     /// processing into a state.
     Preloaded,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Freeze;
+
+    /// A page state whose frozen size can be changed after it's been added.
+    #[derive(Clone)]
+    struct TestState(Rc<RefCell<String>>);
+    impl Freeze for TestState {
+        fn freeze(&self) -> String {
+            self.0.borrow().clone()
+        }
+    }
+
+    fn head(size: usize) -> String {
+        "x".repeat(size)
+    }
+    fn urls(pss: &PageStateStore) -> Vec<String> {
+        let mut urls = pss.map.borrow().keys().cloned().collect::<Vec<_>>();
+        urls.sort();
+        urls
+    }
+
+    #[test]
+    fn evicts_least_recently_used_page() {
+        let pss = PageStateStore::new(2);
+        pss.add_head("a", head(1));
+        pss.add_head("b", head(1));
+        // Using `a` again makes `b` the oldest
+        pss.add_head("a", head(1));
+        pss.add_head("c", head(1));
+        assert_eq!(urls(&pss), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn evicts_oldest_pages_over_byte_budget() {
+        let pss = PageStateStore::new(10).with_memory_policy(PssMemoryPolicy::new(25));
+        pss.add_head("a", head(10));
+        pss.add_head("b", head(10));
+        pss.add_head("c", head(10));
+        assert_eq!(urls(&pss), vec!["b", "c"]);
+        assert_eq!(pss.usage().total, 20);
+    }
+
+    #[test]
+    fn never_evicts_newest_page_for_size() {
+        let pss = PageStateStore::new(10).with_memory_policy(PssMemoryPolicy::new(5));
+        pss.add_head("a", head(10));
+        pss.add_head("b", head(10));
+        assert_eq!(urls(&pss), vec!["b"]);
+    }
+
+    #[test]
+    fn never_evicts_kept_pages() {
+        let pss = PageStateStore::new(10).with_memory_policy(PssMemoryPolicy::new(25));
+        pss.force_keep("a");
+        pss.add_head("a", head(10));
+        pss.add_head("b", head(10));
+        pss.add_head("c", head(10));
+        assert_eq!(urls(&pss), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn template_cap_only_evicts_that_template() {
+        let pss = PageStateStore::new(10)
+            .with_memory_policy(PssMemoryPolicy::new(1000).template_cap("big", 15));
+        for (url, template_name) in [("a", "big"), ("b", "small"), ("c", "small"), ("d", "big")] {
+            pss.set_template(url, template_name);
+            pss.add_head(url, head(10));
+        }
+        assert_eq!(urls(&pss), vec!["b", "c", "d"]);
+        assert_eq!(pss.usage().templates.get("big"), Some(&10));
+    }
+
+    #[test]
+    fn template_weights_scale_sizes() {
+        let pss = PageStateStore::new(10)
+            .with_memory_policy(PssMemoryPolicy::new(35).template_weight("heavy", 2.0));
+        pss.set_template("a", "heavy");
+        pss.add_head("a", head(10));
+        pss.add_head("b", head(10));
+        assert_eq!(pss.usage().pages.get("a"), Some(&20));
+        pss.add_head("c", head(10));
+        assert_eq!(urls(&pss), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn re_estimates_previous_page_on_navigation() {
+        let pss = PageStateStore::new(10).with_memory_policy(PssMemoryPolicy::new(35));
+        let state = TestState(Rc::new(RefCell::new(head(10))));
+        assert!(pss.add_state("a", state.clone()));
+        // The user adds to the page's state before moving on
+        *state.0.borrow_mut() = head(30);
+        pss.add_head("b", head(10));
+        assert_eq!(urls(&pss), vec!["b"]);
+    }
+}
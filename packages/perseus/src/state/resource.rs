@@ -0,0 +1,251 @@
+use super::{apply_merge_patch, Freeze, MakeRx, MakeUnrx};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::Request;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[cfg(target_arch = "wasm32")]
+use std::cell::Cell;
+use std::fmt::Display;
+use std::future::Future;
+use std::ops::Deref;
+#[cfg(target_arch = "wasm32")]
+use std::rc::Rc;
+use sycamore::prelude::{create_rc_signal, Html, RcSignal, Scope, View};
+
+/// A marker that's added to the extensions of requests for page data made by
+/// the browser on subsequent loads, so that resources can be left for the
+/// browser to load itself.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SubsequentLoad;
+
+/// Checks if the given request is for the data of a page the browser is
+/// loading itself (i.e. a subsequent load), rather than for a page that
+/// will be rendered on the server (i.e. an initial load).
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn is_subsequent_load(req: &Request) -> bool {
+    req.extensions().get::<SubsequentLoad>().is_some()
+}
+
+/// Some data that's fetched asynchronously, which can be part of a page's
+/// state. On initial loads, resources can be fetched on the engine while
+/// generating request state (see [`Resource::prerender`]), so they'll be
+/// rendered straight away and serialized with the rest of the page's state
+/// (meaning they won't be fetched again when the page is hydrated). On
+/// subsequent loads, they'll be left as [`Resource::Loading`], and fetched in
+/// the browser instead (see [`ResourceRx::load`]), with a fallback being shown
+/// until they're ready.
+///
+/// To use this in a `#[make_rx]` `struct`, mark the field with
+/// `#[rx(nested)]`, and the reactive version will have a [`ResourceRx`].
+/// Errors are stored as strings, so that they can be serialized.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Resource<T> {
+    /// The resource hasn't been fetched yet, or it's being fetched.
+    #[default]
+    Loading,
+    /// The resource has been fetched successfully.
+    Ready(T),
+    /// The resource couldn't be fetched, with the given error message.
+    Failed(String),
+}
+impl<T, E: Display> From<Result<T, E>> for Resource<T> {
+    fn from(res: Result<T, E>) -> Self {
+        match res {
+            Ok(val) => Self::Ready(val),
+            Err(err) => Self::Failed(err.to_string()),
+        }
+    }
+}
+impl<T> Resource<T> {
+    /// Fetches this resource with the given function if the given request is
+    /// for an initial load, so that it can be rendered on the engine. On
+    /// subsequent loads, this will return [`Resource::Loading`] without
+    /// calling the function, leaving the browser to fetch the resource
+    /// itself. This is intended to be called in request state functions, with
+    /// the same fetcher that's given to [`ResourceRx::load`] in the template.
+    ///
+    /// Note that, if the template caches its request states, states generated
+    /// for initial and subsequent loads will be cached separately.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn prerender<F, Fut, E>(req: &Request, fetcher: F) -> Self
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        if is_subsequent_load(req) {
+            Self::Loading
+        } else {
+            fetcher().await.into()
+        }
+    }
+}
+
+/// The reactive version of [`Resource`], which holds it in a signal. This
+/// dereferences to that signal.
+pub struct ResourceRx<T> {
+    /// The current state of the resource.
+    state: RcSignal<Resource<T>>,
+    /// Whether or not the resource is currently being fetched.
+    #[cfg(target_arch = "wasm32")]
+    fetching: Rc<Cell<bool>>,
+    /// The number of times the resource has been fetched, which is used to
+    /// discard the results of fetches that have been superseded by a reload.
+    #[cfg(target_arch = "wasm32")]
+    generation: Rc<Cell<usize>>,
+}
+// We can't derive this without requiring `T: Clone`
+impl<T> Clone for ResourceRx<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            #[cfg(target_arch = "wasm32")]
+            fetching: self.fetching.clone(),
+            #[cfg(target_arch = "wasm32")]
+            generation: self.generation.clone(),
+        }
+    }
+}
+impl<T> Deref for ResourceRx<T> {
+    type Target = RcSignal<Resource<T>>;
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+impl<T: 'static> ResourceRx<T> {
+    /// Fetches this resource with the given function in the browser, if it
+    /// hasn't been fetched already (e.g. on the engine for an initial load)
+    /// and isn't being fetched right now. This should be called in the
+    /// template, and it does nothing on the engine.
+    ///
+    /// The fetch isn't tied to the lifetime of the page, so, if the user
+    /// navigates away before it's finished, the result will still be stored
+    /// in the page's state for when they come back.
+    #[allow(unused_variables)]
+    pub fn load<F, Fut, E>(&self, fetcher: F)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>> + 'static,
+        E: Display,
+    {
+        #[cfg(target_arch = "wasm32")]
+        if matches!(*self.state.get_untracked(), Resource::Loading) && !self.fetching.get() {
+            self.fetch(fetcher);
+        }
+    }
+    /// Fetches this resource again with the given function in the browser,
+    /// even if it's already been fetched, showing the loading fallback until
+    /// it's ready. This is useful for retrying after an error. Any fetch
+    /// that's already underway will have its result discarded. This does
+    /// nothing on the engine.
+    #[allow(unused_variables)]
+    pub fn reload<F, Fut, E>(&self, fetcher: F)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>> + 'static,
+        E: Display,
+    {
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.state.set(Resource::Loading);
+            self.fetch(fetcher);
+        }
+    }
+    /// Fetches this resource, storing the result unless another fetch has been
+    /// started in the meantime.
+    #[cfg(target_arch = "wasm32")]
+    fn fetch<F, Fut, E>(&self, fetcher: F)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>> + 'static,
+        E: Display,
+    {
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+        self.fetching.set(true);
+
+        let fut = fetcher();
+        let state = self.state.clone();
+        let fetching = self.fetching.clone();
+        let curr_generation = self.generation.clone();
+        crate::spawn_local(async move {
+            let res = fut.await;
+            if curr_generation.get() == generation {
+                fetching.set(false);
+                state.set(res.into());
+            }
+        });
+    }
+    /// Renders this resource reactively, using the first function once it's
+    /// ready, the second while it's loading, and the third if it couldn't be
+    /// fetched (which will be given the error message). On the engine, only
+    /// resources that were prerendered will be ready, so anything else will
+    /// be rendered with the loading fallback.
+    pub fn view<'a, G: Html>(
+        &self,
+        cx: Scope<'a>,
+        ready: impl Fn(Scope<'a>, &T) -> View<G> + 'a,
+        loading: impl Fn(Scope<'a>) -> View<G> + 'a,
+        failed: impl Fn(Scope<'a>, &str) -> View<G> + 'a,
+    ) -> View<G> {
+        let state = self.state.clone();
+        View::new_dyn(cx, move || match &*state.get() {
+            Resource::Ready(val) => ready(cx, val),
+            Resource::Loading => loading(cx),
+            Resource::Failed(err) => failed(cx, err),
+        })
+    }
+}
+impl<T> MakeRx for Resource<T>
+where
+    T: Serialize + DeserializeOwned + Clone + 'static,
+{
+    type Rx = ResourceRx<T>;
+    fn make_rx(self) -> Self::Rx {
+        ResourceRx {
+            state: create_rc_signal(self),
+            #[cfg(target_arch = "wasm32")]
+            fetching: Rc::new(Cell::new(false)),
+            #[cfg(target_arch = "wasm32")]
+            generation: Rc::new(Cell::new(0)),
+        }
+    }
+}
+impl<T> MakeUnrx for ResourceRx<T>
+where
+    T: Serialize + DeserializeOwned + Clone + 'static,
+{
+    type Unrx = Resource<T>;
+    fn make_unrx(self) -> Self::Unrx {
+        (*self.state.get_untracked()).clone()
+    }
+}
+impl<T> Freeze for ResourceRx<T>
+where
+    T: Serialize + DeserializeOwned + Clone + 'static,
+{
+    fn freeze(&self) -> String {
+        serde_json::to_string(&*self.state.get_untracked()).unwrap()
+    }
+    fn track(&self) {
+        self.state.track();
+    }
+    fn merge(&self, patch: serde_json::Value) -> bool {
+        let old = match serde_json::to_value(&*self.state.get_untracked()) {
+            Ok(old) => old,
+            Err(_) => return false,
+        };
+        let mut new = old.clone();
+        apply_merge_patch(&mut new, patch);
+        if new == old {
+            return true;
+        }
+        match serde_json::from_value(new) {
+            Ok(new) => {
+                self.state.set(new);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
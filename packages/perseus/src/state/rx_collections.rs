@@ -0,0 +1,326 @@
+use super::{create_merge_patch, merge_macro_backend, Freeze, MakeRx, MakeUnrx};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+use sycamore::prelude::{create_rc_signal, RcSignal};
+
+/// A `Vec` of reactive state that can be made reactive with fine-grained
+/// reactivity, so that each element gets its own signals. That means updating
+/// one element won't re-render anything that depends on the others, which
+/// wouldn't be possible if the whole `Vec` were wrapped in a single signal.
+///
+/// This can be used like a normal `Vec` (it's serialized in the same way),
+/// and its elements must be types that can be made reactive (usually with
+/// `#[make_rx]`). To use it in a `#[make_rx]` `struct`, mark the field with
+/// `#[rx(nested)]`, and the reactive version will have an
+/// [`RxVecPerseusRxIntermediary`], which holds the reactive elements in a
+/// signal (so adding and removing elements is still reactive).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct RxVec<T>(pub Vec<T>);
+impl<T> Default for RxVec<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+impl<T> From<Vec<T>> for RxVec<T> {
+    fn from(vec: Vec<T>) -> Self {
+        Self(vec)
+    }
+}
+impl<T> Deref for RxVec<T> {
+    type Target = Vec<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<T> DerefMut for RxVec<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// The reactive version of [`RxVec`], which holds the reactive versions of its
+/// elements in a signal. This dereferences to that signal.
+pub struct RxVecPerseusRxIntermediary<T: MakeRx>(RcSignal<Vec<T::Rx>>);
+// We can't derive this without requiring `T: Clone`
+impl<T: MakeRx> Clone for RxVecPerseusRxIntermediary<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+impl<T: MakeRx> Deref for RxVecPerseusRxIntermediary<T> {
+    type Target = RcSignal<Vec<T::Rx>>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<T> MakeRx for RxVec<T>
+where
+    T: MakeRx + Serialize + DeserializeOwned + 'static,
+    T::Rx: MakeUnrx<Unrx = T> + Freeze + Clone + 'static,
+{
+    type Rx = RxVecPerseusRxIntermediary<T>;
+    // Each element is frozen in its own format, so we migrate them all individually
+    const STATE_VERSION: u32 = T::STATE_VERSION;
+    fn make_rx(self) -> Self::Rx {
+        RxVecPerseusRxIntermediary(create_rc_signal(
+            self.0.into_iter().map(|elem| elem.make_rx()).collect(),
+        ))
+    }
+    fn migrate(from_version: u32, state: serde_json::Value) -> Result<serde_json::Value, String> {
+        match state {
+            serde_json::Value::Array(elems) => Ok(serde_json::Value::Array(
+                elems
+                    .into_iter()
+                    .map(|elem| T::migrate(from_version, elem))
+                    .collect::<Result<_, _>>()?,
+            )),
+            _ => Err("expected frozen state for `RxVec` to be an array".to_string()),
+        }
+    }
+}
+impl<T> MakeUnrx for RxVecPerseusRxIntermediary<T>
+where
+    T: MakeRx + Serialize + DeserializeOwned + 'static,
+    T::Rx: MakeUnrx<Unrx = T> + Freeze + Clone + 'static,
+{
+    type Unrx = RxVec<T>;
+    fn make_unrx(self) -> Self::Unrx {
+        RxVec(
+            self.0
+                .get_untracked()
+                .iter()
+                .map(|elem| elem.clone().make_unrx())
+                .collect(),
+        )
+    }
+}
+impl<T> Freeze for RxVecPerseusRxIntermediary<T>
+where
+    T: MakeRx + Serialize + DeserializeOwned + 'static,
+    T::Rx: MakeUnrx<Unrx = T> + Freeze + Clone + 'static,
+{
+    fn freeze(&self) -> String {
+        // We freeze each element individually so that they can control what gets frozen
+        let elems = self
+            .0
+            .get_untracked()
+            .iter()
+            .map(|elem| serde_json::from_str(&elem.freeze()).unwrap())
+            .collect::<Vec<serde_json::Value>>();
+        serde_json::to_string(&elems).unwrap()
+    }
+    fn state_version(&self) -> u32 {
+        T::STATE_VERSION
+    }
+    fn track(&self) {
+        self.0.track();
+        for elem in self.0.get_untracked().iter() {
+            elem.track();
+        }
+    }
+    fn merge(&self, patch: serde_json::Value) -> bool {
+        // Merge patches always replace arrays wholesale
+        let new_elems = match serde_json::from_value::<Vec<T>>(patch) {
+            Ok(new_elems) => new_elems,
+            Err(_) => return false,
+        };
+        let elems = self.0.get_untracked();
+        // If no elements have been added or removed, we can merge them individually, so that
+        // unchanged elements won't be updated
+        if elems.len() == new_elems.len() {
+            let merged = elems.iter().zip(new_elems.iter()).all(|(elem, new_elem)| {
+                match (
+                    serde_json::to_value(elem.clone().make_unrx()),
+                    serde_json::to_value(new_elem),
+                ) {
+                    (Ok(old), Ok(new)) => old == new || elem.merge(create_merge_patch(&old, &new)),
+                    _ => false,
+                }
+            });
+            if merged {
+                return true;
+            }
+        }
+        drop(elems);
+        self.0
+            .set(new_elems.into_iter().map(|elem| elem.make_rx()).collect());
+
+        true
+    }
+}
+
+/// A `HashMap` of reactive state that can be made reactive with fine-grained
+/// reactivity, so that each value gets its own signals. This is exactly like
+/// [`RxVec`], but for maps (e.g. of records keyed by their IDs). Its keys
+/// won't be reactive, and must be serializable as JSON object keys (e.g.
+/// strings or integers).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct RxHashMap<K: Eq + Hash, V>(pub HashMap<K, V>);
+impl<K: Eq + Hash, V> Default for RxHashMap<K, V> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+impl<K: Eq + Hash, V> From<HashMap<K, V>> for RxHashMap<K, V> {
+    fn from(map: HashMap<K, V>) -> Self {
+        Self(map)
+    }
+}
+impl<K: Eq + Hash, V> Deref for RxHashMap<K, V> {
+    type Target = HashMap<K, V>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<K: Eq + Hash, V> DerefMut for RxHashMap<K, V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// The reactive version of [`RxHashMap`], which holds the reactive versions of
+/// its values in a signal. This dereferences to that signal.
+pub struct RxHashMapPerseusRxIntermediary<K: Eq + Hash, V: MakeRx>(RcSignal<HashMap<K, V::Rx>>);
+impl<K: Eq + Hash, V: MakeRx> Clone for RxHashMapPerseusRxIntermediary<K, V> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+impl<K: Eq + Hash, V: MakeRx> Deref for RxHashMapPerseusRxIntermediary<K, V> {
+    type Target = RcSignal<HashMap<K, V::Rx>>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<K, V> MakeRx for RxHashMap<K, V>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned + 'static,
+    V: MakeRx + Serialize + DeserializeOwned + 'static,
+    V::Rx: MakeUnrx<Unrx = V> + Freeze + Clone + 'static,
+{
+    type Rx = RxHashMapPerseusRxIntermediary<K, V>;
+    const STATE_VERSION: u32 = V::STATE_VERSION;
+    fn make_rx(self) -> Self::Rx {
+        RxHashMapPerseusRxIntermediary(create_rc_signal(
+            self.0.into_iter().map(|(k, v)| (k, v.make_rx())).collect(),
+        ))
+    }
+    fn migrate(from_version: u32, state: serde_json::Value) -> Result<serde_json::Value, String> {
+        match state {
+            serde_json::Value::Object(map) => Ok(serde_json::Value::Object(
+                map.into_iter()
+                    .map(|(k, v)| V::migrate(from_version, v).map(|v| (k, v)))
+                    .collect::<Result<_, _>>()?,
+            )),
+            _ => Err("expected frozen state for `RxHashMap` to be an object".to_string()),
+        }
+    }
+}
+impl<K, V> MakeUnrx for RxHashMapPerseusRxIntermediary<K, V>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned + 'static,
+    V: MakeRx + Serialize + DeserializeOwned + 'static,
+    V::Rx: MakeUnrx<Unrx = V> + Freeze + Clone + 'static,
+{
+    type Unrx = RxHashMap<K, V>;
+    fn make_unrx(self) -> Self::Unrx {
+        RxHashMap(
+            self.0
+                .get_untracked()
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone().make_unrx()))
+                .collect(),
+        )
+    }
+}
+impl<K, V> Freeze for RxHashMapPerseusRxIntermediary<K, V>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned + 'static,
+    V: MakeRx + Serialize + DeserializeOwned + 'static,
+    V::Rx: MakeUnrx<Unrx = V> + Freeze + Clone + 'static,
+{
+    fn freeze(&self) -> String {
+        let map = self.0.get_untracked();
+        let frozen_map = map
+            .iter()
+            .map(|(k, v)| (k, serde_json::from_str(&v.freeze()).unwrap()))
+            .collect::<HashMap<&K, serde_json::Value>>();
+        serde_json::to_string(&frozen_map).unwrap()
+    }
+    fn state_version(&self) -> u32 {
+        V::STATE_VERSION
+    }
+    fn track(&self) {
+        self.0.track();
+        for v in self.0.get_untracked().values() {
+            v.track();
+        }
+    }
+    fn merge(&self, patch: serde_json::Value) -> bool {
+        let patch = match patch {
+            serde_json::Value::Object(patch) => serde_json::Value::Object(patch),
+            // Anything other than an object replaces the whole map
+            patch => {
+                return match serde_json::from_value::<HashMap<K, V>>(patch) {
+                    Ok(map) => {
+                        self.0
+                            .set(map.into_iter().map(|(k, v)| (k, v.make_rx())).collect());
+                        true
+                    }
+                    Err(_) => false,
+                }
+            }
+        };
+        // We deserialize the keys all at once, since they could be integers
+        let patch = match serde_json::from_value::<HashMap<K, serde_json::Value>>(patch) {
+            Ok(patch) => patch,
+            Err(_) => return false,
+        };
+        // We check the whole patch before we apply any of it, so that we never leave the map
+        // half-patched
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        let mut patched = Vec::new();
+        {
+            let map = self.0.get_untracked();
+            for (k, v_patch) in patch {
+                match map.get(&k) {
+                    Some(_) if v_patch.is_null() => removed.push(k),
+                    None if v_patch.is_null() => (),
+                    Some(v) => match merge_macro_backend(v.clone().make_unrx(), v_patch.clone()) {
+                        Some(new_v) => patched.push((k, v_patch, new_v)),
+                        None => return false,
+                    },
+                    None => match serde_json::from_value::<V>(v_patch) {
+                        Ok(v) => added.push((k, v.make_rx())),
+                        Err(_) => return false,
+                    },
+                }
+            }
+        }
+        // Existing values are patched in place where possible, and replaced otherwise
+        {
+            let map = self.0.get_untracked();
+            for (k, v_patch, new_v) in patched {
+                if !map[&k].merge(v_patch) {
+                    added.push((k, new_v.make_rx()));
+                }
+            }
+        }
+        // We only need to update the map itself if values have been added or removed
+        if !removed.is_empty() || !added.is_empty() {
+            let mut map = (*self.0.get_untracked()).clone();
+            for k in removed {
+                map.remove(&k);
+            }
+            map.extend(added);
+            self.0.set(map);
+        }
+
+        true
+    }
+}
@@ -10,8 +10,44 @@ pub trait MakeRx {
     /// unreactive, meaning greater inference and fewer arguments that the
     /// user needs to provide to macros.
     type Rx: MakeUnrx;
+    /// The version of this type's schema, which will be stored alongside it
+    /// when it's frozen. This should be incremented whenever the type changes
+    /// in a way that would stop old frozen state from being deserialized into
+    /// it, and `.migrate()` should be implemented to upgrade that old state.
+    /// With the `#[make_rx]` macro, this can be set with
+    /// `#[rx::version(n)]`.
+    const STATE_VERSION: u32 = 0;
     /// Transforms an instance of the `struct` into its reactive version.
     fn make_rx(self) -> Self::Rx;
+    /// Restores anything in freshly thawed state that was changed when it was
+    /// frozen. This is called on all state that's been deserialized from
+    /// frozen state (after any migrations), and, by default, it returns the
+    /// state unchanged. With the `#[make_rx]` macro, fields marked with
+    /// `#[rx(freeze_with = "..", thaw_with = "..")]` will be passed through
+    /// their thaw functions here.
+    fn thaw(self) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
+    /// Upgrades frozen state that was created with an older version of this
+    /// type's schema (given as `from_version`) so that it can be deserialized
+    /// into the current version. Frozen state is given as a raw JSON value,
+    /// since it can't be deserialized into this type yet.
+    ///
+    /// By default, this will fail for any older versions, and the frozen
+    /// state will be discarded in favor of active or generated state as
+    /// usual. With the `#[make_rx]` macro, a function with the same signature
+    /// as this can be provided with `#[rx::migrate(my_fn)]`.
+    #[allow(unused_variables)]
+    fn migrate(from_version: u32, state: serde_json::Value) -> Result<serde_json::Value, String> {
+        Err(format!(
+            "no migration from version {} to version {}",
+            from_version,
+            Self::STATE_VERSION
+        ))
+    }
 }
 
 /// A trait for reactive `struct`s that can be made un-reactive. This is the
@@ -36,6 +72,30 @@ pub trait Freeze {
     /// 'Freezes' the reactive `struct` by making it unreactive and converting
     /// it to a `String`.
     fn freeze(&self) -> String;
+    /// Gets the version of the schema of the frozen state this produces,
+    /// which will be the `STATE_VERSION` of the unreactive type. This will be
+    /// stored alongside it, so it can be migrated if the schema changes.
+    fn state_version(&self) -> u32 {
+        0
+    }
+    /// Subscribes the current reactive scope to every signal in this state,
+    /// so that it will be re-run whenever any part of the state changes.
+    /// This is used to watch for changes to whole states (e.g. to synchronize
+    /// global state across tabs), and it's implemented automatically by the
+    /// `#[make_rx]` macro. By default, this does nothing.
+    fn track(&self) {}
+    /// Merges the given JSON merge patch into this state in place, only
+    /// setting the signals whose values it changes. This is used to apply
+    /// state pushed from the server, and it's implemented automatically by
+    /// the `#[make_rx]` macro. This should return `false`, without changing
+    /// anything, if the patch can't be merged (e.g. if it doesn't match the
+    /// type of the state), in which case the whole state will be frozen,
+    /// patched, and thawed instead. By default, this does nothing and returns
+    /// `false`.
+    #[allow(unused_variables)]
+    fn merge(&self, patch: serde_json::Value) -> bool {
+        false
+    }
 }
 
 // Perseus initializes the global state as an `Option::<()>::None`, so it has to
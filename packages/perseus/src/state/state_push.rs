@@ -0,0 +1,287 @@
+#[cfg(target_arch = "wasm32")]
+use super::{
+    apply_merge_patch, merge_frozen_state, thaw_page_state, FrozenApp, PageStateStore, ThawPrefs,
+};
+#[cfg(target_arch = "wasm32")]
+use crate::router::{RouterLoadState, RouterState};
+#[cfg(not(target_arch = "wasm32"))]
+use futures::{channel::mpsc, Stream};
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Arc, Mutex};
+#[cfg(target_arch = "wasm32")]
+use std::{cell::RefCell, rc::Rc};
+#[cfg(target_arch = "wasm32")]
+use sycamore::prelude::{create_effect, Scope};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{closure::Closure, JsCast};
+#[cfg(target_arch = "wasm32")]
+use web_sys::{EventSource, MessageEvent};
+
+/// The number of updates that can be waiting to be sent to a single browser
+/// before it's disconnected (it will reconnect automatically, but it will miss
+/// those updates).
+#[cfg(not(target_arch = "wasm32"))]
+const SUBSCRIBER_BUFFER: usize = 64;
+
+/// An update to the state of a single page, as it's sent to the browser.
+#[derive(Serialize, Deserialize)]
+struct StatePatch {
+    /// The page's URL, which is its path with its locale (unless the app
+    /// isn't using i18n), as it's stored in the page state store.
+    url: String,
+    /// The JSON merge patch to apply to the page's state.
+    patch: serde_json::Value,
+}
+
+/// A handle for pushing new state to the pages your users currently have open,
+/// which is useful for things like live dashboards. Browsers will subscribe to
+/// updates for the page they're currently showing over a server-sent events
+/// endpoint at `/.perseus/state-push?url=<page url>`, which all the server
+/// integrations provide (resubscribing whenever the user moves to another
+/// page), and any updates they receive will be merged into that page's state in
+/// place (so only the parts of the page that depend on what's changed will be
+/// updated). States for pages that aren't being shown won't be updated, even if
+/// they're still in the browser's page state store.
+///
+/// This can be cheaply cloned, and every clone will publish to the same
+/// browsers, so you should create one, give it to your `PerseusApp` with
+/// `.state_publisher()`, and keep clones wherever you want to publish updates
+/// from. On the browser-side, giving this to your app just makes it subscribe
+/// to updates.
+///
+/// Note that this doesn't change the state the server will render pages with,
+/// so you'll need to make sure that's up-to-date separately (e.g. with request
+/// state or revalidation). Exported apps can't receive updates, since there's
+/// no server to send them.
+#[derive(Clone)]
+pub struct StatePublisher {
+    /// Every browser that's currently subscribed.
+    #[cfg(not(target_arch = "wasm32"))]
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+/// A single browser that's subscribed to updates.
+#[cfg(not(target_arch = "wasm32"))]
+struct Subscriber {
+    /// The URL of the page it's subscribed to updates for.
+    url: String,
+    /// The channel to the browser.
+    sender: mpsc::Sender<String>,
+}
+impl std::fmt::Debug for StatePublisher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatePublisher").finish()
+    }
+}
+impl Default for StatePublisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl StatePublisher {
+    /// Creates a new state publisher with no subscribers.
+    pub fn new() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+    /// Publishes an update to the state of the page at the given path (without
+    /// a leading `/`, just like the `path` of its `PageProps`, but without any
+    /// locale) in the given locale (which should be `xx-XX` if your app isn't
+    /// using i18n) to every browser that's currently showing that page.
+    ///
+    /// The update is a JSON merge patch (see
+    /// [`create_merge_patch`](crate::state::create_merge_patch)), so you can
+    /// either send the whole new state, or just the fields that have changed.
+    /// If it can't be merged in place (e.g. because it doesn't match the type
+    /// of the state), the page's whole state will be replaced, which
+    /// will re-render it if it's currently being shown.
+    ///
+    /// This will only return an error if the given patch can't be serialized.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn publish(
+        &self,
+        path: &str,
+        locale: &str,
+        patch: impl Serialize,
+    ) -> Result<(), serde_json::Error> {
+        let msg = StatePatch {
+            url: match locale {
+                "xx-XX" => path.to_string(),
+                locale => format!("{}/{}", locale, path),
+            },
+            patch: serde_json::to_value(patch)?,
+        };
+        let url = msg.url.clone();
+        let msg = serde_json::to_string(&msg)?;
+        // Browsers that have disconnected, or that have fallen too far behind, will be dropped
+        self.subscribers.lock().unwrap().retain_mut(|subscriber| {
+            if subscriber.url == url {
+                subscriber.sender.try_send(msg.clone()).is_ok()
+            } else {
+                !subscriber.sender.is_closed()
+            }
+        });
+
+        Ok(())
+    }
+    /// Subscribes to the updates published from here for the page at the
+    /// given URL (which is its path with its locale, unless the app isn't
+    /// using i18n, as given in the `url` query parameter of a request to
+    /// `/.perseus/state-push`), returning a stream of messages that should be
+    /// sent to a browser as the data of server-sent events. This is intended
+    /// for server integrations.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn subscribe(&self, url: &str) -> impl Stream<Item = String> + Send + Unpin + 'static {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // Browsers that have disconnected are otherwise only dropped when we publish to them,
+        // which might never happen
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        subscribers.push(Subscriber {
+            url: url.to_string(),
+            sender: tx,
+        });
+
+        rx
+    }
+}
+
+/// The page the browser is subscribed to updates for, the connection to the
+/// server, and the connection's listener (which has to live as long as it
+/// does).
+#[cfg(target_arch = "wasm32")]
+type Subscription = (String, EventSource, Closure<dyn FnMut(MessageEvent)>);
+
+/// Subscribes to state pushed from the server for whichever page is currently
+/// being shown, merging it into that page's state. This lasts for the lifetime
+/// of the given scope, which should be that of the app.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn subscribe_to_state_pushes(
+    cx: Scope,
+    page_state_store: PageStateStore,
+    router: RouterState,
+    frozen_app: Rc<RefCell<Option<(FrozenApp, ThawPrefs)>>>,
+) {
+    let subscription: RefCell<Option<Subscription>> = RefCell::new(None);
+    create_effect(cx, move || {
+        // We'll keep the old subscription while a new page is loading
+        let url = match &*router.get_load_state_rc().get() {
+            RouterLoadState::Loaded { path, .. } => path.to_string(),
+            _ => return,
+        };
+        let mut subscription = subscription.borrow_mut();
+        if matches!(&*subscription, Some((subscribed_url, _, _)) if *subscribed_url == url) {
+            return;
+        }
+        if let Some((_, source, _)) = subscription.take() {
+            source.close();
+        }
+
+        let endpoint = format!(
+            "{}/.perseus/state-push?url={}",
+            crate::utils::get_path_prefix_client(),
+            urlencoding::encode(&url)
+        );
+        // If this fails, the browser doesn't support server-sent events, and the app will
+        // work fine without updates
+        let source = match EventSource::new(&endpoint) {
+            Ok(source) => source,
+            Err(_) => return,
+        };
+        let page_state_store = page_state_store.clone();
+        let router = router.clone();
+        let frozen_app = frozen_app.clone();
+        let closure = Closure::wrap(Box::new(move |ev: MessageEvent| {
+            if let Some(msg) = ev.data().as_string() {
+                apply_state_patch(&msg, &page_state_store, &router, &frozen_app);
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        source.set_onmessage(Some(closure.as_ref().unchecked_ref()));
+        // The browser will reconnect this if it drops, until we move to another page
+        *subscription = Some((url, source, closure));
+    });
+}
+
+/// Applies a state update from the server.
+#[cfg(target_arch = "wasm32")]
+fn apply_state_patch(
+    msg: &str,
+    page_state_store: &PageStateStore,
+    router: &RouterState,
+    frozen_app: &RefCell<Option<(FrozenApp, ThawPrefs)>>,
+) {
+    let StatePatch { url, patch } = match serde_json::from_str(msg) {
+        Ok(msg) => msg,
+        Err(_) => return,
+    };
+    // If we don't have any state for this page, we'll get the latest state from the server
+    // when it's loaded anyway
+    let res = page_state_store.with_state(&url, |state| {
+        if state.merge(patch.clone()) {
+            return None;
+        }
+        // If the patch couldn't be merged as it is, we'll apply it to the frozen state and
+        // merge in only what's actually changed, which leaves anything that isn't frozen
+        // alone
+        let mut frozen = serde_json::from_str(&state.freeze()).ok()?;
+        apply_merge_patch(&mut frozen, patch);
+        let frozen = frozen.to_string();
+        let version = state.state_version();
+        if merge_frozen_state(state, &frozen, version) {
+            None
+        } else {
+            Some((frozen, version))
+        }
+    });
+    // If even that didn't work, we'll replace the whole state
+    if let Some(Some((frozen, version))) = res {
+        thaw_page_state(frozen_app, &url, frozen, version);
+
+        // Any other page will use its new state when it's next visited
+        let is_current = matches!(
+            &*router.get_load_state_rc().get_untracked(),
+            RouterLoadState::Loaded { path, .. } if *path == url
+        );
+        if is_current {
+            router.reload();
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, FutureExt, StreamExt};
+
+    #[test]
+    fn publishes_only_to_subscribers_for_page() {
+        let publisher = StatePublisher::new();
+        let mut dashboard = publisher.subscribe("dashboard");
+        let mut about = publisher.subscribe("en-US/about");
+        publisher
+            .publish("dashboard", "xx-XX", serde_json::json!({ "visitors": 1 }))
+            .unwrap();
+        publisher
+            .publish("about", "en-US", serde_json::json!({ "title": "About" }))
+            .unwrap();
+
+        let msg: StatePatch = serde_json::from_str(&block_on(dashboard.next()).unwrap()).unwrap();
+        assert_eq!(msg.url, "dashboard");
+        assert_eq!(msg.patch, serde_json::json!({ "visitors": 1 }));
+        assert!(dashboard.next().now_or_never().is_none());
+        let msg: StatePatch = serde_json::from_str(&block_on(about.next()).unwrap()).unwrap();
+        assert_eq!(msg.url, "en-US/about");
+    }
+
+    #[test]
+    fn drops_disconnected_subscribers() {
+        let publisher = StatePublisher::new();
+        drop(publisher.subscribe("dashboard"));
+        drop(publisher.subscribe("about"));
+        assert_eq!(publisher.subscribers.lock().unwrap().len(), 1);
+        let _subscriber = publisher.subscribe("about");
+        assert_eq!(publisher.subscribers.lock().unwrap().len(), 1);
+    }
+}
@@ -2,7 +2,7 @@
 use crate::errors::*;
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::{
-    fs::{create_dir_all, File},
+    fs::{create_dir_all, remove_file, File},
     io::{AsyncReadExt, AsyncWriteExt},
 };
 
@@ -112,4 +112,18 @@ impl ImmutableStore {
 
         Ok(())
     }
+    /// Deletes the given asset from the filesystem asynchronously, if it
+    /// exists. Like `.write()`, this must only be used at build-time.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn delete(&self, name: &str) -> Result<(), StoreError> {
+        let asset_path = format!("{}/{}", self.root_path, name);
+        match remove_file(&asset_path).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(StoreError::DeleteFailed {
+                name: asset_path,
+                source: err.into(),
+            }),
+        }
+    }
 }
@@ -1,7 +1,7 @@
 use crate::errors::*;
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::{
-    fs::{create_dir_all, File},
+    fs::{create_dir_all, remove_file, File},
     io::{AsyncReadExt, AsyncWriteExt},
 };
 
@@ -27,6 +27,15 @@ pub trait MutableStore: std::fmt::Debug + Clone + Send + Sync {
     /// Writes data to the named asset. This will create a new asset if one
     /// doesn't exist already.
     async fn write(&self, name: &str, content: &str) -> Result<(), StoreError>;
+    /// Deletes the named asset. Deleting an asset that doesn't exist should
+    /// succeed.
+    ///
+    /// This is used to clean up short-lived data (like cached request states),
+    /// and the default implementation simply empties the asset, so stores that
+    /// can actually delete things should override it.
+    async fn delete(&self, name: &str) -> Result<(), StoreError> {
+        self.write(name, "").await
+    }
 }
 
 /// The default [`MutableStore`], which simply uses the filesystem. This is
@@ -123,6 +132,18 @@ impl MutableStore for FsMutableStore {
 
         Ok(())
     }
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn delete(&self, name: &str) -> Result<(), StoreError> {
+        let asset_path = format!("{}/{}", self.root_path, name);
+        match remove_file(&asset_path).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(StoreError::DeleteFailed {
+                name: asset_path,
+                source: err.into(),
+            }),
+        }
+    }
     #[cfg(target_arch = "wasm32")]
     async fn read(&self, _name: &str) -> Result<String, StoreError> {
         Ok(String::new())
@@ -131,4 +152,8 @@ impl MutableStore for FsMutableStore {
     async fn write(&self, _name: &str, _content: &str) -> Result<(), StoreError> {
         Ok(())
     }
+    #[cfg(target_arch = "wasm32")]
+    async fn delete(&self, _name: &str) -> Result<(), StoreError> {
+        Ok(())
+    }
 }
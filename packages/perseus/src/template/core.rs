@@ -2,12 +2,22 @@
 
 #[cfg(not(target_arch = "wasm32"))]
 use super::default_headers;
-use super::PageProps;
+use super::render_partially_hydrated;
+#[cfg(not(target_arch = "wasm32"))]
+use super::LayoutStates;
 #[cfg(not(target_arch = "wasm32"))]
 use super::RenderCtx;
+use super::RequestCacheLocation;
+#[cfg(not(target_arch = "wasm32"))]
+use super::RequestStateCache;
+use super::StateTimeoutFallback;
+#[cfg(target_arch = "wasm32")]
+use super::{IslandFn, IslandMap};
+use super::{Layout, PageProps};
 use crate::errors::*;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::make_async_trait;
+use crate::router::ScrollBehavior;
 use crate::translator::Translator;
 use crate::utils::provide_context_signal_replace;
 #[cfg(not(target_arch = "wasm32"))]
@@ -16,6 +26,8 @@ use crate::utils::AsyncFnReturn;
 use crate::utils::ComputedDuration;
 use crate::utils::PerseusDuration; /* We do actually want this in both the engine and the
                                     * browser */
+#[cfg(not(target_arch = "wasm32"))]
+use crate::stores::ImmutableStore;
 use crate::Html;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::Request;
@@ -146,6 +158,25 @@ pub struct Template<G: Html> {
     /// because otherwise efficient typing is almost impossible for templates
     /// without any properties (solutions welcome in PRs!).
     template: TemplateFn<G>,
+    /// The layout this template should be rendered inside, if any. This will
+    /// stay mounted in the browser while the user navigates between pages
+    /// that use it.
+    layout: Option<Layout<G>>,
+    /// How the router should handle the scroll position when the user
+    /// navigates to a page using this template.
+    scroll_behavior: ScrollBehavior,
+    /// Whether or not pages using this template should be served as plain
+    /// HTML, without the app's bundles. The router will use full page loads to
+    /// navigate to these.
+    static_only: bool,
+    /// Whether or not pages using this template should only have their islands
+    /// hydrated in the browser, leaving the rest of their prerendered HTML
+    /// alone.
+    partial_hydration: bool,
+    /// The islands that can be rendered inside this template, indexed by their
+    /// names. These are only needed in the browser.
+    #[cfg(target_arch = "wasm32")]
+    islands: IslandMap<G>,
     /// A function that will be used to populate the document's `<head>` with
     /// metadata such as the title. This will be passed state in
     /// the same way as `template`, but will always be rendered to a string,
@@ -186,6 +217,21 @@ pub struct Template<G: Html> {
     /// `get_build_state`, though custom amalgamation logic must be provided.
     #[cfg(not(target_arch = "wasm32"))]
     get_request_state: Option<GetRequestStateFn>,
+    /// A short-lived cache for the states generated by `get_request_state`,
+    /// which allows them to be reused across requests that share a cache key.
+    #[cfg(not(target_arch = "wasm32"))]
+    request_state_cache: Option<RequestStateCache>,
+    /// The maximum length of time `get_build_state` may take, after which it
+    /// will be abandoned and `state_timeout_fallback` will be used.
+    #[cfg(not(target_arch = "wasm32"))]
+    build_state_timeout: Option<ComputedDuration>,
+    /// The maximum length of time `get_request_state` may take, after which it
+    /// will be abandoned and `state_timeout_fallback` will be used.
+    #[cfg(not(target_arch = "wasm32"))]
+    request_state_timeout: Option<ComputedDuration>,
+    /// What to do when a state function times out.
+    #[cfg(not(target_arch = "wasm32"))]
+    state_timeout_fallback: StateTimeoutFallback,
     /// A function to be run on every request to check if a template prerendered
     /// at build-time should be prerendered again. If used with
     /// `revalidate_after`, this function will only be run after that time
@@ -227,6 +273,12 @@ impl<G: Html> Template<G> {
         Self {
             path: path.to_string(),
             template: Box::new(|cx, _| sycamore::view! { cx, }),
+            layout: None,
+            scroll_behavior: ScrollBehavior::default(),
+            static_only: false,
+            partial_hydration: false,
+            #[cfg(target_arch = "wasm32")]
+            islands: IslandMap::new(),
             // Unlike `template`, this may not be set at all (especially in very simple apps)
             #[cfg(not(target_arch = "wasm32"))]
             head: Box::new(|cx, _| sycamore::view! { cx, }),
@@ -242,6 +294,14 @@ impl<G: Html> Template<G> {
             #[cfg(not(target_arch = "wasm32"))]
             get_request_state: None,
             #[cfg(not(target_arch = "wasm32"))]
+            request_state_cache: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            build_state_timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            request_state_timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            state_timeout_fallback: StateTimeoutFallback::default(),
+            #[cfg(not(target_arch = "wasm32"))]
             should_revalidate: None,
             #[cfg(not(target_arch = "wasm32"))]
             revalidate_after: None,
@@ -268,7 +328,11 @@ impl<G: Html> Template<G> {
         // we have to do is provide the translator, replacing whatever is present
         provide_context_signal_replace(cx, translator);
 
-        (self.template)(cx, props)
+        if self.partial_hydration {
+            render_partially_hydrated(cx, || (self.template)(cx, props), &self.islands)
+        } else {
+            (self.template)(cx, props)
+        }
     }
     /// Executes the user-given function that renders the template on the
     /// server-side ONLY. This automatically initializes an isolated global
@@ -279,15 +343,58 @@ impl<G: Html> Template<G> {
         props: PageProps,
         cx: Scope<'a>,
         translator: &Translator,
+        layout_states: &LayoutStates,
     ) -> View<G> {
         // The context we have here has no context elements set on it, so we set all the
         // defaults (job of the router component on the client-side)
         // We don't need the value, we just want the context instantiations
-        let _ = RenderCtx::default().set_ctx(cx);
+        let render_ctx = RenderCtx::default().set_ctx(cx);
+        // Pages rendered at request-time can react to their query parameters
+        render_ctx.router.set_query(props.query.clone());
         // And now provide a translator separately
         provide_context_signal_replace(cx, translator.clone());
 
-        (self.template)(cx, props)
+        let view = if self.partial_hydration {
+            render_partially_hydrated(cx, || (self.template)(cx, props))
+        } else {
+            (self.template)(cx, props)
+        };
+        self.wrap_in_layouts(cx, view, layout_states)
+    }
+    /// Renders the given view inside all this template's layouts, using the
+    /// given layout states. In the browser, the router handles this itself,
+    /// since it keeps layouts mounted between pages.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn wrap_in_layouts(&self, cx: Scope, view: View<G>, layout_states: &LayoutStates) -> View<G> {
+        // We have to go from the innermost layout outwards
+        self.get_layouts()
+            .into_iter()
+            .rev()
+            .fold(view, |view, layout| {
+                layout.render(
+                    cx,
+                    layout_states.get(&layout.get_name()).cloned(),
+                    sycamore::prelude::create_rc_signal(view),
+                )
+            })
+    }
+    /// Gets the build states of all this template's layouts in the given
+    /// locale from the immutable store.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn get_layout_states(
+        &self,
+        locale: &str,
+        immutable_store: &ImmutableStore,
+    ) -> Result<LayoutStates, ServerError> {
+        let mut layout_states = LayoutStates::new();
+        for layout in self.get_layouts() {
+            if layout.uses_build_state() {
+                let state = immutable_store.read(&layout.get_state_file(locale)).await?;
+                layout_states.insert(layout.get_name(), state);
+            }
+        }
+
+        Ok(layout_states)
     }
     /// Executes the user-given function that renders the document `<head>`,
     /// returning a string to be interpolated manually. Reactivity in this
@@ -341,7 +448,13 @@ impl<G: Html> Template<G> {
         locale: String,
     ) -> Result<String, ServerError> {
         if let Some(get_build_state) = &self.get_build_state {
-            let res = get_build_state.call(path, locale).await;
+            let res = self
+                .with_timeout(
+                    "get_build_state",
+                    &self.build_state_timeout,
+                    get_build_state.call(path, locale),
+                )
+                .await?;
             match res {
                 Ok(res) => Ok(res),
                 Err(GenericErrorWithCause { error, cause }) => Err(ServerError::RenderFnFailed {
@@ -373,7 +486,13 @@ impl<G: Html> Template<G> {
         req: Request,
     ) -> Result<String, ServerError> {
         if let Some(get_request_state) = &self.get_request_state {
-            let res = get_request_state.call(path, locale, req).await;
+            let res = self
+                .with_timeout(
+                    "get_request_state",
+                    &self.request_state_timeout,
+                    get_request_state.call(path, locale, req),
+                )
+                .await?;
             match res {
                 Ok(res) => Ok(res),
                 Err(GenericErrorWithCause { error, cause }) => Err(ServerError::RenderFnFailed {
@@ -391,6 +510,26 @@ impl<G: Html> Template<G> {
             .into())
         }
     }
+    /// Runs the given state function future, abandoning it if it takes longer
+    /// than the given timeout (if there is one).
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn with_timeout<T>(
+        &self,
+        fn_name: &str,
+        timeout: &Option<ComputedDuration>,
+        fut: impl Future<Output = T>,
+    ) -> Result<T, ServerError> {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout.to_std(), fut)
+                .await
+                .map_err(|_| ServerError::StateFnTimedOut {
+                    fn_name: fn_name.to_string(),
+                    template_name: self.get_path(),
+                    status: self.state_timeout_fallback.get_status(),
+                }),
+            None => Ok(fut.await),
+        }
+    }
     /// Amalgamates given request and build states. Errors here can be caused by
     /// either the server or the client, so the user must specify
     /// an [`ErrorCause`].
@@ -473,6 +612,33 @@ impl<G: Html> Template<G> {
     pub fn get_path(&self) -> String {
         self.path.clone()
     }
+    /// Gets the layouts this template should be rendered inside, from the
+    /// outermost to the innermost.
+    pub fn get_layouts(&self) -> Vec<&Layout<G>> {
+        let mut layouts = Vec::new();
+        let mut layout = self.layout.as_ref();
+        while let Some(curr) = layout {
+            layouts.push(curr);
+            layout = curr.get_parent();
+        }
+        layouts.reverse();
+
+        layouts
+    }
+    /// Gets how the router should handle the scroll position when the user
+    /// navigates to a page using this template.
+    pub fn get_scroll_behavior(&self) -> ScrollBehavior {
+        self.scroll_behavior
+    }
+    /// Checks if this template's pages should be served as plain HTML,
+    /// without any Wasm.
+    pub fn is_static_only(&self) -> bool {
+        self.static_only
+    }
+    /// Checks if only the islands in this template's pages will be hydrated.
+    pub fn uses_partial_hydration(&self) -> bool {
+        self.partial_hydration
+    }
     /// Gets the interval after which the template will next revalidate.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn get_revalidate_interval(&self) -> Option<ComputedDuration> {
@@ -513,6 +679,17 @@ impl<G: Html> Template<G> {
     pub fn uses_request_state(&self) -> bool {
         self.get_request_state.is_some()
     }
+    /// Gets the cache for this template's request states, if it has one.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_request_state_cache(&self) -> Option<&RequestStateCache> {
+        self.request_state_cache.as_ref()
+    }
+    /// Checks if this template should serve the last successfully generated
+    /// state for a page when one of its state functions times out.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn falls_back_to_last_state(&self) -> bool {
+        self.state_timeout_fallback == StateTimeoutFallback::LastSuccessful
+    }
     /// Checks if this template needs to do anything at build time.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn uses_build_state(&self) -> bool {
@@ -555,6 +732,85 @@ impl<G: Html> Template<G> {
         self
     }
 
+    /// Sets the layout this template should be rendered inside. Layouts are
+    /// rendered on the engine-side along with the template, but, in the
+    /// browser, they'll stay mounted as the user navigates between pages that
+    /// use them, with only the content inside them being replaced. See
+    /// [`Layout`] for further details.
+    pub fn layout(mut self, val: Layout<G>) -> Template<G> {
+        self.layout = Some(val);
+        self
+    }
+    /// Sets how the router should handle the scroll position when the user
+    /// navigates to a page using this template. By default, scroll positions
+    /// will be restored when the user navigates back and forward, and
+    /// fragments in URLs will be scrolled to. See [`ScrollBehavior`] for the
+    /// alternatives.
+    pub fn scroll_behavior(mut self, val: ScrollBehavior) -> Template<G> {
+        self.scroll_behavior = val;
+        self
+    }
+    /// Marks this template as *static-only*, meaning its pages will be served
+    /// as plain prerendered HTML, without the scripts that load your app's
+    /// bundles (or any of the state they'd need). This is ideal for pages
+    /// that are just content, like legal pages or blog posts, since they'll
+    /// load without downloading any Wasm at all.
+    ///
+    /// The catch is that these pages won't be interactive at all, and that
+    /// navigating into or out of them will be done with full page loads
+    /// (since there's no app running on them to handle navigation). Anything
+    /// reactive in the template will only be rendered in its initial state.
+    pub fn static_only(mut self) -> Template<G> {
+        self.static_only = true;
+        self
+    }
+    /// Enables *partial hydration* for this template. Its pages will still be
+    /// prerendered in full, but, when they're first loaded, the browser will
+    /// only bring the islands in them (created with [`island`](super::island)) to life,
+    /// leaving everything else as static HTML. For content-heavy pages with
+    /// only a few interactive parts, this can make hydration dramatically
+    /// faster. Each island used must be registered with `.island()`.
+    ///
+    /// Note that, when the user navigates to one of these pages from
+    /// elsewhere in the app, they'll be rendered in full as usual, and that
+    /// anything reactive outside an island will stay in its initial state on
+    /// the first load.
+    pub fn partial_hydration(mut self) -> Template<G> {
+        self.partial_hydration = true;
+        self
+    }
+    /// Registers a component that can be rendered as an island in this
+    /// template under the given name, which should be the same as that given
+    /// to [`island`](super::island). In templates that use partial hydration, this will be
+    /// used to render the island from its serialized properties in the
+    /// browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn island<P: serde::de::DeserializeOwned>(
+        mut self,
+        name: &str,
+        component: impl Fn(Scope, P) -> View<G> + 'static,
+    ) -> Template<G> {
+        let island_fn: IslandFn<G> = Box::new(move |cx, props| {
+            serde_json::from_str::<P>(props)
+                .ok()
+                .map(|props| component(cx, props))
+        });
+        self.islands.insert(name.to_string(), island_fn);
+        self
+    }
+    /// Registers a component that can be rendered as an island in this
+    /// template under the given name, which should be the same as that given
+    /// to [`island`](super::island). In templates that use partial hydration, this will be
+    /// used to render the island from its serialized properties in the
+    /// browser.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn island<P: serde::de::DeserializeOwned>(
+        self,
+        _name: &str,
+        _component: impl Fn(Scope, P) -> View<G> + 'static,
+    ) -> Template<G> {
+        self
+    }
     /// Sets the document `<head>` rendering function to use. The [`View`]
     /// produced by this will only be rendered on the engine-side, and will
     /// *not* be reactive (since it only contains metadata).
@@ -649,6 +905,138 @@ impl<G: Html> Template<G> {
         self
     }
 
+    /// Caches the states generated by the *request state* strategy for the
+    /// given duration (which takes the same format as for
+    /// [`Self::revalidate_after`]). States will be cached separately for each
+    /// path and locale, and for each key that the given function derives from
+    /// the request (e.g. from a header or cookie), so make sure that requests
+    /// which should see different states produce different keys! If the state
+    /// doesn't depend on the request at all, that function can just return an
+    /// empty string.
+    ///
+    /// This is intended for pages like dashboards, where the same request state
+    /// would otherwise be regenerated for every visitor, multiplying the load
+    /// on whatever backend it comes from. Pages are still rendered on every
+    /// request, and errors from your request state function are never cached.
+    ///
+    /// Note that, as with the other functions provided to templates, the key
+    /// function is only needed on the engine-side, so you'll need a dummy
+    /// function (taking no arguments) for the browser.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cache_request_state<I: PerseusDuration>(
+        mut self,
+        ttl: I,
+        key_fn: impl Fn(&Request) -> String + Send + Sync + 'static,
+        location: RequestCacheLocation,
+    ) -> Template<G> {
+        let ttl = match ttl.into_computed() {
+            Ok(ttl) => ttl,
+            // As with revalidation, this will show up before runtime
+            Err(_) => panic!("invalid request state cache duration"),
+        };
+        self.request_state_cache = Some(RequestStateCache::new(
+            &self.path,
+            ttl,
+            Box::new(key_fn),
+            location,
+        ));
+        self
+    }
+    /// Caches the states generated by the *request state* strategy for the
+    /// given duration (which takes the same format as for
+    /// [`Self::revalidate_after`]). States will be cached separately for each
+    /// path and locale, and for each key that the given function derives from
+    /// the request (e.g. from a header or cookie), so make sure that requests
+    /// which should see different states produce different keys! If the state
+    /// doesn't depend on the request at all, that function can just return an
+    /// empty string.
+    ///
+    /// This is intended for pages like dashboards, where the same request state
+    /// would otherwise be regenerated for every visitor, multiplying the load
+    /// on whatever backend it comes from. Pages are still rendered on every
+    /// request, and errors from your request state function are never cached.
+    ///
+    /// Note that, as with the other functions provided to templates, the key
+    /// function is only needed on the engine-side, so you'll need a dummy
+    /// function (taking no arguments) for the browser.
+    #[cfg(target_arch = "wasm32")]
+    pub fn cache_request_state<I: PerseusDuration>(
+        self,
+        _ttl: I,
+        _key_fn: impl Fn() + 'static,
+        _location: RequestCacheLocation,
+    ) -> Template<G> {
+        self
+    }
+
+    /// Sets the maximum length of time the *build state* function may take (in the
+    /// same format as for [`Self::revalidate_after`]). If it takes any longer,
+    /// it will be abandoned, and the fallback set with
+    /// [`Self::state_timeout_fallback`] will be used.
+    ///
+    /// This also applies at build time, where a timeout will fail the build,
+    /// rather than leaving it hanging indefinitely.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn build_state_timeout<I: PerseusDuration>(mut self, val: I) -> Template<G> {
+        let computed_duration = match val.into_computed() {
+            Ok(val) => val,
+            // This will show up before runtime
+            Err(_) => panic!("invalid state function timeout"),
+        };
+        self.build_state_timeout = Some(computed_duration);
+        self
+    }
+    /// Sets the maximum length of time the *build state* function may take (in the
+    /// same format as for [`Self::revalidate_after`]). If it takes any longer,
+    /// it will be abandoned, and the fallback set with
+    /// [`Self::state_timeout_fallback`] will be used.
+    ///
+    /// This also applies at build time, where a timeout will fail the build,
+    /// rather than leaving it hanging indefinitely.
+    #[cfg(target_arch = "wasm32")]
+    pub fn build_state_timeout<I: PerseusDuration>(self, _val: I) -> Template<G> {
+        self
+    }
+
+    /// Sets the maximum length of time the *request state* function may take (in the
+    /// same format as for [`Self::revalidate_after`]). If it takes any longer,
+    /// it will be abandoned, and the fallback set with
+    /// [`Self::state_timeout_fallback`] will be used.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn request_state_timeout<I: PerseusDuration>(mut self, val: I) -> Template<G> {
+        let computed_duration = match val.into_computed() {
+            Ok(val) => val,
+            // This will show up before runtime
+            Err(_) => panic!("invalid state function timeout"),
+        };
+        self.request_state_timeout = Some(computed_duration);
+        self
+    }
+    /// Sets the maximum length of time the *request state* function may take (in the
+    /// same format as for [`Self::revalidate_after`]). If it takes any longer,
+    /// it will be abandoned, and the fallback set with
+    /// [`Self::state_timeout_fallback`] will be used.
+    #[cfg(target_arch = "wasm32")]
+    pub fn request_state_timeout<I: PerseusDuration>(self, _val: I) -> Template<G> {
+        self
+    }
+
+    /// Sets what to do when one of this template's state functions takes
+    /// longer than the timeout set for it. By default, this will fail with a
+    /// *504 Gateway Timeout* error.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn state_timeout_fallback(mut self, val: StateTimeoutFallback) -> Template<G> {
+        self.state_timeout_fallback = val;
+        self
+    }
+    /// Sets what to do when one of this template's state functions takes
+    /// longer than the timeout set for it. By default, this will fail with a
+    /// *504 Gateway Timeout* error.
+    #[cfg(target_arch = "wasm32")]
+    pub fn state_timeout_fallback(self, _val: StateTimeoutFallback) -> Template<G> {
+        self
+    }
+
     /// Enables the *revalidation* strategy (logic variant) with the given
     /// function.
     #[cfg(not(target_arch = "wasm32"))]
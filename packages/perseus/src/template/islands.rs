@@ -0,0 +1,206 @@
+use crate::Html;
+#[cfg(target_arch = "wasm32")]
+use std::collections::HashMap;
+use sycamore::prelude::{create_node_ref, view, Scope, View};
+use sycamore::utils::hydrate::{
+    hydrate_component, hydration_completed, with_hydration_context, with_no_hydration_context,
+};
+use sycamore::utils::render::insert;
+
+/// The attribute that holds the name of an island on its root element.
+pub const ISLAND_ATTR: &str = "data-perseus-island";
+/// The attribute that holds the serialized properties of an island on its root
+/// element.
+pub const ISLAND_PROPS_ATTR: &str = "data-perseus-island-props";
+
+/// The type of functions that render an island from its serialized
+/// properties. These will return `None` if the properties couldn't be
+/// deserialized.
+pub type IslandFn<G> = Box<dyn Fn(Scope, &str) -> Option<View<G>>>;
+/// A map of island names to the functions that render them.
+#[cfg(target_arch = "wasm32")]
+pub(crate) type IslandMap<G> = HashMap<String, IslandFn<G>>;
+
+/// Renders the given component as an *island*, an interactive part of a page
+/// that can be brought to life in the browser independently of the rest of
+/// it. The given properties will be serialized into the prerendered HTML, so
+/// that, in templates that use partial hydration, the browser can render the
+/// island without rendering the rest of the page at all. For that to work,
+/// the component must be registered on the template with `.island()` under
+/// the same name.
+///
+/// Islands can be used in any template, but, unless partial hydration is
+/// enabled, they'll be hydrated along with the rest of the page. In templates
+/// that use partial hydration, each island is prerendered with hydration keys
+/// of its own, so that it can be hydrated against the engine's HTML without
+/// the rest of the page.
+pub fn island<G: Html, P: serde::Serialize>(
+    cx: Scope,
+    name: &str,
+    props: P,
+    component: impl FnOnce(Scope, P) -> View<G>,
+) -> View<G> {
+    let props_str =
+        serde_json::to_string(&props).expect("couldn't serialize properties for island");
+    let name = name.to_string();
+    let node_ref = create_node_ref::<G>(cx);
+    let view = view! { cx,
+        div(
+            ref = node_ref,
+            data-perseus-island = name,
+            data-perseus-island-props = props_str,
+            style = "display: contents;"
+        ) {}
+    };
+    let node = node_ref.get_raw();
+    #[cfg(target_arch = "wasm32")]
+    let initial =
+        (G::CLIENT_SIDE_HYDRATION && !hydration_completed()).then(|| get_existing_children(&node));
+    #[cfg(not(target_arch = "wasm32"))]
+    let initial = None;
+    // Outside a hydration context on the engine-side, we're in a partially hydrated
+    // page, so the island gets a context of its own, which the browser will recreate
+    // when it mounts the island on its own
+    let content = if !G::IS_BROWSER && hydration_completed() {
+        with_hydration_context(|| hydrate_component(|| component(cx, props)))
+    } else {
+        hydrate_component(|| component(cx, props))
+    };
+    insert(cx, &node, content, initial, None, false);
+
+    view
+}
+
+/// Renders the content of a template that uses partial hydration. On the
+/// engine-side, this will be prerendered without any hydration keys, and, when
+/// the page is hydrated in the browser, it will be left alone, except for the
+/// islands inside it, which will be hydrated from their serialized properties.
+/// Anywhere else, this will be rendered as usual.
+pub(crate) fn render_partially_hydrated<G: Html>(
+    cx: Scope,
+    content: impl FnOnce() -> View<G>,
+    #[cfg(target_arch = "wasm32")] islands: &IslandMap<G>,
+) -> View<G> {
+    let node_ref = create_node_ref::<G>(cx);
+    let view = view! { cx,
+        div(ref = node_ref, data-perseus-partial = "", style = "display: contents;") {}
+    };
+    let node = node_ref.get_raw();
+    if G::CLIENT_SIDE_HYDRATION && !hydration_completed() {
+        // The engine has already rendered everything else, so we only need the islands
+        #[cfg(target_arch = "wasm32")]
+        mount_islands(cx, &node, islands);
+    } else {
+        let content = with_no_hydration_context(content);
+        insert(cx, &node, content, None, None, false);
+    }
+
+    view
+}
+
+/// Hydrates all the islands inside the given prerendered element from their
+/// serialized properties. Any islands that haven't been registered, or whose
+/// properties are invalid, will be left as static HTML.
+#[cfg(target_arch = "wasm32")]
+fn mount_islands<G: Html>(cx: Scope, container: &G, islands: &IslandMap<G>) {
+    use sycamore::rt::JsCast;
+    use web_sys::Element;
+
+    let container: Element = container.to_web_sys().unchecked_into();
+    let selector = format!("[{}]", ISLAND_ATTR);
+    let elems = match container.query_selector_all(&selector) {
+        Ok(elems) => elems,
+        Err(_) => return,
+    };
+    for i in 0..elems.length() {
+        let elem: Element = match elems.item(i) {
+            Some(elem) => elem.unchecked_into(),
+            None => continue,
+        };
+        // Islands inside other islands will be hydrated by their parents
+        let is_nested = elem
+            .parent_element()
+            .and_then(|parent| parent.closest(&selector).ok().flatten())
+            .is_some();
+        if is_nested {
+            continue;
+        }
+
+        let name = elem.get_attribute(ISLAND_ATTR).unwrap_or_default();
+        let props = elem.get_attribute(ISLAND_PROPS_ATTR).unwrap_or_default();
+        let island_fn = match islands.get(&name) {
+            Some(island_fn) => island_fn,
+            None => continue,
+        };
+        let node = sycamore::web::from_web_sys::<G>(elem.clone().into());
+        let initial = get_existing_children(&node);
+        // Every island was prerendered in a hydration context of its own, so their
+        // keys will clash with each other's (and the rest of the page's), and
+        // Sycamore looks for them across the whole document
+        let hidden = hide_outer_hydration_keys(&elem);
+        let content = with_hydration_context(|| hydrate_component(|| island_fn(cx, &props)));
+        restore_hydration_keys(hidden);
+        if let Some(content) = content {
+            insert(cx, &node, content, Some(initial), None, false);
+        }
+    }
+}
+
+/// The attribute hydration keys are moved to while they're hidden from
+/// Sycamore.
+#[cfg(target_arch = "wasm32")]
+const HIDDEN_HK_ATTR: &str = "data-perseus-hk";
+
+/// Gets the nodes currently inside the given node, so that a hydrated view
+/// can be reconciled with them.
+#[cfg(target_arch = "wasm32")]
+fn get_existing_children<G: Html>(node: &G) -> View<G> {
+    let child_nodes = node.to_web_sys().child_nodes();
+    let children = (0..child_nodes.length())
+        .filter_map(|i| child_nodes.get(i))
+        .map(|child| View::new_node(sycamore::web::from_web_sys::<G>(child)))
+        .collect::<Vec<_>>();
+    View::new_fragment(children)
+}
+
+/// Hides the hydration keys of every element in the document outside the
+/// given island, returning the elements whose keys were hidden.
+#[cfg(target_arch = "wasm32")]
+fn hide_outer_hydration_keys(island: &web_sys::Element) -> Vec<web_sys::Element> {
+    use sycamore::rt::JsCast;
+
+    let elems = match web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.query_selector_all("[data-hk]").ok())
+    {
+        Some(elems) => elems,
+        None => return Vec::new(),
+    };
+    let mut hidden = Vec::new();
+    for i in 0..elems.length() {
+        let elem: web_sys::Element = match elems.item(i) {
+            Some(elem) => elem.unchecked_into(),
+            None => continue,
+        };
+        if island.contains(Some(&elem)) {
+            continue;
+        }
+        if let Some(hk) = elem.get_attribute("data-hk") {
+            let _ = elem.set_attribute(HIDDEN_HK_ATTR, &hk);
+            let _ = elem.remove_attribute("data-hk");
+            hidden.push(elem);
+        }
+    }
+    hidden
+}
+
+/// Restores the hydration keys hidden by [`hide_outer_hydration_keys`].
+#[cfg(target_arch = "wasm32")]
+fn restore_hydration_keys(hidden: Vec<web_sys::Element>) {
+    for elem in hidden {
+        if let Some(hk) = elem.get_attribute(HIDDEN_HK_ATTR) {
+            let _ = elem.set_attribute("data-hk", &hk);
+            let _ = elem.remove_attribute(HIDDEN_HK_ATTR);
+        }
+    }
+}
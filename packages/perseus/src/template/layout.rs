@@ -0,0 +1,183 @@
+#[cfg(not(target_arch = "wasm32"))]
+use super::RenderFnResultWithCause;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::errors::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::make_async_trait;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::AsyncFnReturn;
+use crate::Html;
+#[cfg(not(target_arch = "wasm32"))]
+use futures::Future;
+use std::collections::HashMap;
+use sycamore::prelude::{RcSignal, Scope, View};
+
+/// The states of the layouts around a page, stringified and indexed by the
+/// names of the layouts. Layouts that don't generate state won't have entries
+/// in here.
+pub type LayoutStates = HashMap<String, String>;
+
+#[cfg(not(target_arch = "wasm32"))]
+make_async_trait!(
+    GetLayoutBuildStateFnType,
+    RenderFnResultWithCause<String>,
+    locale: String
+);
+
+/// The type of functions that get the build state of a layout.
+#[cfg(not(target_arch = "wasm32"))]
+pub type GetLayoutBuildStateFn = Box<dyn GetLayoutBuildStateFnType + Send + Sync>;
+/// The type of functions that render a layout around some content.
+pub type LayoutFn<G> = Box<dyn Fn(Scope, LayoutProps<G>) -> View<G> + Send + Sync>;
+
+/// The properties a layout will be rendered with.
+#[derive(Debug)]
+pub struct LayoutProps<G: Html> {
+    /// The state of the layout, stringified. This will be `Some(_)` if the
+    /// layout generates build state, we just can't prove that to the compiler.
+    pub state: Option<String>,
+    /// The content the layout should be rendered around. This is reactive, and
+    /// will be updated when the user navigates to another page that uses this
+    /// layout, without the layout itself being rendered again.
+    pub content: View<G>,
+}
+
+/// A layout that can be rendered around the content of one or more templates.
+/// Unlike a component that each template renders itself, a layout is only
+/// rendered once in the browser, and it will stay mounted as long as the user
+/// navigates between pages that use it, with only the content inside it being
+/// replaced. This means any DOM or local state inside the layout (e.g. a
+/// sidebar's scroll position, or a playing media element) will survive route
+/// changes.
+///
+/// Layouts are identified by their names, so any templates that use layouts
+/// with the same name will share them. Layouts can also be nested, by giving
+/// one a parent, in which case navigating between pages that share only the
+/// outer layout will only re-render the inner one.
+///
+/// Layouts may generate their own state at build time, which is generated once
+/// for each locale.
+pub struct Layout<G: Html> {
+    /// The name of the layout, which should be unique among all the layouts in
+    /// the app.
+    name: String,
+    /// The layout this one should be rendered inside, if any.
+    parent: Option<Box<Layout<G>>>,
+    /// The function that renders this layout around some content.
+    template: LayoutFn<G>,
+    /// A function that generates the state of this layout at build time.
+    #[cfg(not(target_arch = "wasm32"))]
+    get_build_state: Option<GetLayoutBuildStateFn>,
+}
+impl<G: Html> std::fmt::Debug for Layout<G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Layout")
+            .field("name", &self.name)
+            .field("parent", &self.parent)
+            .field("template", &"LayoutFn")
+            .finish()
+    }
+}
+impl<G: Html> Layout<G> {
+    /// Creates a new [`Layout`] with the given name. By default, this will just
+    /// render the content it's given.
+    pub fn new(name: impl Into<String> + std::fmt::Display) -> Self {
+        Self {
+            name: name.to_string(),
+            parent: None,
+            template: Box::new(|_, props| props.content),
+            #[cfg(not(target_arch = "wasm32"))]
+            get_build_state: None,
+        }
+    }
+
+    /// Renders this layout around the given content, which will be updated
+    /// reactively.
+    pub(crate) fn render(
+        &self,
+        cx: Scope,
+        state: Option<String>,
+        content: RcSignal<View<G>>,
+    ) -> View<G> {
+        let content = View::new_dyn(cx, move || content.get().as_ref().clone());
+        (self.template)(cx, LayoutProps { state, content })
+    }
+    /// Gets the build state for this layout in the given locale.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn get_build_state(&self, locale: String) -> Result<String, ServerError> {
+        if let Some(get_build_state) = &self.get_build_state {
+            let res = get_build_state.call(locale).await;
+            match res {
+                Ok(res) => Ok(res),
+                Err(GenericErrorWithCause { error, cause }) => Err(ServerError::RenderFnFailed {
+                    fn_name: "get_build_state".to_string(),
+                    template_name: self.name.clone(),
+                    cause,
+                    source: error,
+                }),
+            }
+        } else {
+            Err(BuildError::TemplateFeatureNotEnabled {
+                template_name: self.name.clone(),
+                feature_name: "build_state".to_string(),
+            }
+            .into())
+        }
+    }
+    /// Gets the name of the file in the immutable store that this layout's
+    /// build state for the given locale is kept in.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn get_state_file(&self, locale: &str) -> String {
+        format!(
+            "static/layouts/{}-{}.json",
+            locale,
+            urlencoding::encode(&self.name)
+        )
+    }
+    /// Gets the name of this layout.
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    /// Gets the layout this one is rendered inside, if any.
+    pub fn get_parent(&self) -> Option<&Layout<G>> {
+        self.parent.as_deref()
+    }
+    /// Checks if this layout generates state at build time.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn uses_build_state(&self) -> bool {
+        self.get_build_state.is_some()
+    }
+
+    // Builder setters
+    /// Sets the function that renders this layout. This will be given the
+    /// layout's state (if it has any) and the content to render inside the
+    /// layout.
+    pub fn template(
+        mut self,
+        val: impl Fn(Scope, LayoutProps<G>) -> View<G> + Send + Sync + 'static,
+    ) -> Self {
+        self.template = Box::new(val);
+        self
+    }
+    /// Sets the layout this one should be rendered inside.
+    pub fn parent(mut self, val: Layout<G>) -> Self {
+        self.parent = Some(Box::new(val));
+        self
+    }
+    /// Enables build state for this layout with the given function, which will
+    /// be called once for each locale at build time.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn build_state_fn(
+        mut self,
+        val: impl GetLayoutBuildStateFnType + Send + Sync + 'static,
+    ) -> Self {
+        self.get_build_state = Some(Box::new(val));
+        self
+    }
+    /// Enables build state for this layout with the given function, which will
+    /// be called once for each locale at build time.
+    #[cfg(target_arch = "wasm32")]
+    pub fn build_state_fn(self, _val: impl Fn() + 'static) -> Self {
+        self
+    }
+}
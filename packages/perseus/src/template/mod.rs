@@ -1,8 +1,12 @@
 mod core; // So called because this contains what is essentially the core exposed logic of Perseus
 #[cfg(not(target_arch = "wasm32"))]
 mod default_headers;
+mod islands;
+mod layout;
 mod page_props;
 mod render_ctx;
+mod request_cache;
+mod state_timeout;
 #[cfg(not(target_arch = "wasm32"))]
 mod states;
 mod templates_map;
@@ -11,8 +15,19 @@ pub use self::core::*; /* There are a lot of render function traits in here, the
                         * point in spelling them all out */
 #[cfg(not(target_arch = "wasm32"))]
 pub(crate) use default_headers::default_headers;
+pub(crate) use islands::render_partially_hydrated;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use islands::IslandMap;
+pub use islands::{island, IslandFn, ISLAND_ATTR, ISLAND_PROPS_ATTR};
+#[cfg(not(target_arch = "wasm32"))]
+pub use layout::{GetLayoutBuildStateFn, GetLayoutBuildStateFnType};
+pub use layout::{Layout, LayoutFn, LayoutProps, LayoutStates};
 pub use page_props::PageProps;
 pub use render_ctx::RenderCtx;
+pub use request_cache::RequestCacheLocation;
+#[cfg(not(target_arch = "wasm32"))]
+pub use request_cache::{RequestCacheKeyFn, RequestStateCache};
+pub use state_timeout::StateTimeoutFallback;
 #[cfg(not(target_arch = "wasm32"))]
 pub(crate) use states::States;
 pub use templates_map::{ArcTemplateMap, TemplateMap};
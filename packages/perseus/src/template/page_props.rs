@@ -1,3 +1,5 @@
+use crate::utils::QueryParams;
+
 /// The properties that every page will be initialized with. You shouldn't ever
 /// need to interact with this unless you decide not to use the template macros.
 #[derive(Clone, Debug)]
@@ -10,4 +12,9 @@ pub struct PageProps {
     /// The global state, stringified. This will be `Some(_)` if state was
     /// generated, we just can't prove that to the compiler.
     pub global_state: Option<String>,
+    /// The query parameters of the URL the page is being rendered for. On the
+    /// engine-side, these will only be present for pages rendered at
+    /// request-time (since pages rendered at build-time are shared between
+    /// all requests, whatever their query parameters).
+    pub query: QueryParams,
 }
@@ -3,7 +3,8 @@ use super::TemplateNodeType;
 use crate::errors::*;
 use crate::router::{RouterLoadState, RouterState};
 use crate::state::{
-    AnyFreeze, Freeze, FrozenApp, GlobalState, MakeRx, MakeUnrx, PageStateStore, ThawPrefs,
+    deserialize_frozen_state, AnyFreeze, Freeze, FrozenApp, FrozenStateStore, GlobalState, MakeRx,
+    MakeUnrx, PageStateStore, ThawPrefs,
 };
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -49,6 +50,11 @@ pub struct RenderCtx {
     /// with an error in it.
     #[cfg(target_arch = "wasm32")]
     pub error_pages: Rc<crate::error_pages::ErrorPages<TemplateNodeType>>,
+    /// The recorded history of the app's page and global state, which can be
+    /// used to undo and redo changes to them. This will only record anything
+    /// if it's been enabled with `PerseusApp::record_state_history()`.
+    #[cfg(feature = "state-history")]
+    pub history: crate::state::StateHistory,
     // --- PRIVATE FIELDS ---
     // Any users accessing these are *extremely* likely to shoot themselves in the foot!
     /// Whether or not this page is the very first to have been rendered since
@@ -57,6 +63,10 @@ pub struct RenderCtx {
     /// stored HSR state.
     #[cfg(target_arch = "wasm32")]
     pub(crate) is_first: Rc<std::cell::Cell<bool>>,
+    /// The system that synchronizes global state with other tabs, if that's
+    /// enabled.
+    #[cfg(all(feature = "global-state-sync", target_arch = "wasm32"))]
+    pub(crate) global_state_syncer: Option<crate::state::GlobalStateSyncer>,
     /// The locales, for use in routing.
     #[cfg(target_arch = "wasm32")]
     pub(crate) locales: crate::i18n::Locales,
@@ -69,14 +79,19 @@ pub struct RenderCtx {
     /// The client-side translations manager.
     #[cfg(target_arch = "wasm32")]
     pub(crate) translations_manager: crate::i18n::ClientTranslationsManager,
+    /// The layouts the router currently has mounted.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) layouts: crate::router::MountedLayouts,
 }
 impl Freeze for RenderCtx {
     /// 'Freezes' the relevant parts of the render configuration to a serialized
     /// `String` that can later be used to re-initialize the app to the same
     /// state at the time of freezing.
     fn freeze(&self) -> String {
+        let global_state = self.global_state.0.borrow();
         let frozen_app = FrozenApp {
-            global_state: self.global_state.0.borrow().freeze(),
+            global_state: global_state.freeze(),
+            global_state_version: global_state.state_version(),
             route: match &*self.router.get_load_state_rc().get_untracked() {
                 RouterLoadState::Loaded { path, .. } => path,
                 RouterLoadState::Loading { path, .. } => path,
@@ -87,6 +102,7 @@ impl Freeze for RenderCtx {
             }
             .to_string(),
             page_state_store: self.page_state_store.freeze_to_hash_map(),
+            page_state_versions: self.page_state_store.state_versions(),
         };
         serde_json::to_string(&frozen_app).unwrap()
     }
@@ -100,6 +116,8 @@ impl Default for RenderCtx {
                                                        * server-side */
             global_state: GlobalState::default(),
             frozen_app: Rc::new(RefCell::new(None)),
+            #[cfg(feature = "state-history")]
+            history: crate::state::StateHistory::default(),
         }
     }
 }
@@ -109,25 +127,63 @@ impl RenderCtx {
     #[cfg(target_arch = "wasm32")] // To prevent foot-shooting
     /// Note: this is designed for client-side usage, use `::default()` on the
     /// engine-side.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         pss_max_size: usize,
+        pss_memory_policy: Option<crate::state::PssMemoryPolicy>,
         locales: crate::i18n::Locales,
         templates: crate::template::TemplateMap<TemplateNodeType>,
         render_cfg: Rc<std::collections::HashMap<String, String>>,
         error_pages: Rc<crate::error_pages::ErrorPages<TemplateNodeType>>,
+        #[cfg(feature = "global-state-sync")] global_state_sync: Option<
+            crate::state::GlobalStateSync,
+        >,
+        #[cfg(feature = "state-history")] state_history_len: Option<usize>,
     ) -> Self {
         let translations_manager = crate::i18n::ClientTranslationsManager::new(&locales);
+        let router = RouterState::default();
+        let global_state = GlobalState::default();
+        let frozen_app = Rc::new(RefCell::new(None));
+        #[cfg(feature = "global-state-sync")]
+        let global_state_syncer = global_state_sync.and_then(|settings| {
+            crate::state::GlobalStateSyncer::new(
+                settings,
+                global_state.clone(),
+                router.clone(),
+                frozen_app.clone(),
+            )
+        });
+        let mut page_state_store = PageStateStore::new(pss_max_size);
+        if let Some(policy) = pss_memory_policy {
+            page_state_store = page_state_store.with_memory_policy(policy);
+        }
+        #[cfg(feature = "state-history")]
+        let history = match state_history_len {
+            Some(max_len) => crate::state::StateHistory::new(
+                max_len,
+                router.clone(),
+                global_state.clone(),
+                page_state_store.clone(),
+                frozen_app.clone(),
+            ),
+            None => crate::state::StateHistory::default(),
+        };
         Self {
-            router: RouterState::default(),
-            page_state_store: PageStateStore::new(pss_max_size),
-            global_state: GlobalState::default(),
-            frozen_app: Rc::new(RefCell::new(None)),
+            router,
+            page_state_store,
+            global_state,
+            frozen_app,
+            #[cfg(feature = "state-history")]
+            history,
             is_first: Rc::new(std::cell::Cell::new(true)),
+            #[cfg(feature = "global-state-sync")]
+            global_state_syncer,
             error_pages,
             locales,
             templates,
             render_cfg,
             translations_manager,
+            layouts: crate::router::MountedLayouts::default(),
         }
     }
     // TODO Use a custom, optimized context system instead of Sycamore's? (GIven we
@@ -237,9 +293,10 @@ impl RenderCtx {
         };
 
         // We just needed to acquire the arguments to this function
+        // The page state store is indexed by paths without locales
         self.page_state_store
             .preload(
-                path,
+                &route_info.path,
                 &route_info.locale,
                 &route_info.template.get_path(),
                 route_info.was_incremental_match,
@@ -260,7 +317,9 @@ impl RenderCtx {
     /// This will return an error if the frozen state provided is invalid.
     /// However, if the frozen state for an individual page is invalid, it will
     /// be silently ignored in favor of either the active state or the
-    /// server-provided state.
+    /// server-provided state. Frozen state from an older version of its data
+    /// model will be upgraded with `MakeRx::migrate()` when it's used, and it
+    /// will only be ignored if that fails.
     pub fn thaw(&self, new_frozen_app: &str, thaw_prefs: ThawPrefs) -> Result<(), ClientError> {
         let new_frozen_app: FrozenApp = serde_json::from_str(new_frozen_app)
             .map_err(|err| ClientError::ThawFailed { source: err })?;
@@ -294,6 +353,31 @@ impl RenderCtx {
 
         Ok(())
     }
+    /// Freezes the app's state and saves it into the given frozen state store,
+    /// replacing whatever was there before.
+    pub async fn freeze_to(&self, store: &dyn FrozenStateStore) -> Result<(), ClientError> {
+        let frozen_state = self.freeze();
+        store.set(&frozen_state).await?;
+
+        Ok(())
+    }
+    /// Thaws the app from the state stored in the given frozen state store,
+    /// with the given preferences. This will return `false` if there was no
+    /// state stored, in which case nothing will have been thawed. See
+    /// `.thaw()` for further details.
+    pub async fn thaw_from(
+        &self,
+        store: &dyn FrozenStateStore,
+        thaw_prefs: ThawPrefs,
+    ) -> Result<bool, ClientError> {
+        let frozen_state = match store.get().await? {
+            Some(frozen_state) => frozen_state,
+            None => return Ok(false),
+        };
+        self.thaw(&frozen_state, thaw_prefs)?;
+
+        Ok(true)
+    }
     /// An internal getter for the frozen state for the given page. When this is
     /// called, it will also add any frozen state it finds to the page state
     /// store, overriding what was already there.
@@ -318,15 +402,18 @@ impl RenderCtx {
                 // Get the serialized and unreactive frozen state from the store
                 match frozen_app.page_state_store.get(url) {
                     Some(state_str) => {
-                        // Deserialize into the unreactive version
-                        let unrx = match serde_json::from_str::<R::Unrx>(state_str) {
-                            Ok(unrx) => unrx,
-                            // The frozen state could easily be corrupted, so we'll fall back to the
-                            // active state (which is already reactive)
-                            // We break out here to avoid double-storing this and trying to make a
-                            // reactive thing reactive
-                            Err(_) => return None,
-                        };
+                        // Deserialize into the unreactive version, migrating it if it was frozen
+                        // with an older version of the data model
+                        let version = frozen_app
+                            .page_state_versions
+                            .get(url)
+                            .copied()
+                            .unwrap_or(0);
+                        // The frozen state could easily be corrupted (or impossible to migrate), so
+                        // we'll fall back to the active state (which is already reactive)
+                        // We break out here to avoid double-storing this and trying to make a
+                        // reactive thing reactive
+                        let unrx = deserialize_frozen_state::<R::Unrx>(state_str, version)?;
                         // This returns the reactive version of the unreactive version of `R`, which
                         // is why we have to make everything else do the same
                         // Then we convince the compiler that that actually is `R` with the
@@ -334,6 +421,7 @@ impl RenderCtx {
                         let rx = unrx.make_rx();
                         // And we do want to add this to the page state store (if this returns
                         // false, then this page was never supposed to receive state)
+                        self.register_page_template(url);
                         if !self.page_state_store.add_state(url, rx.clone()) {
                             return None;
                         }
@@ -342,8 +430,11 @@ impl RenderCtx {
                         drop(frozen_app_full);
                         let mut frozen_app_val = self.frozen_app.take().unwrap(); // We're literally in a conditional that checked this
                         frozen_app_val.0.page_state_store.remove(url);
+                        frozen_app_val.0.page_state_versions.remove(url);
                         let mut frozen_app = self.frozen_app.borrow_mut();
                         *frozen_app = Some(frozen_app_val);
+                        drop(frozen_app);
+                        self.page_state_registered(url);
 
                         Some(rx)
                     }
@@ -430,12 +521,12 @@ impl RenderCtx {
                     // See `rx_state.rs` for why this would be the default value
                     "None" => None,
                     state_str => {
-                        // Deserialize into the unreactive version
-                        let unrx = match serde_json::from_str::<R::Unrx>(state_str) {
-                            Ok(unrx) => unrx,
-                            // The frozen state could easily be corrupted
-                            Err(_) => return None,
-                        };
+                        // Deserialize into the unreactive version, migrating it if necessary
+                        // The frozen state could easily be corrupted
+                        let unrx = deserialize_frozen_state::<R::Unrx>(
+                            state_str,
+                            frozen_app.global_state_version,
+                        )?;
                         // This returns the reactive version of the unreactive version of `R`, which
                         // is why we have to make everything else do the same
                         // Then we convince the compiler that that actually is `R` with the
@@ -451,6 +542,8 @@ impl RenderCtx {
                         frozen_app_val.0.global_state = "None".to_string();
                         let mut frozen_app = self.frozen_app.borrow_mut();
                         *frozen_app = Some(frozen_app_val);
+                        drop(active_global_state);
+                        self.watch_global_state();
 
                         Some(rx)
                     }
@@ -536,8 +629,11 @@ impl RenderCtx {
         let unrx = serde_json::from_str::<R::Unrx>(state_str)
             .map_err(|err| ClientError::StateInvalid { source: err })?;
         let rx = unrx.make_rx();
+        self.register_page_template(url);
         // Potential silent failure (see above)
-        let _ = self.page_state_store.add_state(url, rx.clone());
+        if self.page_state_store.add_state(url, rx.clone()) {
+            self.page_state_registered(url);
+        }
 
         Ok(rx)
     }
@@ -560,9 +656,43 @@ impl RenderCtx {
         let rx = unrx.make_rx();
         let mut active_global_state = self.global_state.0.borrow_mut();
         *active_global_state = Box::new(rx.clone());
+        drop(active_global_state);
+        self.watch_global_state();
 
         Ok(rx)
     }
+    /// Starts watching the global state for changes to synchronize with other
+    /// tabs and to record in the state history, if those are enabled. This
+    /// must be called whenever the global state is replaced.
+    fn watch_global_state(&self) {
+        #[cfg(all(feature = "global-state-sync", target_arch = "wasm32"))]
+        if let Some(syncer) = &self.global_state_syncer {
+            syncer.watch();
+        }
+        #[cfg(feature = "state-history")]
+        self.history.registered(None);
+    }
+    /// Lets the state history know that the given page has new state, if it's
+    /// enabled. This must be called whenever a page's state is registered.
+    #[allow(unused_variables)]
+    fn page_state_registered(&self, url: &str) {
+        #[cfg(feature = "state-history")]
+        self.history.registered(Some(url));
+    }
+    /// Records the template of the given page in the PSS, if it's the one the
+    /// router has loaded (which it will be when its state is registered),
+    /// so that the PSS memory policy can account for it.
+    fn register_page_template(&self, url: &str) {
+        if let RouterLoadState::Loaded {
+            template_name,
+            path,
+        } = &*self.router.get_load_state_rc().get_untracked()
+        {
+            if path == url {
+                self.page_state_store.set_template(url, template_name);
+            }
+        }
+    }
     /// Registers a page as definitely taking no state, which allows it to be
     /// cached fully, preventing unnecessary network requests. Any future
     /// attempt to set state will lead to silent failures and/or panics.
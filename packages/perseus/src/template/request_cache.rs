@@ -0,0 +1,268 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::errors::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::state::is_subsequent_load;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::stores::MutableStore;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::ComputedDuration;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::Request;
+#[cfg(not(target_arch = "wasm32"))]
+use chrono::{DateTime, Utc};
+#[cfg(not(target_arch = "wasm32"))]
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Mutex;
+
+/// The index of the entries a [`RequestStateCache`] has written to the mutable
+/// store, which maps their keys to their expiry times (in RFC 3339 format).
+#[cfg(not(target_arch = "wasm32"))]
+type StoreIndex = HashMap<String, String>;
+
+/// Where cached request states should be stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestCacheLocation {
+    /// In the memory of the server process. This is the fastest option, but
+    /// each instance of your app will have its own cache, and the cache will
+    /// be lost on restarts.
+    Memory,
+    /// In the app's mutable store, which allows the cache to be shared between
+    /// instances if your mutable store is (e.g. if it's backed by a database).
+    MutableStore,
+}
+
+/// The type of functions that derive a cache key from a request. Requests
+/// that produce the same key (for the same path and locale) will share the
+/// same cached request state.
+#[cfg(not(target_arch = "wasm32"))]
+pub type RequestCacheKeyFn = Box<dyn Fn(&Request) -> String + Send + Sync>;
+
+/// A cached request state, along with the time at which it expires.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CacheEntry {
+    /// The time after which this entry should no longer be used, in RFC 3339
+    /// format.
+    expiry: String,
+    /// The cached request state.
+    state: String,
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl CacheEntry {
+    /// Checks if this entry is still valid.
+    fn is_fresh(&self) -> bool {
+        is_fresh(&self.expiry)
+    }
+}
+
+/// Checks if the given expiry time (in RFC 3339 format) is still in the future.
+#[cfg(not(target_arch = "wasm32"))]
+fn is_fresh(expiry: &str) -> bool {
+    match DateTime::parse_from_rfc3339(expiry) {
+        Ok(expiry) => expiry > Utc::now(),
+        // An entry we can't understand is treated as expired, and will be replaced
+        Err(_) => false,
+    }
+}
+
+/// A short-lived cache for the request states of a single template. This is
+/// intended for pages whose request state doesn't depend on the specific user
+/// (e.g. dashboards that aggregate data from a backend), so that traffic spikes
+/// don't translate directly into backend load.
+///
+/// Cached states are keyed by the path and locale being rendered, along with a
+/// key derived from the request by a user-provided function (which might read
+/// a header or cookie, or just return a constant if the state is the same for
+/// everyone). Only states are cached: pages are still rendered on every
+/// request, and the state function's errors are never cached.
+///
+/// Expired entries are evicted as they're found, and every write to the cache
+/// sweeps out any others that have expired, so that keys which are never
+/// requested again don't pile up. In the mutable store, this is done with an
+/// index of the entries, which each instance of the app updates as it writes
+/// them (so an entry written by one instance at the same time as another
+/// instance updates the index may only be evicted when it's next read).
+#[cfg(not(target_arch = "wasm32"))]
+pub struct RequestStateCache {
+    /// How long cached states should be used for.
+    ttl: ComputedDuration,
+    /// The function that derives a cache key from a request.
+    key_fn: RequestCacheKeyFn,
+    /// Where cached states are stored.
+    location: RequestCacheLocation,
+    /// The in-memory cache, which is only used if `location` is
+    /// [`RequestCacheLocation::Memory`].
+    memory: Mutex<HashMap<String, CacheEntry>>,
+    /// The name of the index of this cache's entries in the mutable store.
+    index_name: String,
+    /// A lock that's held while the index is updated, so that writes from the
+    /// same instance can't overwrite each other's changes to it.
+    index_lock: futures::lock::Mutex<()>,
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl std::fmt::Debug for RequestStateCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestStateCache")
+            .field("ttl", &self.ttl)
+            .field("location", &self.location)
+            .finish()
+    }
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl RequestStateCache {
+    /// Creates a new, empty cache for the template with the given path.
+    pub(crate) fn new(
+        template_path: &str,
+        ttl: ComputedDuration,
+        key_fn: RequestCacheKeyFn,
+        location: RequestCacheLocation,
+    ) -> Self {
+        Self {
+            ttl,
+            key_fn,
+            location,
+            memory: Mutex::new(HashMap::new()),
+            index_name: format!(
+                "request_cache/index-{}.json",
+                urlencoding::encode(template_path)
+            ),
+            index_lock: futures::lock::Mutex::new(()),
+        }
+    }
+    /// Gets the full cache key for the given request to the page with the
+    /// given encoded path (which includes the locale).
+    pub(crate) fn get_key(&self, path_encoded: &str, req: &Request) -> String {
+        // States for subsequent loads leave any resources for the browser to load, so they
+        // shouldn't be used for initial loads
+        let load_kind = if is_subsequent_load(req) {
+            "subsequent"
+        } else {
+            "initial"
+        };
+        format!(
+            "{}-{}-{}",
+            path_encoded,
+            load_kind,
+            urlencoding::encode(&(self.key_fn)(req))
+        )
+    }
+    /// Gets the cached state for the given key, if there is one that hasn't
+    /// expired yet.
+    pub(crate) async fn get(&self, key: &str, mutable_store: &impl MutableStore) -> Option<String> {
+        let entry = match self.location {
+            RequestCacheLocation::Memory => {
+                let mut memory = self.memory.lock().unwrap();
+                let entry = memory.get(key).cloned();
+                // Clean up after ourselves so stale entries don't pile up
+                if matches!(&entry, Some(entry) if !entry.is_fresh()) {
+                    memory.remove(key);
+                }
+                entry
+            }
+            RequestCacheLocation::MutableStore => {
+                // A missing entry is just a cache miss
+                let contents = mutable_store.read(&get_entry_name(key)).await.ok()?;
+                let entry = serde_json::from_str::<CacheEntry>(&contents).ok()?;
+                // The next write will remove this from the index, and it doesn't matter if
+                // this fails, since the next sweep will try again
+                if !entry.is_fresh() {
+                    let _ = mutable_store.delete(&get_entry_name(key)).await;
+                }
+                Some(entry)
+            }
+        }?;
+
+        if entry.is_fresh() {
+            Some(entry.state)
+        } else {
+            None
+        }
+    }
+    /// Caches the given state under the given key.
+    pub(crate) async fn set(
+        &self,
+        key: &str,
+        state: &str,
+        mutable_store: &impl MutableStore,
+    ) -> Result<(), ServerError> {
+        let entry = CacheEntry {
+            expiry: self.ttl.compute_timestamp(),
+            state: state.to_string(),
+        };
+        match self.location {
+            RequestCacheLocation::Memory => {
+                let mut memory = self.memory.lock().unwrap();
+                // Since keys can come from the user, we make sure expired entries are evicted
+                // even if they're never requested again
+                memory.retain(|_, entry| entry.is_fresh());
+                memory.insert(key.to_string(), entry);
+            }
+            RequestCacheLocation::MutableStore => {
+                let contents = serde_json::to_string(&entry).map_err(|err| {
+                    let build_err: BuildError = err.into();
+                    build_err
+                })?;
+                mutable_store.write(&get_entry_name(key), &contents).await?;
+                self.sweep_store(key, &entry.expiry, mutable_store).await?;
+            }
+        }
+
+        Ok(())
+    }
+    /// Records the entry with the given key and expiry time in the index of
+    /// the mutable store, and deletes any entries in it that have expired.
+    async fn sweep_store(
+        &self,
+        key: &str,
+        expiry: &str,
+        mutable_store: &impl MutableStore,
+    ) -> Result<(), ServerError> {
+        let _guard = self.index_lock.lock().await;
+        // If the index is missing or corrupted, we'll just start a new one
+        let mut index = mutable_store
+            .read(&self.index_name)
+            .await
+            .ok()
+            .and_then(|contents| serde_json::from_str::<StoreIndex>(&contents).ok())
+            .unwrap_or_default();
+
+        let mut expired = Vec::new();
+        index.retain(|old_key, expiry| {
+            // We've just rewritten the entry for our own key
+            let fresh = old_key == key || is_fresh(expiry);
+            if !fresh {
+                expired.push(old_key.clone());
+            }
+            fresh
+        });
+        for expired_key in expired {
+            // If we can't delete this, we'll keep it in the index to try again next time
+            if mutable_store
+                .delete(&get_entry_name(&expired_key))
+                .await
+                .is_err()
+            {
+                // It'll definitely be expired by the next sweep
+                index.insert(expired_key, String::new());
+            }
+        }
+        index.insert(key.to_string(), expiry.to_string());
+
+        let contents = serde_json::to_string(&index).map_err(|err| {
+            let build_err: BuildError = err.into();
+            build_err
+        })?;
+        mutable_store.write(&self.index_name, &contents).await?;
+
+        Ok(())
+    }
+}
+
+/// Gets the name of the entry with the given key in the mutable store.
+#[cfg(not(target_arch = "wasm32"))]
+fn get_entry_name(key: &str) -> String {
+    format!("request_cache/{}.json", key)
+}
@@ -0,0 +1,37 @@
+/// What to do when a template's state function takes longer than the timeout
+/// set for it. In all cases, the state function itself will be abandoned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum StateTimeoutFallback {
+    /// Fail with a *504 Gateway Timeout* error. This is the default.
+    #[default]
+    GatewayTimeout,
+    /// Fail with the given HTTP status code, which will render the error page
+    /// you've defined for it.
+    ErrorPage(u16),
+    /// Serve the last state that was successfully generated for the page from
+    /// the mutable store, or fail with a *504 Gateway Timeout* error if there
+    /// isn't one. For pages that revalidate, this is the state from the last
+    /// successful revalidation.
+    ///
+    /// For request state, this only works if the template caches its request
+    /// states (see `Template::cache_request_state`), since the last state can
+    /// only be served to requests that would share a cached state (otherwise,
+    /// one user's state could be served to another). In that case, Perseus
+    /// will write each state it caches to the mutable store as well, and a
+    /// state served from there won't be cached itself. Without a cache, this
+    /// will fail with a *504 Gateway Timeout* error.
+    ///
+    /// Note that this can't do anything at build time, since the mutable store
+    /// will be empty then.
+    LastSuccessful,
+}
+impl StateTimeoutFallback {
+    /// Gets the HTTP status code that should be returned if a state function
+    /// times out, and this fallback can't (or won't) provide a state.
+    pub fn get_status(&self) -> u16 {
+        match self {
+            Self::ErrorPage(status) => *status,
+            _ => 504,
+        }
+    }
+}
@@ -16,6 +16,11 @@ mod engine {
             let datetime = current + self.0;
             datetime.to_rfc3339()
         }
+        /// Gets this duration as a standard library duration, which will be zero
+        /// if this duration is negative.
+        pub(crate) fn to_std(&self) -> time::Duration {
+            self.0.to_std().unwrap_or_default()
+        }
     }
 
     /// A trait that represents anything we'll accept for specifying durations
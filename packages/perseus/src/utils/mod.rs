@@ -11,6 +11,7 @@ mod log;
 #[cfg(not(target_arch = "wasm32"))]
 mod minify;
 mod path_prefix;
+mod query;
 #[cfg(target_arch = "wasm32")]
 mod replace_head;
 mod test;
@@ -27,5 +28,6 @@ pub(crate) use fetch::fetch;
 #[cfg(not(target_arch = "wasm32"))]
 pub(crate) use minify::minify;
 pub use path_prefix::*;
+pub use query::{parse_query, QueryParams};
 #[cfg(target_arch = "wasm32")]
 pub(crate) use replace_head::replace_head;
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+/// The parsed query parameters of a URL. If a parameter is given more than
+/// once, only its last value will be kept.
+pub type QueryParams = HashMap<String, String>;
+
+/// Parses the given query string (with or without a leading `?`) into a map
+/// of parameters. Parameters that can't be decoded will be skipped.
+pub fn parse_query(query: &str) -> QueryParams {
+    let query = query.strip_prefix('?').unwrap_or(query);
+    let mut params = QueryParams::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, val) = pair.split_once('=').unwrap_or((pair, ""));
+        // Spaces can be encoded as `+` in query strings, which `urlencoding` doesn't
+        // handle
+        let key = urlencoding::decode(&key.replace('+', " ")).map(|key| key.to_string());
+        let val = urlencoding::decode(&val.replace('+', " ")).map(|val| val.to_string());
+        if let (Ok(key), Ok(val)) = (key, val) {
+            params.insert(key, val);
+        }
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parses_pairs() {
+        let params = parse_query("?foo=bar&baz=qux");
+        assert_eq!(params.len(), 2);
+        assert_eq!(params.get("foo").unwrap(), "bar");
+        assert_eq!(params.get("baz").unwrap(), "qux");
+    }
+    #[test]
+    fn works_without_leading_question_mark() {
+        let params = parse_query("foo=bar");
+        assert_eq!(params.get("foo").unwrap(), "bar");
+    }
+    #[test]
+    fn handles_empty_query() {
+        assert!(parse_query("").is_empty());
+        assert!(parse_query("?").is_empty());
+        assert!(parse_query("?&&").is_empty());
+    }
+    #[test]
+    fn handles_keys_without_values() {
+        let params = parse_query("?flag&foo=");
+        assert_eq!(params.get("flag").unwrap(), "");
+        assert_eq!(params.get("foo").unwrap(), "");
+    }
+    #[test]
+    fn decodes_percent_encoding_and_plus() {
+        let params = parse_query("?q=hello+world%21&a%20b=%3D");
+        assert_eq!(params.get("q").unwrap(), "hello world!");
+        assert_eq!(params.get("a b").unwrap(), "=");
+    }
+    #[test]
+    fn keeps_last_duplicate() {
+        let params = parse_query("?foo=1&foo=2");
+        assert_eq!(params.get("foo").unwrap(), "2");
+    }
+    #[test]
+    fn keeps_equals_signs_in_values() {
+        let params = parse_query("?token=abc=def");
+        assert_eq!(params.get("token").unwrap(), "abc=def");
+    }
+    #[test]
+    fn skips_undecodable_pairs() {
+        let params = parse_query("?bad=%FF&good=1");
+        assert!(!params.contains_key("bad"));
+        assert_eq!(params.get("good").unwrap(), "1");
+    }
+}